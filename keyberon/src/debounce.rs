@@ -34,6 +34,12 @@ impl<T> Debouncer<T> {
             nb_bounce,
         }
     }
+
+    /// Changes the number of update with same state needed to
+    /// validate the new state.
+    pub fn set_nb_bounce(&mut self, nb_bounce: u16) {
+        self.nb_bounce = nb_bounce;
+    }
 }
 
 impl<T: PartialEq> Debouncer<T> {
@@ -98,6 +98,10 @@ pub struct Layout<
     active_sequences: ArrayDeque<[SequenceState; 4], arraydeque::behavior::Wrapping>,
     stacked: Stack,
     tap_hold_tracker: TapHoldTracker,
+    virtual_actions: Vec<((u8, u8), Action<T, K>), 8>,
+    settings: LayoutSettings,
+    oneshot_tap_tracker: OneShotTapTracker,
+    locked_oneshot_keys: Vec<(u8, u8), 8>,
 }
 
 /// An event on the key matrix.
@@ -493,6 +497,37 @@ impl TapHoldTracker {
     }
 }
 
+#[derive(Default)]
+struct OneShotTapTracker {
+    coord: (u8, u8),
+    taps: u8,
+    timeout: u16,
+}
+
+impl OneShotTapTracker {
+    fn tick(&mut self) {
+        self.timeout = self.timeout.saturating_sub(1);
+    }
+}
+
+/// Settings that change the behaviour of the actions on a layout. These can be changed at runtime
+/// using [`Layout::set_settings`].
+///
+/// By default, nothing is overridden, and every action behaves as it was defined.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LayoutSettings {
+    /// If set, every [`HoldTapAction`] uses this timeout instead of its own.
+    pub hold_tap_timeout: Option<u16>,
+    /// If set, every [`HoldTapAction`] uses this config instead of its own.
+    pub hold_tap_config: Option<HoldTapConfig>,
+    /// If set, every [`OneShotAction`] uses this timeout instead of its own.
+    pub oneshot_timeout: Option<u16>,
+    /// Number of consecutive taps on a [`OneShotAction`] key that will lock its action. A locked
+    /// action stays active until the key is pressed again. If this is 0, one shot keys are never
+    /// locked.
+    pub oneshot_tap_toggle: u8,
+}
+
 /// Errors that can occur when attempting to change an action
 #[derive(Debug)]
 pub enum ChangeActionError {
@@ -595,6 +630,10 @@ impl<
             active_sequences: ArrayDeque::new(),
             stacked: ArrayDeque::new(),
             tap_hold_tracker: Default::default(),
+            virtual_actions: Vec::new(),
+            settings: Default::default(),
+            oneshot_tap_tracker: Default::default(),
+            locked_oneshot_keys: Vec::new(),
         }
     }
     /// Iterates on the key codes of the current state.
@@ -806,6 +845,7 @@ impl<
         self.states = self.states.iter().filter_map(State::tick).collect();
         self.stacked.iter_mut().for_each(Stacked::tick);
         self.tap_hold_tracker.tick();
+        self.oneshot_tap_tracker.tick();
 
         let mut custom = CustomEvent::NoEvent;
         let mut should_unstack = true;
@@ -862,6 +902,11 @@ impl<
         use Event::*;
         match stacked.event {
             Release(i, j) => {
+                // Locked one shot keys stay active until they are pressed again
+                if self.locked_oneshot_keys.contains(&(i, j)) {
+                    return CustomEvent::NoEvent;
+                }
+
                 let mut custom = CustomEvent::NoEvent;
                 let mut should_release_normally = true;
 
@@ -899,7 +944,21 @@ impl<
                 custom
             }
             Press(i, j) => {
-                let action = self.press_as_action((i, j), self.current_layer());
+                if let Some(idx) = self.locked_oneshot_keys.iter().position(|c| *c == (i, j)) {
+                    self.locked_oneshot_keys.swap_remove(idx);
+                    let mut custom = CustomEvent::NoEvent;
+                    self.states
+                        .retain(|s| s.release((i, j), &mut custom).is_some());
+                    return custom;
+                }
+                if (i, j) != self.oneshot_tap_tracker.coord {
+                    self.oneshot_tap_tracker.taps = 0;
+                }
+
+                let action = match self.virtual_actions.iter().position(|(c, _)| *c == (i, j)) {
+                    Some(idx) => self.virtual_actions.swap_remove(idx).1,
+                    None => self.press_as_action((i, j), self.current_layer()),
+                };
                 self.do_action(action, (i, j), stacked.since, &mut ActionContext::default())
             }
         }
@@ -911,6 +970,30 @@ impl<
             self.unstack(stacked);
         }
     }
+    /// Register a press event for a virtual key at `coord`, which will trigger `action` instead
+    /// of the action defined in the layout.
+    ///
+    /// This is useful for keys that don't exist on the layout, like the result of a combo. The
+    /// virtual key must be released by registering a [`Event::Release`] event with the same
+    /// coordinates, so the coordinates should not be used by any other key.
+    pub fn event_with_action(&mut self, coord: (u8, u8), action: Action<T, K>) {
+        if self.virtual_actions.push((coord, action)).is_ok() {
+            self.event(Event::Press(coord.0, coord.1));
+        }
+    }
+    /// Change the settings that override the behaviour of the actions on this layout.
+    pub fn set_settings(&mut self, settings: LayoutSettings) {
+        self.settings = settings;
+    }
+    /// Get the settings that override the behaviour of the actions on this layout.
+    pub fn settings(&self) -> LayoutSettings {
+        self.settings
+    }
+    /// Get the action that would be triggered if the key at `coord` was pressed on the current
+    /// layer. Transparent actions are resolved using the default layer.
+    pub fn action_at(&self, coord: (u8, u8)) -> Action<T, K> {
+        self.press_as_action(coord, self.current_layer())
+    }
     fn press_as_action(&self, coord: (u8, u8), layer: usize) -> Action<T, K> {
         use crate::action::Action::*;
         let action = self
@@ -972,17 +1055,19 @@ impl<
                 config,
                 tap_hold_interval,
             }) => {
+                let timeout = self.settings.hold_tap_timeout.unwrap_or(*timeout);
+                let config = self.settings.hold_tap_config.unwrap_or(*config);
                 if *tap_hold_interval == 0
                     || coord != self.tap_hold_tracker.coord
                     || self.tap_hold_tracker.timeout == 0
                 {
                     let waiting: WaitingState<T, K> = WaitingState {
                         coord,
-                        timeout: *timeout,
+                        timeout,
                         delay,
                         hold,
                         tap,
-                        config: *config,
+                        config,
                     };
                     self.waiting = Some(waiting);
                     self.tap_hold_tracker.timeout = *tap_hold_interval;
@@ -999,6 +1084,20 @@ impl<
                 end_config,
             }) => {
                 self.tap_hold_tracker.coord = coord;
+                let timeout = self.settings.oneshot_timeout.unwrap_or(timeout);
+                if self.count_oneshot_tap(coord, timeout) {
+                    // The key was tapped enough times, so its action is locked instead of being
+                    // released at the end of the one shot.
+                    if let Some(oneshot) = &mut self.oneshot {
+                        oneshot.active_oneshot_keys.retain(|c| *c != coord);
+                        oneshot.released_oneshot_keys.retain(|c| *c != coord);
+                        if oneshot.active_oneshot_keys.is_empty() {
+                            self.oneshot = None;
+                        }
+                    }
+                    let _ = self.locked_oneshot_keys.push(coord);
+                    return self.do_action(action, coord, delay, context);
+                }
                 context.inside_oneshot = true;
                 let custom = self.do_action(action, coord, delay, context);
                 context.inside_oneshot = false;
@@ -1127,6 +1226,28 @@ impl<
         CustomEvent::NoEvent
     }
 
+    /// Count a tap on a one shot key, and check if the key should be locked.
+    fn count_oneshot_tap(&mut self, coord: (u8, u8), timeout: u16) -> bool {
+        let toggle = self.settings.oneshot_tap_toggle;
+        if toggle == 0 {
+            return false;
+        }
+
+        let tracker = &mut self.oneshot_tap_tracker;
+        if tracker.coord == coord && tracker.timeout > 0 {
+            tracker.taps = tracker.taps.saturating_add(1);
+        } else {
+            tracker.taps = 1;
+        }
+        tracker.coord = coord;
+        tracker.timeout = timeout;
+
+        if tracker.taps >= toggle && !self.locked_oneshot_keys.is_full() {
+            tracker.taps = 0;
+            return true;
+        }
+        false
+    }
     fn handle_terminal_action(&mut self, coord: (u8, u8), context: &mut ActionContext) {
         // ignore actions activated by a oneshot
         if !context.inside_oneshot {
@@ -3408,4 +3529,163 @@ mod test {
         assert_eq!(CustomEvent::NoEvent, layout.tick());
         assert_keys(&[], layout.keycodes()); // Should still be empty
     }
+
+    #[test]
+    fn virtual_key_action() {
+        static mut LAYERS: Layers<2, 1, 2> = [[[k(A), l(1)]], [[k(B), Trans]]];
+        let mut layout = Layout::new(unsafe { &mut LAYERS });
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+
+        // virtual key outside of the layout
+        assert_eq!(k(A), layout.action_at((0, 0)));
+        layout.event_with_action((u8::MAX, 0), k(C));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[C], layout.keycodes());
+        layout.event(Release(u8::MAX, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+
+        // virtual key overriding a key on the layout, only for a single press
+        layout.event_with_action((0, 0), k(D));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[D], layout.keycodes());
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+
+        // actions are resolved on the current layer
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(k(B), layout.action_at((0, 0)));
+        assert_eq!(l(1), layout.action_at((0, 1)));
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+    }
+
+    #[test]
+    fn hold_tap_settings() {
+        static mut LAYERS: Layers<2, 1, 1> = [[[
+            HoldTap(&HoldTapAction {
+                timeout: 200,
+                hold: k(LCtrl),
+                tap: k(Space),
+                config: HoldTapConfig::Default,
+                tap_hold_interval: 0,
+            }),
+            k(A),
+        ]]];
+        let mut layout = Layout::new(unsafe { &mut LAYERS });
+
+        // overridden timeout
+        layout.set_settings(LayoutSettings {
+            hold_tap_timeout: Some(10),
+            ..Default::default()
+        });
+        layout.event(Press(0, 0));
+        for _ in 0..10 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_keys(&[], layout.keycodes());
+        }
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LCtrl], layout.keycodes());
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+
+        // overridden config
+        layout.set_settings(LayoutSettings {
+            hold_tap_config: Some(HoldTapConfig::HoldOnOtherKeyPress),
+            ..Default::default()
+        });
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LCtrl], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LCtrl, A], layout.keycodes());
+        layout.event(Release(0, 1));
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+    }
+
+    #[test]
+    fn one_shot_tap_toggle() {
+        static mut LAYERS: Layers<2, 1, 1> = [[[
+            OneShot(&OneShotAction {
+                timeout: 100,
+                action: k(LShift),
+                end_config: OneShotEndConfig::EndOnFirstPress,
+            }),
+            k(A),
+        ]]];
+        let mut layout = Layout::new(unsafe { &mut LAYERS });
+        layout.set_settings(LayoutSettings {
+            oneshot_tap_toggle: 2,
+            ..Default::default()
+        });
+
+        // first tap behaves like a normal one shot
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LShift], layout.keycodes());
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LShift], layout.keycodes());
+
+        // second tap locks the action, even after the timeout and other key presses
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LShift], layout.keycodes());
+        layout.event(Release(0, 0));
+        for _ in 0..200 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+            assert_keys(&[LShift], layout.keycodes());
+        }
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LShift, A], layout.keycodes());
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LShift], layout.keycodes());
+
+        // pressing the key again unlocks the action
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+        layout.event(Release(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+
+        // taps that are too far apart don't lock the action
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        layout.event(Release(0, 0));
+        for _ in 0..150 {
+            assert_eq!(CustomEvent::NoEvent, layout.tick());
+        }
+        assert_keys(&[], layout.keycodes());
+        layout.event(Press(0, 0));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LShift], layout.keycodes());
+        layout.event(Release(0, 0));
+        layout.event(Press(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[LShift, A], layout.keycodes());
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[A], layout.keycodes());
+        layout.event(Release(0, 1));
+        assert_eq!(CustomEvent::NoEvent, layout.tick());
+        assert_keys(&[], layout.keycodes());
+    }
 }
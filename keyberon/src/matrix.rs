@@ -35,10 +35,11 @@ where
         R: OutputPin<Error = E>,
     {
         let mut res = Self { cols, rows };
-        res.clear()?;
+        res.release_rows()?;
         Ok(res)
     }
-    fn clear<E>(&mut self) -> Result<(), E>
+    /// Sets every row pin high, which is the state expected between scans.
+    pub fn release_rows<E>(&mut self) -> Result<(), E>
     where
         C: InputPin<Error = E>,
         R: OutputPin<Error = E>,
@@ -48,6 +49,26 @@ where
         }
         Ok(())
     }
+    /// Pulls every row pin low at the same time.
+    ///
+    /// While the rows are held low, pressing any key pulls its column
+    /// low. This can be used to wait for a key press without
+    /// scanning, for example with a pin change interrupt on the
+    /// columns. Call [`Matrix::release_rows`] before scanning again.
+    pub fn activate_all_rows<E>(&mut self) -> Result<(), E>
+    where
+        C: InputPin<Error = E>,
+        R: OutputPin<Error = E>,
+    {
+        for r in self.rows.iter_mut() {
+            r.set_low()?;
+        }
+        Ok(())
+    }
+    /// Gives mutable access to the column pins.
+    pub fn cols_mut(&mut self) -> &mut [C; CS] {
+        &mut self.cols
+    }
     /// Scans the matrix and checks which keys are pressed.
     ///
     /// Every row pin in order is pulled low, and then each column
@@ -116,6 +137,11 @@ where
         Ok(res)
     }
 
+    /// Gives mutable access to the pins.
+    pub fn pins_mut(&mut self) -> &mut [[Option<P>; CS]; RS] {
+        &mut self.pins
+    }
+
     /// Scans the pins and checks which keys are pressed (state is "low").
     pub fn get<E>(&mut self) -> Result<[[bool; CS]; RS], E>
     where
@@ -0,0 +1,24 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+pub fn driver_trait() -> TokenStream {
+    quote! {
+        /// A trait that keyboards must implement to use a 24LCxx/AT24Cxx EEPROM for storage.
+        pub(crate) trait At24cxxStorageSettings {
+            /// I2C Address for the EEPROM. Consult the datasheet for more information.
+            const I2C_ADDR: u8 = 0x50;
+
+            /// Number of bytes that the EEPROM can write in a single write cycle. Consult the
+            /// datasheet for more information.
+            const PAGE_SIZE: usize = 64;
+
+            /// Type of the I2C peripheral used to communicate with the EEPROM. It must implement
+            /// both the blocking and async versions of `I2c`.
+            type I2c: ::rumcake::embedded_hal_async::i2c::I2c
+                + ::rumcake::embedded_hal_1::i2c::I2c;
+
+            /// Setup the I2C peripheral to communicate with the EEPROM.
+            fn setup_i2c() -> Self::I2c;
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+pub fn driver_trait() -> TokenStream {
+    quote! {
+        /// A trait that keyboards must implement to set up the Cirque Pinnacle driver.
+        pub(crate) trait CirquePinnacleDriverSettings {
+            /// I2C Address for the Cirque trackpad. Consult the datasheet for more information.
+            const I2C_ADDR: u8 = 0x2A;
+
+            /// Setup the I2C peripheral to communicate with the Cirque trackpad.
+            ///
+            /// It is recommended to use [`rumcake::hw::mcu::setup_i2c`] to implement this function.
+            fn setup_i2c() -> impl ::rumcake::embedded_hal_async::i2c::I2c<Error = impl core::fmt::Debug>;
+        }
+    }
+}
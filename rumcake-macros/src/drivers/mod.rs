@@ -1,9 +1,13 @@
 use proc_macro2::TokenStream;
 use quote::quote;
 
+pub mod at24cxx;
+pub mod cirque_pinnacle;
 pub mod is31fl3731;
 pub mod nrf_ble;
+pub mod pmw33xx;
 pub mod ssd1306;
+pub mod w25qxx;
 pub mod ws2812;
 
 pub fn serial_driver_trait() -> TokenStream {
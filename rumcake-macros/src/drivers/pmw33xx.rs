@@ -0,0 +1,19 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+pub fn driver_trait() -> TokenStream {
+    quote! {
+        /// A trait that keyboards must implement to set up the PMW33xx driver.
+        pub(crate) trait PMW33xxDriverSettings {
+            /// Firmware to upload to the sensor's SROM when it starts. This is provided by PixArt.
+            /// If this is `None`, no firmware will be uploaded.
+            const SROM_FIRMWARE: Option<&'static [u8]> = None;
+
+            /// Setup the SPI peripheral to communicate with the PMW33xx sensor.
+            fn setup_spi() -> impl ::rumcake::embedded_hal_async::spi::SpiBus;
+
+            /// Setup the chip select pin of the PMW33xx sensor.
+            fn setup_cs_pin() -> impl ::rumcake::embedded_hal::digital::v2::OutputPin<Error = core::convert::Infallible>;
+        }
+    }
+}
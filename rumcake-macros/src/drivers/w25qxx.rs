@@ -0,0 +1,17 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+pub fn driver_trait() -> TokenStream {
+    quote! {
+        /// A trait that keyboards must implement to use a W25Qxx flash chip for storage.
+        pub(crate) trait W25QxxStorageSettings {
+            /// Type of the SPI device used to communicate with the flash chip. It must implement
+            /// both the blocking and async versions of `SpiDevice`.
+            type SpiDevice: ::rumcake::embedded_hal_async::spi::SpiDevice
+                + ::rumcake::embedded_hal_1::spi::SpiDevice;
+
+            /// Setup the SPI device (SPI bus and chip select pin) connected to the flash chip.
+            fn setup_spi() -> Self::SpiDevice;
+        }
+    }
+}
@@ -17,6 +17,17 @@ pub fn input_pin(ident: Ident) -> TokenStream {
     }
 }
 
+/// Create an input pin that never needs to wake up the keyboard. This is the same as
+/// [`input_pin`], since all pins can wait for a change in level.
+pub fn polled_input_pin(ident: Ident) -> TokenStream {
+    input_pin(ident)
+}
+
+/// Create input pins that can be used to wake up the keyboard.
+pub fn input_pins<'a>(pins: impl IntoIterator<Item = &'a Ident>) -> Vec<TokenStream> {
+    pins.into_iter().map(|pin| input_pin(pin.clone())).collect()
+}
+
 pub fn output_pin(ident: Ident) -> TokenStream {
     quote! {
         unsafe {
@@ -31,6 +42,18 @@ pub fn output_pin(ident: Ident) -> TokenStream {
     }
 }
 
+pub fn flex_pin(ident: Ident) -> TokenStream {
+    quote! {
+        unsafe {
+            ::rumcake::hw::mcu::embassy_nrf::gpio::Flex::new(
+                ::rumcake::hw::mcu::embassy_nrf::gpio::Pin::degrade(
+                    ::rumcake::hw::mcu::embassy_nrf::peripherals::#ident::steal(),
+                ),
+            )
+        }
+    }
+}
+
 fn setup_i2c_inner(args: Punctuated<Ident, Token![,]>) -> TokenStream {
     let mut args = args.iter();
 
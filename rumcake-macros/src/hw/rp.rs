@@ -17,6 +17,17 @@ pub fn input_pin(ident: Ident) -> TokenStream {
     }
 }
 
+/// Create an input pin that never needs to wake up the keyboard. This is the same as
+/// [`input_pin`], since all pins can wait for a change in level.
+pub fn polled_input_pin(ident: Ident) -> TokenStream {
+    input_pin(ident)
+}
+
+/// Create input pins that can be used to wake up the keyboard.
+pub fn input_pins<'a>(pins: impl IntoIterator<Item = &'a Ident>) -> Vec<TokenStream> {
+    pins.into_iter().map(|pin| input_pin(pin.clone())).collect()
+}
+
 pub fn output_pin(ident: Ident) -> TokenStream {
     quote! {
         unsafe {
@@ -30,6 +41,18 @@ pub fn output_pin(ident: Ident) -> TokenStream {
     }
 }
 
+pub fn flex_pin(ident: Ident) -> TokenStream {
+    quote! {
+        unsafe {
+            ::rumcake::hw::mcu::embassy_rp::gpio::Flex::new(
+                ::rumcake::hw::mcu::embassy_rp::gpio::Pin::degrade(
+                    ::rumcake::hw::mcu::embassy_rp::peripherals::#ident::steal(),
+                ),
+            )
+        }
+    }
+}
+
 pub fn internal_storage_trait() -> TokenStream {
     quote! {
         /// A trait that must be implemented to use the flash chip connected to your RP2040 for storage..
@@ -135,3 +158,49 @@ pub fn setup_buffered_uart(args: Punctuated<Ident, Token![,]>) -> TokenStream {
         }
     }
 }
+
+pub fn setup_adc_sampler(args: Punctuated<Ident, Token![,]>) -> TokenStream {
+    let channels = args.iter().map(|pin| {
+        quote! {
+            ::rumcake::hw::mcu::embassy_rp::adc::Channel::new_pin(
+                ::rumcake::hw::mcu::embassy_rp::peripherals::#pin::steal(),
+                ::rumcake::hw::mcu::embassy_rp::gpio::Pull::None,
+            )
+        }
+    });
+    let count = args.len();
+
+    quote! {
+        {
+            struct AdcSampler {
+                adc: ::rumcake::hw::mcu::embassy_rp::adc::Adc<
+                    'static,
+                    ::rumcake::hw::mcu::embassy_rp::adc::Async,
+                >,
+                channels: [::rumcake::hw::mcu::embassy_rp::adc::Channel<'static>; #count],
+            }
+
+            impl ::rumcake::matrix::analog::AnalogSampler for AdcSampler {
+                async fn sample(&mut self, input: usize) -> u16 {
+                    self.adc.read(&mut self.channels[input]).await.unwrap_or(0)
+                }
+            }
+
+            unsafe {
+                ::rumcake::hw::mcu::embassy_rp::bind_interrupts! {
+                    struct Irqs {
+                        ADC_IRQ_FIFO => ::rumcake::hw::mcu::embassy_rp::adc::InterruptHandler;
+                    }
+                };
+                AdcSampler {
+                    adc: ::rumcake::hw::mcu::embassy_rp::adc::Adc::new(
+                        ::rumcake::hw::mcu::embassy_rp::peripherals::ADC::steal(),
+                        Irqs,
+                        Default::default(),
+                    ),
+                    channels: [#(#channels),*],
+                }
+            }
+        }
+    }
+}
@@ -1,22 +1,73 @@
 use proc_macro2::{Ident, TokenStream};
 use proc_macro_error::{abort, OptionExt};
-use quote::quote;
+use quote::{format_ident, quote};
 use syn::punctuated::Punctuated;
 use syn::Token;
 
+fn exti_line(ident: &Ident) -> String {
+    let name = ident.to_string();
+    name.strip_prefix('P')
+        .and_then(|port| port.get(1..))
+        .expect_or_abort("Expected a pin name like `PA0`.")
+        .to_owned()
+}
+
 pub fn input_pin(ident: Ident) -> TokenStream {
+    // Input pins are connected to the EXTI line with the same number as the pin, so that they can
+    // be used to wake up the keyboard.
+    let exti = format_ident!("EXTI{}", exti_line(&ident));
+
     quote! {
-        unsafe {
+        ::rumcake::hw::mcu::WakeInput::Exti(unsafe {
+            ::rumcake::hw::mcu::embassy_stm32::exti::ExtiInput::new(
+                ::rumcake::hw::mcu::embassy_stm32::gpio::Input::new(
+                    ::rumcake::hw::mcu::embassy_stm32::gpio::Pin::degrade(
+                        ::rumcake::hw::mcu::embassy_stm32::peripherals::#ident::steal(),
+                    ),
+                    ::rumcake::hw::mcu::embassy_stm32::gpio::Pull::Up,
+                ),
+                ::rumcake::hw::mcu::embassy_stm32::exti::Channel::degrade(
+                    ::rumcake::hw::mcu::embassy_stm32::peripherals::#exti::steal(),
+                ),
+            )
+        })
+    }
+}
+
+/// Create an input pin that doesn't use an EXTI line, for pins that never need to wake up the
+/// keyboard.
+pub fn polled_input_pin(ident: Ident) -> TokenStream {
+    quote! {
+        ::rumcake::hw::mcu::WakeInput::Polled(unsafe {
             ::rumcake::hw::mcu::embassy_stm32::gpio::Input::new(
                 ::rumcake::hw::mcu::embassy_stm32::gpio::Pin::degrade(
                     ::rumcake::hw::mcu::embassy_stm32::peripherals::#ident::steal(),
                 ),
                 ::rumcake::hw::mcu::embassy_stm32::gpio::Pull::Up,
             )
-        }
+        })
     }
 }
 
+/// Create input pins that can be used to wake up the keyboard. An EXTI line can only be connected
+/// to one port at a time, so if multiple pins have the same number (e.g. `PA3` and `PB3`), only
+/// the first one uses the EXTI line, and the rest are polled.
+pub fn input_pins<'a>(pins: impl IntoIterator<Item = &'a Ident>) -> Vec<TokenStream> {
+    let mut used_lines = Vec::new();
+
+    pins.into_iter()
+        .map(|pin| {
+            let line = exti_line(pin);
+            if used_lines.contains(&line) {
+                polled_input_pin(pin.clone())
+            } else {
+                used_lines.push(line);
+                input_pin(pin.clone())
+            }
+        })
+        .collect()
+}
+
 pub fn output_pin(ident: Ident) -> TokenStream {
     quote! {
         unsafe {
@@ -31,6 +82,18 @@ pub fn output_pin(ident: Ident) -> TokenStream {
     }
 }
 
+pub fn flex_pin(ident: Ident) -> TokenStream {
+    quote! {
+        unsafe {
+            ::rumcake::hw::mcu::embassy_stm32::gpio::Flex::new(
+                ::rumcake::hw::mcu::embassy_stm32::gpio::Pin::degrade(
+                    ::rumcake::hw::mcu::embassy_stm32::peripherals::#ident::steal(),
+                ),
+            )
+        }
+    }
+}
+
 fn setup_i2c_inner(args: Punctuated<Ident, Token![,]>) -> TokenStream {
     let mut args = args.iter();
 
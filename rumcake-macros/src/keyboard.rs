@@ -2,17 +2,18 @@ use std::collections::HashMap;
 
 use darling::util::Override;
 use darling::FromMeta;
-use proc_macro2::{Ident, TokenStream, TokenTree};
-use proc_macro_error::OptionExt;
+use proc_macro2::{Ident, Literal, TokenStream, TokenTree};
+use proc_macro_error::{abort, OptionExt};
 use quote::{quote, quote_spanned, ToTokens};
 use syn::parse::{Parse, Parser};
 use syn::spanned::Spanned;
-use syn::{braced, bracketed, ItemStruct};
+use syn::{braced, bracketed, parenthesized, ItemStruct};
 
 #[derive(Debug, FromMeta, Default)]
 #[darling(default)]
 pub(crate) struct KeyboardSettings {
     no_matrix: bool,
+    encoders: bool,
     bluetooth: bool,
     usb: bool,
     storage: Option<StorageSettings>,
@@ -21,10 +22,13 @@ pub(crate) struct KeyboardSettings {
     rgb_backlight_matrix: Option<LightingSettings>,
     underglow: Option<LightingSettings>,
     display: Option<DisplaySettings>,
+    pointing_device: Option<PointingDeviceSettings>,
     split_peripheral: Option<SplitPeripheralSettings>,
     split_central: Option<SplitCentralSettings>,
     via: Option<Override<ViaSettings>>,
     vial: Option<Override<ViaSettings>>,
+    analog_matrix: Option<Override<AnalogMatrixSettings>>,
+    unicode: Option<Override<UnicodeSettings>>,
 }
 
 #[derive(Debug, FromMeta, Default)]
@@ -40,6 +44,12 @@ pub(crate) struct DisplaySettings {
     driver: String,
 }
 
+#[derive(Debug, FromMeta, Default)]
+#[darling(default)]
+pub(crate) struct PointingDeviceSettings {
+    driver: String,
+}
+
 #[derive(Debug, FromMeta, Default)]
 #[darling(default)]
 pub(crate) struct SplitCentralSettings {
@@ -58,6 +68,18 @@ pub(crate) struct ViaSettings {
     use_storage: bool,
 }
 
+#[derive(Debug, FromMeta, Default)]
+#[darling(default)]
+pub(crate) struct AnalogMatrixSettings {
+    use_storage: bool,
+}
+
+#[derive(Debug, FromMeta, Default)]
+#[darling(default)]
+pub(crate) struct UnicodeSettings {
+    use_storage: bool,
+}
+
 #[derive(Debug, FromMeta, Default)]
 #[darling(default)]
 pub(crate) struct StorageSettings {
@@ -255,6 +277,56 @@ fn setup_display_driver(
     });
 }
 
+fn setup_pointing_driver(
+    initialization: &mut TokenStream,
+    traits: &mut HashMap<String, TokenStream>,
+    kb_name: &Ident,
+    config: &PointingDeviceSettings,
+) {
+    match config.driver.as_str() {
+        "pmw3360" | "pmw3389" => {
+            let variant = if config.driver == "pmw3360" {
+                quote! { PMW3360 }
+            } else {
+                quote! { PMW3389 }
+            };
+            return {
+                traits.insert(
+                    "pmw33xx".to_string(),
+                    crate::drivers::pmw33xx::driver_trait(),
+                );
+                initialization.extend(quote! {
+                    let pointing_driver = ::rumcake::drivers::pmw33xx::setup_driver(
+                        <#kb_name as PMW33xxDriverSettings>::setup_spi(),
+                        <#kb_name as PMW33xxDriverSettings>::setup_cs_pin(),
+                        ::rumcake::drivers::pmw33xx::PMW33xxVariant::#variant,
+                        <#kb_name as PMW33xxDriverSettings>::SROM_FIRMWARE,
+                    ).await;
+                });
+            };
+        }
+        "cirque_pinnacle" => {
+            return {
+                traits.insert(
+                    config.driver.clone(),
+                    crate::drivers::cirque_pinnacle::driver_trait(),
+                );
+                initialization.extend(quote! {
+                    let pointing_driver = ::rumcake::drivers::cirque_pinnacle::setup_driver(
+                        <#kb_name as CirquePinnacleDriverSettings>::setup_i2c(),
+                        <#kb_name as CirquePinnacleDriverSettings>::I2C_ADDR,
+                    ).await;
+                });
+            }
+        }
+        _ => (),
+    }
+
+    initialization.extend(quote_spanned! {
+        config.driver.span() => compile_error!("Unknown pointing device driver.");
+    });
+}
+
 fn setup_storage_driver(
     initialization: &mut TokenStream,
     traits: &mut HashMap<String, TokenStream>,
@@ -313,6 +385,66 @@ fn setup_storage_driver(
                 });
             };
         }
+        "w25qxx" => {
+            traits.insert(
+                config.driver.clone(),
+                crate::drivers::w25qxx::driver_trait(),
+            );
+            return if config.flash_size == 0 {
+                initialization.extend(quote_spanned! {
+                    config.driver.span() => compile_error!("You must specify a non-zero size for the storage region of your flash chip.");
+                });
+            } else {
+                let size = config.flash_size;
+                initialization.extend(quote! {
+                    let flash = ::rumcake::drivers::w25qxx::setup_driver(<#kb_name as W25QxxStorageSettings>::setup_spi()).await;
+                    static mut READ_BUF: [u8; ::rumcake::drivers::w25qxx::SECTOR_SIZE] = [0; ::rumcake::drivers::w25qxx::SECTOR_SIZE];
+                    static mut OP_BUF: [u8; ::rumcake::drivers::w25qxx::SECTOR_SIZE] = [0; ::rumcake::drivers::w25qxx::SECTOR_SIZE];
+                    static DATABASE: ::rumcake::storage::StorageService<'static, ::rumcake::drivers::w25qxx::W25Qxx<<#kb_name as W25QxxStorageSettings>::SpiDevice>> = ::rumcake::storage::StorageService::new();
+                    unsafe { DATABASE.setup(flash, 0, #size, &mut READ_BUF, &mut OP_BUF).await; }
+                })
+            };
+        }
+        "at24cxx" => {
+            traits.insert(
+                config.driver.clone(),
+                crate::drivers::at24cxx::driver_trait(),
+            );
+            return if config.flash_size == 0 {
+                initialization.extend(quote_spanned! {
+                    config.driver.span() => compile_error!("You must specify a non-zero size for the storage region of your EEPROM.");
+                });
+            } else if config.flash_size > 0x10000 {
+                initialization.extend(quote_spanned! {
+                    config.driver.span() => compile_error!("The storage region of your EEPROM can't be larger than 64 KiB, since the driver uses 16-bit addresses.");
+                });
+            } else if config.flash_size % 512 != 0 {
+                // 512 is `rumcake::drivers::at24cxx::REGION_SIZE`
+                initialization.extend(quote_spanned! {
+                    config.driver.span() => compile_error!("The size of the storage region of your EEPROM must be a multiple of 512 bytes.");
+                });
+            } else {
+                let size = config.flash_size;
+                initialization.extend(quote! {
+                    const _: () = ::core::assert!(
+                        <#kb_name as At24cxxStorageSettings>::PAGE_SIZE > 0
+                            && ::rumcake::drivers::at24cxx::REGION_SIZE
+                                % <#kb_name as At24cxxStorageSettings>::PAGE_SIZE
+                                == 0,
+                        "`PAGE_SIZE` must be a factor of the EEPROM driver's `REGION_SIZE`."
+                    );
+                    let flash = ::rumcake::drivers::at24cxx::setup_driver(
+                        <#kb_name as At24cxxStorageSettings>::setup_i2c(),
+                        <#kb_name as At24cxxStorageSettings>::I2C_ADDR,
+                        <#kb_name as At24cxxStorageSettings>::PAGE_SIZE,
+                    );
+                    static mut READ_BUF: [u8; ::rumcake::drivers::at24cxx::REGION_SIZE] = [0; ::rumcake::drivers::at24cxx::REGION_SIZE];
+                    static mut OP_BUF: [u8; ::rumcake::drivers::at24cxx::REGION_SIZE] = [0; ::rumcake::drivers::at24cxx::REGION_SIZE];
+                    static DATABASE: ::rumcake::storage::StorageService<'static, ::rumcake::drivers::at24cxx::At24cxx<<#kb_name as At24cxxStorageSettings>::I2c>> = ::rumcake::storage::StorageService::new();
+                    unsafe { DATABASE.setup(flash, 0, #size, &mut READ_BUF, &mut OP_BUF).await; }
+                })
+            };
+        }
         _ => (),
     };
 
@@ -374,6 +506,18 @@ pub(crate) fn keyboard_main(
         });
     }
 
+    // Encoder polling task
+    if keyboard.encoders {
+        initialization.extend(quote! {
+            let encoders = <#kb_name as ::rumcake::keyboard::EncoderDevice>::setup_encoders();
+        });
+        spawning.extend(quote! {
+            spawner
+                .spawn(::rumcake::encoder_poll!(#kb_name, encoders))
+                .unwrap();
+        });
+    }
+
     // Flash setup
     if let Some(ref driver) = keyboard.storage {
         if !cfg!(feature = "storage") {
@@ -440,6 +584,17 @@ pub(crate) fn keyboard_main(
                 spawner.spawn(::rumcake::usb_hid_consumer_write_task!(consumer_class)).unwrap();
             });
         }
+
+        if cfg!(any(feature = "mouse-keys", feature = "pointing-device")) {
+            initialization.extend(quote! {
+                // HID mouse
+                let mouse_class = ::rumcake::usb::setup_usb_hid_mouse_writer(&mut builder);
+            });
+            spawning.extend(quote! {
+                // HID Mouse Report sending
+                spawner.spawn(::rumcake::usb_hid_mouse_write_task!(mouse_class)).unwrap();
+            });
+        }
     }
 
     if keyboard.usb && (keyboard.via.is_some() || keyboard.vial.is_some()) {
@@ -475,6 +630,9 @@ pub(crate) fn keyboard_main(
                 spawner
                     .spawn(::rumcake::via_storage_task!(#kb_name, &DATABASE))
                     .unwrap();
+                spawner
+                    .spawn(::rumcake::config_backup_task!(#kb_name, &DATABASE))
+                    .unwrap();
             });
         }
 
@@ -492,9 +650,18 @@ pub(crate) fn keyboard_main(
             });
         } else if args.use_storage {
             spawning.extend(quote! {
+                spawner
+                    .spawn(::rumcake::via_storage_task!(#kb_name, &DATABASE))
+                    .unwrap();
                 spawner
                     .spawn(::rumcake::vial_storage_task!(#kb_name, &DATABASE))
                     .unwrap();
+                spawner
+                    .spawn(::rumcake::config_backup_task!(#kb_name, &DATABASE))
+                    .unwrap();
+                spawner
+                    .spawn(::rumcake::keyboard_settings_storage_task!(#kb_name, &DATABASE))
+                    .unwrap();
             });
         }
 
@@ -674,6 +841,77 @@ pub(crate) fn keyboard_main(
         }
     }
 
+    // Pointing device setup
+    if let Some(args) = keyboard.pointing_device {
+        if args.driver.is_empty() {
+            initialization.extend(quote_spanned! {
+                args.driver.span() => compile_error!("You must specify a pointing device driver.");
+            })
+        } else {
+            setup_pointing_driver(&mut initialization, &mut traits, &kb_name, &args);
+            spawning.extend(quote! {
+                spawner.spawn(::rumcake::pointing_task!(#kb_name, pointing_driver)).unwrap();
+            });
+        }
+    }
+
+    // Analog matrix setup
+    if let Some(args) = keyboard.analog_matrix {
+        let args = args.unwrap_or_default();
+
+        if args.use_storage && keyboard.storage.is_none() {
+            initialization.extend(quote_spanned! {
+                args.use_storage.span() => compile_error!("The analog matrix uses storage but no `storage` driver was specified. Either specify a `storage` driver, or remove `use_storage` from your analog matrix settings.");
+            });
+        } else if args.use_storage {
+            spawning.extend(quote! {
+                spawner
+                    .spawn(::rumcake::analog_matrix_storage_task!(#kb_name, &DATABASE))
+                    .unwrap();
+            });
+        }
+    }
+
+    // Unicode setup
+    if let Some(args) = keyboard.unicode {
+        let args = args.unwrap_or_default();
+
+        if args.use_storage && keyboard.storage.is_none() {
+            initialization.extend(quote_spanned! {
+                args.use_storage.span() => compile_error!("Unicode uses storage but no `storage` driver was specified. Either specify a `storage` driver, or remove `use_storage` from your Unicode settings.");
+            });
+        } else if args.use_storage {
+            spawning.extend(quote! {
+                spawner
+                    .spawn(::rumcake::unicode_storage_task!(#kb_name, &DATABASE))
+                    .unwrap();
+            });
+        }
+    }
+
+    // Storage profiles
+    if cfg!(feature = "profiles") {
+        if keyboard.storage.is_none() {
+            initialization.extend(quote_spanned! {
+                str.span() => compile_error!("Storage profiles require a `storage` driver. Either specify a `storage` driver, or disable rumcake's `profiles` feature flag.");
+            });
+        } else {
+            initialization.extend(quote! {
+                const _: () = ::core::assert!(
+                    <#kb_name as ::rumcake::keyboard::KeyboardLayout>::PROFILE_COUNT > 0
+                        && <#kb_name as ::rumcake::keyboard::KeyboardLayout>::PROFILE_COUNT
+                            <= ::rumcake::storage::MAX_PROFILES,
+                    "`PROFILE_COUNT` must be between 1 and `MAX_PROFILES`."
+                );
+            });
+            spawning.extend(quote! {
+                spawner
+                    .spawn(::rumcake::profile_storage_task!(#kb_name, &DATABASE))
+                    .unwrap();
+            });
+        }
+    }
+
     let final_traits = traits.values();
 
     quote! {
@@ -729,39 +967,107 @@ impl<T: ToTokens> ToTokens for OptionalItem<T> {
 #[derive(Debug)]
 pub struct MatrixDefinition<T> {
     pub row_brace: syn::token::Brace,
-    pub rows: Vec<T>,
+    pub rows: MatrixPins<T>,
     pub col_brace: syn::token::Brace,
-    pub cols: Vec<T>,
+    pub cols: MatrixPins<T>,
 }
 
-impl<T: Parse> syn::parse::Parse for MatrixDefinition<T> {
+/// Rows or columns of a matrix, which can be connected directly to the MCU, or through a chain of
+/// shift registers.
+#[derive(Debug)]
+pub enum MatrixPins<T> {
+    Direct(Vec<T>),
+    ShiftRegister(ShiftRegisterDefinition),
+}
+
+impl<T> MatrixPins<T> {
+    fn len(&self) -> usize {
+        match self {
+            MatrixPins::Direct(pins) => pins.len(),
+            MatrixPins::ShiftRegister(definition) => definition
+                .count
+                .base10_parse()
+                .expect_or_abort("Expected a number of rows or columns."),
+        }
+    }
+}
+
+impl<T: Parse> syn::parse::Parse for MatrixPins<T> {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
-        let row_content;
-        let row_brace = braced!(row_content in input);
-        let mut rows = Vec::new();
-        while let Ok(t) = row_content.parse() {
-            rows.push(t)
+        if input.peek(Ident) && input.peek2(syn::token::Paren) {
+            return Ok(MatrixPins::ShiftRegister(input.parse()?));
+        }
+
+        let mut pins = Vec::new();
+        while let Ok(t) = input.parse() {
+            pins.push(t)
         }
-        if !row_content.is_empty() {
+        if !input.is_empty() {
             return Err(syn::Error::new(
-                row_content.span(),
+                input.span(),
                 "Encountered an invalid token.",
             ));
         }
 
-        let col_content;
-        let col_brace = braced!(col_content in input);
-        let mut cols = Vec::new();
-        while let Ok(t) = col_content.parse() {
-            cols.push(t)
+        Ok(MatrixPins::Direct(pins))
+    }
+}
+
+/// A chain of shift registers, in the form of `kind(DATA, CLOCK, LATCH, COUNT)`. `kind` is either
+/// `hc595` (for rows) or `hc165` (for columns).
+#[derive(Debug)]
+pub struct ShiftRegisterDefinition {
+    pub kind: Ident,
+    pub paren: syn::token::Paren,
+    pub data: Ident,
+    pub clock: Ident,
+    pub latch: Ident,
+    pub count: syn::LitInt,
+}
+
+impl syn::parse::Parse for ShiftRegisterDefinition {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let content;
+        let kind = input.parse()?;
+        let paren = parenthesized!(content in input);
+        let data = content.parse()?;
+        content.parse::<syn::Token![,]>()?;
+        let clock = content.parse()?;
+        content.parse::<syn::Token![,]>()?;
+        let latch = content.parse()?;
+        content.parse::<syn::Token![,]>()?;
+        let count = content.parse()?;
+        if !content.is_empty() {
+            content.parse::<syn::Token![,]>()?;
         }
-        if !col_content.is_empty() {
+        if !content.is_empty() || !input.is_empty() {
             return Err(syn::Error::new(
-                row_content.span(),
-                "Encountered an invalid token.",
+                content.span(),
+                "Encountered an invalid token. Expected a shift register in the form of `hc595(DATA, CLOCK, LATCH, COUNT)` or `hc165(DATA, CLOCK, LOAD, COUNT)`.",
             ));
         }
 
+        Ok(Self {
+            kind,
+            paren,
+            data,
+            clock,
+            latch,
+            count,
+        })
+    }
+}
+
+impl<T: Parse> syn::parse::Parse for MatrixDefinition<T> {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let row_content;
+        let row_brace = braced!(row_content in input);
+        let rows = row_content.parse()?;
+
+        let col_content;
+        let col_brace = braced!(col_content in input);
+        let cols = col_content.parse()?;
+
         Ok(Self {
             row_brace,
             rows,
@@ -776,21 +1082,223 @@ pub fn build_matrix(input: MatrixDefinition<Ident>) -> TokenStream {
     let row_count = rows.len();
     let col_count = cols.len();
 
+    let matrix = match (rows, cols) {
+        (MatrixPins::Direct(rows), MatrixPins::Direct(cols)) => {
+            let cols = crate::hw::input_pins(&cols);
+
+            quote! {
+                ::rumcake::keyberon::matrix::Matrix::new([
+                    #(#cols),*
+                ], [
+                    #(
+                        ::rumcake::hw::mcu::output_pin!(#rows)
+                    ),*
+                ])
+            }
+        }
+        (rows, cols) => {
+            let rows = match rows {
+                MatrixPins::Direct(rows) => quote! {
+                    [
+                        #(
+                            ::rumcake::hw::mcu::output_pin!(#rows)
+                        ),*
+                    ]
+                },
+                MatrixPins::ShiftRegister(ShiftRegisterDefinition {
+                    kind,
+                    data,
+                    clock,
+                    latch,
+                    ..
+                }) => {
+                    if kind != "hc595" {
+                        abort!(
+                            kind.span(),
+                            "Matrix rows can only be driven by `hc595` shift registers."
+                        )
+                    }
+
+                    quote! {
+                        ::rumcake::matrix::shift_register::HC595Rows::new(
+                            ::rumcake::matrix::shift_register::BitBangShiftOut::new(
+                                ::rumcake::hw::mcu::output_pin!(#data),
+                                ::rumcake::hw::mcu::output_pin!(#clock),
+                                ::rumcake::hw::mcu::output_pin!(#latch)
+                            )
+                        )
+                    }
+                }
+            };
+
+            let cols = match cols {
+                MatrixPins::Direct(cols) => {
+                    let cols = crate::hw::input_pins(&cols);
+
+                    quote! {
+                        [
+                            #(#cols),*
+                        ]
+                    }
+                }
+                MatrixPins::ShiftRegister(ShiftRegisterDefinition {
+                    kind,
+                    data,
+                    clock,
+                    latch,
+                    ..
+                }) => {
+                    if kind != "hc165" {
+                        abort!(
+                            kind.span(),
+                            "Matrix columns can only be read from `hc165` shift registers."
+                        )
+                    }
+
+                    // The data pin is read after each clock pulse, so it doesn't need to use an
+                    // interrupt
+                    let data = crate::hw::polled_input_pin(data);
+
+                    quote! {
+                        ::rumcake::matrix::shift_register::HC165Columns::new(
+                            ::rumcake::matrix::shift_register::BitBangShiftIn::new(
+                                #data,
+                                ::rumcake::hw::mcu::output_pin!(#clock),
+                                ::rumcake::hw::mcu::output_pin!(#latch)
+                            )
+                        )
+                    }
+                }
+            };
+
+            quote! {
+                Ok(::rumcake::matrix::StrobedMatrix::new(#rows, #cols))
+            }
+        }
+    };
+
+    quote! {
+        const MATRIX_ROWS: usize = #row_count;
+        const MATRIX_COLS: usize = #col_count;
+
+        fn build_matrix(
+        ) -> Result<impl ::rumcake::matrix::MatrixScanner<{ Self::MATRIX_COLS }, { Self::MATRIX_ROWS }>, core::convert::Infallible> {
+            #matrix
+        }
+    }
+}
+
+pub fn build_direct_pin_matrix(input: MatrixLike<OptionalItem<Ident>>) -> TokenStream {
+    let row_count = input.rows.len();
+    let col_count = input
+        .rows
+        .first()
+        .expect_or_abort("At least one row is required.")
+        .cols
+        .len();
+
+    let mut pins = crate::hw::input_pins(input.rows.iter().flat_map(|row| {
+        row.cols.iter().filter_map(|pin| match pin {
+            OptionalItem::None => None,
+            OptionalItem::Some(pin) => Some(pin),
+        })
+    }))
+    .into_iter();
+
+    let rows = input.rows.iter().map(|row| {
+        let pins = row.cols.iter().map(|pin| match pin {
+            OptionalItem::None => quote! { None },
+            OptionalItem::Some(_) => {
+                let pin = pins.next().unwrap();
+                quote! { Some(#pin) }
+            }
+        });
+
+        quote! { [#(#pins),*] }
+    });
+
+    quote! {
+        const MATRIX_ROWS: usize = #row_count;
+        const MATRIX_COLS: usize = #col_count;
+
+        fn build_matrix(
+        ) -> Result<impl ::rumcake::matrix::MatrixScanner<{ Self::MATRIX_COLS }, { Self::MATRIX_ROWS }>, core::convert::Infallible> {
+            ::rumcake::keyberon::matrix::DirectPinMatrix::new([
+                #(#rows),*
+            ])
+        }
+    }
+}
+
+pub fn build_duplex_matrix(input: MatrixDefinition<Ident>) -> TokenStream {
+    let MatrixDefinition { rows, cols, .. } = input;
+
+    let rows = match rows {
+        MatrixPins::Direct(rows) => rows,
+        MatrixPins::ShiftRegister(definition) => abort!(
+            definition.kind.span(),
+            "Duplex matrices only support row pins connected to the MCU."
+        ),
+    };
+    let cols = match cols {
+        MatrixPins::Direct(cols) => cols,
+        MatrixPins::ShiftRegister(definition) => abort!(
+            definition.kind.span(),
+            "Duplex matrices only support column pins connected to the MCU."
+        ),
+    };
+
+    let row_count = rows.len() * 2;
+    let col_count = cols.len();
+
     quote! {
         const MATRIX_ROWS: usize = #row_count;
         const MATRIX_COLS: usize = #col_count;
 
         fn build_matrix(
-        ) -> Result<::rumcake::keyberon::matrix::Matrix<impl ::rumcake::embedded_hal::digital::v2::InputPin<Error = core::convert::Infallible>, impl ::rumcake::embedded_hal::digital::v2::OutputPin<Error = core::convert::Infallible>, { Self::MATRIX_COLS }, { Self::MATRIX_ROWS }>, core::convert::Infallible> {
-            ::rumcake::keyberon::matrix::Matrix::new([
+        ) -> Result<impl ::rumcake::matrix::MatrixScanner<{ Self::MATRIX_COLS }, { Self::MATRIX_ROWS }>, core::convert::Infallible> {
+            Ok(::rumcake::matrix::DuplexMatrix::new([
                 #(
-                    ::rumcake::hw::mcu::input_pin!(#cols)
+                    ::rumcake::hw::mcu::flex_pin!(#rows)
                 ),*
             ], [
                 #(
-                    ::rumcake::hw::mcu::output_pin!(#rows)
+                    ::rumcake::hw::mcu::flex_pin!(#cols)
                 ),*
-            ])
+            ]))
+        }
+    }
+}
+
+pub fn setup_encoders(input: MatrixLike<Ident>) -> TokenStream {
+    let encoders = input.rows.iter().map(|encoder| {
+        let mut pins = encoder.cols.iter();
+
+        let a = pins.next().expect_or_abort("Missing A pin for encoder.");
+        let b = pins.next().expect_or_abort("Missing B pin for encoder.");
+
+        if let Some(pin) = pins.next() {
+            abort!(
+                pin.span(),
+                "Unexpected extra pins. Encoders only use an A and B pin."
+            )
+        }
+
+        // Encoder pins only wait for a change in level while the keyboard is idle, so they don't
+        // need to use interrupts
+        let a = crate::hw::polled_input_pin(a.clone());
+        let b = crate::hw::polled_input_pin(b.clone());
+
+        quote! {
+            (#a, #b)
+        }
+    });
+
+    quote! {
+        fn setup_encoders() -> [(impl ::rumcake::embedded_hal::digital::v2::InputPin<Error = core::convert::Infallible> + ::rumcake::embedded_hal_async::digital::Wait<Error = core::convert::Infallible>, impl ::rumcake::embedded_hal::digital::v2::InputPin<Error = core::convert::Infallible> + ::rumcake::embedded_hal_async::digital::Wait<Error = core::convert::Infallible>); Self::NUM_ENCODERS] {
+            [
+                #(#encoders),*
+            ]
         }
     }
 }
@@ -920,6 +1428,251 @@ pub fn build_layout(raw: TokenStream, layers: LayoutLike<TokenTree>) -> TokenStr
     }
 }
 
+#[derive(Debug)]
+pub struct ComboDefinition {
+    pub keys: MatrixRow<TokenTree>,
+    pub output: TokenTree,
+    pub timeout: Option<syn::LitInt>,
+}
+
+impl syn::parse::Parse for ComboDefinition {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let keys = input.parse()?;
+        input.parse::<syn::Token![=>]>()?;
+        let output = input.parse()?;
+
+        let timeout = if input.parse::<syn::Token![,]>().is_ok() {
+            let name = input.parse::<Ident>()?;
+            if name != "timeout" {
+                return Err(syn::Error::new(name.span(), "Expected `timeout`."));
+            }
+            input.parse::<syn::Token![:]>()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+
+        input.parse::<syn::Token![;]>()?;
+
+        Ok(Self {
+            keys,
+            output,
+            timeout,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct CombosMacroInput {
+    pub slots: Option<syn::LitInt>,
+    pub combos: Vec<ComboDefinition>,
+}
+
+impl syn::parse::Parse for CombosMacroInput {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let slots = if input.peek(Ident) {
+            let name = input.parse::<Ident>()?;
+            if name != "slots" {
+                return Err(syn::Error::new(name.span(), "Expected `slots`."));
+            }
+            input.parse::<syn::Token![:]>()?;
+            let slots = input.parse()?;
+            input.parse::<syn::Token![;]>()?;
+            Some(slots)
+        } else {
+            None
+        };
+
+        let mut combos = Vec::new();
+        while !input.is_empty() {
+            combos.push(input.parse()?)
+        }
+
+        Ok(Self { slots, combos })
+    }
+}
+
+pub fn build_combos(input: CombosMacroInput) -> TokenStream {
+    let declared_count = input.combos.len();
+    let slot_count = match &input.slots {
+        Some(slots) => {
+            let count = slots
+                .base10_parse::<usize>()
+                .unwrap_or_else(|e| abort!(slots, e));
+            if count < declared_count {
+                abort!(
+                    slots,
+                    "Number of slots must be at least the number of combos defined ({}).",
+                    declared_count
+                )
+            }
+            count
+        }
+        None => declared_count,
+    };
+
+    // Each combo is turned into a row of a single layer, so that the keys and output can be
+    // parsed by keyberon's `layout!` macro: [key key key key output]
+    let rows = input.combos.iter().map(|combo| {
+        if combo.keys.cols.is_empty() {
+            abort!(
+                combo.keys.row_bracket.span.join(),
+                "Combos need at least one key."
+            )
+        }
+
+        if let Some(key) = combo.keys.cols.get(4) {
+            abort!(key.span(), "Combos can have at most 4 keys.")
+        }
+
+        let keys = &combo.keys.cols;
+        let unused = (keys.len()..4).map(|_| quote! { n });
+        let output = &combo.output;
+
+        quote! { [ #(#keys)* #(#unused)* #output ] }
+    });
+
+    let combos = input.combos.iter().enumerate().map(|(i, combo)| {
+        let timeout = match &combo.timeout {
+            Some(timeout) => quote! { Some(#timeout) },
+            None => quote! { None },
+        };
+
+        quote! {
+            ::rumcake::combos::Combo::new(
+                [KEYS[0][#i][0], KEYS[0][#i][1], KEYS[0][#i][2], KEYS[0][#i][3]],
+                KEYS[0][#i][4],
+                #timeout,
+            )
+        }
+    });
+
+    let empty = (declared_count..slot_count).map(|_| quote! { ::rumcake::combos::Combo::empty() });
+
+    let keys = if declared_count > 0 {
+        quote! {
+            const KEYS: ::rumcake::keyberon::layout::Layers<5, #declared_count, 1, ::rumcake::keyboard::Keycode> = ::rumcake::keyberon::layout::layout! { { #(#rows)* } };
+        }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        const NUM_COMBOS: usize = #slot_count;
+
+        fn get_combos() -> Option<&'static ::rumcake::combos::Combos<{ Self::NUM_COMBOS }>> {
+            use ::rumcake::keyberon;
+            #keys
+            static COMBOS: ::rumcake::combos::Combos<#slot_count> = ::rumcake::combos::Combos::new([
+                #(#combos,)*
+                #(#empty,)*
+            ]);
+            Some(&COMBOS)
+        }
+    }
+}
+
+pub fn setup_tap_dances(count: Literal) -> TokenStream {
+    quote! {
+        const NUM_TAP_DANCES: usize = #count;
+
+        fn get_tap_dances(
+        ) -> Option<&'static ::rumcake::tap_dance::TapDances<{ Self::NUM_TAP_DANCES }>> {
+            static TAP_DANCES: ::rumcake::tap_dance::TapDances<#count> =
+                ::rumcake::tap_dance::TapDances::new(
+                    [::rumcake::tap_dance::TapDanceEntry::empty(); #count],
+                );
+            Some(&TAP_DANCES)
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct LeaderSequenceDefinition {
+    pub keys: MatrixRow<TokenTree>,
+    pub output: TokenTree,
+}
+
+impl syn::parse::Parse for LeaderSequenceDefinition {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let keys = input.parse()?;
+        input.parse::<syn::Token![=>]>()?;
+        let output = input.parse()?;
+        input.parse::<syn::Token![;]>()?;
+
+        Ok(Self { keys, output })
+    }
+}
+
+#[derive(Debug)]
+pub struct LeaderSequencesMacroInput {
+    pub sequences: Vec<LeaderSequenceDefinition>,
+}
+
+impl syn::parse::Parse for LeaderSequencesMacroInput {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        let mut sequences = Vec::new();
+        while !input.is_empty() {
+            sequences.push(input.parse()?)
+        }
+
+        Ok(Self { sequences })
+    }
+}
+
+pub fn build_leader_sequences(input: LeaderSequencesMacroInput) -> TokenStream {
+    let count = input.sequences.len();
+
+    // Like combos, each sequence is turned into a row of a single layer, so that the keys and
+    // output can be parsed by keyberon's `layout!` macro: [key key key key key output]
+    let rows = input.sequences.iter().map(|sequence| {
+        if sequence.keys.cols.is_empty() {
+            abort!(
+                sequence.keys.row_bracket.span.join(),
+                "Leader sequences need at least one key."
+            )
+        }
+
+        if let Some(key) = sequence.keys.cols.get(5) {
+            abort!(key.span(), "Leader sequences can have at most 5 keys.")
+        }
+
+        let keys = &sequence.keys.cols;
+        let unused = (keys.len()..5).map(|_| quote! { n });
+        let output = &sequence.output;
+
+        quote! { [ #(#keys)* #(#unused)* #output ] }
+    });
+
+    let sequences = (0..count).map(|i| {
+        quote! {
+            ::rumcake::leader::LeaderSequence::new(
+                [KEYS[0][#i][0], KEYS[0][#i][1], KEYS[0][#i][2], KEYS[0][#i][3], KEYS[0][#i][4]],
+                KEYS[0][#i][5],
+            )
+        }
+    });
+
+    let keys = if count > 0 {
+        quote! {
+            const KEYS: ::rumcake::keyberon::layout::Layers<6, #count, 1, ::rumcake::keyboard::Keycode> = ::rumcake::keyberon::layout::layout! { { #(#rows)* } };
+        }
+    } else {
+        quote! {}
+    };
+
+    quote! {
+        fn get_leader_sequences() -> &'static [::rumcake::leader::LeaderSequence] {
+            use ::rumcake::keyberon;
+            #keys
+            static SEQUENCES: [::rumcake::leader::LeaderSequence; #count] = [
+                #(#sequences,)*
+            ];
+            &SEQUENCES
+        }
+    }
+}
+
 pub struct RemapMacroInput {
     pub original_matrix_brace: syn::token::Brace,
     pub original_matrix: MatrixLike<OptionalItem<Ident>>,
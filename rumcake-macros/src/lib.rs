@@ -132,11 +132,33 @@ pub fn keyboard_main(
 }
 
 #[proc_macro]
+#[proc_macro_error]
 pub fn build_matrix(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let matrix = parse_macro_input!(input as keyboard::MatrixDefinition<Ident>);
     keyboard::build_matrix(matrix).into()
 }
 
+#[proc_macro]
+#[proc_macro_error]
+pub fn build_direct_pin_matrix(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let matrix = parse_macro_input!(input as keyboard::MatrixLike<keyboard::OptionalItem<Ident>>);
+    keyboard::build_direct_pin_matrix(matrix).into()
+}
+
+#[proc_macro]
+#[proc_macro_error]
+pub fn build_duplex_matrix(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let matrix = parse_macro_input!(input as keyboard::MatrixDefinition<Ident>);
+    keyboard::build_duplex_matrix(matrix).into()
+}
+
+#[proc_macro]
+#[proc_macro_error]
+pub fn setup_encoders(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let encoders = parse_macro_input!(input as keyboard::MatrixLike<Ident>);
+    keyboard::setup_encoders(encoders).into()
+}
+
 #[proc_macro]
 pub fn build_layout(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let raw = input.clone();
@@ -144,6 +166,26 @@ pub fn build_layout(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     keyboard::build_layout(raw.into(), layers).into()
 }
 
+#[proc_macro]
+#[proc_macro_error]
+pub fn build_combos(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let combos = parse_macro_input!(input as keyboard::CombosMacroInput);
+    keyboard::build_combos(combos).into()
+}
+
+#[proc_macro]
+#[proc_macro_error]
+pub fn build_leader_sequences(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let sequences = parse_macro_input!(input as keyboard::LeaderSequencesMacroInput);
+    keyboard::build_leader_sequences(sequences).into()
+}
+
+#[proc_macro]
+pub fn setup_tap_dances(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let count = parse_macro_input!(input as Literal);
+    keyboard::setup_tap_dances(count).into()
+}
+
 #[proc_macro]
 pub fn remap_matrix(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let remap = parse_macro_input!(input as keyboard::RemapMacroInput);
@@ -225,6 +267,12 @@ pub fn output_pin(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     hw::output_pin(ident).into()
 }
 
+#[proc_macro]
+pub fn flex_pin(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let ident = parse_macro_input!(input as Ident);
+    hw::flex_pin(ident).into()
+}
+
 #[proc_macro]
 pub fn setup_i2c(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     let args = parse_macro_input!(input with Punctuated<Ident, Token![,]>::parse_terminated);
@@ -259,6 +307,13 @@ pub fn setup_dma_channel(input: proc_macro::TokenStream) -> proc_macro::TokenStr
     hw::setup_dma_channel(args).into()
 }
 
+#[cfg(feature = "rp")]
+#[proc_macro]
+pub fn setup_adc_sampler(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let args = parse_macro_input!(input with Punctuated<Ident, Token![,]>::parse_terminated);
+    hw::setup_adc_sampler(args).into()
+}
+
 mod via;
 
 #[proc_macro]
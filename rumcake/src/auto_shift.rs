@@ -0,0 +1,145 @@
+//! Support for Auto Shift.
+//!
+//! With Auto Shift enabled, holding a letter, number or symbol key for longer than the auto shift
+//! timeout sends its shifted version, and tapping it sends the key as usual. The behaviour of Auto
+//! Shift is based on QMK's implementation. It can be enabled and configured at runtime using
+//! [`crate::settings::KeyboardSettings::auto_shift`] and
+//! [`crate::settings::KeyboardSettings::auto_shift_timeout`], or toggled using the
+//! [`Keycode::AutoShift`] keycode.
+//!
+//! [`Keycode::AutoShift`]: crate::keyboard::Keycode::AutoShift
+
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+use usbd_human_interface_device::page::Keyboard as KeyboardKeycode;
+
+use crate::key_overrides::ModifierMask;
+use crate::settings::{DEFAULT_AUTO_SHIFT_TIMEOUT, KEYBOARD_SETTINGS_STATE};
+
+/// Commands that can be used to control Auto Shift.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutoShiftCommand {
+    /// Enable Auto Shift.
+    On,
+    /// Disable Auto Shift.
+    Off,
+    /// Enable Auto Shift if it is disabled, or disable it if it is enabled.
+    Toggle,
+}
+
+/// Process an [`AutoShiftCommand`], by changing the keyboard settings.
+pub(crate) async fn process_command(command: AutoShiftCommand) {
+    KEYBOARD_SETTINGS_STATE
+        .update(|settings| {
+            settings.auto_shift = Some(match command {
+                AutoShiftCommand::On => true,
+                AutoShiftCommand::Off => false,
+                AutoShiftCommand::Toggle => !settings.auto_shift.unwrap_or_default(),
+            })
+        })
+        .await;
+}
+
+/// Check if a key can be shifted by Auto Shift. Like QMK, this includes letters, numbers and
+/// symbols.
+fn is_auto_shiftable(keycode: KeyboardKeycode) -> bool {
+    let keycode = keycode as u8;
+    (KeyboardKeycode::A as u8..=KeyboardKeycode::Keyboard0 as u8).contains(&keycode)
+        || (KeyboardKeycode::Minus as u8..=KeyboardKeycode::ForwardSlash as u8).contains(&keycode)
+}
+
+/// Delays keys collected from the layout until Auto Shift decides whether they should be shifted.
+pub(crate) struct AutoShiftProcessor {
+    enabled: bool,
+    timeout: Duration,
+
+    /// Keycodes collected from the layout on the previous tick.
+    last_keys: Vec<KeyboardKeycode, 24>,
+
+    /// Key that has been pressed, but has not been sent yet, and when it was pressed.
+    pending: Option<(KeyboardKeycode, Instant)>,
+
+    /// Key that was held past the timeout, and is being sent with shift.
+    shifted: Option<KeyboardKeycode>,
+
+    /// Key that was released before the timeout, and is sent for one tick.
+    tap: Option<KeyboardKeycode>,
+}
+
+impl AutoShiftProcessor {
+    pub(crate) fn new() -> Self {
+        Self {
+            enabled: false,
+            timeout: Duration::from_millis(DEFAULT_AUTO_SHIFT_TIMEOUT as u64),
+            last_keys: Vec::new(),
+            pending: None,
+            shifted: None,
+            tap: None,
+        }
+    }
+
+    /// Change whether Auto Shift is enabled, and how long keys must be held to be shifted.
+    pub(crate) fn set_settings(&mut self, enabled: bool, timeout: u16) {
+        self.enabled = enabled;
+        self.timeout = Duration::from_millis(timeout as u64);
+    }
+
+    /// Apply Auto Shift to the keycodes collected from the layout.
+    pub(crate) fn process(&mut self, keys: &mut Vec<KeyboardKeycode, 24>) {
+        self.tap = None;
+
+        let mut newly_pressed = keys
+            .iter()
+            .filter(|k| !self.last_keys.contains(k) && ModifierMask::from_keycode(**k).is_empty());
+        let other_key_pressed = newly_pressed
+            .clone()
+            .any(|k| self.pending.map_or(true, |(pending, _)| *k != pending));
+
+        if let Some((key, pressed_at)) = self.pending {
+            if !keys.contains(&key) {
+                // Released before the timeout, so the key is tapped without shift
+                self.pending = None;
+                self.tap = Some(key);
+            } else if pressed_at.elapsed() >= self.timeout {
+                self.pending = None;
+                self.shifted = Some(key);
+            } else if other_key_pressed {
+                // Rolling over to another key sends the pending key without shift
+                self.pending = None;
+            }
+        }
+
+        if let Some(key) = self.shifted {
+            if !keys.contains(&key) || other_key_pressed {
+                self.shifted = None;
+            }
+        }
+
+        // Keys pressed while modifiers are held are not delayed, so that shortcuts are unaffected
+        if self.enabled
+            && self.pending.is_none()
+            && self.shifted.is_none()
+            && ModifierMask::from_keycodes(keys).is_empty()
+        {
+            self.pending = newly_pressed
+                .find(|k| is_auto_shiftable(**k))
+                .map(|k| (*k, Instant::now()));
+        }
+
+        self.last_keys.clone_from(keys);
+
+        if let Some((key, _)) = self.pending {
+            keys.retain(|k| *k != key);
+        }
+
+        if let Some(key) = self.tap {
+            if !keys.contains(&key) {
+                let _ = keys.push(key);
+            }
+        }
+
+        if self.shifted.is_some() && !keys.contains(&KeyboardKeycode::LeftShift) {
+            let _ = keys.push(KeyboardKeycode::LeftShift);
+        }
+    }
+}
@@ -18,6 +18,8 @@ compile_error!("Exactly one of `simple-backlight`, `simple-backlight-matrix`, `r
 
 use bitflags::bitflags;
 
+use crate::hw::LedIndicators;
+
 pub mod drivers;
 
 pub use rumcake_macros::{led_flags, led_layout, setup_backlight_matrix};
@@ -82,6 +84,12 @@ pub trait BacklightMatrixDevice: BacklightDevice {
     /// physical LED position, and LED flags. It is recommended to use the
     /// [`setup_backlight_matrix`] macro to set this value.
     fn get_backlight_matrix() -> BacklightMatrix<{ Self::LIGHTING_COLS }, { Self::LIGHTING_ROWS }>;
+
+    /// LEDs used to show the state of the host's LEDs (e.g. Caps Lock), in the form of
+    /// `(indicator, (col, row))`, where `col` and `row` are positions in your lighting matrix.
+    /// While an indicator is on, its LED is lit at the current brightness, regardless of the
+    /// current effect.
+    const LED_INDICATORS: &'static [(LedIndicators, (u8, u8))] = &[];
 }
 
 #[doc(hidden)]
@@ -173,10 +181,37 @@ macro_rules! backlight_task_fn {
             animator.tick().await; // Force a frame to be rendered in the event that the initial effect is static.
 
             loop {
+                // Turn off the LEDs while the keyboard is idle, and wait for it to wake up.
+                if IDLE_STATE.get().await {
+                    if animator.config.enabled {
+                        animator.turn_off().await;
+                    }
+
+                    while IDLE_STATE.get().await {
+                        IDLE_STATE_LISTENER.wait().await;
+                    }
+
+                    if animator.config.enabled {
+                        animator.turn_on().await;
+                    }
+
+                    ticker.reset();
+                }
+
                 let command = if !(animator.config.enabled && animator.config.effect.is_animated())
                 {
                     // We want to wait for a command if the animator is not rendering any animated effects. This allows the task to sleep when the LEDs are static.
-                    Some(BACKLIGHT_COMMAND_CHANNEL.receive().await)
+                    // A change in the host's LEDs renders a new frame, to update the indicators.
+                    match select::select3(
+                        BACKLIGHT_COMMAND_CHANNEL.receive(),
+                        HOST_LED_STATE_LISTENER.wait(),
+                        IDLE_STATE_LISTENER.wait(),
+                    )
+                    .await
+                    {
+                        select::Either3::First(command) => Some(command),
+                        select::Either3::Second(()) | select::Either3::Third(()) => None,
+                    }
                 } else {
                     #[cfg(feature = "vial")]
                     {
@@ -275,7 +310,7 @@ macro_rules! backlight_task_fn {
 
 macro_rules! backlight_module {
     () => {
-        use crate::keyboard::MATRIX_EVENTS;
+        use crate::keyboard::{IDLE_STATE, MATRIX_EVENTS};
         use crate::{LEDEffect, State};
         use embassy_futures::select;
         use embassy_sync::channel::Channel;
@@ -294,6 +329,11 @@ macro_rules! backlight_module {
         pub static BACKLIGHT_COMMAND_CHANNEL: Channel<RawMutex, BacklightCommand, 2> =
             Channel::new();
 
+        pub(crate) static HOST_LED_STATE_LISTENER: embassy_sync::signal::Signal<RawMutex, ()> =
+            embassy_sync::signal::Signal::new();
+        pub(crate) static IDLE_STATE_LISTENER: embassy_sync::signal::Signal<RawMutex, ()> =
+            embassy_sync::signal::Signal::new();
+
         /// State that contains the current configuration for the backlight animator.
         pub static BACKLIGHT_CONFIG_STATE: State<BacklightConfig> = State::new(
             BacklightConfig::default(),
@@ -307,11 +347,9 @@ macro_rules! backlight_module {
 
 macro_rules! storage_module {
     () => {
-        use core::any::TypeId;
-
         use defmt::{info, warn, Debug2Format};
         use embassy_futures::select;
-        use embassy_futures::select::Either;
+        use embassy_futures::select::Either3;
         use embassy_sync::signal::Signal;
         use embassy_time::Duration;
         use embassy_time::Timer;
@@ -319,12 +357,19 @@ macro_rules! storage_module {
         use crate::hw::mcu::RawMutex;
         use crate::storage::{FlashStorage, StorageDevice};
 
+        use super::animations::BacklightCommand;
         use super::BacklightConfig;
-        use super::BACKLIGHT_CONFIG_STATE;
+        use super::{BACKLIGHT_COMMAND_CHANNEL, BACKLIGHT_CONFIG_STATE};
 
         pub(super) static BACKLIGHT_CONFIG_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
 
         pub(super) static BACKLIGHT_SAVE_SIGNAL: Signal<RawMutex, ()> = Signal::new();
+
+        pub(crate) static BACKLIGHT_PROFILE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+        impl crate::storage::StorageSchema for BacklightConfig {
+            const SCHEMA_VERSION: u16 = 1;
+        }
     };
 }
 
@@ -337,78 +382,100 @@ macro_rules! storage_task_fn {
         ) where
             [(); F::ERASE_SIZE]:,
         {
-            {
-                // Check stored backlight config metadata (type id) to see if it has changed
-                let metadata: [u8; core::mem::size_of::<TypeId>()] =
-                    unsafe { core::mem::transmute(TypeId::of::<BacklightConfig>()) };
+            let load = |profile: u8| async move {
+                let key = crate::storage::StorageKey::$key.in_profile(profile);
+
+                // Migrate the stored backlight config if it was saved with an older schema version
                 let _ = database
-                    .check_metadata(
+                    .check_schema::<BacklightConfig>(
                         K::get_storage_buffer(),
-                        crate::storage::StorageKey::$key,
-                        &metadata,
+                        key,
+                        K::get_migrations(),
                     )
                     .await;
 
                 // Get backlight config from storage
-                if let Ok(config) = database
-                    .read(
-                        K::get_storage_buffer(),
-                        crate::storage::StorageKey::$key,
-                    )
-                    .await
-                {
-                    info!(
+                let config = database.read(K::get_storage_buffer(), key).await;
+                match config {
+                    Ok(config) => info!(
                         "[BACKLIGHT] Obtained backlight config from storage: {}",
                         Debug2Format(&config)
-                    );
-                    // Quietly update the config state so that we don't save the config to storage again
-                    BACKLIGHT_CONFIG_STATE.quiet_set(config).await;
-                } else {
-                    warn!("[BACKLIGHT] Could not get backlight config from storage, using default config.",);
-                };
-            }
+                    ),
+                    Err(()) => warn!(
+                        "[BACKLIGHT] Could not get backlight config from storage, using default config.",
+                    ),
+                }
+                config.ok()
+            };
 
-            let save = || async {
+            let save = |profile: u8| async move {
                 let _ = database
                     .write(
                         K::get_storage_buffer(),
-                        crate::storage::StorageKey::$key,
+                        crate::storage::StorageKey::$key.in_profile(profile),
                         BACKLIGHT_CONFIG_STATE.get().await,
                     )
                     .await;
             };
 
+            let mut profile = crate::storage::active_profile().await;
+            if let Some(config) = load(profile).await {
+                // Quietly update the config state so that we don't save the config to storage again
+                BACKLIGHT_CONFIG_STATE.quiet_set(config).await;
+            }
+
             // Save the backlight config if it hasn't been changed in 5 seconds, or if a save was signalled
             loop {
-                match select::select(
+                let new_profile = match select::select3(
                     BACKLIGHT_SAVE_SIGNAL.wait(),
                     BACKLIGHT_CONFIG_STATE_LISTENER.wait(),
+                    crate::storage::wait_for_profile_change(&BACKLIGHT_PROFILE_LISTENER),
                 )
                 .await
                 {
-                    Either::First(_) => {
-                        save().await;
+                    Either3::First(_) => {
+                        save(profile).await;
+                        None
                     }
-                    Either::Second(_) => {
-                        match select::select(
+                    Either3::Second(_) => {
+                        match select::select3(
                             select::select(
                                 Timer::after(Duration::from_secs(5)),
                                 BACKLIGHT_SAVE_SIGNAL.wait(),
                             ),
                             BACKLIGHT_CONFIG_STATE_LISTENER.wait(),
+                            crate::storage::wait_for_profile_change(&BACKLIGHT_PROFILE_LISTENER),
                         )
                         .await
                         {
-                            Either::First(_) => {
-                                save().await;
+                            Either3::First(_) => {
+                                save(profile).await;
+                                None
                             }
-                            Either::Second(_) => {
+                            Either3::Second(_) => {
                                 // Re-signal, so that we skip the `wait()` call at the beginning of this loop
                                 BACKLIGHT_CONFIG_STATE_LISTENER.signal(());
+                                None
+                            }
+                            Either3::Third(new_profile) => {
+                                // Save pending changes to the previous profile before switching
+                                save(profile).await;
+                                Some(new_profile)
                             }
                         }
                     }
+                    Either3::Third(new_profile) => Some(new_profile),
                 };
+
+                // Apply the config of the new profile. The backlight task updates the config state
+                // once it has been applied.
+                if let Some(new_profile) = new_profile.filter(|new_profile| *new_profile != profile) {
+                    profile = new_profile;
+                    let config = load(profile).await.unwrap_or_default();
+                    BACKLIGHT_COMMAND_CHANNEL
+                        .send(BacklightCommand::SetConfig(config))
+                        .await;
+                }
             }
         }
     }
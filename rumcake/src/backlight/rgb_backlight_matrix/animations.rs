@@ -1,19 +1,22 @@
 use crate::backlight::drivers::RGBBacklightMatrixDriver;
 use crate::backlight::{
-    get_led_layout_bounds, BacklightDevice, BacklightMatrixDevice, LayoutBounds,
+    get_led_layout_bounds, BacklightDevice, BacklightMatrixDevice, LEDFlags, LayoutBounds,
 };
+use crate::hw::HOST_LED_STATE;
+use crate::math::{atan2f, cos, scale, sin, sqrtf};
 use crate::{Cycle, LEDEffect};
 use postcard::experimental::max_size::MaxSize;
 use rumcake_macros::{generate_items_from_enum_variants, Cycle, LEDEffect};
 
+use core::f32::consts::PI;
 use defmt::{error, warn, Debug2Format};
 use keyberon::layout::Event;
 use num_derive::FromPrimitive;
 use rand::rngs::SmallRng;
-use rand_core::SeedableRng;
+use rand_core::{RngCore, SeedableRng};
 use ringbuffer::{ConstGenericRingBuffer, RingBuffer};
 use serde::{Deserialize, Serialize};
-use smart_leds::hsv::Hsv;
+use smart_leds::hsv::{hsv2rgb, Hsv};
 use smart_leds::RGB8;
 
 #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, MaxSize)]
@@ -45,7 +48,7 @@ impl Default for BacklightConfig {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, MaxSize)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, MaxSize)]
 pub enum BacklightCommand {
     Toggle,
     TurnOn,
@@ -65,6 +68,7 @@ pub enum BacklightCommand {
     SetSpeed(u8),
     IncreaseSpeed(u8),
     DecreaseSpeed(u8),
+    SetConfig(BacklightConfig), // normally used internally for switching storage profiles
     #[cfg(feature = "storage")]
     SaveConfig,
     ResetTime, // normally used internally for syncing LEDs for split keyboards
@@ -171,6 +175,7 @@ pub enum BacklightEffect {
     PixelFractal,
 
     #[animated]
+    #[reactive]
     TypingHeatmap,
 
     #[animated]
@@ -316,8 +321,9 @@ where
     [(); K::LIGHTING_ROWS]:,
 {
     pub(super) config: BacklightConfig,
-    pub(super) buf: [[RGB8; K::LIGHTING_COLS]; K::LIGHTING_ROWS], // Stores the brightness/value of each LED
+    pub(super) buf: [[RGB8; K::LIGHTING_COLS]; K::LIGHTING_ROWS], // Stores the color of each LED
     pub(super) last_presses: ConstGenericRingBuffer<((u8, u8), u32), 8>, // Stores the row and col of the last 8 key presses, and the time (in ticks) it was pressed
+    pub(super) led_state: [[u8; K::LIGHTING_COLS]; K::LIGHTING_ROWS], // Stores extra data for each LED, used by effects like TypingHeatmap and DigitalRain
     pub(super) tick: u32,
     pub(super) driver: D,
     pub(super) bounds: LayoutBounds,
@@ -336,6 +342,7 @@ where
             driver,
             last_presses: ConstGenericRingBuffer::new(),
             buf: [[RGB8::new(0, 0, 0); K::LIGHTING_COLS]; K::LIGHTING_ROWS],
+            led_state: [[0; K::LIGHTING_COLS]; K::LIGHTING_ROWS],
             bounds: get_led_layout_bounds::<K>(),
             rng: SmallRng::seed_from_u64(1337),
         }
@@ -415,6 +422,9 @@ where
             BacklightCommand::DecreaseSpeed(amount) => {
                 self.config.speed = self.config.speed.saturating_sub(amount);
             }
+            BacklightCommand::SetConfig(config) => {
+                self.config = config;
+            }
             #[cfg(feature = "storage")]
             BacklightCommand::SaveConfig => {
                 super::storage::BACKLIGHT_SAVE_SIGNAL.signal(());
@@ -423,15 +433,90 @@ where
                 self.tick = 0;
             }
         };
+
+        if matches!(
+            command,
+            BacklightCommand::NextEffect
+                | BacklightCommand::PrevEffect
+                | BacklightCommand::SetEffect(_)
+        ) {
+            // Effects that use the LED state should start from a clean slate
+            self.led_state = [[0; K::LIGHTING_COLS]; K::LIGHTING_ROWS];
+        }
+    }
+
+    pub fn set_brightness_for_each_led(
+        &mut self,
+        calc: impl Fn(&mut Self, u32, (u8, u8), (u8, u8)) -> Hsv,
+    ) {
+        let time = (self.tick << 8)
+            / (((K::FPS as u32) << 8)
+                / (self.config.speed as u32 + 128 + (self.config.speed as u32 >> 1))); // `time` should increment by 255 every second
+
+        for row in 0..K::LIGHTING_ROWS {
+            for col in 0..K::LIGHTING_COLS {
+                if let Some(position) = K::get_backlight_matrix().layout[row][col] {
+                    let mut hsv = calc(self, time, (row as u8, col as u8), position);
+                    hsv.val = scale(hsv.val, self.config.val);
+                    self.buf[row][col] = hsv2rgb(hsv);
+                }
+            }
+        }
     }
 
-    pub fn set_brightness_for_each_led(&mut self, calc: impl Fn(&mut Self, f32, u8) -> Hsv) {
-        unimplemented!()
+    /// Calculate the color of an LED at the given position, based on the recorded key presses.
+    /// `effect` is called for each key press (or just the most recent one, if `multi` is
+    /// `false`), with the change in x and y, the distance between the LED and the key, and the
+    /// time since the key was pressed.
+    fn splash(
+        &self,
+        time: u32,
+        (led_x, led_y): (u8, u8),
+        multi: bool,
+        effect: impl Fn(Hsv, i16, i16, u16, u16) -> Hsv,
+    ) -> Hsv {
+        let latest = self
+            .last_presses
+            .iter()
+            .map(|(_coord, press_time)| *press_time)
+            .max();
+
+        self.last_presses
+            .iter()
+            .filter(|(_coord, press_time)| multi || Some(*press_time) == latest)
+            .fold(
+                Hsv {
+                    hue: self.config.hue,
+                    sat: self.config.sat,
+                    val: 0,
+                },
+                |hsv, ((pressed_row, pressed_col), press_time)| {
+                    if let Some((key_x, key_y)) = K::get_backlight_matrix().layout
+                        [*pressed_row as usize][*pressed_col as usize]
+                    {
+                        let dx = led_x as i16 - key_x as i16;
+                        let dy = led_y as i16 - key_y as i16;
+                        let dist = sqrtf(((dx as i32).pow(2) + (dy as i32).pow(2)) as f32) as u16;
+                        let tick = time.saturating_sub(*press_time).min(u16::MAX as u32) as u16;
+                        effect(hsv, dx, dy, dist, tick)
+                    } else {
+                        hsv
+                    }
+                },
+            )
     }
 
     pub fn register_event(&mut self, event: Event) {
+        let time = (self.tick << 8)
+            / (((K::FPS as u32) << 8)
+                / (self.config.speed as u32 + 128 + (self.config.speed as u32 >> 1)));
+
         match event {
             Event::Press(row, col) => {
+                if self.config.effect == BacklightEffect::TypingHeatmap {
+                    self.increase_heat(row, col);
+                }
+
                 match self
                     .last_presses
                     .iter_mut()
@@ -439,7 +524,7 @@ where
                         *pressed_row == row && *pressed_col == col
                     }) {
                     Some(press) => {
-                        press.1 = self.tick;
+                        press.1 = time;
                     }
                     None => {
                         // Check if the matrix position corresponds to a LED position before pushing
@@ -450,7 +535,7 @@ where
                             .and_then(|pos| *pos)
                             .is_some()
                         {
-                            self.last_presses.push(((row, col), self.tick));
+                            self.last_presses.push(((row, col), time));
                         }
                     }
                 };
@@ -459,62 +544,800 @@ where
         }
     }
 
+    fn increase_heat(&mut self, row: u8, col: u8) {
+        let Some((key_x, key_y)) = K::get_backlight_matrix()
+            .layout
+            .get(row as usize)
+            .and_then(|row| row.get(col as usize))
+            .and_then(|pos| *pos)
+        else {
+            return;
+        };
+
+        // The pressed key heats up the most, and nearby LEDs heat up depending on their distance to the key
+        for led_row in 0..K::LIGHTING_ROWS {
+            for led_col in 0..K::LIGHTING_COLS {
+                if let Some((led_x, led_y)) = K::get_backlight_matrix().layout[led_row][led_col] {
+                    let amount = if led_row == row as usize && led_col == col as usize {
+                        32
+                    } else {
+                        let dx = key_x.abs_diff(led_x) as u32;
+                        let dy = key_y.abs_diff(led_y) as u32;
+                        let dist = sqrtf((dx.pow(2) + dy.pow(2)) as f32) as u16;
+                        (40u16.saturating_sub(dist) as u8).min(16)
+                    };
+
+                    self.led_state[led_row][led_col] =
+                        self.led_state[led_row][led_col].saturating_add(amount);
+                }
+            }
+        }
+    }
+
     pub async fn tick(&mut self) {
         if !self.config.enabled {
             return;
         }
 
-        // TODO: animations
         match self.config.effect {
-            BacklightEffect::Solid => todo!(),
-            BacklightEffect::AlphasMods => todo!(),
-            BacklightEffect::GradientUpDown => todo!(),
-            BacklightEffect::GradientLeftRight => todo!(),
-            BacklightEffect::Breathing => todo!(),
-            BacklightEffect::ColorbandSat => todo!(),
-            BacklightEffect::ColorbandVal => todo!(),
-            BacklightEffect::ColorbandPinWheelSat => todo!(),
-            BacklightEffect::ColorbandPinWheelVal => todo!(),
-            BacklightEffect::ColorbandSpiralSat => todo!(),
-            BacklightEffect::ColorbandSpiralVal => todo!(),
-            BacklightEffect::CycleAll => todo!(),
-            BacklightEffect::CycleLeftRight => todo!(),
-            BacklightEffect::CycleUpDown => todo!(),
-            BacklightEffect::RainbowMovingChevron => todo!(),
-            BacklightEffect::CycleOutIn => todo!(),
-            BacklightEffect::CycleOutInDual => todo!(),
-            BacklightEffect::CyclePinWheel => todo!(),
-            BacklightEffect::CycleSpiral => todo!(),
-            BacklightEffect::DualBeacon => todo!(),
-            BacklightEffect::RainbowBeacon => todo!(),
-            BacklightEffect::RainbowPinWheels => todo!(),
-            BacklightEffect::Raindrops => todo!(),
-            BacklightEffect::JellybeanRaindrops => todo!(),
-            BacklightEffect::HueBreathing => todo!(),
-            BacklightEffect::HuePendulum => todo!(),
-            BacklightEffect::HueWave => todo!(),
-            BacklightEffect::PixelRain => todo!(),
-            BacklightEffect::PixelFlow => todo!(),
-            BacklightEffect::PixelFractal => todo!(),
-            BacklightEffect::TypingHeatmap => todo!(),
-            BacklightEffect::DigitalRain => todo!(),
-            BacklightEffect::SolidReactiveSimple => todo!(),
-            BacklightEffect::SolidReactive => todo!(),
-            BacklightEffect::SolidReactiveWide => todo!(),
-            BacklightEffect::SolidReactiveMultiWide => todo!(),
-            BacklightEffect::SolidReactiveCross => todo!(),
-            BacklightEffect::SolidReactiveMultiCross => todo!(),
-            BacklightEffect::SolidReactiveNexus => todo!(),
-            BacklightEffect::SolidReactiveMultiNexus => todo!(),
-            BacklightEffect::Splash => todo!(),
-            BacklightEffect::MultiSplash => todo!(),
-            BacklightEffect::SolidSplash => todo!(),
-            BacklightEffect::SolidMultiSplash => todo!(),
+            BacklightEffect::Solid => {
+                if K::RGB_BACKLIGHT_MATRIX_SOLID_ENABLED {
+                    self.set_brightness_for_each_led(|animator, _time, _coord, _pos| Hsv {
+                        hue: animator.config.hue,
+                        sat: animator.config.sat,
+                        val: u8::MAX,
+                    })
+                }
+            }
+            BacklightEffect::AlphasMods => {
+                if K::RGB_BACKLIGHT_MATRIX_ALPHAS_MODS_ENABLED {
+                    self.set_brightness_for_each_led(|animator, _time, (row, col), _pos| {
+                        // Modifiers are shifted in hue by the speed
+                        let hue = if K::get_backlight_matrix().flags[row as usize][col as usize]
+                            .contains(LEDFlags::ALPHA)
+                        {
+                            animator.config.hue
+                        } else {
+                            animator.config.hue.wrapping_add(animator.config.speed)
+                        };
+
+                        Hsv {
+                            hue,
+                            sat: animator.config.sat,
+                            val: u8::MAX,
+                        }
+                    })
+                }
+            }
+            BacklightEffect::GradientUpDown => {
+                if K::RGB_BACKLIGHT_MATRIX_GRADIENT_UP_DOWN_ENABLED {
+                    let size = (self.bounds.max.1 - self.bounds.min.1).max(1);
+                    self.set_brightness_for_each_led(|animator, _time, _coord, (_x, y)| {
+                        // Speed will be used to determine how much the hue changes from top to bottom.
+                        let hue_scale = scale(64, animator.config.speed) as u32;
+                        Hsv {
+                            hue: animator.config.hue.wrapping_add(
+                                (hue_scale * (y - animator.bounds.min.1) as u32 * 4 / size as u32)
+                                    as u8,
+                            ),
+                            sat: animator.config.sat,
+                            val: u8::MAX,
+                        }
+                    })
+                }
+            }
+            BacklightEffect::GradientLeftRight => {
+                if K::RGB_BACKLIGHT_MATRIX_GRADIENT_LEFT_RIGHT_ENABLED {
+                    let size = (self.bounds.max.0 - self.bounds.min.0).max(1);
+                    self.set_brightness_for_each_led(|animator, _time, _coord, (x, _y)| {
+                        // Speed will be used to determine how much the hue changes from left to right.
+                        let hue_scale = scale(64, animator.config.speed) as u32;
+                        Hsv {
+                            hue: animator.config.hue.wrapping_add(
+                                (hue_scale * (x - animator.bounds.min.0) as u32 * 7 / size as u32)
+                                    as u8,
+                            ),
+                            sat: animator.config.sat,
+                            val: u8::MAX,
+                        }
+                    })
+                }
+            }
+            BacklightEffect::Breathing => {
+                if K::RGB_BACKLIGHT_MATRIX_BREATHING_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, _coord, _pos| Hsv {
+                        hue: animator.config.hue,
+                        sat: animator.config.sat,
+                        val: sin((time >> 2) as u8), // 4 seconds for one full cycle
+                    })
+                }
+            }
+            BacklightEffect::ColorbandSat => {
+                if K::RGB_BACKLIGHT_MATRIX_COLORBAND_SAT_ENABLED {
+                    let size = self.bounds.max.0 - self.bounds.min.0;
+                    self.set_brightness_for_each_led(|animator, time, _coord, (x, _y)| {
+                        // Base speed: 1 cycle every second
+                        let pos = scale(time as u8, size);
+                        Hsv {
+                            hue: animator.config.hue,
+                            sat: animator.config.sat.saturating_sub(
+                                (x - animator.bounds.min.0).abs_diff(pos).saturating_mul(8),
+                            ),
+                            val: u8::MAX,
+                        }
+                    })
+                }
+            }
+            BacklightEffect::ColorbandVal => {
+                if K::RGB_BACKLIGHT_MATRIX_COLORBAND_VAL_ENABLED {
+                    let size = self.bounds.max.0 - self.bounds.min.0;
+                    self.set_brightness_for_each_led(|animator, time, _coord, (x, _y)| {
+                        // Base speed: 1 cycle every second
+                        let pos = scale(time as u8, size);
+                        Hsv {
+                            hue: animator.config.hue,
+                            sat: animator.config.sat,
+                            val: u8::MAX.saturating_sub(
+                                (x - animator.bounds.min.0).abs_diff(pos).saturating_mul(8),
+                            ),
+                        }
+                    })
+                }
+            }
+            BacklightEffect::ColorbandPinWheelSat => {
+                if K::RGB_BACKLIGHT_MATRIX_COLORBAND_PIN_WHEEL_SAT_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, _coord, (x, y)| {
+                        // Base speed: 1 cycle every second
+                        let dy = y as i16 - animator.bounds.mid.1 as i16;
+                        let dx = x as i16 - animator.bounds.mid.0 as i16;
+                        Hsv {
+                            hue: animator.config.hue,
+                            sat: animator
+                                .config
+                                .sat
+                                .wrapping_sub(time as u8)
+                                .wrapping_sub(angle(dx, dy).wrapping_mul(3)),
+                            val: u8::MAX,
+                        }
+                    })
+                }
+            }
+            BacklightEffect::ColorbandPinWheelVal => {
+                if K::RGB_BACKLIGHT_MATRIX_COLORBAND_PIN_WHEEL_VAL_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, _coord, (x, y)| {
+                        // Base speed: 1 cycle every second
+                        let dy = y as i16 - animator.bounds.mid.1 as i16;
+                        let dx = x as i16 - animator.bounds.mid.0 as i16;
+                        Hsv {
+                            hue: animator.config.hue,
+                            sat: animator.config.sat,
+                            val: u8::MAX
+                                .wrapping_sub(time as u8)
+                                .wrapping_sub(angle(dx, dy).wrapping_mul(3)),
+                        }
+                    })
+                }
+            }
+            BacklightEffect::ColorbandSpiralSat => {
+                if K::RGB_BACKLIGHT_MATRIX_COLORBAND_SPIRAL_SAT_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, _coord, (x, y)| {
+                        // Base speed: 1 cycle every second
+                        let dy = y as i16 - animator.bounds.mid.1 as i16;
+                        let dx = x as i16 - animator.bounds.mid.0 as i16;
+                        Hsv {
+                            hue: animator.config.hue,
+                            sat: animator
+                                .config
+                                .sat
+                                .wrapping_add(distance(dx, dy) as u8)
+                                .wrapping_sub(time as u8)
+                                .wrapping_sub(angle(dx, dy)),
+                            val: u8::MAX,
+                        }
+                    })
+                }
+            }
+            BacklightEffect::ColorbandSpiralVal => {
+                if K::RGB_BACKLIGHT_MATRIX_COLORBAND_SPIRAL_VAL_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, _coord, (x, y)| {
+                        // Base speed: 1 cycle every second
+                        let dy = y as i16 - animator.bounds.mid.1 as i16;
+                        let dx = x as i16 - animator.bounds.mid.0 as i16;
+                        Hsv {
+                            hue: animator.config.hue,
+                            sat: animator.config.sat,
+                            val: u8::MAX
+                                .wrapping_add(distance(dx, dy) as u8)
+                                .wrapping_sub(time as u8)
+                                .wrapping_sub(angle(dx, dy)),
+                        }
+                    })
+                }
+            }
+            BacklightEffect::CycleAll => {
+                if K::RGB_BACKLIGHT_MATRIX_CYCLE_ALL_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, _coord, _pos| Hsv {
+                        hue: time as u8, // Base speed: 1 cycle every second
+                        sat: animator.config.sat,
+                        val: u8::MAX,
+                    })
+                }
+            }
+            BacklightEffect::CycleLeftRight => {
+                if K::RGB_BACKLIGHT_MATRIX_CYCLE_LEFT_RIGHT_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, _coord, (x, _y)| Hsv {
+                        // Base speed: 1 cycle every second
+                        hue: (x - animator.bounds.min.0).wrapping_sub(time as u8),
+                        sat: animator.config.sat,
+                        val: u8::MAX,
+                    })
+                }
+            }
+            BacklightEffect::CycleUpDown => {
+                if K::RGB_BACKLIGHT_MATRIX_CYCLE_UP_DOWN_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, _coord, (_x, y)| Hsv {
+                        // Base speed: 1 cycle every second
+                        hue: (y - animator.bounds.min.1).wrapping_sub(time as u8),
+                        sat: animator.config.sat,
+                        val: u8::MAX,
+                    })
+                }
+            }
+            BacklightEffect::RainbowMovingChevron => {
+                if K::RGB_BACKLIGHT_MATRIX_RAINBOW_MOVING_CHEVRON_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, _coord, (x, y)| Hsv {
+                        // Base speed: 1 cycle every second
+                        hue: animator
+                            .config
+                            .hue
+                            .wrapping_add(y.abs_diff(animator.bounds.mid.1))
+                            .wrapping_add((x - animator.bounds.min.0).wrapping_sub(time as u8)),
+                        sat: animator.config.sat,
+                        val: u8::MAX,
+                    })
+                }
+            }
+            BacklightEffect::CycleOutIn => {
+                if K::RGB_BACKLIGHT_MATRIX_CYCLE_OUT_IN_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, _coord, (x, y)| {
+                        // Base speed: 1 cycle every second
+                        let dy = y as i16 - animator.bounds.mid.1 as i16;
+                        let dx = x as i16 - animator.bounds.mid.0 as i16;
+                        Hsv {
+                            hue: ((3 * distance(dx, dy) / 2) as u8).wrapping_add(time as u8),
+                            sat: animator.config.sat,
+                            val: u8::MAX,
+                        }
+                    })
+                }
+            }
+            BacklightEffect::CycleOutInDual => {
+                if K::RGB_BACKLIGHT_MATRIX_CYCLE_OUT_IN_DUAL_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, _coord, (x, y)| {
+                        // Base speed: 1 cycle every second
+                        let dy = y as i16 - animator.bounds.mid.1 as i16;
+                        let dx = (animator.bounds.mid.0 / 2) as i16
+                            - x.abs_diff(animator.bounds.mid.0) as i16;
+                        Hsv {
+                            hue: ((3 * distance(dx, dy)) as u8).wrapping_add(time as u8),
+                            sat: animator.config.sat,
+                            val: u8::MAX,
+                        }
+                    })
+                }
+            }
+            BacklightEffect::CyclePinWheel => {
+                if K::RGB_BACKLIGHT_MATRIX_CYCLE_PIN_WHEEL_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, _coord, (x, y)| {
+                        // Base speed: 1 cycle every second
+                        let dy = y as i16 - animator.bounds.mid.1 as i16;
+                        let dx = x as i16 - animator.bounds.mid.0 as i16;
+                        Hsv {
+                            hue: angle(dx, dy).wrapping_add(time as u8),
+                            sat: animator.config.sat,
+                            val: u8::MAX,
+                        }
+                    })
+                }
+            }
+            BacklightEffect::CycleSpiral => {
+                if K::RGB_BACKLIGHT_MATRIX_CYCLE_SPIRAL_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, _coord, (x, y)| {
+                        // Base speed: 1 cycle every second
+                        let dy = y as i16 - animator.bounds.mid.1 as i16;
+                        let dx = x as i16 - animator.bounds.mid.0 as i16;
+                        Hsv {
+                            hue: (distance(dx, dy) as u8)
+                                .wrapping_sub(time as u8)
+                                .wrapping_sub(angle(dx, dy)),
+                            sat: animator.config.sat,
+                            val: u8::MAX,
+                        }
+                    })
+                }
+            }
+            BacklightEffect::DualBeacon => {
+                if K::RGB_BACKLIGHT_MATRIX_DUAL_BEACON_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, _coord, (x, y)| {
+                        // Base speed: 1 cycle every second
+                        let pos = time as u8;
+                        let dy = y as i32 - animator.bounds.mid.1 as i32;
+                        let dx = x as i32 - animator.bounds.mid.0 as i32;
+                        let sin = sin(pos) as i32 - 128;
+                        let cos = cos(pos) as i32 - 128;
+                        Hsv {
+                            hue: animator
+                                .config
+                                .hue
+                                .wrapping_add(((dy * cos + dx * sin) / 128) as u8),
+                            sat: animator.config.sat,
+                            val: u8::MAX,
+                        }
+                    })
+                }
+            }
+            BacklightEffect::RainbowBeacon => {
+                if K::RGB_BACKLIGHT_MATRIX_RAINBOW_BEACON_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, _coord, (x, y)| {
+                        // Base speed: 1 cycle every second
+                        let pos = time as u8;
+                        let dy = y as i32 - animator.bounds.mid.1 as i32;
+                        let dx = x as i32 - animator.bounds.mid.0 as i32;
+                        let sin = sin(pos) as i32 - 128;
+                        let cos = cos(pos) as i32 - 128;
+                        Hsv {
+                            hue: animator
+                                .config
+                                .hue
+                                .wrapping_add(((dy * 2 * cos + dx * 2 * sin) / 128) as u8),
+                            sat: animator.config.sat,
+                            val: u8::MAX,
+                        }
+                    })
+                }
+            }
+            BacklightEffect::RainbowPinWheels => {
+                if K::RGB_BACKLIGHT_MATRIX_RAINBOW_PIN_WHEELS_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, _coord, (x, y)| {
+                        // Base speed: 1 cycle every second
+                        let pos = time as u8;
+                        let dy = y as i32 - animator.bounds.mid.1 as i32;
+                        let dx = x as i32 - animator.bounds.mid.0 as i32;
+                        let sin = sin(pos) as i32 - 128;
+                        let cos = cos(pos) as i32 - 128;
+                        Hsv {
+                            hue: animator.config.hue.wrapping_add(
+                                ((dy * 3 * cos + (56 - dx.abs()) * 3 * sin) / 128) as u8,
+                            ),
+                            sat: animator.config.sat,
+                            val: u8::MAX,
+                        }
+                    })
+                }
+            }
+            BacklightEffect::Raindrops => {
+                if K::RGB_BACKLIGHT_MATRIX_RAINDROPS_ENABLED {
+                    let adjusted_fps = (((K::FPS as u32) << 8)
+                        / (self.config.speed as u32 + 128 + (self.config.speed as u32 >> 1)))
+                        as u8;
+
+                    // Randomly choose an LED to change color every 0.05 seconds
+                    if self.tick % (1 + scale(adjusted_fps, 13)) as u32 == 0 {
+                        let rand = self.rng.next_u32();
+                        let row = rand as u8 % K::LIGHTING_ROWS as u8;
+                        let col = (rand >> 8) as u8 % K::LIGHTING_COLS as u8;
+
+                        // Hue will be shifted away from the configured hue by a random amount
+                        self.buf[row as usize][col as usize] = hsv2rgb(Hsv {
+                            hue: self
+                                .config
+                                .hue
+                                .wrapping_add(((rand >> 16) as u8 & 0b11) * 32),
+                            sat: self.config.sat,
+                            val: self.config.val,
+                        })
+                    }
+                }
+            }
+            BacklightEffect::JellybeanRaindrops => {
+                if K::RGB_BACKLIGHT_MATRIX_JELLYBEAN_RAINDROPS_ENABLED {
+                    let adjusted_fps = (((K::FPS as u32) << 8)
+                        / (self.config.speed as u32 + 128 + (self.config.speed as u32 >> 1)))
+                        as u8;
+
+                    // Randomly choose an LED to change color every 0.05 seconds
+                    if self.tick % (1 + scale(adjusted_fps, 13)) as u32 == 0 {
+                        let rand = self.rng.next_u32();
+                        let row = rand as u8 % K::LIGHTING_ROWS as u8;
+                        let col = (rand >> 8) as u8 % K::LIGHTING_COLS as u8;
+
+                        self.buf[row as usize][col as usize] = hsv2rgb(Hsv {
+                            hue: (rand >> 16) as u8,
+                            sat: (rand >> 24) as u8,
+                            val: self.config.val,
+                        })
+                    }
+                }
+            }
+            BacklightEffect::HueBreathing => {
+                if K::RGB_BACKLIGHT_MATRIX_HUE_BREATHING_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, _coord, _pos| {
+                        // 4 seconds for one full cycle
+                        let delta = ((sin((time >> 2) as u8) as i16 - 128).unsigned_abs() * 2)
+                            .min(u8::MAX as u16) as u8;
+                        Hsv {
+                            hue: animator.config.hue.wrapping_add(scale(delta, 12)),
+                            sat: animator.config.sat,
+                            val: u8::MAX,
+                        }
+                    })
+                }
+            }
+            BacklightEffect::HuePendulum => {
+                if K::RGB_BACKLIGHT_MATRIX_HUE_PENDULUM_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, _coord, (x, _y)| {
+                        // 4 seconds for one full cycle
+                        let delta = ((sin((time >> 2) as u8)
+                            .wrapping_add(x - animator.bounds.min.0)
+                            .wrapping_sub(128) as i8)
+                            .unsigned_abs() as u16
+                            * 2)
+                        .min(u8::MAX as u16) as u8;
+                        Hsv {
+                            hue: animator.config.hue.wrapping_add(scale(delta, 12)),
+                            sat: animator.config.sat,
+                            val: u8::MAX,
+                        }
+                    })
+                }
+            }
+            BacklightEffect::HueWave => {
+                if K::RGB_BACKLIGHT_MATRIX_HUE_WAVE_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, _coord, (x, _y)| {
+                        // Base speed: 1 cycle every second
+                        let delta = ((x - animator.bounds.min.0).wrapping_sub(time as u8) as i8)
+                            .unsigned_abs();
+                        Hsv {
+                            hue: animator.config.hue.wrapping_add(scale(delta, 12)),
+                            sat: animator.config.sat,
+                            val: u8::MAX,
+                        }
+                    })
+                }
+            }
+            BacklightEffect::PixelRain => {
+                if K::RGB_BACKLIGHT_MATRIX_PIXEL_RAIN_ENABLED {
+                    let adjusted_fps = (((K::FPS as u32) << 8)
+                        / (self.config.speed as u32 + 128 + (self.config.speed as u32 >> 1)))
+                        as u8;
+
+                    // Randomly choose an LED to change every 0.08 seconds
+                    if self.tick % (1 + scale(adjusted_fps, 21)) as u32 == 0 {
+                        let rand = self.rng.next_u32();
+                        let row = rand as u8 % K::LIGHTING_ROWS as u8;
+                        let col = (rand >> 8) as u8 % K::LIGHTING_COLS as u8;
+
+                        // Half of the time, the chosen LED will be turned off instead
+                        self.buf[row as usize][col as usize] = if (rand >> 16) & 0b10 != 0 {
+                            RGB8::new(0, 0, 0)
+                        } else {
+                            hsv2rgb(Hsv {
+                                hue: (rand >> 16) as u8,
+                                sat: ((rand >> 24) as u8 >> 1).saturating_add(127),
+                                val: self.config.val,
+                            })
+                        }
+                    }
+                }
+            }
+            BacklightEffect::PixelFlow => {
+                if K::RGB_BACKLIGHT_MATRIX_PIXEL_FLOW_ENABLED {
+                    let adjusted_fps = (((K::FPS as u32) << 8)
+                        / (self.config.speed as u32 + 128 + (self.config.speed as u32 >> 1)))
+                        as u8;
+
+                    // Shift the colors of each LED down by one position every 0.5 seconds
+                    if self.tick % (1 + scale(adjusted_fps, 128)) as u32 == 0 {
+                        let mut prev: Option<(usize, usize)> = None;
+                        for row in 0..K::LIGHTING_ROWS {
+                            for col in 0..K::LIGHTING_COLS {
+                                if K::get_backlight_matrix().layout[row][col].is_some() {
+                                    if let Some((prev_row, prev_col)) = prev {
+                                        self.buf[prev_row][prev_col] = self.buf[row][col];
+                                    }
+                                    prev = Some((row, col));
+                                }
+                            }
+                        }
+
+                        // Half of the time, the new LED will be turned off
+                        if let Some((row, col)) = prev {
+                            let rand = self.rng.next_u32();
+                            self.buf[row][col] = if rand & 0b10 != 0 {
+                                RGB8::new(0, 0, 0)
+                            } else {
+                                hsv2rgb(Hsv {
+                                    hue: (rand >> 8) as u8,
+                                    sat: ((rand >> 16) as u8 >> 1).saturating_add(127),
+                                    val: self.config.val,
+                                })
+                            }
+                        }
+                    }
+                }
+            }
+            BacklightEffect::PixelFractal => {
+                // There is no middle column to start from if the matrix has no columns
+                if K::RGB_BACKLIGHT_MATRIX_PIXEL_FRACTAL_ENABLED && K::LIGHTING_COLS > 0 {
+                    let adjusted_fps = (((K::FPS as u32) << 8)
+                        / (self.config.speed as u32 + 128 + (self.config.speed as u32 >> 1)))
+                        as u8;
+
+                    // Move the LEDs of each row outwards from the middle every 0.5 seconds
+                    if self.tick % (1 + scale(adjusted_fps, 128)) as u32 == 0 {
+                        let color = hsv2rgb(Hsv {
+                            hue: self.config.hue,
+                            sat: self.config.sat,
+                            val: self.config.val,
+                        });
+                        let mid = (K::LIGHTING_COLS + 1) / 2;
+
+                        for row in 0..K::LIGHTING_ROWS {
+                            for col in 0..(mid - 1) {
+                                let next = self.buf[row][col + 1];
+                                self.buf[row][col] = next;
+                                self.buf[row][K::LIGHTING_COLS - 1 - col] = next;
+                            }
+
+                            // A quarter of the time, a new pair of LEDs will be lit in the middle
+                            let new = if self.rng.next_u32() & 0b11 == 0 {
+                                color
+                            } else {
+                                RGB8::new(0, 0, 0)
+                            };
+                            self.buf[row][mid - 1] = new;
+                            self.buf[row][K::LIGHTING_COLS - mid] = new;
+                        }
+                    }
+                }
+            }
+            BacklightEffect::TypingHeatmap => {
+                if K::RGB_BACKLIGHT_MATRIX_TYPING_HEATMAP_ENABLED {
+                    // Heat decreases by 1 every 25ms
+                    if self.tick % (K::FPS as u32 / 40).max(1) == 0 {
+                        let decay = (40 / K::FPS).max(1) as u8;
+                        for row in self.led_state.iter_mut() {
+                            for heat in row.iter_mut() {
+                                *heat = heat.saturating_sub(decay);
+                            }
+                        }
+                    }
+
+                    self.set_brightness_for_each_led(|animator, _time, (row, col), _pos| {
+                        let heat = animator.led_state[row as usize][col as usize];
+                        Hsv {
+                            hue: 170u8.wrapping_sub(heat.saturating_sub(85)),
+                            sat: animator.config.sat,
+                            val: heat.min(85) * 3,
+                        }
+                    })
+                }
+            }
+            BacklightEffect::DigitalRain => {
+                if K::RGB_BACKLIGHT_MATRIX_DIGITAL_RAIN_ENABLED {
+                    // Adapted from QMK, which ported the algorithm from https://github.com/tremby/Kaleidoscope-LEDEffect-DigitalRain
+                    const PURE_GREEN_INTENSITY: u8 = 0xbf;
+                    const MAX_BRIGHTNESS_BOOST: u8 = 0xbf;
+
+                    let adjusted_fps = (((K::FPS as u32) << 8)
+                        / (self.config.speed as u32 + 128 + (self.config.speed as u32 >> 1)))
+                        as u8;
+
+                    // Drops fall down by one row every 0.5 seconds
+                    if self.tick % (1 + scale(adjusted_fps, 119)) as u32 == 0 {
+                        for row in (1..K::LIGHTING_ROWS).rev() {
+                            for col in 0..K::LIGHTING_COLS {
+                                // Allow bright LEDs on the bottom row to decay
+                                if row == K::LIGHTING_ROWS - 1
+                                    && self.led_state[row][col] == u8::MAX
+                                {
+                                    self.led_state[row][col] -= 1;
+                                }
+
+                                // If the LED above is bright, move the head of the drop down
+                                if self.led_state[row - 1][col] == u8::MAX {
+                                    self.led_state[row - 1][col] -= 1;
+                                    self.led_state[row][col] = u8::MAX;
+                                }
+                            }
+                        }
+
+                        // Create new drops in the top row
+                        for col in 0..K::LIGHTING_COLS {
+                            if self.rng.next_u32() % 24 == 0 {
+                                self.led_state[0][col] = u8::MAX;
+                            }
+                        }
+                    }
+
+                    // Trails take about 4 seconds to fade
+                    let decay = (u8::MAX / adjusted_fps.max(1) / 4).max(1);
+                    for row in 0..K::LIGHTING_ROWS {
+                        for col in 0..K::LIGHTING_COLS {
+                            let intensity = &mut self.led_state[row][col];
+                            if *intensity > 0 && *intensity < u8::MAX {
+                                *intensity = intensity.saturating_sub(decay);
+                            }
+
+                            if K::get_backlight_matrix().layout[row][col].is_some() {
+                                let color = if *intensity > PURE_GREEN_INTENSITY {
+                                    // The head of the drop is whiter
+                                    let boost = (MAX_BRIGHTNESS_BOOST as u16
+                                        * (*intensity - PURE_GREEN_INTENSITY) as u16
+                                        / (u8::MAX - PURE_GREEN_INTENSITY) as u16)
+                                        as u8;
+                                    RGB8::new(boost, u8::MAX, boost)
+                                } else {
+                                    let green = (u8::MAX as u16 * *intensity as u16
+                                        / PURE_GREEN_INTENSITY as u16)
+                                        as u8;
+                                    RGB8::new(0, green, 0)
+                                };
+
+                                self.buf[row][col] = RGB8::new(
+                                    scale(color.r, self.config.val),
+                                    scale(color.g, self.config.val),
+                                    scale(color.b, self.config.val),
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+            BacklightEffect::SolidReactiveSimple => {
+                if K::RGB_BACKLIGHT_MATRIX_SOLID_REACTIVE_SIMPLE_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, (row, col), _pos| {
+                        // Base speed: LED fades after one second
+                        let offset = animator
+                            .last_presses
+                            .iter()
+                            .find(|((pressed_row, pressed_col), _time)| {
+                                *pressed_row == row && *pressed_col == col
+                            })
+                            .map_or(u8::MAX, |(_coord, press_time)| {
+                                time.saturating_sub(*press_time).min(u8::MAX as u32) as u8
+                            });
+
+                        Hsv {
+                            hue: animator.config.hue,
+                            sat: animator.config.sat,
+                            val: u8::MAX - offset,
+                        }
+                    })
+                }
+            }
+            BacklightEffect::SolidReactive => {
+                if K::RGB_BACKLIGHT_MATRIX_SOLID_REACTIVE_ENABLED {
+                    self.set_brightness_for_each_led(|animator, time, (row, col), _pos| {
+                        // Base speed: LED returns to the configured hue after one second
+                        let offset = animator
+                            .last_presses
+                            .iter()
+                            .find(|((pressed_row, pressed_col), _time)| {
+                                *pressed_row == row && *pressed_col == col
+                            })
+                            .map_or(u8::MAX, |(_coord, press_time)| {
+                                time.saturating_sub(*press_time).min(u8::MAX as u32) as u8
+                            });
+
+                        Hsv {
+                            hue: animator
+                                .config
+                                .hue
+                                .wrapping_add(130u8.saturating_sub(offset)),
+                            sat: animator.config.sat,
+                            val: u8::MAX,
+                        }
+                    })
+                }
+            }
+            BacklightEffect::SolidReactiveWide | BacklightEffect::SolidReactiveMultiWide => {
+                let multi = self.config.effect == BacklightEffect::SolidReactiveMultiWide;
+                if self.config.effect.is_enabled::<K>() {
+                    self.set_brightness_for_each_led(|animator, time, _coord, pos| {
+                        animator.splash(time, pos, multi, |mut hsv, _dx, _dy, dist, tick| {
+                            let effect = tick.saturating_add(dist.saturating_mul(5)).min(255);
+                            hsv.val = hsv.val.saturating_add(u8::MAX - effect as u8);
+                            hsv
+                        })
+                    })
+                }
+            }
+            BacklightEffect::SolidReactiveCross | BacklightEffect::SolidReactiveMultiCross => {
+                let multi = self.config.effect == BacklightEffect::SolidReactiveMultiCross;
+                if self.config.effect.is_enabled::<K>() {
+                    self.set_brightness_for_each_led(|animator, time, _coord, pos| {
+                        animator.splash(time, pos, multi, |mut hsv, dx, dy, dist, tick| {
+                            let dx = (dx.unsigned_abs() * 16).min(255);
+                            let dy = (dy.unsigned_abs() * 16).min(255);
+                            let effect = tick
+                                .saturating_add(dist)
+                                .saturating_add(dx.min(dy))
+                                .min(255);
+                            hsv.val = hsv.val.saturating_add(u8::MAX - effect as u8);
+                            hsv
+                        })
+                    })
+                }
+            }
+            BacklightEffect::SolidReactiveNexus | BacklightEffect::SolidReactiveMultiNexus => {
+                let multi = self.config.effect == BacklightEffect::SolidReactiveMultiNexus;
+                if self.config.effect.is_enabled::<K>() {
+                    self.set_brightness_for_each_led(|animator, time, _coord, pos| {
+                        let hue = animator.config.hue;
+                        animator.splash(time, pos, multi, |mut hsv, dx, dy, dist, tick| {
+                            let effect = if dist > 72 || (dx.abs() > 8 && dy.abs() > 8) {
+                                255
+                            } else {
+                                tick.checked_sub(dist).map_or(255, |effect| effect.min(255))
+                            };
+                            hsv.val = hsv.val.saturating_add(u8::MAX - effect as u8);
+                            hsv.hue = hue.wrapping_add((dy / 4) as u8);
+                            hsv
+                        })
+                    })
+                }
+            }
+            BacklightEffect::Splash
+            | BacklightEffect::MultiSplash
+            | BacklightEffect::SolidSplash
+            | BacklightEffect::SolidMultiSplash => {
+                let multi = matches!(
+                    self.config.effect,
+                    BacklightEffect::MultiSplash | BacklightEffect::SolidMultiSplash
+                );
+                let solid = matches!(
+                    self.config.effect,
+                    BacklightEffect::SolidSplash | BacklightEffect::SolidMultiSplash
+                );
+                if self.config.effect.is_enabled::<K>() {
+                    self.set_brightness_for_each_led(|animator, time, _coord, pos| {
+                        animator.splash(time, pos, multi, |mut hsv, _dx, _dy, dist, tick| {
+                            let effect =
+                                tick.checked_sub(dist).map_or(255, |effect| effect.min(255)) as u8;
+                            if !solid {
+                                hsv.hue = hsv.hue.wrapping_add(effect);
+                            }
+                            hsv.val = hsv.val.saturating_add(u8::MAX - effect);
+                            hsv
+                        })
+                    })
+                }
+            }
             #[cfg(feature = "vial")]
             BacklightEffect::DirectSet => {} // We just move onto calling the driver, since the frame buffer is updated by the backlight task
         }
 
-        if let Err(err) = self.driver.write(&self.buf).await {
+        let result = if K::LED_INDICATORS.is_empty() {
+            self.driver.write(&self.buf).await
+        } else {
+            // Indicators are drawn on a copy, so that they don't affect the effect's next frame
+            let host_leds = HOST_LED_STATE.get().await;
+            let mut buf = self.buf;
+            for (indicator, (col, row)) in K::LED_INDICATORS {
+                if host_leds.contains(*indicator) {
+                    if let Some(led) = buf
+                        .get_mut(*row as usize)
+                        .and_then(|row| row.get_mut(*col as usize))
+                    {
+                        *led = RGB8::new(self.config.val, self.config.val, self.config.val);
+                    }
+                }
+            }
+            self.driver.write(&buf).await
+        };
+
+        if let Err(err) = result {
             error!(
                 "[BACKLIGHT] Couldn't update backlight colors: {}",
                 Debug2Format(&err)
@@ -524,3 +1347,12 @@ where
         self.tick += 1;
     }
 }
+
+/// Angle of a point relative to the origin, where a full rotation is 256.
+fn angle(dx: i16, dy: i16) -> u8 {
+    ((atan2f(dy as f32, dx as f32) * 128.0 / PI) as i32) as u8
+}
+
+fn distance(dx: i16, dy: i16) -> u16 {
+    sqrtf(((dx as i32).pow(2) + (dy as i32).pow(2)) as f32) as u16
+}
@@ -39,7 +39,7 @@ impl Default for BacklightConfig {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, MaxSize)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, MaxSize)]
 pub enum BacklightCommand {
     Toggle,
     TurnOn,
@@ -53,6 +53,7 @@ pub enum BacklightCommand {
     SetSpeed(u8),
     IncreaseSpeed(u8),
     DecreaseSpeed(u8),
+    SetConfig(BacklightConfig), // normally used internally for switching storage profiles
     #[cfg(feature = "storage")]
     SaveConfig,
     ResetTime, // normally used internally for syncing LEDs for split keyboards
@@ -174,6 +175,9 @@ impl<K: BacklightDevice, D: SimpleBacklightDriver<K>> BacklightAnimator<K, D> {
             BacklightCommand::DecreaseSpeed(amount) => {
                 self.config.speed = self.config.speed.saturating_sub(amount);
             }
+            BacklightCommand::SetConfig(config) => {
+                self.config = config;
+            }
             #[cfg(feature = "storage")]
             BacklightCommand::SaveConfig => {
                 super::storage::BACKLIGHT_SAVE_SIGNAL.signal(());
@@ -2,6 +2,7 @@ use crate::backlight::drivers::SimpleBacklightMatrixDriver;
 use crate::backlight::{
     get_led_layout_bounds, BacklightDevice, BacklightMatrixDevice, LEDFlags, LayoutBounds,
 };
+use crate::hw::HOST_LED_STATE;
 use crate::math::{atan2f, cos, scale, sin, sqrtf};
 use crate::{Cycle, LEDEffect};
 use rumcake_macros::{generate_items_from_enum_variants, Cycle, LEDEffect};
@@ -42,7 +43,7 @@ impl Default for BacklightConfig {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, MaxSize)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, MaxSize)]
 pub enum BacklightCommand {
     Toggle,
     TurnOn,
@@ -56,6 +57,7 @@ pub enum BacklightCommand {
     SetSpeed(u8),
     IncreaseSpeed(u8),
     DecreaseSpeed(u8),
+    SetConfig(BacklightConfig), // normally used internally for switching storage profiles
     #[cfg(feature = "storage")]
     SaveConfig,
     ResetTime, // normally used internally for syncing LEDs for split keyboards
@@ -253,6 +255,9 @@ where
             BacklightCommand::DecreaseSpeed(amount) => {
                 self.config.speed = self.config.speed.saturating_sub(amount);
             }
+            BacklightCommand::SetConfig(config) => {
+                self.config = config;
+            }
             #[cfg(feature = "storage")]
             BacklightCommand::SaveConfig => {
                 super::storage::BACKLIGHT_SAVE_SIGNAL.signal(());
@@ -643,7 +648,26 @@ where
             }
         }
 
-        if let Err(err) = self.driver.write(&self.buf).await {
+        let result = if K::LED_INDICATORS.is_empty() {
+            self.driver.write(&self.buf).await
+        } else {
+            // Indicators are drawn on a copy, so that they don't affect the effect's next frame
+            let host_leds = HOST_LED_STATE.get().await;
+            let mut buf = self.buf;
+            for (indicator, (col, row)) in K::LED_INDICATORS {
+                if host_leds.contains(*indicator) {
+                    if let Some(led) = buf
+                        .get_mut(*row as usize)
+                        .and_then(|row| row.get_mut(*col as usize))
+                    {
+                        *led = self.config.val;
+                    }
+                }
+            }
+            self.driver.write(&buf).await
+        };
+
+        if let Err(err) = result {
             error!(
                 "[BACKLIGHT] Couldn't update backlight: {}",
                 Debug2Format(&err)
@@ -25,7 +25,7 @@ pub trait BluetoothKeyboard: Keyboard + KeyboardLayout {
     const BLE_PRODUCT_VERSION: &'static str = Self::HARDWARE_REVISION;
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// An enumeration of possible commands that will be processed by the bluetooth task.
 pub enum BluetoothCommand {
     #[cfg(feature = "usb")]
@@ -1,8 +1,10 @@
 use core::cell::{Cell, RefCell};
+use core::mem::{size_of, MaybeUninit};
 
 use defmt::{debug, error, info, warn, Debug2Format};
 use embassy_futures::join;
-use embassy_futures::select::{self, select3, select4};
+use embassy_futures::select::{self, select, select3, select4};
+use embassy_sync::signal::Signal;
 use heapless::Vec;
 use nrf_softdevice::ble::gatt_server::builder::ServiceBuilder;
 use nrf_softdevice::ble::gatt_server::characteristic::{Attribute, Metadata, Properties};
@@ -20,12 +22,16 @@ use packed_struct::prelude::{PackedStruct, PrimitiveEnum};
 use static_cell::StaticCell;
 use usbd_human_interface_device::device::consumer::MultipleConsumerReport;
 use usbd_human_interface_device::device::keyboard::NKROBootKeyboardReport;
+use usbd_human_interface_device::device::mouse::WheelMouseReport;
 
-use crate::hw::mcu::BLUETOOTH_ADVERTISING_MUTEX;
+use crate::hw::mcu::{RawMutex, BLUETOOTH_ADVERTISING_MUTEX};
 use crate::hw::{
     HIDOutput, OutputMode, BATTERY_LEVEL_STATE, CURRENT_OUTPUT_STATE, OUTPUT_MODE_STATE,
 };
-use crate::keyboard::{CONSUMER_REPORT_HID_SEND_CHANNEL, KEYBOARD_REPORT_HID_SEND_CHANNEL};
+use crate::keyboard::{
+    CONSUMER_REPORT_HID_SEND_CHANNEL, KEYBOARD_REPORT_HID_SEND_CHANNEL,
+    MOUSE_REPORT_HID_SEND_CHANNEL,
+};
 
 use crate::bluetooth::{
     BluetoothCommand, BluetoothKeyboard, BATTERY_LEVEL_LISTENER, BLUETOOTH_COMMAND_CHANNEL,
@@ -149,6 +155,91 @@ impl SecurityHandler for Bonder {
     }
 }
 
+impl Bonder {
+    /// Copy the bond with the current host, and the output mode, to RAM that stays powered while
+    /// the MCU is in System OFF.
+    fn retain(&self, output_mode: OutputMode) {
+        let sys_attrs = self.sys_attrs.borrow();
+        let mut state = RetainedState {
+            magic: RETAINED_STATE_MAGIC,
+            output_mode,
+            peer: self.peer.get(),
+            sys_attrs_len: sys_attrs.len() as u8,
+            sys_attrs: [0; 62],
+        };
+        state.sys_attrs[..sys_attrs.len()].copy_from_slice(&sys_attrs);
+
+        unsafe {
+            let retained = core::ptr::addr_of_mut!(RETAINED_STATE).cast::<RetainedState>();
+            retained.write(state);
+            crate::hw::mcu::retain_ram(retained as *const u8, size_of::<RetainedState>());
+        }
+    }
+
+    /// If the MCU woke up from System OFF, restore the bond that was retained before entering it,
+    /// and return the output mode that was used.
+    fn restore(&self) -> Option<OutputMode> {
+        let mut reset_reason = 0;
+        unsafe {
+            nrf_softdevice::raw::sd_power_reset_reason_get(&mut reset_reason);
+        }
+
+        if reset_reason & RESETREAS_OFF == 0 {
+            return None;
+        }
+
+        let state = unsafe {
+            nrf_softdevice::raw::sd_power_reset_reason_clr(RESETREAS_OFF);
+
+            let retained = core::ptr::addr_of_mut!(RETAINED_STATE).cast::<RetainedState>();
+            if core::ptr::addr_of!((*retained).magic).read_volatile() != RETAINED_STATE_MAGIC {
+                return None;
+            }
+            core::ptr::addr_of_mut!((*retained).magic).write_volatile(0);
+            retained.read()
+        };
+
+        self.peer.set(state.peer);
+        let mut sys_attrs = self.sys_attrs.borrow_mut();
+        sys_attrs.clear();
+        sys_attrs
+            .extend_from_slice(&state.sys_attrs[..state.sys_attrs_len as usize])
+            .unwrap();
+
+        Some(state.output_mode)
+    }
+}
+
+/// Used to check that [`RETAINED_STATE`] was written before entering System OFF.
+const RETAINED_STATE_MAGIC: u32 = 0x524D_4342;
+
+/// Bit in the RESETREAS register that is set after waking up from System OFF.
+const RESETREAS_OFF: u32 = 1 << 16;
+
+#[derive(Clone, Copy)]
+struct RetainedState {
+    magic: u32,
+    output_mode: OutputMode,
+    peer: Option<Peer>,
+    sys_attrs_len: u8,
+    sys_attrs: [u8; 62],
+}
+
+/// State that is kept in RAM while the MCU is in System OFF. This is placed in a section that
+/// isn't initialized on startup, so that it can be read after waking up.
+#[link_section = ".uninit.rumcake"]
+static mut RETAINED_STATE: MaybeUninit<RetainedState> = MaybeUninit::uninit();
+
+static SYSTEM_OFF_SIGNAL: Signal<RawMutex, ()> = Signal::new();
+static SYSTEM_OFF_READY_SIGNAL: Signal<RawMutex, ()> = Signal::new();
+
+/// Disconnect from the current host, and retain the information needed to reconnect to it after
+/// waking up from System OFF.
+pub(crate) async fn prepare_for_system_off() {
+    SYSTEM_OFF_SIGNAL.signal(());
+    SYSTEM_OFF_READY_SIGNAL.wait().await;
+}
+
 #[repr(u8)]
 #[derive(Clone, Copy, PrimitiveEnum, Default)]
 pub enum VidSource {
@@ -324,19 +415,24 @@ impl Service for DeviceInformationService {
 pub struct HIDService {
     keyboard_report_value_handle: u16,
     keyboard_report_cccd_handle: u16,
+    keyboard_led_report_value_handle: u16,
     consumer_report_value_handle: u16,
     consumer_report_cccd_handle: u16,
     via_input_report_value_handle: u16,
     via_input_report_cccd_handle: u16,
     via_output_report_value_handle: u16,
+    mouse_report_value_handle: u16,
+    mouse_report_cccd_handle: u16,
     hid_control_value_handle: u16,
 }
 
-/// Report descriptor with NKRO, consumer control and Via functionality. This is basically a
+/// Report descriptor with NKRO, consumer control, Via and mouse functionality. This is basically a
 /// combination of
 /// [`usbd_human_interface_device::device::keyboard::NKRO_BOOT_KEYBOARD_REPORT_DESCRIPTOR`],
-/// [`usbd_human_interface_device::device::consumer::MULTIPLE_CODE_REPORT_DESCRIPTOR`], and
-/// [`crate::via::VIA_REPORT_DESCRIPTOR`], with report IDs included. Without report IDs, some
+/// [`usbd_human_interface_device::device::consumer::MULTIPLE_CODE_REPORT_DESCRIPTOR`],
+/// [`crate::via::VIA_REPORT_DESCRIPTOR`], and
+/// [`usbd_human_interface_device::device::mouse::WHEEL_MOUSE_REPORT_DESCRIPTOR`], with report IDs
+/// included. Without report IDs, some
 /// functionality doesn't seem to work as expected. In testing, exclusion of a report ID seems to
 /// prevent Via output reports from being received. Potentially related:
 /// https://devzone.nordicsemi.com/f/nordic-q-a/24486/hid-get-report-from-a-mac-not-as-expected
@@ -412,6 +508,42 @@ pub(crate) const REPORT_MAP: &[u8] = &[
     0x75, 0x08, //   Report Size (8)
     0x91, 0x02, //   Output (Data, Variable, Absolute)
     0xC0, // End Collection
+    // Mouse reports
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x02, // Usage (Mouse)
+    0xA1, 0x01, // Collection (Application)
+    0x85, 0x04, //   Report ID (4)
+    0x09, 0x01, //   Usage (Pointer)
+    0xA1, 0x00, //   Collection (Physical)
+    // bitmap of buttons
+    0x05, 0x09, //     Usage Page (Buttons)
+    0x19, 0x01, //     Usage Minimum (1)
+    0x29, 0x08, //     Usage Maximum (8)
+    0x15, 0x00, //     Logical Minimum (0)
+    0x25, 0x01, //     Logical Maximum (1)
+    0x75, 0x01, //     Report Size (1)
+    0x95, 0x08, //     Report Count (8)
+    0x81, 0x02, //     Input (Data, Variable, Absolute)
+    // x, y and vertical wheel
+    0x05, 0x01, //     Usage Page (Generic Desktop)
+    0x09, 0x30, //     Usage (X)
+    0x09, 0x31, //     Usage (Y)
+    0x09, 0x38, //     Usage (Wheel)
+    0x15, 0x81, //     Logical Minimum (-127)
+    0x25, 0x7F, //     Logical Maximum (127)
+    0x75, 0x08, //     Report Size (8)
+    0x95, 0x03, //     Report Count (3)
+    0x81, 0x06, //     Input (Data, Variable, Relative)
+    // horizontal wheel
+    0x05, 0x0C, //     Usage Page (Consumer)
+    0x0A, 0x38, 0x02, //     Usage (AC Pan)
+    0x15, 0x81, //     Logical Minimum (-127)
+    0x25, 0x7F, //     Logical Maximum (127)
+    0x75, 0x08, //     Report Size (8)
+    0x95, 0x01, //     Report Count (1)
+    0x81, 0x06, //     Input (Data, Variable, Relative)
+    0xC0, //   End Collection
+    0xC0, // End Collection
 ];
 
 impl HIDService {
@@ -456,6 +588,28 @@ impl HIDService {
             .unwrap();
         let keyboard_report_handles = keyboard_report_builder.build();
 
+        let mut keyboard_led_report_builder = sb
+            .add_characteristic(
+                Uuid::new_16(0x2a4d),
+                Attribute::new(&[0]).security(SecurityMode::JustWorks),
+                Metadata::with_security(
+                    Properties::new().read().write().write_without_response(),
+                    SecurityMode::JustWorks,
+                ),
+            )
+            .unwrap();
+        keyboard_led_report_builder
+            .add_descriptor(
+                Uuid::new_16(0x2908),
+                Attribute::new(&[
+                    0x01, // ID
+                    0x02, // Output
+                ])
+                .security(SecurityMode::JustWorks),
+            )
+            .unwrap();
+        let keyboard_led_report_handles = keyboard_led_report_builder.build();
+
         let mut consumer_report_builder = sb
             .add_characteristic(
                 Uuid::new_16(0x2a4d),
@@ -517,6 +671,26 @@ impl HIDService {
             .unwrap();
         let via_output_report_handles = via_output_report_builder.build();
 
+        let mut mouse_report_builder = sb
+            .add_characteristic(
+                Uuid::new_16(0x2a4d),
+                Attribute::new(WheelMouseReport::default().pack().unwrap())
+                    .security(SecurityMode::JustWorks),
+                Metadata::with_security(Properties::new().read().notify(), SecurityMode::JustWorks),
+            )
+            .unwrap();
+        mouse_report_builder
+            .add_descriptor(
+                Uuid::new_16(0x2908),
+                Attribute::new(&[
+                    0x04, // ID
+                    0x01, // Input
+                ])
+                .security(SecurityMode::JustWorks),
+            )
+            .unwrap();
+        let mouse_report_handles = mouse_report_builder.build();
+
         let hid_control_builder = sb
             .add_characteristic(
                 Uuid::new_16(0x2a4c),
@@ -531,11 +705,14 @@ impl HIDService {
         Ok(Self {
             keyboard_report_value_handle: keyboard_report_handles.value_handle,
             keyboard_report_cccd_handle: keyboard_report_handles.cccd_handle,
+            keyboard_led_report_value_handle: keyboard_led_report_handles.value_handle,
             consumer_report_value_handle: consumer_report_handles.value_handle,
             consumer_report_cccd_handle: consumer_report_handles.cccd_handle,
             via_input_report_value_handle: via_input_report_handles.value_handle,
             via_input_report_cccd_handle: via_input_report_handles.cccd_handle,
             via_output_report_value_handle: via_output_report_handles.value_handle,
+            mouse_report_value_handle: mouse_report_handles.value_handle,
+            mouse_report_cccd_handle: mouse_report_handles.cccd_handle,
             hid_control_value_handle: hid_control_handles.value_handle,
         })
     }
@@ -566,6 +743,19 @@ impl HIDService {
         Ok(())
     }
 
+    pub fn mouse_report_notify(
+        &self,
+        connection: &Connection,
+        report: WheelMouseReport,
+    ) -> Result<(), NotifyValueError> {
+        gatt_server::notify_value(
+            connection,
+            self.mouse_report_value_handle,
+            &report.pack().unwrap(),
+        )?;
+        Ok(())
+    }
+
     pub fn via_report_notify(
         &self,
         connection: &Connection,
@@ -584,6 +774,15 @@ impl HIDService {
         }
     }
 
+    pub fn unsafe_keyboard_led_report_get(&self) -> Result<u8, GetValueError> {
+        unsafe {
+            let sd = nrf_softdevice::Softdevice::steal();
+            let buf = &mut [0];
+            gatt_server::get_value(sd, self.keyboard_led_report_value_handle, buf)?;
+            Ok(buf[0])
+        }
+    }
+
     pub fn unsafe_hid_control_get(&self) -> Result<u8, GetValueError> {
         unsafe {
             let sd = nrf_softdevice::Softdevice::steal();
@@ -596,9 +795,11 @@ impl HIDService {
 
 pub enum HIDServiceEvent {
     KeyboardReportCccdWrite { notifications: bool },
+    KeyboardLedReportWrite(u8),
     ConsumerReportCccdWrite { notifications: bool },
     ViaReportCccdWrite { notifications: bool },
     ViaReportWrite([u8; 32]),
+    MouseReportCccdWrite { notifications: bool },
     HidControlWrite(u8),
 }
 
@@ -651,6 +852,21 @@ impl Service for HIDService {
                 _ => {}
             }
         }
+        if handle == self.mouse_report_cccd_handle {
+            match data[0] & 0x01 {
+                0x00 => {
+                    return Some(HIDServiceEvent::MouseReportCccdWrite {
+                        notifications: false,
+                    })
+                }
+                0x01 => {
+                    return Some(HIDServiceEvent::MouseReportCccdWrite {
+                        notifications: true,
+                    })
+                }
+                _ => {}
+            }
+        }
         if handle == self.via_output_report_value_handle {
             if data.len() < <u8 as GattValue>::MIN_SIZE {
                 return self
@@ -661,6 +877,16 @@ impl Service for HIDService {
                 return Some(HIDServiceEvent::ViaReportWrite(<[u8; 32]>::from_gatt(data)));
             }
         }
+        if handle == self.keyboard_led_report_value_handle {
+            if data.len() < <u8 as GattValue>::MIN_SIZE {
+                return self
+                    .unsafe_keyboard_led_report_get()
+                    .ok()
+                    .map(HIDServiceEvent::KeyboardLedReportWrite);
+            } else {
+                return Some(HIDServiceEvent::KeyboardLedReportWrite(u8::from_gatt(data)));
+            }
+        }
         if handle == self.hid_control_value_handle {
             if data.len() < <u8 as GattValue>::MIN_SIZE {
                 return self
@@ -739,6 +965,11 @@ where
     static BONDER: StaticCell<Bonder> = StaticCell::new();
     let bonder = BONDER.init(Bonder::default());
 
+    if let Some(output_mode) = bonder.restore() {
+        info!("[BT_HID] Restored bond after waking up from System OFF");
+        OUTPUT_MODE_STATE.set(output_mode).await;
+    }
+
     let connection_fut = async {
         loop {
             let advertisement = ConnectableAdvertisement::ScannableUndirected {
@@ -748,16 +979,22 @@ where
 
             let connection = {
                 let _lock = BLUETOOTH_ADVERTISING_MUTEX.lock().await;
-                match advertise_pairable(sd, advertisement, &Default::default(), bonder).await {
-                    Ok(connection) => {
+                match select(
+                    advertise_pairable(sd, advertisement, &Default::default(), bonder),
+                    SYSTEM_OFF_SIGNAL.wait(),
+                )
+                .await
+                {
+                    select::Either::First(Ok(connection)) => {
                         info!("[BT_HID] Connection established with host device");
                         BLUETOOTH_CONNECTED_STATE.set(true).await;
                         connection
                     }
-                    Err(error) => {
+                    select::Either::First(Err(error)) => {
                         warn!("[BT_HID] BLE advertising error: {}", Debug2Format(&error));
                         continue;
                     }
+                    select::Either::Second(()) => break,
                 }
             };
 
@@ -772,6 +1009,10 @@ where
                     HIDServiceEvent::KeyboardReportCccdWrite { notifications } => {
                         debug!("[BT_HID] Keyboard report CCCD updated: {}", notifications);
                     }
+                    HIDServiceEvent::KeyboardLedReportWrite(leds) => {
+                        debug!("[BT_HID] Received LED output report: {=u8:#X}", leds);
+                        crate::hw::set_host_leds(HIDOutput::Bluetooth, leds);
+                    }
                     HIDServiceEvent::ConsumerReportCccdWrite { notifications } => {
                         debug!("[BT_HID] Consumer report CCCD updated: {}", notifications);
                     }
@@ -796,6 +1037,9 @@ where
                         #[cfg(not(feature = "via"))]
                         warn!("[BT_HID] Via is not enabled. Ignoring report: {}", report);
                     }
+                    HIDServiceEvent::MouseReportCccdWrite { notifications } => {
+                        debug!("[BT_HID] Mouse report CCCD updated: {}", notifications);
+                    }
                     HIDServiceEvent::HidControlWrite(val) => {
                         debug!("[BT_HID] Received HID control value: {=u8}", val);
                     }
@@ -829,6 +1073,7 @@ where
                 // Discard any reports that haven't been processed due to lack of a connection
                 while KEYBOARD_REPORT_HID_SEND_CHANNEL.try_receive().is_ok() {}
                 while CONSUMER_REPORT_HID_SEND_CHANNEL.try_receive().is_ok() {}
+                while MOUSE_REPORT_HID_SEND_CHANNEL.try_receive().is_ok() {}
 
                 #[cfg(feature = "via")]
                 while crate::via::VIA_REPORT_HID_SEND_CHANNEL
@@ -842,7 +1087,10 @@ where
                         match select4(
                             CURRENT_OUTPUT_STATE_LISTENER.wait(),
                             KEYBOARD_REPORT_HID_SEND_CHANNEL.receive(),
-                            CONSUMER_REPORT_HID_SEND_CHANNEL.receive(),
+                            select(
+                                CONSUMER_REPORT_HID_SEND_CHANNEL.receive(),
+                                MOUSE_REPORT_HID_SEND_CHANNEL.receive(),
+                            ),
                             crate::via::VIA_REPORT_HID_SEND_CHANNEL.receive(),
                         )
                         .await
@@ -863,7 +1111,7 @@ where
                                     );
                                 };
                             }
-                            select::Either4::Third(report) => {
+                            select::Either4::Third(select::Either::First(report)) => {
                                 info!(
                                     "[BT_HID] Writing consumer HID report to bluetooth: {:?}",
                                     Debug2Format(&report)
@@ -878,6 +1126,21 @@ where
                                     );
                                 };
                             }
+                            select::Either4::Third(select::Either::Second(report)) => {
+                                info!(
+                                    "[BT_HID] Writing mouse HID report to bluetooth: {:?}",
+                                    Debug2Format(&report)
+                                );
+
+                                if let Err(err) =
+                                    server.hids.mouse_report_notify(&connection, report)
+                                {
+                                    error!(
+                                        "[BT_HID] Couldn't write mouse HID report: {:?}",
+                                        Debug2Format(&err)
+                                    );
+                                };
+                            }
                             select::Either4::Fourth(report) => {
                                 info!(
                                     "[BT_HID] Writing Via HID report to bluetooth: {:?}",
@@ -898,7 +1161,10 @@ where
                         match select3(
                             CURRENT_OUTPUT_STATE_LISTENER.wait(),
                             KEYBOARD_REPORT_HID_SEND_CHANNEL.receive(),
-                            CONSUMER_REPORT_HID_SEND_CHANNEL.receive(),
+                            select(
+                                CONSUMER_REPORT_HID_SEND_CHANNEL.receive(),
+                                MOUSE_REPORT_HID_SEND_CHANNEL.receive(),
+                            ),
                         )
                         .await
                         {
@@ -918,7 +1184,7 @@ where
                                     );
                                 };
                             }
-                            select::Either3::Third(report) => {
+                            select::Either3::Third(select::Either::First(report)) => {
                                 info!(
                                     "[BT_HID] Writing consumer HID report to bluetooth: {:?}",
                                     Debug2Format(&report)
@@ -933,6 +1199,21 @@ where
                                     );
                                 };
                             }
+                            select::Either3::Third(select::Either::Second(report)) => {
+                                info!(
+                                    "[BT_HID] Writing mouse HID report to bluetooth: {:?}",
+                                    Debug2Format(&report)
+                                );
+
+                                if let Err(err) =
+                                    server.hids.mouse_report_notify(&connection, report)
+                                {
+                                    error!(
+                                        "[BT_HID] Couldn't write mouse HID report: {:?}",
+                                        Debug2Format(&err)
+                                    );
+                                };
+                            }
                         };
                     } else {
                         CURRENT_OUTPUT_STATE_LISTENER.wait().await;
@@ -940,22 +1221,36 @@ where
                 }
             };
 
-            match select3(conn_fut, adc_fut, hid_fut).await {
-                select::Either3::First(error) => {
+            match select4(conn_fut, adc_fut, hid_fut, SYSTEM_OFF_SIGNAL.wait()).await {
+                select::Either4::First(error) => {
                     warn!(
                         "[BT_HID] Connection has been lost: {}",
                         Debug2Format(&error)
                     );
+                    // The next host may not send its LED state until it changes
+                    crate::hw::set_host_leds(HIDOutput::Bluetooth, 0);
                     BLUETOOTH_CONNECTED_STATE.set(false).await;
                 }
-                select::Either3::Second(_) => {
+                select::Either4::Second(_) => {
                     error!("[BT_HID] Battery task failed. This should not happen.");
                 }
-                select::Either3::Third(_) => {
+                select::Either4::Third(_) => {
                     error!("[BT_HID] HID task failed. This should not happen.");
                 }
+                select::Either4::Fourth(()) => {
+                    info!("[BT_HID] Disconnecting from host device before entering System OFF");
+                    if connection.disconnect().is_ok() {
+                        // Wait for the host to acknowledge the disconnection
+                        run(&connection, &server, |_| {}).await;
+                    }
+                    BLUETOOTH_CONNECTED_STATE.set(false).await;
+                    break;
+                }
             };
         }
+
+        bonder.retain(OUTPUT_MODE_STATE.get().await);
+        SYSTEM_OFF_READY_SIGNAL.signal(());
     };
 
     let command_fut = async {
@@ -0,0 +1,136 @@
+//! Support for Caps Word.
+//!
+//! Caps Word shifts letters until a key that ends a word is pressed, which makes it easy to type
+//! a single word in capitals (e.g. `MAX_SIZE`) without holding shift or toggling Caps Lock. It can
+//! be toggled using the [`Keycode::CapsWord`] keycode. The behaviour of Caps Word is based on
+//! QMK's implementation, and can be configured using
+//! [`crate::keyboard::KeyboardLayout::CAPS_WORD_CONFIG`].
+//!
+//! [`Keycode::CapsWord`]: crate::keyboard::Keycode::CapsWord
+
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+use usbd_human_interface_device::page::Keyboard as KeyboardKeycode;
+
+use crate::key_overrides::ModifierMask;
+
+/// Options that control which keys are shifted by Caps Word, and which keys end it.
+///
+/// Default values match QMK's defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CapsWordConfig {
+    /// Keys that are shifted while Caps Word is active, in addition to letters.
+    pub shifted_keys: &'static [KeyboardKeycode],
+
+    /// Keys that don't end Caps Word, but are not shifted.
+    pub continue_keys: &'static [KeyboardKeycode],
+
+    /// Amount of time (in milliseconds) without a key press before Caps Word is turned off. If
+    /// this is 0, Caps Word stays on until a key that ends a word is pressed.
+    pub idle_timeout: u16,
+}
+
+impl CapsWordConfig {
+    /// Create a new Caps Word config, with the default options. `-` is shifted (to type `_`), and
+    /// numbers, backspace and delete continue the word.
+    pub const fn new() -> Self {
+        Self {
+            shifted_keys: &[KeyboardKeycode::Minus],
+            continue_keys: &[
+                KeyboardKeycode::Keyboard1,
+                KeyboardKeycode::Keyboard2,
+                KeyboardKeycode::Keyboard3,
+                KeyboardKeycode::Keyboard4,
+                KeyboardKeycode::Keyboard5,
+                KeyboardKeycode::Keyboard6,
+                KeyboardKeycode::Keyboard7,
+                KeyboardKeycode::Keyboard8,
+                KeyboardKeycode::Keyboard9,
+                KeyboardKeycode::Keyboard0,
+                KeyboardKeycode::DeleteBackspace,
+                KeyboardKeycode::DeleteForward,
+            ],
+            idle_timeout: 5000,
+        }
+    }
+
+    fn is_shifted(&self, keycode: KeyboardKeycode) -> bool {
+        (KeyboardKeycode::A as u8..=KeyboardKeycode::Z as u8).contains(&(keycode as u8))
+            || self.shifted_keys.contains(&keycode)
+    }
+}
+
+impl Default for CapsWordConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Adds shift to the keycodes collected from the layout while Caps Word is active.
+pub(crate) struct CapsWordProcessor {
+    active: bool,
+
+    /// Keycodes collected from the layout on the previous tick.
+    last_keys: Vec<KeyboardKeycode, 24>,
+
+    /// Time of the last key press while Caps Word was active.
+    last_press: Instant,
+}
+
+impl CapsWordProcessor {
+    pub(crate) fn new() -> Self {
+        Self {
+            active: false,
+            last_keys: Vec::new(),
+            last_press: Instant::now(),
+        }
+    }
+
+    /// Turn Caps Word on if it is off, or off if it is on.
+    pub(crate) fn toggle(&mut self) {
+        self.active = !self.active;
+        self.last_press = Instant::now();
+    }
+
+    /// Apply Caps Word to the keycodes collected from the layout.
+    pub(crate) fn process(&mut self, config: &CapsWordConfig, keys: &mut Vec<KeyboardKeycode, 24>) {
+        if self.active {
+            for key in keys.iter().filter(|k| !self.last_keys.contains(k)) {
+                self.last_press = Instant::now();
+
+                let mods = ModifierMask::from_keycode(*key);
+                let continues = if mods.is_empty() {
+                    config.is_shifted(*key) || config.continue_keys.contains(key)
+                } else {
+                    // Shift can be used in a word, but other modifiers usually start a shortcut
+                    ModifierMask::SHIFT.contains(mods)
+                };
+
+                if !continues {
+                    self.active = false;
+                    break;
+                }
+            }
+
+            if config.idle_timeout != 0
+                && self.last_press.elapsed() > Duration::from_millis(config.idle_timeout as u64)
+            {
+                self.active = false;
+            }
+        }
+
+        self.last_keys.clone_from(keys);
+
+        // Shift is only added if every held key should be shifted. Otherwise, keys that continue
+        // the word (e.g. numbers) would be shifted if they are pressed while a letter is held.
+        if self.active
+            && keys.iter().any(|k| config.is_shifted(*k))
+            && keys
+                .iter()
+                .all(|k| config.is_shifted(*k) || !ModifierMask::from_keycode(*k).is_empty())
+            && !keys.contains(&KeyboardKeycode::LeftShift)
+        {
+            let _ = keys.push(KeyboardKeycode::LeftShift);
+        }
+    }
+}
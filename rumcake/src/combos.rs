@@ -0,0 +1,282 @@
+//! Support for combos (chords).
+//!
+//! A combo triggers an action when a set of keys is pressed at the same time. Combos are matched
+//! using the actions on the current layer, so a combo will trigger regardless of which physical
+//! keys produced those actions. To use combos, your keyboard must implement
+//! [`crate::keyboard::KeyboardLayout::NUM_COMBOS`] and
+//! [`crate::keyboard::KeyboardLayout::get_combos`]. It is recommended to use [`build_combos`] to
+//! implement these.
+
+use embassy_sync::mutex::{Mutex, MutexGuard};
+use heapless::Vec;
+use keyberon::action::Action;
+use keyberon::layout::{Event, Layout as KeyberonLayout};
+
+use crate::hw::mcu::RawMutex;
+use crate::keyboard::Keycode;
+
+pub use rumcake_macros::build_combos;
+
+/// Maximum number of keys that can be used in a single combo. This matches the number of keys
+/// supported by Vial.
+pub const MAX_COMBO_KEYS: usize = 4;
+
+/// Maximum number of combos that can be held down at the same time.
+const MAX_ACTIVE_COMBOS: usize = 8;
+
+/// Layout row used for the virtual keys that are pressed when a combo is triggered. The column of
+/// the virtual key corresponds to the index of the combo.
+const COMBO_ROW: u8 = u8::MAX;
+
+/// A set of keys that trigger a different action when they are pressed at the same time.
+#[derive(Debug, Clone, Copy)]
+pub struct Combo {
+    /// Actions that need to be pressed to trigger the combo. Unused slots should be set to
+    /// [`Action::NoOp`]. A combo with no keys is disabled.
+    pub keys: [Action<Keycode>; MAX_COMBO_KEYS],
+
+    /// Action that will be triggered when all of the keys are pressed.
+    pub output: Action<Keycode>,
+
+    /// Amount of time (in milliseconds) that all of the keys must be pressed within. If this is
+    /// `None`, [`crate::keyboard::KeyboardLayout::COMBO_TIMEOUT_MS`] is used.
+    pub timeout: Option<u16>,
+}
+
+impl Combo {
+    /// Create a new combo.
+    pub const fn new(
+        keys: [Action<Keycode>; MAX_COMBO_KEYS],
+        output: Action<Keycode>,
+        timeout: Option<u16>,
+    ) -> Self {
+        Self {
+            keys,
+            output,
+            timeout,
+        }
+    }
+
+    /// Create a combo with no keys, which will never be triggered.
+    pub const fn empty() -> Self {
+        Self::new([Action::NoOp; MAX_COMBO_KEYS], Action::NoOp, None)
+    }
+
+    fn len(&self) -> usize {
+        self.keys.iter().filter(|k| **k != Action::NoOp).count()
+    }
+
+    /// Check if all of the given actions are keys of this combo. Returns `Some(true)` if the
+    /// actions make up the entire combo, `Some(false)` if some keys of the combo are missing, and
+    /// `None` if any of the actions are not part of this combo.
+    fn matches<'a>(&self, actions: impl Iterator<Item = &'a Action<Keycode>>) -> Option<bool> {
+        let mut used = [false; MAX_COMBO_KEYS];
+        let mut count = 0;
+
+        for action in actions {
+            let idx = self
+                .keys
+                .iter()
+                .enumerate()
+                .position(|(i, key)| !used[i] && *key != Action::NoOp && key == action)?;
+            used[idx] = true;
+            count += 1;
+        }
+
+        Some(count == self.len())
+    }
+}
+
+/// Mutex-guarded combos. This allows combos to be changed at runtime (e.g. using Vial).
+pub struct Combos<const N: usize> {
+    combos: Mutex<RawMutex, [Combo; N]>,
+}
+
+impl<const N: usize> Combos<N> {
+    /// Create a new set of combos.
+    pub const fn new(combos: [Combo; N]) -> Self {
+        Self {
+            combos: Mutex::new(combos),
+        }
+    }
+
+    /// Obtain a lock on the combos, so that they can be read or modified.
+    pub async fn lock(&self) -> MutexGuard<RawMutex, [Combo; N]> {
+        self.combos.lock().await
+    }
+}
+
+/// Resolves combos from matrix events, before they are sent to the layout.
+pub(crate) struct ComboProcessor {
+    /// Keys that have been pressed, but not yet sent to the layout because they may be part of a
+    /// combo.
+    pending: Vec<((u8, u8), Action<Keycode>), MAX_COMBO_KEYS>,
+
+    /// Time (in milliseconds) since the first pending key was pressed.
+    elapsed: u16,
+
+    /// Combos that have been triggered, along with the keys of the combo that are still held.
+    active: Vec<(usize, Vec<(u8, u8), MAX_COMBO_KEYS>), MAX_ACTIVE_COMBOS>,
+
+    /// Timeout used for combos that don't specify one.
+    default_timeout: u16,
+}
+
+impl ComboProcessor {
+    pub(crate) fn new(default_timeout: u16) -> Self {
+        Self {
+            pending: Vec::new(),
+            elapsed: 0,
+            active: Vec::new(),
+            default_timeout,
+        }
+    }
+
+    /// Change the timeout used for combos that don't specify one.
+    pub(crate) fn set_default_timeout(&mut self, timeout: u16) {
+        self.default_timeout = timeout;
+    }
+
+    /// Find a combo that is fully pressed by the pending keys, and whether any other combo could
+    /// still be completed by pressing more keys.
+    fn search(
+        &self,
+        combos: &[Combo],
+        extra: Option<&Action<Keycode>>,
+    ) -> (Option<usize>, bool, u16) {
+        let mut complete = None;
+        let mut extendable = false;
+        let mut timeout = 0;
+
+        for (idx, combo) in combos.iter().enumerate() {
+            if combo.len() == 0 {
+                continue;
+            }
+
+            let actions = self
+                .pending
+                .iter()
+                .map(|(_coord, action)| action)
+                .chain(extra);
+            match combo.matches(actions) {
+                Some(true) => {
+                    complete = complete.or(Some(idx));
+                }
+                Some(false) => {
+                    extendable = true;
+                }
+                None => continue,
+            }
+
+            timeout = timeout.max(combo.timeout.unwrap_or(self.default_timeout));
+        }
+
+        (complete, extendable, timeout)
+    }
+
+    /// Trigger the combo that is fully pressed by the pending keys, or send the pending keys to
+    /// the layout if there isn't one.
+    fn resolve<const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+        combos: &[Combo],
+    ) {
+        if let (Some(idx), _, _) = self.search(combos, None) {
+            let keys = self.pending.iter().map(|(coord, _action)| *coord).collect();
+            if self.active.push((idx, keys)).is_ok() {
+                self.pending.clear();
+                layout.event_with_action((COMBO_ROW, idx as u8), combos[idx].output);
+                return;
+            }
+        }
+
+        for (coord, _action) in self.pending.iter() {
+            layout.event(Event::Press(coord.0, coord.1));
+        }
+        self.pending.clear();
+    }
+
+    /// Process a matrix event. If the event is not part of a combo, it will be sent to the layout.
+    pub(crate) fn event<const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+        combos: &[Combo],
+        event: Event,
+    ) {
+        match event {
+            Event::Press(row, col) => {
+                let action = layout.action_at((row, col));
+                let (complete, extendable, _timeout) = self.search(combos, Some(&action));
+
+                if complete.is_none() && !extendable {
+                    if self.pending.is_empty() {
+                        layout.event(event);
+                    } else {
+                        // The new key can't be used with the pending keys, so resolve them first
+                        self.resolve(layout, combos);
+                        self.event(layout, combos, event);
+                    }
+                    return;
+                }
+
+                if self.pending.is_empty() {
+                    self.elapsed = 0;
+                }
+
+                if self.pending.push(((row, col), action)).is_err() {
+                    self.resolve(layout, combos);
+                    layout.event(event);
+                    return;
+                }
+
+                if complete.is_some() && !extendable {
+                    self.resolve(layout, combos);
+                }
+            }
+            Event::Release(row, col) => {
+                if self
+                    .pending
+                    .iter()
+                    .any(|(coord, _action)| *coord == (row, col))
+                {
+                    // Key was released before the combo could be completed
+                    self.resolve(layout, combos);
+                }
+
+                if let Some(active_idx) = self
+                    .active
+                    .iter()
+                    .position(|(_idx, keys)| keys.contains(&(row, col)))
+                {
+                    // Release the combo once all of its keys have been released
+                    let (idx, keys) = &mut self.active[active_idx];
+                    keys.retain(|coord| *coord != (row, col));
+                    if keys.is_empty() {
+                        layout.event(Event::Release(COMBO_ROW, *idx as u8));
+                        self.active.swap_remove(active_idx);
+                    }
+                } else {
+                    layout.event(event);
+                }
+            }
+        }
+    }
+
+    /// Update the combo timers. This should be called every millisecond.
+    pub(crate) fn tick<const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+        combos: &[Combo],
+    ) {
+        if self.pending.is_empty() {
+            return;
+        }
+
+        self.elapsed = self.elapsed.saturating_add(1);
+
+        let (_complete, _extendable, timeout) = self.search(combos, None);
+        if self.elapsed >= timeout {
+            self.resolve(layout, combos);
+        }
+    }
+}
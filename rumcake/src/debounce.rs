@@ -0,0 +1,159 @@
+//! Debouncing for switch matrices.
+//!
+//! When pressed or released, switches don't change state cleanly: they bounce. A debouncer filters
+//! out these bounces before key events are sent to the layout. Several algorithms are available,
+//! chosen with [`crate::keyboard::KeyboardMatrix::DEBOUNCE_ALGORITHM`]. The names of the
+//! algorithms are based on QMK's implementations. All algorithms use a debounce time measured in
+//! milliseconds, set by [`crate::keyboard::KeyboardMatrix::DEBOUNCE_MS`], so the result does not
+//! depend on how often the matrix is scanned.
+
+use embassy_time::{Duration, Instant};
+use keyberon::layout::Event;
+
+/// Algorithms that can be used to debounce a switch matrix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DebounceAlgorithm {
+    /// Changes are registered once the whole matrix has been stable for the debounce time. This is
+    /// the cheapest algorithm, but pressing keys in quick succession delays all of their events.
+    #[default]
+    DeferGlobal,
+    /// Changes to a key are registered once that key has been stable for the debounce time.
+    DeferPerKey,
+    /// Changes to the keys in a row are registered once that row has been stable for the debounce
+    /// time.
+    DeferPerRow,
+    /// Key presses are registered immediately, and releases are registered once the key has been
+    /// released for the debounce time. This gives the lowest latency for presses, but switches
+    /// that are prone to noise can cause extra key presses.
+    EagerPressDeferRelease,
+}
+
+/// Debouncer for a switch matrix with `C` columns and `R` rows.
+pub struct Debouncer<const C: usize, const R: usize> {
+    algorithm: DebounceAlgorithm,
+    debounce: Duration,
+    /// Debounced state of the matrix.
+    state: [[bool; C]; R],
+    /// Raw state of the matrix, from the last scan.
+    raw: [[bool; C]; R],
+    /// Last time that the raw state of each key changed.
+    raw_changed: [[Instant; C]; R],
+    /// Keys that changed in the debounced state during the last update.
+    changed: [[bool; C]; R],
+}
+
+impl<const C: usize, const R: usize> Debouncer<C, R> {
+    /// Create a new debouncer, where all keys start released.
+    pub fn new(algorithm: DebounceAlgorithm, debounce_ms: u16) -> Self {
+        Self {
+            algorithm,
+            debounce: Duration::from_millis(debounce_ms as u64),
+            state: [[false; C]; R],
+            raw: [[false; C]; R],
+            raw_changed: [[Instant::from_ticks(0); C]; R],
+            changed: [[false; C]; R],
+        }
+    }
+
+    /// Change the debounce time.
+    pub fn set_debounce_ms(&mut self, debounce_ms: u16) {
+        self.debounce = Duration::from_millis(debounce_ms as u64);
+    }
+
+    /// Get the current debounced state of the matrix.
+    pub fn get(&self) -> &[[bool; C]; R] {
+        &self.state
+    }
+
+    fn stable(&self, last_change: Instant, now: Instant) -> bool {
+        now.duration_since(last_change) >= self.debounce
+    }
+
+    /// Update the debouncer with a new scan of the matrix, and iterate over the events generated
+    /// by the update.
+    pub fn events(&mut self, new: [[bool; C]; R]) -> impl Iterator<Item = Event> + '_ {
+        let now = Instant::now();
+
+        for (row, new_row) in new.iter().enumerate() {
+            for (col, pressed) in new_row.iter().enumerate() {
+                if self.raw[row][col] != *pressed {
+                    self.raw[row][col] = *pressed;
+                    self.raw_changed[row][col] = now;
+                }
+            }
+        }
+
+        self.changed = [[false; C]; R];
+
+        match self.algorithm {
+            DebounceAlgorithm::DeferGlobal => {
+                let last_change = self.raw_changed.iter().flatten().max();
+                if self.state != self.raw
+                    && last_change.map_or(true, |last| self.stable(*last, now))
+                {
+                    for row in 0..R {
+                        for col in 0..C {
+                            self.update_key(row, col);
+                        }
+                    }
+                }
+            }
+            DebounceAlgorithm::DeferPerKey => {
+                for row in 0..R {
+                    for col in 0..C {
+                        if self.state[row][col] != self.raw[row][col]
+                            && self.stable(self.raw_changed[row][col], now)
+                        {
+                            self.update_key(row, col);
+                        }
+                    }
+                }
+            }
+            DebounceAlgorithm::DeferPerRow => {
+                for row in 0..R {
+                    let last_change = self.raw_changed[row].iter().max();
+                    if self.state[row] != self.raw[row]
+                        && last_change.map_or(true, |last| self.stable(*last, now))
+                    {
+                        for col in 0..C {
+                            self.update_key(row, col);
+                        }
+                    }
+                }
+            }
+            DebounceAlgorithm::EagerPressDeferRelease => {
+                for row in 0..R {
+                    for col in 0..C {
+                        let pressed = self.raw[row][col];
+                        if self.state[row][col] != pressed
+                            && (pressed || self.stable(self.raw_changed[row][col], now))
+                        {
+                            self.update_key(row, col);
+                        }
+                    }
+                }
+            }
+        }
+
+        self.changed
+            .iter()
+            .zip(self.state.iter())
+            .enumerate()
+            .flat_map(|(row, (changed, state))| {
+                changed.iter().zip(state.iter()).enumerate().filter_map(
+                    move |(col, (changed, pressed))| match (changed, pressed) {
+                        (true, true) => Some(Event::Press(row as u8, col as u8)),
+                        (true, false) => Some(Event::Release(row as u8, col as u8)),
+                        _ => None,
+                    },
+                )
+            })
+    }
+
+    fn update_key(&mut self, row: usize, col: usize) {
+        if self.state[row][col] != self.raw[row][col] {
+            self.state[row][col] = self.raw[row][col];
+            self.changed[row][col] = true;
+        }
+    }
+}
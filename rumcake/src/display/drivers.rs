@@ -87,6 +87,21 @@ macro_rules! on_update_default {
             }
         ));
 
+        // Caps lock
+        let contents = contents.append(text_box!(
+            bounding_box,
+            $text_type,
+            if crate::hw::HOST_LED_STATE
+                .get()
+                .await
+                .contains(crate::hw::LedIndicators::CAPS_LOCK)
+            {
+                "CAPS"
+            } else {
+                ""
+            }
+        ));
+
         embedded_layout::layout::linear::LinearLayout::$direction(contents)
             .with_spacing(embedded_layout::layout::linear::FixedMargin($margin))
             .align_to(
@@ -117,6 +132,8 @@ pub enum Orientation {
 /// - Battery level (BAT): `nrf-ble` must be enabled.
 /// - Mode: `usb` and `bluetooth` enabled at the same time. See
 /// [`rumcake::bluetooth::BluetoothCommand::ToggleOutput`]
+/// - Caps lock (CAPS): shown while the host has turned on the Caps Lock LED. See
+/// [`crate::hw::HOST_LED_STATE`]
 pub async fn on_update_default(
     display: &mut impl DrawTarget<Color = BinaryColor, Error = impl Debug>,
     orientation: Orientation,
@@ -12,9 +12,12 @@ pub mod drivers;
 
 use self::drivers::DisplayDriver;
 use crate::hw::mcu::RawMutex;
+use crate::keyboard::IDLE_STATE;
 
 pub(crate) static OUTPUT_MODE_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
 pub(crate) static BATTERY_LEVEL_LISTENER: Signal<RawMutex, ()> = Signal::new();
+pub(crate) static HOST_LED_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+pub(crate) static IDLE_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
 
 /// A trait that keyboards must implement to use a display.
 pub trait DisplayDevice {
@@ -45,6 +48,22 @@ pub async fn display_task<K: DisplayDevice>(_k: K, mut display: impl DisplayDriv
     display.on_update().await;
 
     loop {
+        // Turn off the display while the keyboard is idle, and wait for it to wake up.
+        if IDLE_STATE.get().await {
+            if display_on {
+                display.turn_off().await;
+                display_on = false;
+            }
+
+            while IDLE_STATE.get().await {
+                IDLE_STATE_LISTENER.wait().await;
+            }
+
+            display.turn_on().await;
+            display_on = true;
+            display.on_update().await;
+        }
+
         let update_fut = async {
             if let Some(ref mut ticker) = ticker {
                 ticker.next().await;
@@ -53,6 +72,8 @@ pub async fn display_task<K: DisplayDevice>(_k: K, mut display: impl DisplayDriv
                 let mut result = select_array([
                     OUTPUT_MODE_STATE_LISTENER.wait(),
                     BATTERY_LEVEL_LISTENER.wait(),
+                    HOST_LED_STATE_LISTENER.wait(),
+                    IDLE_STATE_LISTENER.wait(),
                 ])
                 .await;
                 result.1 += 1;
@@ -70,8 +91,9 @@ pub async fn display_task<K: DisplayDevice>(_k: K, mut display: impl DisplayDriv
             match select(update_fut, timer).await {
                 Either::First(((), idx)) => {
                     match idx {
-                        0 | 1 => {
-                            // Turn the display on in the event of a tick, or change in USB state.
+                        0 | 1 | 3 => {
+                            // Turn the display on in the event of a tick, a change in USB state,
+                            // or a change in the host's LEDs.
                             if !display_on {
                                 display.turn_on().await;
                                 display_on = true;
@@ -0,0 +1,164 @@
+//! Rumcake driver implementation for 24LCxx/AT24Cxx I2C EEPROMs (e.g. 24LC256, AT24C512).
+//!
+//! This driver provides an implementation of [`FlashStorage`](`crate::storage::FlashStorage`),
+//! so an external EEPROM can be used for storage instead of the MCU's internal flash.
+//!
+//! To use this driver for the storage feature, keyboards must implement `At24cxxStorageSettings`,
+//! which is generated by the `keyboard` macro when `storage(driver = "at24cxx")` is used.
+//!
+//! EEPROMs don't need to be erased before they are written to, but the storage task expects a
+//! device that behaves like flash memory. This driver emulates erase regions of
+//! [`REGION_SIZE`] bytes, by filling them with `0xFF`. Only EEPROMs that use 16-bit addresses
+//! (24LC32 and larger) are supported. Smaller EEPROMs can't hold enough regions to be useful.
+//!
+//! The I2C peripheral must implement both the blocking and async versions of
+//! [`embedded_hal_1::i2c::I2c`]. This is needed by the storage task, which occasionally needs
+//! to read a region synchronously before writing to it.
+
+use embassy_time::{Duration, Timer};
+use embedded_hal_async::i2c::{ErrorType, I2c};
+
+/// Size of the erase regions emulated by this driver.
+pub const REGION_SIZE: usize = 512;
+
+/// Maximum time that the EEPROM takes to complete a write cycle.
+const WRITE_CYCLE_TIME: Duration = Duration::from_millis(5);
+
+/// Driver for 24LCxx/AT24Cxx EEPROMs.
+pub struct At24cxx<I: I2c + embedded_hal_1::i2c::I2c> {
+    i2c: I,
+    address: u8,
+    page_size: usize,
+}
+
+impl<I: I2c + embedded_hal_1::i2c::I2c> At24cxx<I> {
+    /// Create a new driver. `page_size` is the number of bytes that the EEPROM can write in a
+    /// single write cycle. Consult the datasheet for your EEPROM (e.g. 64 bytes for the 24LC256,
+    /// 128 bytes for the 24LC512).
+    pub fn new(i2c: I, address: u8, page_size: usize) -> Self {
+        assert!(
+            page_size > 0 && REGION_SIZE % page_size == 0,
+            "EEPROM page size must be a factor of the emulated region size."
+        );
+
+        Self {
+            i2c,
+            address,
+            page_size,
+        }
+    }
+
+    async fn write_page(
+        &mut self,
+        address: u32,
+        bytes: &[u8],
+    ) -> Result<(), <I as ErrorType>::Error> {
+        let mut buf = [0; REGION_SIZE + 2];
+        let [_, _, a1, a0] = address.to_be_bytes();
+        buf[0] = a1;
+        buf[1] = a0;
+        buf[2..(bytes.len() + 2)].copy_from_slice(bytes);
+
+        I2c::write(&mut self.i2c, self.address, &buf[..(bytes.len() + 2)]).await?;
+        Timer::after(WRITE_CYCLE_TIME).await;
+
+        Ok(())
+    }
+
+    /// Emulate an erase of the regions between the given addresses, by filling them with `0xFF`.
+    /// `from` and `to` must be multiples of [`REGION_SIZE`].
+    pub async fn erase(&mut self, from: u32, to: u32) -> Result<(), <I as ErrorType>::Error> {
+        let erased = [0xFF; REGION_SIZE];
+
+        for address in (from..to).step_by(self.page_size) {
+            self.write_page(address, &erased[..self.page_size]).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Write data to the EEPROM, starting at the given address. The data is split up so that
+    /// writes never cross a page boundary.
+    pub async fn write(
+        &mut self,
+        offset: u32,
+        bytes: &[u8],
+    ) -> Result<(), <I as ErrorType>::Error> {
+        let mut address = offset;
+        let mut remaining = bytes;
+
+        while !remaining.is_empty() {
+            let len = (self.page_size - address as usize % self.page_size).min(remaining.len());
+            let (chunk, rest) = remaining.split_at(len);
+
+            self.write_page(address, chunk).await?;
+
+            address += len as u32;
+            remaining = rest;
+        }
+
+        Ok(())
+    }
+
+    /// Read data from the EEPROM, starting at the given address.
+    pub async fn read(
+        &mut self,
+        offset: u32,
+        bytes: &mut [u8],
+    ) -> Result<(), <I as ErrorType>::Error> {
+        let [_, _, a1, a0] = offset.to_be_bytes();
+        I2c::write_read(&mut self.i2c, self.address, &[a1, a0], bytes).await
+    }
+
+    /// Read data from the EEPROM, starting at the given address. This blocks until the read is
+    /// complete.
+    pub fn blocking_read(
+        &mut self,
+        offset: u32,
+        bytes: &mut [u8],
+    ) -> Result<(), <I as ErrorType>::Error> {
+        let [_, _, a1, a0] = offset.to_be_bytes();
+        embedded_hal_1::i2c::I2c::write_read(&mut self.i2c, self.address, &[a1, a0], bytes)
+    }
+}
+
+/// Create an instance of the AT24Cxx driver with the provided I2C peripheral, address and page
+/// size.
+pub fn setup_driver<I: I2c + embedded_hal_1::i2c::I2c>(
+    i2c: I,
+    address: u8,
+    page_size: usize,
+) -> At24cxx<I> {
+    At24cxx::new(i2c, address, page_size)
+}
+
+#[cfg(feature = "storage")]
+/// AT24Cxx storage driver implementations
+pub mod storage {
+    use embedded_hal_async::i2c::{ErrorType, I2c};
+
+    use super::{At24cxx, REGION_SIZE};
+    use crate::storage::FlashStorage;
+
+    impl<I: I2c + embedded_hal_1::i2c::I2c> FlashStorage for At24cxx<I> {
+        type Error = <I as ErrorType>::Error;
+
+        const ERASE_SIZE: usize = REGION_SIZE;
+
+        async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            At24cxx::erase(self, from, to).await
+        }
+
+        async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            At24cxx::write(self, offset, bytes).await
+        }
+
+        async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            At24cxx::read(self, offset, bytes).await
+        }
+
+        fn blocking_read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            At24cxx::blocking_read(self, offset, bytes)
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! Rumcake driver implementations for Cirque's Pinnacle-based trackpads (e.g. TM040040,
+//! TM035035), over I2C.
+//!
+//! This driver provides implementations for
+//! [`PointingDriver`](`crate::pointing::drivers::PointingDriver`).
+//!
+//! To use this driver for the pointing device feature, keyboards must implement
+//! `CirquePinnacleDriverSettings`, which is generated by the `keyboard` macro. The result of
+//! [`setup_driver`] should be passed to a pointing task.
+//!
+//! The trackpad is used in relative mode, so it reports movement like a mouse. Taps and gestures
+//! are not supported.
+
+use embedded_hal_async::i2c::I2c;
+
+const STATUS1: u8 = 0x02;
+const SYS_CONFIG1: u8 = 0x03;
+const FEED_CONFIG1: u8 = 0x04;
+const FEED_CONFIG2: u8 = 0x05;
+const PACKET_BYTE_0: u8 = 0x12;
+
+/// Set in the Status1 register when new data is available.
+const STATUS1_SW_DR: u8 = 0x04;
+
+const READ_MASK: u8 = 0xA0;
+const WRITE_MASK: u8 = 0x80;
+
+/// Driver for Cirque Pinnacle trackpads.
+pub struct CirquePinnacle<I: I2c> {
+    i2c: I,
+    address: u8,
+}
+
+impl<I: I2c> CirquePinnacle<I> {
+    /// Create a new driver. [`CirquePinnacle::init`] must be called before the trackpad can be
+    /// used.
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self { i2c, address }
+    }
+
+    async fn read_registers(&mut self, register: u8, buf: &mut [u8]) -> Result<(), I::Error> {
+        self.i2c
+            .write_read(self.address, &[READ_MASK | register], buf)
+            .await
+    }
+
+    async fn write_register(&mut self, register: u8, value: u8) -> Result<(), I::Error> {
+        self.i2c
+            .write(self.address, &[WRITE_MASK | register, value])
+            .await
+    }
+
+    /// Configure the trackpad to report relative motion.
+    pub async fn init(&mut self) -> Result<(), I::Error> {
+        // Clear any flags, and make sure the trackpad isn't sleeping
+        self.write_register(STATUS1, 0x00).await?;
+        self.write_register(SYS_CONFIG1, 0x00).await?;
+
+        // Disable taps, secondary taps, scrolling and glide extend
+        self.write_register(FEED_CONFIG2, 0x1E).await?;
+
+        // Enable the feed in relative mode
+        self.write_register(FEED_CONFIG1, 0x01).await
+    }
+}
+
+/// Create an instance of the Cirque Pinnacle driver with the provided I2C peripheral and address.
+pub async fn setup_driver<I: I2c>(i2c: I, address: u8) -> CirquePinnacle<I> {
+    let mut driver = CirquePinnacle::new(i2c, address);
+
+    driver.init().await.unwrap();
+
+    driver
+}
+
+#[cfg(feature = "pointing-device")]
+/// Cirque Pinnacle pointing device driver implementations
+pub mod pointing {
+    use embedded_hal_async::i2c::I2c;
+
+    use super::{CirquePinnacle, PACKET_BYTE_0, STATUS1, STATUS1_SW_DR};
+    use crate::pointing::drivers::PointingDriver;
+    use crate::pointing::{PointingDevice, PointingMotion};
+
+    impl<I: I2c, K: PointingDevice> PointingDriver<K> for CirquePinnacle<I> {
+        type DriverError = I::Error;
+
+        async fn read_motion(&mut self) -> Result<PointingMotion, Self::DriverError> {
+            let mut status = [0];
+            self.read_registers(STATUS1, &mut status).await?;
+            if status[0] & STATUS1_SW_DR == 0 {
+                return Ok(PointingMotion::default());
+            }
+
+            let mut packet = [0; 3];
+            self.read_registers(PACKET_BYTE_0, &mut packet).await?;
+            self.write_register(STATUS1, 0x00).await?;
+
+            // Deltas are 9-bit values, with the sign bits stored in the first byte
+            let x = if packet[0] & 0x10 != 0 {
+                packet[1] as i16 - 256
+            } else {
+                packet[1] as i16
+            };
+            let y = if packet[0] & 0x20 != 0 {
+                packet[2] as i16 - 256
+            } else {
+                packet[2] as i16
+            };
+
+            // Positive Y values move up on the trackpad
+            Ok(PointingMotion { x, y: -y })
+        }
+
+        /// The resolution of Cirque trackpads can not be changed in relative mode, so this does
+        /// nothing.
+        async fn set_cpi(&mut self, _cpi: u16) -> Result<(), Self::DriverError> {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,60 @@
+//! Rumcake driver implementations for the Microchip MCP23017 16-bit I/O expander, over I2C.
+//!
+//! This driver provides an implementation for
+//! [`GpioExpander`](`crate::matrix::expander::GpioExpander`), so it can be used to scan a matrix
+//! with an [`ExpanderMatrix`](`crate::matrix::expander::ExpanderMatrix`).
+//!
+//! Pins `GPA0` to `GPA7` are pins 0 to 7, and `GPB0` to `GPB7` are pins 8 to 15. Internal pull-ups
+//! are enabled on all inputs.
+
+use embedded_hal_async::i2c::I2c;
+
+use crate::matrix::expander::GpioExpander;
+
+// Register addresses, with IOCON.BANK = 0 (the default). Registers for port B directly follow
+// the registers for port A, so both ports can be accessed in one transaction.
+const IODIRA: u8 = 0x00;
+const GPPUA: u8 = 0x0C;
+const GPIOA: u8 = 0x12;
+const OLATA: u8 = 0x14;
+
+/// Driver for the MCP23017.
+pub struct Mcp23017<I: I2c> {
+    i2c: I,
+    address: u8,
+}
+
+impl<I: I2c> Mcp23017<I> {
+    /// Create a new driver. The default address of the MCP23017 is `0x20`, but it can be changed
+    /// to any address up to `0x27` using the `A0`-`A2` pins.
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self { i2c, address }
+    }
+
+    async fn write_registers(&mut self, register: u8, value: u16) -> Result<(), I::Error> {
+        let [a, b] = value.to_le_bytes();
+        self.i2c.write(self.address, &[register, a, b]).await
+    }
+}
+
+impl<I: I2c> GpioExpander for Mcp23017<I> {
+    type Error = I::Error;
+
+    async fn configure(&mut self, outputs: u16) -> Result<(), Self::Error> {
+        // A set bit in IODIR configures the pin as an input
+        self.write_registers(IODIRA, !outputs).await?;
+        self.write_registers(GPPUA, !outputs).await
+    }
+
+    async fn write_outputs(&mut self, value: u16) -> Result<(), Self::Error> {
+        self.write_registers(OLATA, value).await
+    }
+
+    async fn read_inputs(&mut self) -> Result<u16, Self::Error> {
+        let mut buf = [0; 2];
+        self.i2c
+            .write_read(self.address, &[GPIOA], &mut buf)
+            .await?;
+        Ok(u16::from_le_bytes(buf))
+    }
+}
@@ -2,15 +2,33 @@
 
 use embedded_io_async::{Read, Write};
 
+#[cfg(feature = "at24cxx")]
+pub mod at24cxx;
+
+#[cfg(feature = "cirque-pinnacle")]
+pub mod cirque_pinnacle;
+
 #[cfg(feature = "is31fl3731")]
 pub mod is31fl3731;
 
+#[cfg(feature = "mcp23017")]
+pub mod mcp23017;
+
 #[cfg(feature = "nrf-ble")]
 pub mod nrf_ble;
 
+#[cfg(feature = "pca9555")]
+pub mod pca9555;
+
+#[cfg(feature = "pmw33xx")]
+pub mod pmw33xx;
+
 #[cfg(feature = "ssd1306")]
 pub mod ssd1306;
 
+#[cfg(feature = "w25qxx")]
+pub mod w25qxx;
+
 #[cfg(feature = "ws2812-bitbang")]
 pub mod ws2812_bitbang;
 
@@ -0,0 +1,62 @@
+//! Rumcake driver implementations for the NXP PCA9555 (and compatible TCA9555) 16-bit I/O
+//! expander, over I2C.
+//!
+//! This driver provides an implementation for
+//! [`GpioExpander`](`crate::matrix::expander::GpioExpander`), so it can be used to scan a matrix
+//! with an [`ExpanderMatrix`](`crate::matrix::expander::ExpanderMatrix`).
+//!
+//! Pins `IO0_0` to `IO0_7` are pins 0 to 7, and `IO1_0` to `IO1_7` are pins 8 to 15. The inputs
+//! of the PCA9555 have 100kΩ pull-ups, which may be too weak for long matrix traces. In that
+//! case, external pull-up resistors should be added to the columns.
+
+use embedded_hal_async::i2c::I2c;
+
+use crate::matrix::expander::GpioExpander;
+
+// Registers come in pairs for port 0 and port 1. After accessing the register for port 0, the
+// device moves on to the register for port 1, so both ports can be accessed in one transaction.
+const INPUT_PORT_0: u8 = 0x00;
+const OUTPUT_PORT_0: u8 = 0x02;
+const CONFIGURATION_PORT_0: u8 = 0x06;
+
+/// Driver for the PCA9555.
+pub struct Pca9555<I: I2c> {
+    i2c: I,
+    address: u8,
+}
+
+impl<I: I2c> Pca9555<I> {
+    /// Create a new driver. The address of the PCA9555 can be set to any address from `0x20` to
+    /// `0x27` using the `A0`-`A2` pins.
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self { i2c, address }
+    }
+
+    async fn write_registers(&mut self, register: u8, value: u16) -> Result<(), I::Error> {
+        let [port0, port1] = value.to_le_bytes();
+        self.i2c
+            .write(self.address, &[register, port0, port1])
+            .await
+    }
+}
+
+impl<I: I2c> GpioExpander for Pca9555<I> {
+    type Error = I::Error;
+
+    async fn configure(&mut self, outputs: u16) -> Result<(), Self::Error> {
+        // A set bit in the configuration register configures the pin as an input
+        self.write_registers(CONFIGURATION_PORT_0, !outputs).await
+    }
+
+    async fn write_outputs(&mut self, value: u16) -> Result<(), Self::Error> {
+        self.write_registers(OUTPUT_PORT_0, value).await
+    }
+
+    async fn read_inputs(&mut self) -> Result<u16, Self::Error> {
+        let mut buf = [0; 2];
+        self.i2c
+            .write_read(self.address, &[INPUT_PORT_0], &mut buf)
+            .await?;
+        Ok(u16::from_le_bytes(buf))
+    }
+}
@@ -0,0 +1,259 @@
+//! Rumcake driver implementations for PixArt's PMW3360 and PMW3389 optical sensors.
+//!
+//! This driver provides implementations for
+//! [`PointingDriver`](`crate::pointing::drivers::PointingDriver`).
+//!
+//! To use this driver for the pointing device feature, keyboards must implement
+//! `PMW33xxDriverSettings`, which is generated by the `keyboard` macro. The result of
+//! [`setup_driver`] should be passed to a pointing task.
+//!
+//! These sensors require a firmware blob to be uploaded to their SROM when they start. The
+//! firmware is provided by PixArt, and is not distributed with rumcake. If no firmware is
+//! provided, the sensor will run without it, which may reduce tracking performance.
+
+use core::convert::Infallible;
+
+use defmt::{debug, warn};
+use embassy_time::{Duration, Timer};
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal_async::spi::SpiBus;
+
+const PRODUCT_ID: u8 = 0x00;
+const MOTION: u8 = 0x02;
+const DELTA_Y_H: u8 = 0x06;
+const RESOLUTION_L: u8 = 0x0E;
+const CONFIG1: u8 = 0x0F;
+const CONFIG2: u8 = 0x10;
+const SROM_ENABLE: u8 = 0x13;
+const SROM_ID: u8 = 0x2A;
+const POWER_UP_RESET: u8 = 0x3A;
+const MOTION_BURST: u8 = 0x50;
+const SROM_LOAD_BURST: u8 = 0x62;
+
+/// Sensors supported by this driver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PMW33xxVariant {
+    /// PixArt PMW3360. Supports a resolution of 100-12000 CPI, in steps of 100.
+    PMW3360,
+    /// PixArt PMW3389. Supports a resolution of 50-16000 CPI, in steps of 50.
+    PMW3389,
+}
+
+impl PMW33xxVariant {
+    fn product_id(self) -> u8 {
+        match self {
+            PMW33xxVariant::PMW3360 => 0x42,
+            PMW33xxVariant::PMW3389 => 0x47,
+        }
+    }
+}
+
+/// Driver for PMW3360 and PMW3389 sensors, using an SPI bus and a chip select pin.
+pub struct PMW33xx<S: SpiBus, CS: OutputPin<Error = Infallible>> {
+    spi: S,
+    cs: CS,
+    variant: PMW33xxVariant,
+    in_burst: bool,
+}
+
+impl<S: SpiBus, CS: OutputPin<Error = Infallible>> PMW33xx<S, CS> {
+    /// Create a new driver. [`PMW33xx::init`] must be called before the sensor can be used.
+    pub fn new(spi: S, cs: CS, variant: PMW33xxVariant) -> Self {
+        Self {
+            spi,
+            cs,
+            variant,
+            in_burst: false,
+        }
+    }
+
+    async fn write_register(&mut self, register: u8, value: u8) -> Result<(), S::Error> {
+        // Writing to any register exits burst mode
+        self.in_burst = false;
+
+        self.cs.set_low().unwrap();
+        let result = async {
+            self.spi.write(&[register | 0x80, value]).await?;
+            self.spi.flush().await
+        }
+        .await;
+        Timer::after(Duration::from_micros(35)).await; // tSCLK-NCS (write)
+        self.cs.set_high().unwrap();
+        Timer::after(Duration::from_micros(145)).await; // tSWW/tSWR, minus tSCLK-NCS
+
+        result
+    }
+
+    async fn read_register(&mut self, register: u8) -> Result<u8, S::Error> {
+        let mut buf = [0];
+
+        self.cs.set_low().unwrap();
+        let result = async {
+            self.spi.write(&[register & 0x7F]).await?;
+            self.spi.flush().await?;
+            Timer::after(Duration::from_micros(160)).await; // tSRAD
+            self.spi.read(&mut buf).await?;
+            self.spi.flush().await
+        }
+        .await;
+        Timer::after(Duration::from_micros(1)).await; // tSCLK-NCS (read)
+        self.cs.set_high().unwrap();
+        Timer::after(Duration::from_micros(19)).await; // tSRW/tSRR, minus tSCLK-NCS
+
+        result.map(|_| buf[0])
+    }
+
+    async fn upload_srom(&mut self, firmware: &[u8]) -> Result<(), S::Error> {
+        self.write_register(CONFIG2, 0x00).await?;
+        self.write_register(SROM_ENABLE, 0x1D).await?;
+        Timer::after(Duration::from_millis(10)).await;
+        self.write_register(SROM_ENABLE, 0x18).await?;
+
+        self.cs.set_low().unwrap();
+        let result = async {
+            self.spi.write(&[SROM_LOAD_BURST | 0x80]).await?;
+            self.spi.flush().await?;
+            Timer::after(Duration::from_micros(15)).await;
+            for byte in firmware {
+                self.spi.write(&[*byte]).await?;
+                self.spi.flush().await?;
+                Timer::after(Duration::from_micros(15)).await;
+            }
+            Ok(())
+        }
+        .await;
+        self.cs.set_high().unwrap();
+        Timer::after(Duration::from_micros(200)).await;
+        result?;
+
+        let srom_id = self.read_register(SROM_ID).await?;
+        if srom_id == 0 {
+            warn!("[PMW33XX] SROM firmware upload failed");
+        } else {
+            debug!("[PMW33XX] Uploaded SROM firmware, ID: {=u8:#X}", srom_id);
+        }
+
+        Ok(())
+    }
+
+    /// Reset the sensor, and upload the SROM firmware if provided.
+    pub async fn init(&mut self, firmware: Option<&[u8]>) -> Result<(), S::Error> {
+        // Reset the SPI port
+        self.cs.set_high().unwrap();
+        Timer::after(Duration::from_micros(1)).await;
+        self.cs.set_low().unwrap();
+        Timer::after(Duration::from_micros(1)).await;
+        self.cs.set_high().unwrap();
+
+        self.write_register(POWER_UP_RESET, 0x5A).await?;
+        Timer::after(Duration::from_millis(50)).await;
+
+        // Clear the motion registers
+        for register in MOTION..=DELTA_Y_H {
+            self.read_register(register).await?;
+        }
+
+        if let Some(firmware) = firmware {
+            self.upload_srom(firmware).await?;
+        }
+
+        // Disable rest mode, and use the same resolution for both axes
+        self.write_register(CONFIG2, 0x00).await?;
+
+        let product_id = self.read_register(PRODUCT_ID).await?;
+        if product_id != self.variant.product_id() {
+            warn!(
+                "[PMW33XX] Unexpected product ID: {=u8:#X}. Check the wiring of your sensor.",
+                product_id
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Create an instance of the PMW33xx driver with the provided SPI bus and chip select pin. If
+/// `firmware` is provided, it will be uploaded to the sensor's SROM.
+pub async fn setup_driver<S: SpiBus, CS: OutputPin<Error = Infallible>>(
+    spi: S,
+    cs: CS,
+    variant: PMW33xxVariant,
+    firmware: Option<&[u8]>,
+) -> PMW33xx<S, CS> {
+    let mut driver = PMW33xx::new(spi, cs, variant);
+
+    driver.init(firmware).await.unwrap();
+
+    driver
+}
+
+#[cfg(feature = "pointing-device")]
+/// PMW33xx pointing device driver implementations
+pub mod pointing {
+    use core::convert::Infallible;
+
+    use embassy_time::{Duration, Timer};
+    use embedded_hal::digital::v2::OutputPin;
+    use embedded_hal_async::spi::SpiBus;
+
+    use super::{PMW33xx, PMW33xxVariant, CONFIG1, MOTION_BURST, RESOLUTION_L};
+    use crate::pointing::drivers::PointingDriver;
+    use crate::pointing::{PointingDevice, PointingMotion};
+
+    impl<S: SpiBus, CS: OutputPin<Error = Infallible>, K: PointingDevice> PointingDriver<K>
+        for PMW33xx<S, CS>
+    {
+        type DriverError = S::Error;
+
+        async fn read_motion(&mut self) -> Result<PointingMotion, Self::DriverError> {
+            if !self.in_burst {
+                self.write_register(MOTION_BURST, 0x00).await?;
+                self.in_burst = true;
+            }
+
+            // Only the motion, observation and delta registers are read from the burst
+            let mut buf = [0; 6];
+            self.cs.set_low().unwrap();
+            let result = async {
+                self.spi.write(&[MOTION_BURST]).await?;
+                self.spi.flush().await?;
+                Timer::after(Duration::from_micros(35)).await; // tSRAD_MOTBR
+                self.spi.read(&mut buf).await?;
+                self.spi.flush().await
+            }
+            .await;
+            self.cs.set_high().unwrap();
+            Timer::after(Duration::from_micros(1)).await; // tBEXIT
+
+            if result.is_err() {
+                self.in_burst = false;
+                result?;
+            }
+
+            // Ignore motion if there is none, or if the sensor has been lifted
+            if buf[0] & 0x80 == 0 || buf[0] & 0x08 != 0 {
+                return Ok(PointingMotion::default());
+            }
+
+            // Burst data starts with the motion and observation registers, followed by the deltas
+            Ok(PointingMotion {
+                x: i16::from_le_bytes([buf[2], buf[3]]),
+                y: i16::from_le_bytes([buf[4], buf[5]]),
+            })
+        }
+
+        async fn set_cpi(&mut self, cpi: u16) -> Result<(), Self::DriverError> {
+            match self.variant {
+                PMW33xxVariant::PMW3360 => {
+                    let value = (cpi.clamp(100, 12000) / 100 - 1) as u8;
+                    self.write_register(CONFIG1, value).await
+                }
+                PMW33xxVariant::PMW3389 => {
+                    let [low, high] = (cpi.clamp(50, 16000) / 50).to_le_bytes();
+                    self.write_register(RESOLUTION_L, low).await?;
+                    self.write_register(RESOLUTION_L + 1, high).await
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! Rumcake driver implementation for Winbond's W25Qxx SPI NOR flash chips (e.g. W25Q16,
+//! W25Q64, W25Q128).
+//!
+//! This driver provides an implementation of [`FlashStorage`](`crate::storage::FlashStorage`),
+//! so an external flash chip can be used for storage instead of the MCU's internal flash.
+//!
+//! To use this driver for the storage feature, keyboards must implement `W25QxxStorageSettings`,
+//! which is generated by the `keyboard` macro when `storage(driver = "w25qxx")` is used.
+//!
+//! Only 3-byte addressing is supported, so chips larger than 128Mbit (16MB) can only use their
+//! first 16MB for storage.
+//!
+//! The SPI device must implement both the blocking and async versions of
+//! [`embedded_hal_1::spi::SpiDevice`]. This is needed by the storage task, which occasionally
+//! needs to read a sector synchronously before writing to it.
+
+use defmt::{debug, warn};
+use embassy_time::{Duration, Timer};
+use embedded_hal_async::spi::{ErrorType, Operation, SpiDevice};
+
+const WRITE_ENABLE: u8 = 0x06;
+const READ_STATUS1: u8 = 0x05;
+const PAGE_PROGRAM: u8 = 0x02;
+const READ_DATA: u8 = 0x03;
+const SECTOR_ERASE: u8 = 0x20;
+const RELEASE_POWER_DOWN: u8 = 0xAB;
+const JEDEC_ID: u8 = 0x9F;
+
+/// Set in the Status Register-1 while a program or erase operation is in progress.
+const STATUS1_BUSY: u8 = 0x01;
+
+/// Winbond's JEDEC manufacturer ID.
+const WINBOND_MANUFACTURER_ID: u8 = 0xEF;
+
+/// Maximum number of bytes that can be written with a single page program instruction.
+pub const PAGE_SIZE: usize = 256;
+
+/// Size of the smallest region that can be erased on the chip (a 4KB sector).
+pub const SECTOR_SIZE: usize = 4096;
+
+/// Driver for W25Qxx flash chips.
+pub struct W25Qxx<S: SpiDevice + embedded_hal_1::spi::SpiDevice> {
+    spi: S,
+}
+
+fn address_bytes(address: u32) -> [u8; 3] {
+    let [_, a2, a1, a0] = address.to_be_bytes();
+    [a2, a1, a0]
+}
+
+impl<S: SpiDevice + embedded_hal_1::spi::SpiDevice> W25Qxx<S> {
+    /// Create a new driver. [`W25Qxx::init`] should be called before the flash chip is used.
+    pub fn new(spi: S) -> Self {
+        Self { spi }
+    }
+
+    /// Wake the flash chip up, in case it was left in power-down mode, and check its JEDEC ID.
+    pub async fn init(&mut self) -> Result<(), <S as ErrorType>::Error> {
+        SpiDevice::write(&mut self.spi, &[RELEASE_POWER_DOWN]).await?;
+        Timer::after(Duration::from_micros(3)).await; // tRES1
+
+        let mut id = [0; 3];
+        SpiDevice::transaction(
+            &mut self.spi,
+            &mut [Operation::Write(&[JEDEC_ID]), Operation::Read(&mut id)],
+        )
+        .await?;
+
+        if id[0] != WINBOND_MANUFACTURER_ID {
+            warn!(
+                "[W25QXX] Unexpected manufacturer ID: {=u8:#X}. The flash chip may not be supported.",
+                id[0]
+            );
+        } else {
+            debug!(
+                "[W25QXX] Found flash chip, device ID: {=u8:#X} {=u8:#X}",
+                id[1], id[2]
+            );
+        }
+
+        self.wait_until_ready().await
+    }
+
+    async fn write_enable(&mut self) -> Result<(), <S as ErrorType>::Error> {
+        SpiDevice::write(&mut self.spi, &[WRITE_ENABLE]).await
+    }
+
+    async fn wait_until_ready(&mut self) -> Result<(), <S as ErrorType>::Error> {
+        let mut status = [0];
+
+        loop {
+            SpiDevice::transaction(
+                &mut self.spi,
+                &mut [
+                    Operation::Write(&[READ_STATUS1]),
+                    Operation::Read(&mut status),
+                ],
+            )
+            .await?;
+
+            if status[0] & STATUS1_BUSY == 0 {
+                return Ok(());
+            }
+
+            Timer::after(Duration::from_micros(100)).await;
+        }
+    }
+
+    /// Erase the sectors between the given addresses. `from` and `to` must be multiples of
+    /// [`SECTOR_SIZE`].
+    pub async fn erase(&mut self, from: u32, to: u32) -> Result<(), <S as ErrorType>::Error> {
+        for address in (from..to).step_by(SECTOR_SIZE) {
+            self.write_enable().await?;
+            let [a2, a1, a0] = address_bytes(address);
+            SpiDevice::write(&mut self.spi, &[SECTOR_ERASE, a2, a1, a0]).await?;
+            self.wait_until_ready().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Write data to the flash chip, starting at the given address. The data is split up so that
+    /// page program instructions never cross a page boundary.
+    pub async fn write(
+        &mut self,
+        offset: u32,
+        bytes: &[u8],
+    ) -> Result<(), <S as ErrorType>::Error> {
+        let mut address = offset;
+        let mut remaining = bytes;
+
+        while !remaining.is_empty() {
+            let len = (PAGE_SIZE - address as usize % PAGE_SIZE).min(remaining.len());
+            let (chunk, rest) = remaining.split_at(len);
+
+            self.write_enable().await?;
+            let [a2, a1, a0] = address_bytes(address);
+            SpiDevice::transaction(
+                &mut self.spi,
+                &mut [
+                    Operation::Write(&[PAGE_PROGRAM, a2, a1, a0]),
+                    Operation::Write(chunk),
+                ],
+            )
+            .await?;
+            self.wait_until_ready().await?;
+
+            address += len as u32;
+            remaining = rest;
+        }
+
+        Ok(())
+    }
+
+    /// Read data from the flash chip, starting at the given address.
+    pub async fn read(
+        &mut self,
+        offset: u32,
+        bytes: &mut [u8],
+    ) -> Result<(), <S as ErrorType>::Error> {
+        let [a2, a1, a0] = address_bytes(offset);
+        SpiDevice::transaction(
+            &mut self.spi,
+            &mut [
+                Operation::Write(&[READ_DATA, a2, a1, a0]),
+                Operation::Read(bytes),
+            ],
+        )
+        .await
+    }
+
+    /// Read data from the flash chip, starting at the given address. This blocks until the read
+    /// is complete.
+    pub fn blocking_read(
+        &mut self,
+        offset: u32,
+        bytes: &mut [u8],
+    ) -> Result<(), <S as ErrorType>::Error> {
+        let [a2, a1, a0] = address_bytes(offset);
+        embedded_hal_1::spi::SpiDevice::transaction(
+            &mut self.spi,
+            &mut [
+                Operation::Write(&[READ_DATA, a2, a1, a0]),
+                Operation::Read(bytes),
+            ],
+        )
+    }
+}
+
+/// Create an instance of the W25Qxx driver with the provided SPI device.
+pub async fn setup_driver<S: SpiDevice + embedded_hal_1::spi::SpiDevice>(spi: S) -> W25Qxx<S> {
+    let mut driver = W25Qxx::new(spi);
+
+    driver.init().await.unwrap();
+
+    driver
+}
+
+#[cfg(feature = "storage")]
+/// W25Qxx storage driver implementations
+pub mod storage {
+    use embedded_hal_async::spi::{ErrorType, SpiDevice};
+
+    use super::{W25Qxx, SECTOR_SIZE};
+    use crate::storage::FlashStorage;
+
+    impl<S: SpiDevice + embedded_hal_1::spi::SpiDevice> FlashStorage for W25Qxx<S> {
+        type Error = <S as ErrorType>::Error;
+
+        const ERASE_SIZE: usize = SECTOR_SIZE;
+
+        async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            W25Qxx::erase(self, from, to).await
+        }
+
+        async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            W25Qxx::write(self, offset, bytes).await
+        }
+
+        async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            W25Qxx::read(self, offset, bytes).await
+        }
+
+        fn blocking_read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            W25Qxx::blocking_read(self, offset, bytes)
+        }
+    }
+}
@@ -0,0 +1,321 @@
+//! Support for recording and playing back macros on the keyboard.
+//!
+//! After pressing [`Keycode::DynamicMacroRecord`], the keys that are sent to the host are recorded
+//! into RAM, until [`Keycode::DynamicMacroStop`] or a record key is pressed. The recorded keys can
+//! then be played back using [`Keycode::DynamicMacroPlay`]. The behaviour of dynamic macros is
+//! based on QMK's implementation, and can be configured using
+//! [`crate::keyboard::KeyboardLayout::DYNAMIC_MACRO_CONFIG`].
+//!
+//! Recorded macros are lost when the keyboard is powered off. If you are using Via, a recorded
+//! macro can be saved into the Via macro buffer instead, by enabling
+//! [`DynamicMacroConfig::save_to_via`].
+//!
+//! [`Keycode::DynamicMacroRecord`]: crate::keyboard::Keycode::DynamicMacroRecord
+//! [`Keycode::DynamicMacroStop`]: crate::keyboard::Keycode::DynamicMacroStop
+//! [`Keycode::DynamicMacroPlay`]: crate::keyboard::Keycode::DynamicMacroPlay
+
+use defmt::{info, warn};
+use embassy_time::{Duration, Instant};
+use heapless::Vec;
+use usbd_human_interface_device::page::Keyboard as KeyboardKeycode;
+
+/// Number of macros that can be recorded. This matches QMK.
+pub const MAX_DYNAMIC_MACROS: usize = 2;
+
+/// Maximum number of key presses and releases that can be recorded in a single macro.
+pub const MAX_DYNAMIC_MACRO_EVENTS: usize = 128;
+
+/// Options that control how dynamic macros are recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DynamicMacroConfig {
+    /// Whether the time between key presses and releases is recorded. If this is `false`, the
+    /// keys of a macro are played back as fast as possible.
+    pub record_delays: bool,
+
+    /// Whether a recorded macro should also be saved to the Via macro buffer, replacing the Via
+    /// macro with the same index. This requires the `via` feature, and a macro buffer (see
+    /// [`crate::via::setup_macro_buffer`]). If you are using storage, the macro will be kept after
+    /// the keyboard is powered off, and can be played back using Via's macro keycodes.
+    pub save_to_via: bool,
+}
+
+impl DynamicMacroConfig {
+    /// Create a new dynamic macro config, with the default options. Delays are not recorded, and
+    /// macros are not saved to Via.
+    pub const fn new() -> Self {
+        Self {
+            record_delays: false,
+            save_to_via: false,
+        }
+    }
+}
+
+impl Default for DynamicMacroConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A key press or release in a recorded macro.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct MacroEvent {
+    keycode: KeyboardKeycode,
+    pressed: bool,
+
+    /// Time (in milliseconds) since the previous event.
+    delay: u16,
+}
+
+/// Convert a recorded macro to the format used by Via's macro buffer. Returns the number of bytes
+/// written to `buf`, or `None` if the macro does not fit.
+#[cfg(feature = "via")]
+pub(crate) fn encode_via_macro(events: &[MacroEvent], buf: &mut [u8]) -> Option<usize> {
+    // Bytes used by QMK's macro format
+    const SS_QMK_PREFIX: u8 = 1;
+    const SS_DOWN_CODE: u8 = 2;
+    const SS_UP_CODE: u8 = 3;
+    const SS_DELAY_CODE: u8 = 4;
+
+    let mut encoded = Vec::<u8, 12>::new();
+    let mut len = 0;
+
+    for event in events {
+        encoded.clear();
+
+        if event.delay > 0 {
+            let _ = encoded.extend_from_slice(&[SS_QMK_PREFIX, SS_DELAY_CODE]);
+            let mut digits = [0; 5];
+            let mut delay = event.delay;
+            let mut count = 0;
+            while delay > 0 {
+                digits[count] = b'0' + (delay % 10) as u8;
+                delay /= 10;
+                count += 1;
+            }
+            for digit in digits[..count].iter().rev() {
+                let _ = encoded.push(*digit);
+            }
+            let _ = encoded.push(b'|');
+        }
+
+        let code = if event.pressed {
+            SS_DOWN_CODE
+        } else {
+            SS_UP_CODE
+        };
+        let _ = encoded.extend_from_slice(&[SS_QMK_PREFIX, code, event.keycode as u8]);
+
+        buf.get_mut(len..(len + encoded.len()))?
+            .copy_from_slice(&encoded);
+        len += encoded.len();
+    }
+
+    Some(len)
+}
+
+/// Channel used to send recorded macros to the Via task, so that they can be saved to the Via
+/// macro buffer.
+#[cfg(feature = "via")]
+pub(crate) static DYNAMIC_MACRO_SAVE_CHANNEL: embassy_sync::channel::Channel<
+    crate::hw::mcu::RawMutex,
+    (u8, Vec<MacroEvent, MAX_DYNAMIC_MACRO_EVENTS>),
+    1,
+> = embassy_sync::channel::Channel::new();
+
+/// Records the keycodes collected from the layout, and plays back recorded macros.
+pub(crate) struct DynamicMacroProcessor {
+    macros: [Vec<MacroEvent, MAX_DYNAMIC_MACRO_EVENTS>; MAX_DYNAMIC_MACROS],
+
+    /// Index of the macro that is being recorded.
+    recording: Option<u8>,
+
+    /// Keycodes collected from the layout on the previous tick.
+    last_keys: Vec<KeyboardKeycode, 24>,
+
+    /// Time of the last recorded event.
+    last_event: Instant,
+
+    /// Index of the macro that is being played back, and the index of its next event.
+    playing: Option<(u8, usize)>,
+
+    /// Keys held by the macro that is being played back.
+    play_keys: Vec<KeyboardKeycode, 24>,
+
+    /// Time that the next event of the macro being played back should be applied.
+    next_event_at: Instant,
+
+    /// Report that should be sent next, if the macro's keys have changed.
+    pending_report: Option<Vec<KeyboardKeycode, 24>>,
+}
+
+impl DynamicMacroProcessor {
+    pub(crate) fn new() -> Self {
+        Self {
+            macros: [Vec::new(), Vec::new()],
+            recording: None,
+            last_keys: Vec::new(),
+            last_event: Instant::now(),
+            playing: None,
+            play_keys: Vec::new(),
+            next_event_at: Instant::now(),
+            pending_report: None,
+        }
+    }
+
+    /// Start recording a macro. If a macro is already being recorded, recording is stopped
+    /// instead, like QMK.
+    pub(crate) fn record(&mut self, config: &DynamicMacroConfig, idx: u8) {
+        if self.recording.is_some() {
+            self.stop(config);
+            return;
+        }
+
+        if self.playing.is_some() {
+            return;
+        }
+
+        if let Some(events) = self.macros.get_mut(idx as usize) {
+            info!("[DYNAMIC_MACROS] Recording macro {}", idx);
+            events.clear();
+            self.recording = Some(idx);
+            self.last_event = Instant::now();
+        }
+    }
+
+    /// Stop recording the current macro.
+    pub(crate) fn stop(&mut self, config: &DynamicMacroConfig) {
+        let Some(idx) = self.recording.take() else {
+            return;
+        };
+
+        info!(
+            "[DYNAMIC_MACROS] Recorded macro {} ({} events)",
+            idx,
+            self.macros[idx as usize].len()
+        );
+
+        #[cfg(feature = "via")]
+        if config.save_to_via
+            && DYNAMIC_MACRO_SAVE_CHANNEL
+                .try_send((idx, self.macros[idx as usize].clone()))
+                .is_err()
+        {
+            warn!("[DYNAMIC_MACROS] Could not save macro {} to Via.", idx);
+        }
+
+        #[cfg(not(feature = "via"))]
+        let _ = config;
+    }
+
+    /// Start playing back a recorded macro. Macros can't be played back while one is being
+    /// recorded.
+    pub(crate) fn play(&mut self, idx: u8) {
+        if self.recording.is_some() || self.playing.is_some() {
+            return;
+        }
+
+        if self
+            .macros
+            .get(idx as usize)
+            .is_some_and(|events| !events.is_empty())
+        {
+            self.playing = Some((idx, 0));
+            self.play_keys.clear();
+            self.next_event_at = Instant::now();
+        }
+    }
+
+    /// Record the keycodes collected from the layout, if a macro is being recorded.
+    pub(crate) fn process(&mut self, config: &DynamicMacroConfig, keys: &Vec<KeyboardKeycode, 24>) {
+        if let Some(idx) = self.recording {
+            let released = self
+                .last_keys
+                .iter()
+                .filter(|k| !keys.contains(k))
+                .map(|k| (*k, false));
+            let pressed = keys
+                .iter()
+                .filter(|k| !self.last_keys.contains(k))
+                .map(|k| (*k, true));
+
+            let mut full = false;
+            for (keycode, pressed) in released.chain(pressed) {
+                // The first event is played back immediately
+                let delay = if config.record_delays && !self.macros[idx as usize].is_empty() {
+                    self.last_event.elapsed().as_millis().min(u16::MAX as u64) as u16
+                } else {
+                    0
+                };
+                self.last_event = Instant::now();
+
+                if self.macros[idx as usize]
+                    .push(MacroEvent {
+                        keycode,
+                        pressed,
+                        delay,
+                    })
+                    .is_err()
+                {
+                    full = true;
+                    break;
+                }
+            }
+
+            if full {
+                warn!(
+                    "[DYNAMIC_MACROS] Macro {} is full, stopping recording.",
+                    idx
+                );
+                self.stop(config);
+            }
+        }
+
+        self.last_keys.clone_from(keys);
+    }
+
+    /// Check if a macro is being played back. While this is `true`, the reports from
+    /// [`DynamicMacroProcessor::peek`] should be sent instead of the layout's keys.
+    pub(crate) fn is_playing(&self) -> bool {
+        self.playing.is_some() || self.pending_report.is_some()
+    }
+
+    /// Get the next report that should be sent while a macro is being played back. This should
+    /// be called every tick.
+    pub(crate) fn peek(&mut self) -> Option<&Vec<KeyboardKeycode, 24>> {
+        if self.pending_report.is_none() {
+            if let Some((idx, pos)) = self.playing {
+                if Instant::now() >= self.next_event_at {
+                    let events = &self.macros[idx as usize];
+                    if let Some(event) = events.get(pos) {
+                        if event.pressed {
+                            if !self.play_keys.contains(&event.keycode) {
+                                let _ = self.play_keys.push(event.keycode);
+                            }
+                        } else {
+                            self.play_keys.retain(|k| *k != event.keycode);
+                        }
+
+                        self.playing = Some((idx, pos + 1));
+                        if let Some(next) = events.get(pos + 1) {
+                            self.next_event_at =
+                                Instant::now() + Duration::from_millis(next.delay as u64);
+                        }
+                    } else {
+                        // Release any keys that are still held at the end of the macro
+                        self.playing = None;
+                        self.play_keys.clear();
+                    }
+
+                    self.pending_report = Some(self.play_keys.clone());
+                }
+            }
+        }
+
+        self.pending_report.as_ref()
+    }
+
+    /// Remove the next report, after it has been sent.
+    pub(crate) fn pop(&mut self) {
+        self.pending_report = None;
+    }
+}
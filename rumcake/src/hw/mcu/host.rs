@@ -0,0 +1,17 @@
+//! Utilities for running `rumcake`'s tests on the host machine.
+//!
+//! This version of the `mcu` module is only used when running tests without a chip feature flag
+//! enabled. It provides the members of the `mcu` module that hardware-agnostic parts of `rumcake`
+//! depend on.
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+
+/// Mutex used by `rumcake`'s statics. Tests may run on multiple threads, so a critical section is
+/// used instead of the thread mode mutex used on MCUs.
+pub type RawMutex = CriticalSectionRawMutex;
+
+/// Does nothing, since there is no bootloader to jump to.
+pub fn jump_to_bootloader() {}
+
+/// Does nothing, since there are no clocks to configure.
+pub fn initialize_rcc() {}
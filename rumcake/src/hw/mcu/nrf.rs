@@ -4,6 +4,7 @@
 //! of other versions of the `mcu` module. This is the case so that parts of `rumcake` can remain
 //! hardware-agnostic.
 
+use defmt::info;
 use embassy_nrf::bind_interrupts;
 use embassy_nrf::interrupt::{InterruptExt, Priority};
 use embassy_nrf::nvmc::Nvmc;
@@ -18,7 +19,7 @@ use static_cell::StaticCell;
 use crate::hw::BATTERY_LEVEL_STATE;
 
 pub use rumcake_macros::{
-    input_pin, output_pin, setup_buffered_uarte, setup_i2c, setup_i2c_blocking,
+    flex_pin, input_pin, output_pin, setup_buffered_uarte, setup_i2c, setup_i2c_blocking,
 };
 
 pub use embassy_nrf;
@@ -35,9 +36,25 @@ pub fn jump_to_bootloader() {
     // TODO
 }
 
+impl crate::matrix::FlexPin for embassy_nrf::gpio::Flex<'static, embassy_nrf::gpio::AnyPin> {
+    fn release(&mut self) {
+        self.set_as_input(embassy_nrf::gpio::Pull::Up);
+    }
+
+    fn drive_low(&mut self) {
+        self.set_low();
+        self.set_as_output(embassy_nrf::gpio::OutputDrive::Standard);
+    }
+
+    fn is_low(&self) -> bool {
+        embassy_nrf::gpio::Flex::is_low(self)
+    }
+}
+
 pub fn initialize_rcc() {
     let mut conf = embassy_nrf::config::Config::default();
     conf.time_interrupt_priority = Priority::P2;
+    conf.gpiote_interrupt_priority = Priority::P2;
     embassy_nrf::init(conf);
 }
 
@@ -194,6 +211,65 @@ pub async fn adc_task() {
     }
 }
 
+/// Put the MCU into System OFF mode, the deepest sleep mode available on nRF5x-based MCUs.
+///
+/// Only the pins that have been configured to sense a level (e.g. matrix columns that are waiting
+/// for a key press) can wake up the MCU. Waking up from System OFF resets the MCU, so this
+/// function never returns.
+pub async fn enter_system_off() -> ! {
+    #[cfg(feature = "bluetooth")]
+    crate::bluetooth::nrf_ble::prepare_for_system_off().await;
+
+    info!("[NRF] Entering System OFF");
+
+    unsafe {
+        #[cfg(feature = "nrf-ble")]
+        nrf_softdevice::raw::sd_power_system_off();
+
+        #[cfg(not(feature = "nrf-ble"))]
+        (*embassy_nrf::pac::POWER::ptr())
+            .systemoff
+            .write(|w| w.systemoff().enter());
+    }
+
+    // System OFF is emulated while a debugger is attached, so we wait here instead.
+    loop {
+        cortex_m::asm::wfe();
+    }
+}
+
+#[cfg(all(feature = "nrf-ble", feature = "nrf52840"))]
+/// Keep the RAM sections containing `len` bytes at `start` powered while the MCU is in System OFF.
+pub(crate) fn retain_ram(start: *const u8, len: usize) {
+    const RAM_START: usize = 0x2000_0000;
+
+    // RAM0 to RAM7 each have two 4 KB sections, and RAM8 has six 32 KB sections.
+    let section = |offset: usize| {
+        if offset < 0x10000 {
+            ((offset / 0x2000) as u8, (offset % 0x2000) / 0x1000)
+        } else {
+            (8, (offset - 0x10000) / 0x8000)
+        }
+    };
+
+    let offset = start as usize - RAM_START;
+    let (first_block, first_section) = section(offset);
+    let (last_block, last_section) = section(offset + len - 1);
+
+    for block in first_block..=last_block {
+        let from = if block == first_block {
+            first_section
+        } else {
+            0
+        };
+        let to = if block == last_block { last_section } else { 1 };
+        let mask = (from..=to).fold(0, |mask, section| mask | 1 << (16 + section));
+        unsafe {
+            nrf_softdevice::raw::sd_power_ram_power_set(block, mask);
+        }
+    }
+}
+
 #[cfg(feature = "nrf-ble")]
 /// A mutex that is locked when the softdevice is advertising. This is mainly to prevent
 /// [`nrf_softdevice::ble::peripheral::ADV_PORTAL`] from being opened by more than one task at the
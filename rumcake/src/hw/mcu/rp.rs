@@ -14,7 +14,8 @@ use embassy_rp::usb::Driver;
 use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
 
 pub use rumcake_macros::{
-    input_pin, output_pin, setup_buffered_uart, setup_dma_channel, setup_i2c,
+    flex_pin, input_pin, output_pin, setup_adc_sampler, setup_buffered_uart, setup_dma_channel,
+    setup_i2c,
 };
 
 pub use embassy_rp;
@@ -28,6 +29,22 @@ pub fn jump_to_bootloader() {
     // TODO
 }
 
+impl crate::matrix::FlexPin for embassy_rp::gpio::Flex<'static, embassy_rp::gpio::AnyPin> {
+    fn release(&mut self) {
+        self.set_pull(embassy_rp::gpio::Pull::Up);
+        self.set_as_input();
+    }
+
+    fn drive_low(&mut self) {
+        self.set_low();
+        self.set_as_output();
+    }
+
+    fn is_low(&self) -> bool {
+        embassy_rp::gpio::Flex::is_low(self)
+    }
+}
+
 /// Initialize the MCU's internal clocks.
 pub fn initialize_rcc() {
     let conf = Config::default();
@@ -4,15 +4,18 @@
 //! of other versions of the `mcu` module. This is the case so that parts of `rumcake` can remain
 //! hardware-agnostic.
 
+use core::convert::Infallible;
+
 use embassy_stm32::bind_interrupts;
 use embassy_stm32::flash::{Blocking, Flash as HALFlash};
 use embassy_stm32::peripherals::{FLASH, PA11, PA12, USB};
 use embassy_stm32::rcc::{APBPrescaler, Hse, Pll, PllMul, PllPreDiv, PllSource, Sysclk, HSI_FREQ};
 use embassy_stm32::usb::Driver;
 use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
+use embassy_time::{Duration, Timer};
 use static_cell::StaticCell;
 
-pub use rumcake_macros::{input_pin, output_pin, setup_buffered_uart, setup_i2c};
+pub use rumcake_macros::{flex_pin, input_pin, output_pin, setup_buffered_uart, setup_i2c};
 
 pub use embassy_stm32;
 
@@ -45,6 +48,117 @@ const fn gcd(a: u32, b: u32) -> u32 {
     }
 }
 
+impl crate::matrix::FlexPin for embassy_stm32::gpio::Flex<'static, embassy_stm32::gpio::AnyPin> {
+    fn release(&mut self) {
+        self.set_as_input(embassy_stm32::gpio::Pull::Up);
+    }
+
+    fn drive_low(&mut self) {
+        self.set_low();
+        self.set_as_output(embassy_stm32::gpio::Speed::Low);
+    }
+
+    fn is_low(&self) -> bool {
+        embassy_stm32::gpio::Flex::is_low(self)
+    }
+}
+
+/// An input pin created by [`input_pin!`]. Pins that are connected to an EXTI line can wait for a
+/// change in level using interrupts, so they can wake up the keyboard while it is idle. An EXTI
+/// line can only be connected to one port at a time, so pins that can't use an EXTI line are
+/// polled every 10 milliseconds instead.
+pub enum WakeInput {
+    Exti(embassy_stm32::exti::ExtiInput<'static, embassy_stm32::gpio::AnyPin>),
+    Polled(embassy_stm32::gpio::Input<'static, embassy_stm32::gpio::AnyPin>),
+}
+
+const WAKE_INPUT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+impl WakeInput {
+    fn is_high(&self) -> bool {
+        match self {
+            WakeInput::Exti(pin) => pin.is_high(),
+            WakeInput::Polled(pin) => pin.is_high(),
+        }
+    }
+}
+
+async fn poll_until(
+    pin: &embassy_stm32::gpio::Input<'static, embassy_stm32::gpio::AnyPin>,
+    high: bool,
+) {
+    while pin.is_high() != high {
+        Timer::after(WAKE_INPUT_POLL_INTERVAL).await;
+    }
+}
+
+impl embedded_hal::digital::v2::InputPin for WakeInput {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        Ok(WakeInput::is_high(self))
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        Ok(!WakeInput::is_high(self))
+    }
+}
+
+impl embedded_hal_async::digital::ErrorType for WakeInput {
+    type Error = Infallible;
+}
+
+impl embedded_hal_async::digital::Wait for WakeInput {
+    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+        match self {
+            WakeInput::Exti(pin) => pin.wait_for_high().await,
+            WakeInput::Polled(pin) => poll_until(pin, true).await,
+        }
+        Ok(())
+    }
+
+    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+        match self {
+            WakeInput::Exti(pin) => pin.wait_for_low().await,
+            WakeInput::Polled(pin) => poll_until(pin, false).await,
+        }
+        Ok(())
+    }
+
+    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+        match self {
+            WakeInput::Exti(pin) => pin.wait_for_rising_edge().await,
+            WakeInput::Polled(pin) => {
+                poll_until(pin, false).await;
+                poll_until(pin, true).await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+        match self {
+            WakeInput::Exti(pin) => pin.wait_for_falling_edge().await,
+            WakeInput::Polled(pin) => {
+                poll_until(pin, true).await;
+                poll_until(pin, false).await;
+            }
+        }
+        Ok(())
+    }
+
+    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+        match self {
+            WakeInput::Exti(pin) => pin.wait_for_any_edge().await,
+            WakeInput::Polled(pin) => {
+                let level = pin.is_high();
+                poll_until(pin, !level).await;
+            }
+        }
+        Ok(())
+    }
+}
+
 /// Initialize the MCU's internal clocks.
 pub fn initialize_rcc() {
     let mut conf = embassy_stm32::Config::default();
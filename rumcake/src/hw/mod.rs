@@ -1,6 +1,11 @@
 //! Utilities for interfacing with hardware.
 
-#[cfg(all(not(feature = "stm32"), not(feature = "nrf"), not(feature = "rp")))]
+#[cfg(all(
+    not(test),
+    not(feature = "stm32"),
+    not(feature = "nrf"),
+    not(feature = "rp")
+))]
 compile_error!("Please enable the appropriate feature flag for the chip you're using.");
 
 #[cfg(any(
@@ -13,9 +18,21 @@ compile_error!("Please enable only one chip feature flag.");
 #[cfg_attr(feature = "stm32", path = "mcu/stm32.rs")]
 #[cfg_attr(feature = "nrf", path = "mcu/nrf.rs")]
 #[cfg_attr(feature = "rp", path = "mcu/rp.rs")]
+#[cfg_attr(
+    all(
+        test,
+        not(feature = "stm32"),
+        not(feature = "nrf"),
+        not(feature = "rp")
+    ),
+    path = "mcu/host.rs"
+)]
 pub mod mcu;
 
+use core::sync::atomic::{AtomicU8, Ordering};
+
 use crate::State;
+use bitflags::bitflags;
 use embassy_futures::select;
 use embassy_sync::signal::Signal;
 
@@ -74,6 +91,8 @@ pub static CURRENT_OUTPUT_STATE: State<Option<HIDOutput>> = State::new(
         &crate::usb::KB_CURRENT_OUTPUT_STATE_LISTENER,
         #[cfg(feature = "usb")]
         &crate::usb::CONSUMER_CURRENT_OUTPUT_STATE_LISTENER,
+        #[cfg(feature = "usb")]
+        &crate::usb::MOUSE_CURRENT_OUTPUT_STATE_LISTENER,
         #[cfg(all(feature = "usb", feature = "via"))]
         &crate::usb::VIA_CURRENT_OUTPUT_STATE_LISTENER,
         #[cfg(feature = "bluetooth")]
@@ -85,9 +104,60 @@ pub(crate) static OUTPUT_MODE_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new
 pub(crate) static USB_RUNNING_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
 pub(crate) static BLUETOOTH_CONNECTED_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
 
+bitflags! {
+    /// LEDs that a host device can turn on, like Caps Lock.
+    ///
+    /// Bits used for the LEDs correspond to the HID keyboard LED output report.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct LedIndicators: u8 {
+        const NUM_LOCK = 0b00000001;
+        const CAPS_LOCK = 0b00000010;
+        const SCROLL_LOCK = 0b00000100;
+        const COMPOSE = 0b00001000;
+        const KANA = 0b00010000;
+    }
+}
+
+/// State that contains the LEDs that the host device has turned on. This follows the host that is
+/// currently receiving HID reports (see [`CURRENT_OUTPUT_STATE`]). If no host is connected, all
+/// LEDs are off.
+pub static HOST_LED_STATE: State<LedIndicators> = State::new(
+    LedIndicators::empty(),
+    &[
+        #[cfg(feature = "display")]
+        &crate::display::HOST_LED_STATE_LISTENER,
+        #[cfg(feature = "underglow")]
+        &crate::underglow::HOST_LED_STATE_LISTENER,
+        #[cfg(feature = "simple-backlight")]
+        &crate::backlight::simple_backlight::HOST_LED_STATE_LISTENER,
+        #[cfg(feature = "simple-backlight-matrix")]
+        &crate::backlight::simple_backlight_matrix::HOST_LED_STATE_LISTENER,
+        #[cfg(feature = "rgb-backlight-matrix")]
+        &crate::backlight::rgb_backlight_matrix::HOST_LED_STATE_LISTENER,
+    ],
+);
+
+/// Last LED output report received from each host, so that [`HOST_LED_STATE`] can be updated
+/// when the output changes.
+static USB_HOST_LEDS: AtomicU8 = AtomicU8::new(0);
+static BLUETOOTH_HOST_LEDS: AtomicU8 = AtomicU8::new(0);
+
+static HOST_LEDS_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+/// Store an LED output report received from a host. This doesn't need to be called from an async
+/// context, so it can be used in HID request handlers. [`HOST_LED_STATE`] is updated by the
+/// [`output_switcher`] task.
+pub(crate) fn set_host_leds(output: HIDOutput, leds: u8) {
+    match output {
+        HIDOutput::Usb => USB_HOST_LEDS.store(leds, Ordering::Relaxed),
+        HIDOutput::Bluetooth => BLUETOOTH_HOST_LEDS.store(leds, Ordering::Relaxed),
+    }
+    HOST_LEDS_LISTENER.signal(());
+}
+
 #[rumcake_macros::task]
 pub async fn output_switcher() {
-    // This task doesn't need to run if only one of USB or Bluetooth is enabled.
+    // This task also keeps `HOST_LED_STATE` in sync with the current output, so it should always run.
     loop {
         let output = match OUTPUT_MODE_STATE.get().await {
             #[cfg(feature = "usb")]
@@ -113,11 +183,21 @@ pub async fn output_switcher() {
         CURRENT_OUTPUT_STATE.set(output).await;
         defmt::info!("[HW] Output updated: {:?}", defmt::Debug2Format(&output));
 
+        let leds = match output {
+            Some(HIDOutput::Usb) => USB_HOST_LEDS.load(Ordering::Relaxed),
+            Some(HIDOutput::Bluetooth) => BLUETOOTH_HOST_LEDS.load(Ordering::Relaxed),
+            None => 0,
+        };
+        HOST_LED_STATE
+            .set(LedIndicators::from_bits_truncate(leds))
+            .await;
+
         // Wait for a change in state before attempting to update the output again.
-        select::select3(
+        select::select4(
             USB_RUNNING_STATE_LISTENER.wait(),
             BLUETOOTH_CONNECTED_STATE_LISTENER.wait(),
             OUTPUT_MODE_STATE_LISTENER.wait(),
+            HOST_LEDS_LISTENER.wait(),
         )
         .await;
     }
@@ -0,0 +1,309 @@
+//! Support for key overrides.
+//!
+//! A key override replaces a key with a different key when certain modifiers are held. For
+//! example, you can make Shift + Backspace send Delete. Key overrides are processed after the
+//! layout's keycodes are collected, so they work with any action that produces a keyboard keycode.
+//! The behaviour of key overrides is based on QMK's implementation. To use key overrides, your
+//! keyboard must implement [`crate::keyboard::KeyboardLayout::NUM_KEY_OVERRIDES`] and
+//! [`crate::keyboard::KeyboardLayout::get_key_overrides`].
+
+use bitflags::bitflags;
+use embassy_sync::mutex::{Mutex, MutexGuard};
+use heapless::Vec;
+pub use usbd_human_interface_device::page::Keyboard as KeyboardKeycode;
+
+use crate::hw::mcu::RawMutex;
+
+bitflags! {
+    /// Modifier keys used by a key override.
+    ///
+    /// Bits used for the modifiers correspond to QMK's 8-bit modifier masks.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct ModifierMask: u8 {
+        const NONE = 0b00000000;
+        const LEFT_CTRL = 0b00000001;
+        const LEFT_SHIFT = 0b00000010;
+        const LEFT_ALT = 0b00000100;
+        const LEFT_GUI = 0b00001000;
+        const RIGHT_CTRL = 0b00010000;
+        const RIGHT_SHIFT = 0b00100000;
+        const RIGHT_ALT = 0b01000000;
+        const RIGHT_GUI = 0b10000000;
+        const CTRL = Self::LEFT_CTRL.bits() | Self::RIGHT_CTRL.bits();
+        const SHIFT = Self::LEFT_SHIFT.bits() | Self::RIGHT_SHIFT.bits();
+        const ALT = Self::LEFT_ALT.bits() | Self::RIGHT_ALT.bits();
+        const GUI = Self::LEFT_GUI.bits() | Self::RIGHT_GUI.bits();
+    }
+}
+
+impl ModifierMask {
+    pub(crate) fn from_keycode(keycode: KeyboardKeycode) -> Self {
+        match keycode {
+            KeyboardKeycode::LeftControl => Self::LEFT_CTRL,
+            KeyboardKeycode::LeftShift => Self::LEFT_SHIFT,
+            KeyboardKeycode::LeftAlt => Self::LEFT_ALT,
+            KeyboardKeycode::LeftGUI => Self::LEFT_GUI,
+            KeyboardKeycode::RightControl => Self::RIGHT_CTRL,
+            KeyboardKeycode::RightShift => Self::RIGHT_SHIFT,
+            KeyboardKeycode::RightAlt => Self::RIGHT_ALT,
+            KeyboardKeycode::RightGUI => Self::RIGHT_GUI,
+            _ => Self::NONE,
+        }
+    }
+
+    pub(crate) fn from_keycodes(keycodes: &[KeyboardKeycode]) -> Self {
+        keycodes
+            .iter()
+            .fold(Self::NONE, |mods, k| mods | Self::from_keycode(*k))
+    }
+}
+
+bitflags! {
+    /// Options that control when a key override activates and deactivates.
+    ///
+    /// Bits used for the options correspond to Vial's key override options.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct KeyOverrideOptions: u8 {
+        /// Allow the override to activate when the trigger key is pressed down.
+        const ACTIVATION_TRIGGER_DOWN = 0b00000001;
+        /// Allow the override to activate when one of the required modifiers is pressed down.
+        const ACTIVATION_REQUIRED_MOD_DOWN = 0b00000010;
+        /// Allow the override to activate when one of the negative modifiers is released.
+        const ACTIVATION_NEGATIVE_MOD_UP = 0b00000100;
+        /// Only one of the trigger modifiers needs to be held, instead of all of them.
+        const ONE_MOD = 0b00001000;
+        /// Don't send the trigger key again if it is still held when the override deactivates.
+        const NO_REREGISTER_TRIGGER = 0b00010000;
+        /// Don't deactivate the override when another key is pressed.
+        const NO_UNREGISTER_ON_OTHER_KEY_DOWN = 0b00100000;
+        /// Whether the override is enabled.
+        const ENABLED = 0b10000000;
+        /// All activation options, which is what QMK uses by default.
+        const ALL_ACTIVATIONS = Self::ACTIVATION_TRIGGER_DOWN.bits()
+            | Self::ACTIVATION_REQUIRED_MOD_DOWN.bits()
+            | Self::ACTIVATION_NEGATIVE_MOD_UP.bits();
+    }
+}
+
+/// A key that is replaced by another key when certain modifiers are held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyOverride {
+    /// Key that activates the override. If this is [`KeyboardKeycode::NoEventIndicated`], the
+    /// override only depends on the modifiers that are held.
+    pub trigger: KeyboardKeycode,
+
+    /// Modifiers that must be held for the override to activate.
+    pub trigger_mods: ModifierMask,
+
+    /// Bitmask of layers that the override can activate on. Bit 0 corresponds to layer 0.
+    pub layers: u16,
+
+    /// Modifiers that prevent the override from activating if any of them are held.
+    pub negative_mod_mask: ModifierMask,
+
+    /// Modifiers that are not sent to the host while the override is active.
+    pub suppressed_mods: ModifierMask,
+
+    /// Key that is sent instead of the trigger.
+    pub replacement: KeyboardKeycode,
+
+    /// Options that control when the override activates and deactivates.
+    pub options: KeyOverrideOptions,
+}
+
+impl KeyOverride {
+    /// Create a key override that replaces `trigger` with `replacement` when all of the
+    /// `trigger_mods` are held, on any layer. The trigger modifiers are suppressed while the
+    /// override is active. This is equivalent to QMK's `ko_make_basic`.
+    pub const fn basic(
+        trigger_mods: ModifierMask,
+        trigger: KeyboardKeycode,
+        replacement: KeyboardKeycode,
+    ) -> Self {
+        Self {
+            trigger,
+            trigger_mods,
+            layers: u16::MAX,
+            negative_mod_mask: ModifierMask::NONE,
+            suppressed_mods: trigger_mods,
+            replacement,
+            options: KeyOverrideOptions::ALL_ACTIVATIONS.union(KeyOverrideOptions::ENABLED),
+        }
+    }
+
+    /// Create a key override that is disabled.
+    pub const fn empty() -> Self {
+        Self {
+            trigger: KeyboardKeycode::NoEventIndicated,
+            trigger_mods: ModifierMask::NONE,
+            layers: 0,
+            negative_mod_mask: ModifierMask::NONE,
+            suppressed_mods: ModifierMask::NONE,
+            replacement: KeyboardKeycode::NoEventIndicated,
+            options: KeyOverrideOptions::empty(),
+        }
+    }
+
+    fn has_trigger(&self) -> bool {
+        self.trigger != KeyboardKeycode::NoEventIndicated
+    }
+
+    /// Check if the override's conditions are met, regardless of which event happened.
+    fn is_satisfied(&self, keys: &[KeyboardKeycode], mods: ModifierMask, layer: usize) -> bool {
+        if !self.options.contains(KeyOverrideOptions::ENABLED)
+            || layer >= u16::BITS as usize
+            || self.layers & (1 << layer) == 0
+        {
+            return false;
+        }
+
+        if self.has_trigger() && !keys.contains(&self.trigger) {
+            return false;
+        }
+
+        if mods.intersects(self.negative_mod_mask) {
+            return false;
+        }
+
+        if self.options.contains(KeyOverrideOptions::ONE_MOD) {
+            self.trigger_mods.is_empty() || mods.intersects(self.trigger_mods)
+        } else {
+            // Left and right modifiers are treated the same, so Shift can be either side
+            let one_sided = |mods: ModifierMask| (mods.bits() & 0b1111) | (mods.bits() >> 4);
+            one_sided(mods) & one_sided(self.trigger_mods) == one_sided(self.trigger_mods)
+        }
+    }
+}
+
+/// Mutex-guarded key overrides. This allows key overrides to be changed at runtime (e.g. using
+/// Vial).
+pub struct KeyOverrides<const N: usize> {
+    overrides: Mutex<RawMutex, [KeyOverride; N]>,
+}
+
+impl<const N: usize> KeyOverrides<N> {
+    /// Create a new set of key overrides.
+    pub const fn new(overrides: [KeyOverride; N]) -> Self {
+        Self {
+            overrides: Mutex::new(overrides),
+        }
+    }
+
+    /// Obtain a lock on the key overrides, so that they can be read or modified.
+    pub async fn lock(&self) -> MutexGuard<RawMutex, [KeyOverride; N]> {
+        self.overrides.lock().await
+    }
+}
+
+/// Rewrites the keycodes collected from the layout according to the key overrides.
+pub(crate) struct KeyOverrideProcessor {
+    /// Keycodes collected from the layout on the previous tick.
+    last_keys: Vec<KeyboardKeycode, 24>,
+
+    /// Index of the key override that is currently active.
+    active: Option<usize>,
+
+    /// Trigger key that should not be sent again until it is released.
+    suppressed_trigger: Option<KeyboardKeycode>,
+}
+
+impl KeyOverrideProcessor {
+    pub(crate) fn new() -> Self {
+        Self {
+            last_keys: Vec::new(),
+            active: None,
+            suppressed_trigger: None,
+        }
+    }
+
+    /// Apply the key overrides to the keycodes collected from the layout.
+    pub(crate) fn process(
+        &mut self,
+        overrides: &[KeyOverride],
+        keys: &mut Vec<KeyboardKeycode, 24>,
+        layer: usize,
+    ) {
+        let mods = ModifierMask::from_keycodes(keys);
+        let last_mods = ModifierMask::from_keycodes(&self.last_keys);
+        let pressed_mods = mods.difference(last_mods);
+        let released_mods = last_mods.difference(mods);
+        let newly_pressed = || keys.iter().filter(|k| !self.last_keys.contains(k));
+
+        // Check if the active override should be deactivated
+        if let Some(ko) = self.active.and_then(|idx| overrides.get(idx)) {
+            let other_key_pressed = newly_pressed().any(|k| {
+                ModifierMask::from_keycode(*k).is_empty() && (!ko.has_trigger() || *k != ko.trigger)
+            });
+
+            if !ko.is_satisfied(keys, mods, layer)
+                || (other_key_pressed
+                    && !ko
+                        .options
+                        .contains(KeyOverrideOptions::NO_UNREGISTER_ON_OTHER_KEY_DOWN))
+            {
+                if ko.has_trigger()
+                    && keys.contains(&ko.trigger)
+                    && ko
+                        .options
+                        .contains(KeyOverrideOptions::NO_REREGISTER_TRIGGER)
+                {
+                    self.suppressed_trigger = Some(ko.trigger);
+                }
+                self.active = None;
+            }
+        } else {
+            self.active = None;
+        }
+
+        // Check if a new override should be activated
+        if self.active.is_none() {
+            self.active = overrides.iter().position(|ko| {
+                if !ko.is_satisfied(keys, mods, layer) {
+                    return false;
+                }
+
+                (ko.options
+                    .contains(KeyOverrideOptions::ACTIVATION_TRIGGER_DOWN)
+                    && ko.has_trigger()
+                    && newly_pressed().any(|k| *k == ko.trigger))
+                    || (ko
+                        .options
+                        .contains(KeyOverrideOptions::ACTIVATION_REQUIRED_MOD_DOWN)
+                        && pressed_mods.intersects(ko.trigger_mods))
+                    || (ko
+                        .options
+                        .contains(KeyOverrideOptions::ACTIVATION_NEGATIVE_MOD_UP)
+                        && released_mods.intersects(ko.negative_mod_mask))
+            });
+
+            if self.active.is_some() {
+                self.suppressed_trigger = None;
+            }
+        }
+
+        self.last_keys.clone_from(keys);
+
+        if let Some(trigger) = self.suppressed_trigger {
+            if keys.contains(&trigger) {
+                keys.retain(|k| *k != trigger);
+            } else {
+                self.suppressed_trigger = None;
+            }
+        }
+
+        // Replace the trigger, and remove the suppressed modifiers
+        if let Some(ko) = self.active.and_then(|idx| overrides.get(idx)) {
+            keys.retain(|k| {
+                (!ko.has_trigger() || *k != ko.trigger)
+                    && !ko
+                        .suppressed_mods
+                        .intersects(ModifierMask::from_keycode(*k))
+            });
+            if ko.replacement != KeyboardKeycode::NoEventIndicated
+                && !keys.contains(&ko.replacement)
+            {
+                let _ = keys.push(ko.replacement);
+            }
+        }
+    }
+}
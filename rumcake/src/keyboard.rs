@@ -8,13 +8,14 @@ use defmt::{debug, info, warn, Debug2Format};
 use embassy_sync::channel::Channel;
 use embassy_sync::mutex::{Mutex, MutexGuard};
 use embassy_sync::pubsub::{PubSubBehavior, PubSubChannel};
-use embassy_time::{Duration, Ticker, Timer};
-use embedded_hal::digital::v2::{InputPin, OutputPin};
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Ticker, Timer};
+use embedded_hal::digital::v2::InputPin;
+use embedded_hal_async::digital::Wait;
 use heapless::Vec;
-use keyberon::debounce::Debouncer;
 use keyberon::layout::{CustomEvent, Event, Layers, Layout as KeyberonLayout};
-use keyberon::matrix::Matrix;
 use usbd_human_interface_device::device::consumer::MultipleConsumerReport;
+use usbd_human_interface_device::device::mouse::WheelMouseReport;
 use usbd_human_interface_device::{
     device::keyboard::NKROBootKeyboardReport, page::Keyboard as KeyboardKeycode,
 };
@@ -22,10 +23,39 @@ use usbd_human_interface_device::{
 #[cfg(feature = "media-keycodes")]
 pub use usbd_human_interface_device::page::Consumer;
 
+use crate::auto_shift::AutoShiftProcessor;
+use crate::caps_word::{CapsWordConfig, CapsWordProcessor};
+use crate::combos::{ComboProcessor, Combos};
+use crate::debounce::{DebounceAlgorithm, Debouncer};
+#[cfg(feature = "dynamic-macros")]
+use crate::dynamic_macros::{DynamicMacroConfig, DynamicMacroProcessor};
 use crate::hw::mcu::RawMutex;
 use crate::hw::CURRENT_OUTPUT_STATE;
+use crate::key_overrides::{KeyOverrideProcessor, KeyOverrides};
+use crate::leader::{LeaderProcessor, LeaderSequence};
+use crate::matrix::MatrixScanner;
+#[cfg(feature = "mouse-keys")]
+use crate::mouse::{MouseKeyProcessor, MouseKeysConfig};
+#[cfg(feature = "pointing-device")]
+use crate::pointing::{
+    PointingCommand, PointingConfig, PointingProcessor, POINTING_MOTION_CHANNEL,
+};
+use crate::settings::{
+    DEFAULT_AUTO_SHIFT_TIMEOUT, KEYBOARD_SETTINGS_STATE, LAYOUT_SETTINGS_LISTENER,
+    MATRIX_SETTINGS_LISTENER,
+};
+use crate::tap_dance::{TapDanceProcessor, TapDances};
+#[cfg(feature = "unicode")]
+use crate::unicode::{UnicodeProcessor, UNICODE_MODE_LISTENER, UNICODE_MODE_STATE};
+use crate::State;
 
-pub use rumcake_macros::{build_layout, build_matrix, remap_matrix};
+pub use crate::combos::build_combos;
+pub use crate::leader::build_leader_sequences;
+pub use crate::tap_dance::setup_tap_dances;
+pub use rumcake_macros::{
+    build_direct_pin_matrix, build_duplex_matrix, build_layout, build_matrix, remap_matrix,
+    setup_encoders,
+};
 
 /// Basic keyboard trait that must be implemented to use rumcake. Defines basic keyboard information.
 pub trait Keyboard {
@@ -47,7 +77,70 @@ pub trait Keyboard {
 
 /// A trait that must be implemented on a device that communicates with the host device.
 pub trait KeyboardLayout {
-    const NUM_ENCODERS: usize = 0; // Only for VIA compatibility, no proper encoder support. This is the default if not set in QMK
+    /// Number of rotary encoders used in the layout.
+    ///
+    /// This must match the length of [`KeyboardLayout::ENCODER_LAYOUT_POSITIONS`].
+    const NUM_ENCODERS: usize = 0; // This is the default if not set in QMK
+
+    /// The layout positions that each rotary encoder will "tap" when it is turned. Tuples in this
+    /// array should be in the form of `((row, col), (row, col))`, where the first position is
+    /// used for clockwise steps, and the second position is used for counter-clockwise steps.
+    ///
+    /// These positions are usually extra keys added to your layout that don't correspond to any
+    /// switch on your matrix. Because the encoder actions are part of your layout, they can be
+    /// changed per layer, like any other key.
+    const ENCODER_LAYOUT_POSITIONS: &'static [((u8, u8), (u8, u8))] = &[];
+
+    /// Number of combos that can be used with the layout. This includes empty combos, which can
+    /// be set at runtime (e.g. using Vial).
+    ///
+    /// It is recommended to use [`build_combos`] to set this constant.
+    const NUM_COMBOS: usize = 0;
+
+    /// Default amount of time (in milliseconds) that all of the keys of a combo must be pressed
+    /// within. This is used for any combo that doesn't specify its own timeout.
+    const COMBO_TIMEOUT_MS: u16 = 50;
+
+    /// Number of key overrides that can be used with the layout. This includes disabled key
+    /// overrides, which can be set at runtime (e.g. using Vial).
+    const NUM_KEY_OVERRIDES: usize = 0;
+
+    /// Number of tap dance keys that can be used with the layout. This includes empty tap dances,
+    /// which can be set at runtime (e.g. using Vial).
+    ///
+    /// It is recommended to use [`setup_tap_dances`] to set this constant.
+    const NUM_TAP_DANCES: usize = 0;
+
+    /// Amount of time (in milliseconds) to wait for the next key of a leader sequence, before
+    /// the sequence ends.
+    const LEADER_TIMEOUT_MS: u16 = 300;
+
+    /// Options that control which keys are shifted by Caps Word, and which keys end it.
+    const CAPS_WORD_CONFIG: CapsWordConfig = CapsWordConfig::new();
+
+    #[cfg(feature = "unicode")]
+    /// Characters that can be typed using [`Keycode::UnicodeMap`]. This can be used to refer to
+    /// characters by index, so that they can be assigned using Via or Vial.
+    const UNICODE_MAP: &'static [char] = &[];
+
+    #[cfg(feature = "dynamic-macros")]
+    /// Options that control how dynamic macros are recorded.
+    const DYNAMIC_MACRO_CONFIG: DynamicMacroConfig = DynamicMacroConfig::new();
+
+    #[cfg(feature = "profiles")]
+    /// Number of storage profiles that can be switched between, using [`Keycode::Profile`],
+    /// [`Keycode::NextProfile`] and [`Keycode::PrevProfile`]. This must be between 1 and
+    /// [`crate::storage::MAX_PROFILES`], which is checked at compile time by the `keyboard` macro.
+    /// See [`crate::profiles`].
+    const PROFILE_COUNT: u8 = 2;
+
+    #[cfg(feature = "mouse-keys")]
+    /// Options that control the movement of the cursor and scroll wheel when using mouse keys.
+    const MOUSE_KEYS_CONFIG: MouseKeysConfig = MouseKeysConfig::new();
+
+    #[cfg(feature = "pointing-device")]
+    /// Options that control how the motion from a pointing device is sent to the host.
+    const POINTING_CONFIG: PointingConfig = PointingConfig::new();
 
     /// Number of columns in the layout.
     ///
@@ -78,6 +171,36 @@ pub trait KeyboardLayout {
     fn get_original_layout(
     ) -> Layers<{ Self::LAYOUT_COLS }, { Self::LAYOUT_ROWS }, { Self::LAYERS }, Keycode>;
 
+    /// Get a reference to the mutex-guarded combos, which can be locked to read or modify them.
+    /// By default, this returns `None`, which means that the layout has no combos.
+    ///
+    /// It is recommended to use [`build_combos`] to implement this function.
+    fn get_combos() -> Option<&'static Combos<{ Self::NUM_COMBOS }>> {
+        None
+    }
+
+    /// Get the leader sequences that can be triggered after pressing [`Keycode::Leader`]. By
+    /// default, this returns an empty slice, which means that the layout has no leader sequences.
+    ///
+    /// It is recommended to use [`build_leader_sequences`] to implement this function.
+    fn get_leader_sequences() -> &'static [LeaderSequence] {
+        &[]
+    }
+
+    /// Get a reference to the mutex-guarded key overrides, which can be locked to read or modify
+    /// them. By default, this returns `None`, which means that the layout has no key overrides.
+    fn get_key_overrides() -> Option<&'static KeyOverrides<{ Self::NUM_KEY_OVERRIDES }>> {
+        None
+    }
+
+    /// Get a reference to the mutex-guarded tap dances, which can be locked to read or modify
+    /// them. By default, this returns `None`, which means that the layout has no tap dance keys.
+    ///
+    /// It is recommended to use [`setup_tap_dances`] to implement this function.
+    fn get_tap_dances() -> Option<&'static TapDances<{ Self::NUM_TAP_DANCES }>> {
+        None
+    }
+
     /// Handle a [`Keycode::Custom`] event. By default this does nothing.
     ///
     /// `press` is set to `true` if the event was a key press. Otherwise, it will be `false`. `id`
@@ -110,9 +233,37 @@ impl<const C: usize, const R: usize, const L: usize> Layout<C, R, L> {
 
 /// A trait that must be implemented for any device that needs to poll a switch matrix.
 pub trait KeyboardMatrix {
-    /// Debounce setting.
+    /// Amount of time (in milliseconds) that switches are debounced for. How this is used depends
+    /// on [`KeyboardMatrix::DEBOUNCE_ALGORITHM`].
     const DEBOUNCE_MS: u16 = 5;
 
+    /// Algorithm used to debounce the switch matrix.
+    const DEBOUNCE_ALGORITHM: DebounceAlgorithm = DebounceAlgorithm::DeferGlobal;
+
+    /// How long the matrix can go without any key events before the keyboard becomes idle, in
+    /// milliseconds.
+    ///
+    /// While idle, the matrix stops scanning, and waits for a key press using pin interrupts on the
+    /// columns (see [`crate::matrix::MatrixScanner::wait_for_press`]). Lighting and displays are
+    /// also turned off until a key is pressed. If set to 0, the keyboard will never become idle.
+    const IDLE_TIMEOUT_MS: u32 = 0;
+
+    #[cfg(feature = "nrf")]
+    /// How long the matrix can go without any key events before the keyboard enters deep sleep
+    /// (System OFF), in milliseconds.
+    ///
+    /// Before entering deep sleep, the keyboard disconnects from its bluetooth host. Pressing any
+    /// key will wake up the keyboard, which restarts the firmware. The output mode and the bond
+    /// with the last host are kept, so the keyboard will reconnect to it. If set to 0, the
+    /// keyboard will never enter deep sleep. Otherwise, this must be greater than
+    /// [`KeyboardMatrix::IDLE_TIMEOUT_MS`], and [`KeyboardMatrix::IDLE_TIMEOUT_MS`] must not be 0.
+    ///
+    /// Waking up from deep sleep requires the matrix columns to be connected directly to the MCU
+    /// (see [`MatrixScanner::CAN_WAKE_FROM_DEEP_SLEEP`]). Keyboards with other matrices (e.g.
+    /// shift registers, expanders, analog or duplex matrices) will fail to compile if this is not
+    /// 0.
+    const DEEP_SLEEP_TIMEOUT_MS: u32 = 0;
+
     /// Number of matrix columns.
     ///
     /// It is recommended to use [`build_matrix`] to set this constant.
@@ -123,18 +274,13 @@ pub trait KeyboardMatrix {
     /// It is recommended to use [`build_matrix`] to set this constant.
     const MATRIX_ROWS: usize;
 
-    /// Create the keyboard matrix by initializing a set of GPIO pins to use for columns and rows.
+    /// Create the keyboard matrix by initializing the GPIO pins or other peripherals used for
+    /// columns and rows.
     ///
-    /// It is recommended to use [`build_matrix`] to implement this function.
-    fn build_matrix() -> Result<
-        Matrix<
-            impl InputPin<Error = Infallible>,
-            impl OutputPin<Error = Infallible>,
-            { Self::MATRIX_COLS },
-            { Self::MATRIX_ROWS },
-        >,
-        Infallible,
-    >;
+    /// It is recommended to use [`build_matrix`] to implement this function. See
+    /// [`crate::matrix`] for the available matrix implementations.
+    fn build_matrix(
+    ) -> Result<impl MatrixScanner<{ Self::MATRIX_COLS }, { Self::MATRIX_ROWS }>, Infallible>;
 
     /// Optional function to remap a matrix position to a position on the keyboard layout defined
     /// by [`KeyboardLayout::get_layout`].
@@ -149,36 +295,80 @@ pub trait KeyboardMatrix {
 pub fn setup_keyboard_matrix<K: KeyboardMatrix>(
     _k: K,
 ) -> (
-    Matrix<
-        impl InputPin<Error = Infallible>,
-        impl OutputPin<Error = Infallible>,
-        { K::MATRIX_COLS },
-        { K::MATRIX_ROWS },
-    >,
-    Debouncer<[[bool; K::MATRIX_COLS]; K::MATRIX_ROWS]>,
+    impl MatrixScanner<{ K::MATRIX_COLS }, { K::MATRIX_ROWS }>,
+    Debouncer<{ K::MATRIX_COLS }, { K::MATRIX_ROWS }>,
 ) {
     let matrix = K::build_matrix().unwrap();
-    let debouncer = Debouncer::new(
-        [[false; K::MATRIX_COLS]; K::MATRIX_ROWS],
-        [[false; K::MATRIX_COLS]; K::MATRIX_ROWS],
-        K::DEBOUNCE_MS,
-    );
+    let debouncer = Debouncer::new(K::DEBOUNCE_ALGORITHM, K::DEBOUNCE_MS);
     (matrix, debouncer)
 }
 
+/// A trait that must be implemented to use rotary encoders.
+///
+/// Steps from each encoder will be converted into key presses on the layout positions defined by
+/// [`KeyboardLayout::ENCODER_LAYOUT_POSITIONS`].
+pub trait EncoderDevice: KeyboardLayout {
+    /// Number of quadrature state changes required to register one step of an encoder. Most
+    /// encoders go through 4 state changes per detent.
+    const ENCODER_RESOLUTION: u8 = 4;
+
+    /// Create the rotary encoders by initializing the A and B pins of each encoder. While the
+    /// keyboard is idle, the pins are only read after a change in level.
+    ///
+    /// It is recommended to use [`setup_encoders`] to implement this function.
+    fn setup_encoders() -> [(
+        impl InputPin<Error = Infallible> + Wait<Error = Infallible>,
+        impl InputPin<Error = Infallible> + Wait<Error = Infallible>,
+    ); Self::NUM_ENCODERS];
+}
+
+/// Compile-time check that each encoder has a layout position.
+struct EncoderCheck<K>(core::marker::PhantomData<K>);
+
+impl<K: KeyboardLayout> EncoderCheck<K> {
+    const CHECK: () = core::assert!(
+        K::ENCODER_LAYOUT_POSITIONS.len() == K::NUM_ENCODERS,
+        "The number of encoder layout positions must match the number of encoders."
+    );
+}
+
 /// Custom keycodes used to interact with other rumcake features.
 ///
 /// These can be used in your keyboard layout, defined in [`KeyboardLayout::get_layout`]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Keycode {
     /// Custom keycode, which can be used to run custom code. You can use
     /// [`KeyboardLayout::on_custom_keycode`] to handle it.
     Custom(u8),
 
+    /// Start a leader sequence. The next keys that are tapped are matched against the layout's
+    /// leader sequences. See [`crate::leader`].
+    Leader,
+
+    /// Toggle Caps Word, which shifts letters until a key that ends a word is pressed. See
+    /// [`crate::caps_word`].
+    CapsWord,
+
+    /// Auto Shift keycode, which can be any variant in [`crate::auto_shift::AutoShiftCommand`]
+    AutoShift(crate::auto_shift::AutoShiftCommand),
+
+    /// Tap dance key with the given index, which performs a different action depending on
+    /// whether it is tapped, held, or tapped twice. See [`crate::tap_dance`].
+    TapDance(u8),
+
     #[cfg(feature = "media-keycodes")]
     /// Media keycode, which can be any variant in [`usbd_human_interface_device::page::Consumer`]
     Media(usbd_human_interface_device::page::Consumer),
 
+    #[cfg(feature = "mouse-keys")]
+    /// Mouse keycode, which can be any variant in [`crate::mouse::MouseKeycode`]
+    Mouse(crate::mouse::MouseKeycode),
+
+    #[cfg(feature = "pointing-device")]
+    /// Keycode used to control a pointing device, which can be any variant in
+    /// [`crate::pointing::PointingCommand`]
+    Pointing(crate::pointing::PointingCommand),
+
     #[cfg(feature = "underglow")]
     /// Underglow keycode, which can be any variant in [`crate::underglow::animations::UnderglowCommand`]
     Underglow(crate::underglow::animations::UnderglowCommand),
@@ -198,9 +388,52 @@ pub enum Keycode {
     /// [`crate::backlight::rgb_backlight_matrix::animations::BacklightCommand`]
     RGBBacklightMatrix(crate::backlight::rgb_backlight_matrix::animations::BacklightCommand),
 
+    #[cfg(feature = "unicode")]
+    /// Type a Unicode character, using the current [`crate::unicode::UnicodeMode`].
+    Unicode(char),
+
+    #[cfg(feature = "unicode")]
+    /// Type the Unicode character at the given index of [`KeyboardLayout::UNICODE_MAP`].
+    UnicodeMap(u8),
+
+    #[cfg(feature = "unicode")]
+    /// Keycode used to change the Unicode input mode, which can be any variant in
+    /// [`crate::unicode::UnicodeCommand`]
+    UnicodeMode(crate::unicode::UnicodeCommand),
+
+    #[cfg(feature = "dynamic-macros")]
+    /// Start recording the dynamic macro with the given index. If a macro is already being
+    /// recorded, recording is stopped instead. See [`crate::dynamic_macros`].
+    DynamicMacroRecord(u8),
+
+    #[cfg(feature = "dynamic-macros")]
+    /// Stop recording the current dynamic macro.
+    DynamicMacroStop,
+
+    #[cfg(feature = "dynamic-macros")]
+    /// Play back the dynamic macro with the given index.
+    DynamicMacroPlay(u8),
+
+    #[cfg(feature = "profiles")]
+    /// Switch to the storage profile with the given index. See [`crate::profiles`].
+    Profile(u8),
+
+    #[cfg(feature = "profiles")]
+    /// Switch to the next storage profile.
+    NextProfile,
+
+    #[cfg(feature = "profiles")]
+    /// Switch to the previous storage profile.
+    PrevProfile,
+
     #[cfg(feature = "bluetooth")]
     /// Bluetooth keycode, which can be any variant in [`crate::bluetooth::BluetoothCommand`]
     Bluetooth(crate::bluetooth::BluetoothCommand),
+
+    #[cfg(feature = "analog-matrix")]
+    /// Keycode used to control an analog matrix, which can be any variant in
+    /// [`crate::matrix::analog::AnalogCommand`]
+    Analog(crate::matrix::analog::AnalogCommand),
 }
 
 /// Channel with keyboard events polled from the swtich matrix
@@ -209,28 +442,89 @@ pub enum Keycode {
 /// [`KeyboardMatrix::remap_to_layout`].
 pub(crate) static POLLED_EVENTS_CHANNEL: Channel<RawMutex, Event, 1> = Channel::new();
 
+/// Signalled when there is activity that doesn't come from the local matrix (e.g. encoders,
+/// pointing devices, or key events from split peripherals). This resets the idle timeout, and
+/// wakes up the keyboard if it is idle.
+pub(crate) static ACTIVITY_SIGNAL: Signal<RawMutex, ()> = Signal::new();
+
+/// State that indicates whether the keyboard is idle. The keyboard becomes idle once the matrix
+/// has not had any key events for [`KeyboardMatrix::IDLE_TIMEOUT_MS`].
+///
+/// Lighting and displays are turned off while the keyboard is idle.
+pub static IDLE_STATE: State<bool> = State::new(
+    false,
+    &[
+        &LAYOUT_IDLE_STATE_LISTENER,
+        #[cfg(feature = "pointing-device")]
+        &crate::pointing::IDLE_STATE_LISTENER,
+        #[cfg(feature = "display")]
+        &crate::display::IDLE_STATE_LISTENER,
+        #[cfg(feature = "underglow")]
+        &crate::underglow::IDLE_STATE_LISTENER,
+        #[cfg(feature = "simple-backlight")]
+        &crate::backlight::simple_backlight::IDLE_STATE_LISTENER,
+        #[cfg(feature = "simple-backlight-matrix")]
+        &crate::backlight::simple_backlight_matrix::IDLE_STATE_LISTENER,
+        #[cfg(feature = "rgb-backlight-matrix")]
+        &crate::backlight::rgb_backlight_matrix::IDLE_STATE_LISTENER,
+    ],
+);
+
+static LAYOUT_IDLE_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+/// Compile-time check that the matrix can wake up the keyboard, and that the keyboard goes idle
+/// before entering deep sleep, if deep sleep is enabled.
+#[cfg(feature = "nrf")]
+struct DeepSleepCheck<K, M>(core::marker::PhantomData<(K, M)>);
+
+#[cfg(feature = "nrf")]
+impl<K: KeyboardMatrix, M: MatrixScanner<{ K::MATRIX_COLS }, { K::MATRIX_ROWS }>>
+    DeepSleepCheck<K, M>
+{
+    const CHECK: () = {
+        core::assert!(
+            K::DEEP_SLEEP_TIMEOUT_MS == 0 || M::CAN_WAKE_FROM_DEEP_SLEEP,
+            "DEEP_SLEEP_TIMEOUT_MS must be 0, since this matrix can't wake up the keyboard from deep sleep"
+        );
+        core::assert!(
+            K::DEEP_SLEEP_TIMEOUT_MS == 0
+                || (K::IDLE_TIMEOUT_MS > 0 && K::IDLE_TIMEOUT_MS < K::DEEP_SLEEP_TIMEOUT_MS),
+            "DEEP_SLEEP_TIMEOUT_MS must be greater than IDLE_TIMEOUT_MS, and IDLE_TIMEOUT_MS must not be 0"
+        );
+    };
+}
+
 #[rumcake_macros::task]
-pub async fn matrix_poll<K: KeyboardMatrix>(
+pub async fn matrix_poll<
+    K: KeyboardMatrix,
+    M: MatrixScanner<{ K::MATRIX_COLS }, { K::MATRIX_ROWS }>,
+>(
     _k: K,
-    mut matrix: Matrix<
-        impl InputPin<Error = Infallible>,
-        impl OutputPin<Error = Infallible>,
-        { K::MATRIX_COLS },
-        { K::MATRIX_ROWS },
-    >,
-    mut debouncer: Debouncer<[[bool; K::MATRIX_COLS]; K::MATRIX_ROWS]>,
+    mut matrix: M,
+    mut debouncer: Debouncer<{ K::MATRIX_COLS }, { K::MATRIX_ROWS }>,
 ) {
+    #[cfg(feature = "nrf")]
+    #[allow(clippy::let_unit_value)]
+    let () = DeepSleepCheck::<K, M>::CHECK;
+
+    let mut last_event = Instant::now();
+
     loop {
+        if MATRIX_SETTINGS_LISTENER.try_take().is_some() {
+            let settings = KEYBOARD_SETTINGS_STATE.get().await;
+            debouncer.set_debounce_ms(settings.debounce.unwrap_or(K::DEBOUNCE_MS));
+        }
+
+        if ACTIVITY_SIGNAL.try_take().is_some() {
+            last_event = Instant::now();
+        }
+
         {
             debug!("[KEYBOARD] Scanning matrix");
-            let events = debouncer.events(
-                matrix
-                    .get_with_delay(|| {
-                        embassy_time::block_for(Duration::from_ticks(2));
-                    })
-                    .unwrap(),
-            );
+            let events = debouncer.events(matrix.scan().await);
             for e in events {
+                last_event = Instant::now();
+
                 let (row, col) = e.coord();
                 let (new_row, new_col) = K::remap_to_layout(row, col);
 
@@ -248,6 +542,124 @@ pub async fn matrix_poll<K: KeyboardMatrix>(
                 POLLED_EVENTS_CHANNEL.send(remapped_event).await;
             }
         }
+
+        // Keys that are being held down don't generate events, so we don't go idle while any key
+        // is pressed.
+        if K::IDLE_TIMEOUT_MS > 0
+            && last_event.elapsed() >= Duration::from_millis(K::IDLE_TIMEOUT_MS as u64)
+            && !debouncer.get().iter().flatten().any(|pressed| *pressed)
+        {
+            info!("[KEYBOARD] Keyboard is idle, waiting for a key press");
+            IDLE_STATE.set(true).await;
+
+            let wake_fut =
+                embassy_futures::select::select(matrix.wait_for_press(), ACTIVITY_SIGNAL.wait());
+
+            #[cfg(feature = "nrf")]
+            {
+                if K::DEEP_SLEEP_TIMEOUT_MS > 0 {
+                    // The columns are polled first, so that they are already set up to detect a key
+                    // press by the time System OFF is entered.
+                    let deadline =
+                        last_event + Duration::from_millis(K::DEEP_SLEEP_TIMEOUT_MS as u64);
+                    embassy_futures::select::select(wake_fut, async {
+                        Timer::at(deadline).await;
+                        crate::hw::mcu::enter_system_off().await
+                    })
+                    .await;
+                } else {
+                    wake_fut.await;
+                }
+            }
+
+            #[cfg(not(feature = "nrf"))]
+            wake_fut.await;
+
+            info!("[KEYBOARD] Waking up from idle");
+            IDLE_STATE.set(false).await;
+            last_event = Instant::now();
+
+            // Scan the matrix right away, so that the key that woke the keyboard is registered.
+            continue;
+        }
+
+        Timer::after(Duration::from_micros(500)).await;
+    }
+}
+
+/// Lookup table used to decode quadrature signals. The index is formed using the previous state of
+/// the A and B pins in the upper 2 bits, and the new state in the lower 2 bits. Positive values
+/// represent clockwise movement.
+const ENCODER_LUT: [i8; 16] = [0, -1, 1, 0, 1, 0, 0, -1, -1, 0, 0, 1, 0, 1, -1, 0];
+
+fn read_encoder_state(
+    a: &impl InputPin<Error = Infallible>,
+    b: &impl InputPin<Error = Infallible>,
+) -> u8 {
+    (a.is_high().unwrap() as u8) << 1 | b.is_high().unwrap() as u8
+}
+
+#[rumcake_macros::task]
+pub async fn encoder_poll<K: EncoderDevice>(
+    _k: K,
+    mut encoders: [(
+        impl InputPin<Error = Infallible> + Wait<Error = Infallible>,
+        impl InputPin<Error = Infallible> + Wait<Error = Infallible>,
+    ); K::NUM_ENCODERS],
+) where
+    [(); K::NUM_ENCODERS]:,
+{
+    #[allow(clippy::let_unit_value)]
+    let () = EncoderCheck::<K>::CHECK;
+
+    // Stores the last known state of the A and B pins, and the number of state changes since the
+    // last registered step, for each encoder.
+    let mut states = [(0u8, 0i8); K::NUM_ENCODERS];
+    for ((a, b), (state, _)) in encoders.iter().zip(states.iter_mut()) {
+        *state = read_encoder_state(a, b);
+    }
+
+    loop {
+        // Stop polling while the keyboard is idle, until one of the encoders is turned
+        if IDLE_STATE.get().await {
+            embassy_futures::select::select_array(encoders.each_mut().map(|(a, b)| {
+                embassy_futures::select::select(a.wait_for_any_edge(), b.wait_for_any_edge())
+            }))
+            .await;
+        }
+
+        for (id, ((a, b), (state, pulses))) in encoders.iter().zip(states.iter_mut()).enumerate() {
+            let new_state = read_encoder_state(a, b);
+
+            if new_state == *state {
+                continue;
+            }
+
+            *pulses += ENCODER_LUT[((*state << 2) | new_state) as usize];
+            *state = new_state;
+
+            if pulses.unsigned_abs() >= K::ENCODER_RESOLUTION {
+                let clockwise = *pulses > 0;
+                *pulses = 0;
+
+                let (cw, ccw) = K::ENCODER_LAYOUT_POSITIONS[id];
+                let (row, col) = if clockwise { cw } else { ccw };
+
+                info!(
+                    "[KEYBOARD] Encoder {} step, clockwise: {}, layout position: {:?}",
+                    id,
+                    clockwise,
+                    Debug2Format(&(row, col))
+                );
+
+                ACTIVITY_SIGNAL.signal(());
+
+                // Tap the key at the encoder's layout position
+                POLLED_EVENTS_CHANNEL.send(Event::Press(row, col)).await;
+                POLLED_EVENTS_CHANNEL.send(Event::Release(row, col)).await;
+            }
+        }
+
         Timer::after(Duration::from_micros(500)).await;
     }
 }
@@ -277,28 +689,112 @@ pub static KEYBOARD_REPORT_HID_SEND_CHANNEL: Channel<RawMutex, NKROBootKeyboardR
 pub static CONSUMER_REPORT_HID_SEND_CHANNEL: Channel<RawMutex, MultipleConsumerReport, 1> =
     Channel::new();
 
+/// Channel for sending mouse HID reports.
+///
+/// Channel messages should be consumed by the bluetooth task or USB task, so user-level code
+/// should **not** attempt to receive messages from the channel, otherwise commands may not be
+/// processed appropriately. You should only send to this channel.
+pub static MOUSE_REPORT_HID_SEND_CHANNEL: Channel<RawMutex, WheelMouseReport, 1> = Channel::new();
+
 #[rumcake_macros::task]
 pub async fn layout_collect<K: KeyboardLayout + 'static>(_k: K)
 where
     [(); K::LAYERS]:,
     [(); K::LAYOUT_COLS]:,
     [(); K::LAYOUT_ROWS]:,
+    [(); K::NUM_COMBOS]:,
+    [(); K::NUM_KEY_OVERRIDES]:,
+    [(); K::NUM_TAP_DANCES]:,
 {
     let mut last_keys = Vec::<KeyboardKeycode, 24>::new();
     let layout = K::get_layout();
+    let combos = K::get_combos();
+    let mut combo_processor = ComboProcessor::new(K::COMBO_TIMEOUT_MS);
+    let tap_dances = K::get_tap_dances();
+    let mut tap_dance_processor = TapDanceProcessor::new();
+    let key_overrides = K::get_key_overrides();
+    let mut key_override_processor = KeyOverrideProcessor::new();
+    let mut auto_shift_processor = AutoShiftProcessor::new();
+    let mut caps_word_processor = CapsWordProcessor::new();
+    let leader_sequences = K::get_leader_sequences();
+    let mut leader_processor = LeaderProcessor::new();
 
     #[cfg(feature = "media-keycodes")]
     let mut codes = [Consumer::Unassigned; 4];
 
+    #[cfg(feature = "mouse-keys")]
+    let mut mouse_key_processor = MouseKeyProcessor::new();
+
+    #[cfg(feature = "unicode")]
+    let mut unicode_processor = UnicodeProcessor::new();
+
+    #[cfg(feature = "dynamic-macros")]
+    let mut dynamic_macro_processor = DynamicMacroProcessor::new();
+
+    #[cfg(feature = "pointing-device")]
+    let mut pointing_processor = PointingProcessor::new();
+
     let mut ticker = Ticker::every(Duration::from_millis(1));
 
     loop {
+        if LAYOUT_SETTINGS_LISTENER.try_take().is_some() {
+            let settings = KEYBOARD_SETTINGS_STATE.get().await;
+            layout.lock().await.set_settings(settings.layout_settings());
+            combo_processor.set_default_timeout(settings.combo_term.unwrap_or(K::COMBO_TIMEOUT_MS));
+            auto_shift_processor.set_settings(
+                settings.auto_shift.unwrap_or_default(),
+                settings
+                    .auto_shift_timeout
+                    .unwrap_or(DEFAULT_AUTO_SHIFT_TIMEOUT),
+            );
+        }
+
+        #[cfg(feature = "unicode")]
+        {
+            if UNICODE_MODE_LISTENER.try_take().is_some() {
+                unicode_processor.set_mode(UNICODE_MODE_STATE.get().await);
+            }
+        }
+
+        #[cfg(feature = "pointing-device")]
+        let current_layer;
+
+        // CPI changes are applied after the layout is unlocked, since sending them to split
+        // peripherals can wait on a full channel
+        #[cfg(feature = "pointing-device")]
+        let mut new_cpi = None;
+
         let keys = {
             let mut layout = layout.lock().await;
 
-            if let Ok(event) = POLLED_EVENTS_CHANNEL.try_receive() {
+            // Keys tapped after the leader key are captured before they reach combos or the layout
+            let mut event = match POLLED_EVENTS_CHANNEL.try_receive() {
+                Ok(event) => {
+                    MATRIX_EVENTS.publish_immediate(event); // Just immediately publish since we don't want to hold up any key events to be converted into keycodes.
+                    leader_processor.event(&mut layout, leader_sequences, event)
+                }
+                Err(_) => None,
+            };
+            leader_processor.tick(&mut layout, leader_sequences, K::LEADER_TIMEOUT_MS);
+
+            // Tap dance keys decide which action to perform before combos are resolved
+            if let Some(tap_dances) = tap_dances {
+                let tap_dances = tap_dances.lock().await;
+                event = event.and_then(|event| {
+                    tap_dance_processor.event(&mut layout, tap_dances.as_slice(), event)
+                });
+                tap_dance_processor.tick(&mut layout, tap_dances.as_slice());
+            }
+
+            // Combos are resolved before events reach the layout
+            if let Some(combos) = combos {
+                let combos = combos.lock().await;
+                if let Some(event) = event {
+                    combo_processor.event(&mut layout, combos.as_slice(), event);
+                };
+                combo_processor.tick(&mut layout, combos.as_slice());
+            } else if let Some(event) = event {
                 layout.event(event);
-                MATRIX_EVENTS.publish_immediate(event); // Just immediately publish since we don't want to hold up any key events to be converted into keycodes.
             };
 
             let tick = layout.tick();
@@ -311,6 +807,19 @@ where
                     Keycode::Custom(id) => {
                         K::on_custom_keycode(id, true);
                     }
+                    Keycode::CapsWord => {
+                        caps_word_processor.toggle();
+                    }
+                    Keycode::Leader => {
+                        leader_processor.start();
+                    }
+                    Keycode::AutoShift(command) => {
+                        crate::auto_shift::process_command(command).await;
+                    }
+                    Keycode::TapDance(_) => {
+                        // Tap dance keys are resolved by the tap dance processor, so this is only
+                        // reached if a tap dance is triggered by something else (e.g. a combo)
+                    }
                     #[cfg(feature = "media-keycodes")]
                     Keycode::Media(keycode) => {
                         if let Some(c) =
@@ -322,6 +831,17 @@ where
                             .send(MultipleConsumerReport { codes })
                             .await;
                     }
+                    #[cfg(feature = "mouse-keys")]
+                    Keycode::Mouse(keycode) => {
+                        mouse_key_processor.press(keycode);
+                    }
+                    #[cfg(feature = "pointing-device")]
+                    Keycode::Pointing(command) => {
+                        if let PointingCommand::SetCpi(cpi) = command {
+                            new_cpi = Some(cpi);
+                        }
+                        pointing_processor.process_command(command, true);
+                    }
                     #[cfg(feature = "underglow")]
                     Keycode::Underglow(command) => {
                         crate::underglow::UNDERGLOW_COMMAND_CHANNEL
@@ -368,6 +888,48 @@ where
                             .send(command)
                             .await;
                     }
+                    #[cfg(feature = "analog-matrix")]
+                    Keycode::Analog(command) => {
+                        crate::matrix::analog::process_command(command).await;
+                    }
+                    #[cfg(feature = "unicode")]
+                    Keycode::Unicode(c) => {
+                        unicode_processor.type_char(c);
+                    }
+                    #[cfg(feature = "unicode")]
+                    Keycode::UnicodeMap(idx) => {
+                        if let Some(c) = K::UNICODE_MAP.get(idx as usize) {
+                            unicode_processor.type_char(*c);
+                        }
+                    }
+                    #[cfg(feature = "unicode")]
+                    Keycode::UnicodeMode(command) => {
+                        crate::unicode::process_command(command).await;
+                    }
+                    #[cfg(feature = "dynamic-macros")]
+                    Keycode::DynamicMacroRecord(idx) => {
+                        dynamic_macro_processor.record(&K::DYNAMIC_MACRO_CONFIG, idx);
+                    }
+                    #[cfg(feature = "dynamic-macros")]
+                    Keycode::DynamicMacroStop => {
+                        dynamic_macro_processor.stop(&K::DYNAMIC_MACRO_CONFIG);
+                    }
+                    #[cfg(feature = "dynamic-macros")]
+                    Keycode::DynamicMacroPlay(idx) => {
+                        dynamic_macro_processor.play(idx);
+                    }
+                    #[cfg(feature = "profiles")]
+                    Keycode::Profile(profile) => {
+                        crate::profiles::set_profile::<K>(profile).await;
+                    }
+                    #[cfg(feature = "profiles")]
+                    Keycode::NextProfile => {
+                        crate::profiles::next_profile::<K>().await;
+                    }
+                    #[cfg(feature = "profiles")]
+                    Keycode::PrevProfile => {
+                        crate::profiles::prev_profile::<K>().await;
+                    }
                 },
                 CustomEvent::Release(keycode) => match keycode {
                     Keycode::Custom(id) => {
@@ -382,6 +944,14 @@ where
                             .send(MultipleConsumerReport { codes })
                             .await;
                     }
+                    #[cfg(feature = "mouse-keys")]
+                    Keycode::Mouse(keycode) => {
+                        mouse_key_processor.release(keycode);
+                    }
+                    #[cfg(feature = "pointing-device")]
+                    Keycode::Pointing(command) => {
+                        pointing_processor.process_command(command, false);
+                    }
                     #[allow(unreachable_patterns)]
                     _ => {}
                 },
@@ -389,16 +959,117 @@ where
 
             debug!("[KEYBOARD] Collecting keyboard keycodes");
 
-            let keys = layout
+            let mut keys = layout
                 .keycodes()
                 .filter_map(|k| KeyboardKeycode::try_from(k as u8).ok())
                 .collect::<Vec<KeyboardKeycode, 24>>();
 
+            if let Some(key_overrides) = key_overrides {
+                key_override_processor.process(
+                    key_overrides.lock().await.as_slice(),
+                    &mut keys,
+                    layout.current_layer(),
+                );
+            }
+
+            auto_shift_processor.process(&mut keys);
+            caps_word_processor.process(&K::CAPS_WORD_CONFIG, &mut keys);
+
+            #[cfg(feature = "dynamic-macros")]
+            dynamic_macro_processor.process(&K::DYNAMIC_MACRO_CONFIG, &keys);
+
+            #[cfg(feature = "pointing-device")]
+            {
+                current_layer = layout.current_layer();
+            }
+
             debug!("[KEYBOARD] Collected {:?}", Debug2Format(&keys));
 
             keys
         }; // unlock the layout, so that another task can register new layout events
 
+        #[cfg(feature = "pointing-device")]
+        if let Some(cpi) = new_cpi {
+            crate::pointing::POINTING_CPI_STATE.set(Some(cpi)).await;
+            #[cfg(feature = "split-central")]
+            crate::split::central::MESSAGE_TO_PERIPHERALS
+                .send(crate::split::MessageToPeripheral::PointingCpi(cpi))
+                .await;
+        }
+
+        #[cfg(any(feature = "mouse-keys", feature = "pointing-device"))]
+        {
+            #[cfg(feature = "mouse-keys")]
+            let report = mouse_key_processor.tick(&K::MOUSE_KEYS_CONFIG);
+            #[cfg(not(feature = "mouse-keys"))]
+            let report = None;
+
+            // Motion from the pointing device is merged into the mouse keys report, so that the
+            // buttons held by mouse keys aren't released
+            #[cfg(feature = "pointing-device")]
+            let report = {
+                #[cfg(feature = "mouse-keys")]
+                let buttons = mouse_key_processor.buttons();
+                #[cfg(not(feature = "mouse-keys"))]
+                let buttons = 0;
+
+                while let Ok(motion) = POINTING_MOTION_CHANNEL.try_receive() {
+                    pointing_processor.add_motion(motion);
+                }
+
+                pointing_processor.report(&K::POINTING_CONFIG, current_layer, buttons, report)
+            };
+
+            if let Some(report) = report {
+                // Mouse movement is relative, so reports are dropped if there is no connection
+                if CURRENT_OUTPUT_STATE.get().await.is_some() {
+                    MOUSE_REPORT_HID_SEND_CHANNEL.send(report).await;
+                }
+            }
+        }
+
+        // Unicode input sequences are sent one report per tick, without waiting for the channel,
+        // so that the layout keeps processing events. The layout's keys are sent again once the
+        // sequence is done.
+        #[cfg(feature = "unicode")]
+        {
+            if let Some(report) = unicode_processor.peek() {
+                if CURRENT_OUTPUT_STATE.get().await.is_none() {
+                    unicode_processor.pop();
+                } else if KEYBOARD_REPORT_HID_SEND_CHANNEL
+                    .try_send(NKROBootKeyboardReport::new(report.clone()))
+                    .is_ok()
+                {
+                    unicode_processor.pop();
+                }
+
+                last_keys.clear();
+                ticker.next().await;
+                continue;
+            }
+        }
+
+        // Dynamic macros are played back in the same way as Unicode input sequences
+        #[cfg(feature = "dynamic-macros")]
+        {
+            if dynamic_macro_processor.is_playing() {
+                if let Some(report) = dynamic_macro_processor.peek() {
+                    if CURRENT_OUTPUT_STATE.get().await.is_none() {
+                        dynamic_macro_processor.pop();
+                    } else if KEYBOARD_REPORT_HID_SEND_CHANNEL
+                        .try_send(NKROBootKeyboardReport::new(report.clone()))
+                        .is_ok()
+                    {
+                        dynamic_macro_processor.pop();
+                    }
+                }
+
+                last_keys.clear();
+                ticker.next().await;
+                continue;
+            }
+        }
+
         if last_keys != keys {
             last_keys.clone_from(&keys);
 
@@ -417,6 +1088,16 @@ where
             }
         }
 
+        // No keys are held while the keyboard is idle, so the layout doesn't need to be updated
+        // until the keyboard wakes up
+        if IDLE_STATE.get().await {
+            while IDLE_STATE.get().await {
+                LAYOUT_IDLE_STATE_LISTENER.wait().await;
+            }
+
+            ticker.reset();
+        }
+
         ticker.next().await;
     }
 }
@@ -0,0 +1,175 @@
+//! Support for leader key sequences.
+//!
+//! After the [`Keycode::Leader`] key is pressed, the next keys that are tapped are captured
+//! instead of being sent to the host. When the captured keys match one of the layout's leader
+//! sequences, the sequence's action is triggered. The behaviour of leader keys is based on QMK's
+//! implementation. To use leader sequences, your keyboard must implement
+//! [`crate::keyboard::KeyboardLayout::get_leader_sequences`]. It is recommended to use
+//! [`build_leader_sequences`] to implement it.
+//!
+//! Capturing ends when:
+//! - the captured keys match a sequence, and no other sequence starts with those keys
+//! - no key is pressed for [`crate::keyboard::KeyboardLayout::LEADER_TIMEOUT_MS`]
+//! - the leader key is pressed again
+//!
+//! [`Keycode::Leader`]: crate::keyboard::Keycode::Leader
+
+use heapless::Vec;
+use keyberon::action::Action;
+use keyberon::layout::{Event, Layout as KeyberonLayout};
+
+use crate::keyboard::Keycode;
+
+pub use rumcake_macros::build_leader_sequences;
+
+/// Maximum number of keys that can be used in a single leader sequence. This matches QMK.
+pub const MAX_LEADER_KEYS: usize = 5;
+
+/// Layout row used for the virtual keys that are pressed when a leader sequence is triggered. The
+/// column of the virtual key corresponds to the index of the sequence. Combos use the row after
+/// this one.
+const LEADER_ROW: u8 = u8::MAX - 1;
+
+/// A sequence of keys that triggers an action when it is tapped after the leader key.
+#[derive(Debug, Clone, Copy)]
+pub struct LeaderSequence {
+    /// Actions that need to be tapped, in order, to trigger the sequence. Unused slots at the end
+    /// should be set to [`Action::NoOp`].
+    pub keys: [Action<Keycode>; MAX_LEADER_KEYS],
+
+    /// Action that will be tapped when the sequence is completed.
+    pub output: Action<Keycode>,
+}
+
+impl LeaderSequence {
+    /// Create a new leader sequence.
+    pub const fn new(keys: [Action<Keycode>; MAX_LEADER_KEYS], output: Action<Keycode>) -> Self {
+        Self { keys, output }
+    }
+
+    fn len(&self) -> usize {
+        self.keys.iter().filter(|k| **k != Action::NoOp).count()
+    }
+
+    /// Check if the captured actions are the beginning of this sequence.
+    fn starts_with(&self, actions: &[Action<Keycode>]) -> bool {
+        actions.len() <= self.len() && self.keys[..actions.len()] == *actions
+    }
+}
+
+/// Captures the keys tapped after the leader key, before they are sent to the layout.
+pub(crate) struct LeaderProcessor {
+    active: bool,
+
+    /// Actions of the keys that have been tapped since the leader key was pressed.
+    captured: Vec<Action<Keycode>, MAX_LEADER_KEYS>,
+
+    /// Time (in milliseconds) since the leader key or the last captured key was pressed.
+    elapsed: u16,
+
+    /// Index of the sequence whose output was pressed on the last tick, and should be released.
+    triggered: Option<u8>,
+}
+
+impl LeaderProcessor {
+    pub(crate) fn new() -> Self {
+        Self {
+            active: false,
+            captured: Vec::new(),
+            elapsed: 0,
+            triggered: None,
+        }
+    }
+
+    /// Start capturing keys. This should be called when the leader key is pressed.
+    pub(crate) fn start(&mut self) {
+        self.active = true;
+        self.captured.clear();
+        self.elapsed = 0;
+    }
+
+    /// Stop capturing keys, and trigger the sequence that matches the captured keys, if there is
+    /// one.
+    fn finish<const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+        sequences: &[LeaderSequence],
+    ) {
+        self.active = false;
+
+        if let Some(idx) = sequences
+            .iter()
+            .position(|s| s.len() == self.captured.len() && s.starts_with(&self.captured))
+        {
+            layout.event_with_action((LEADER_ROW, idx as u8), sequences[idx].output);
+            self.triggered = Some(idx as u8);
+        }
+
+        self.captured.clear();
+    }
+
+    /// Process a matrix event. Returns the event if it should be sent to the layout.
+    pub(crate) fn event<const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+        sequences: &[LeaderSequence],
+        event: Event,
+    ) -> Option<Event> {
+        if !self.active {
+            return Some(event);
+        }
+
+        match event {
+            Event::Press(row, col) => {
+                let action = layout.action_at((row, col));
+
+                if action == Action::Custom(Keycode::Leader) {
+                    self.finish(layout, sequences);
+                    return None;
+                }
+
+                self.elapsed = 0;
+
+                if self.captured.push(action).is_err() {
+                    self.finish(layout, sequences);
+                    return None;
+                }
+
+                // Finish early if waiting for more keys can't change the result
+                if !sequences
+                    .iter()
+                    .any(|s| s.len() > self.captured.len() && s.starts_with(&self.captured))
+                {
+                    self.finish(layout, sequences);
+                }
+
+                None
+            }
+            // Releases are always sent, since some keys may have been pressed before the leader
+            // key. Releasing a key that was captured has no effect on the layout.
+            Event::Release(_, _) => Some(event),
+        }
+    }
+
+    /// Update the leader timer, and release the output of a triggered sequence. This should be
+    /// called every millisecond.
+    pub(crate) fn tick<const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+        sequences: &[LeaderSequence],
+        timeout: u16,
+    ) {
+        if let Some(idx) = self.triggered.take() {
+            layout.event(Event::Release(LEADER_ROW, idx));
+        }
+
+        if !self.active {
+            return;
+        }
+
+        self.elapsed = self.elapsed.saturating_add(1);
+        if self.elapsed >= timeout {
+            self.finish(layout, sequences);
+        }
+    }
+}
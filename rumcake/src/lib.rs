@@ -112,6 +112,8 @@ impl<'a, T: Clone + PartialEq> State<'a, T> {
 // TODO: remove re-exports
 
 pub use embedded_hal;
+#[cfg(any(feature = "w25qxx", feature = "at24cxx"))]
+pub use embedded_hal_1;
 pub use embedded_hal_async;
 pub use embedded_io_async;
 pub use embedded_storage_async;
@@ -119,12 +121,32 @@ pub use keyberon;
 
 pub use rumcake_macros::keyboard_main as keyboard;
 
+pub mod auto_shift;
+pub mod caps_word;
+pub mod combos;
+pub mod debounce;
+#[cfg(feature = "dynamic-macros")]
+pub mod dynamic_macros;
+pub mod key_overrides;
 pub mod keyboard;
+pub mod leader;
 mod math;
+pub mod matrix;
+#[cfg(feature = "mouse-keys")]
+pub mod mouse;
+#[cfg(feature = "pointing-device")]
+pub mod pointing;
+pub mod settings;
+pub mod tap_dance;
+#[cfg(feature = "unicode")]
+pub mod unicode;
 
 #[cfg(feature = "storage")]
 pub mod storage;
 
+#[cfg(feature = "profiles")]
+pub mod profiles;
+
 #[cfg(feature = "underglow")]
 pub mod underglow;
 
@@ -155,7 +177,20 @@ pub mod drivers;
 
 pub mod tasks {
     pub use crate::hw::__output_switcher;
-    pub use crate::keyboard::{__layout_collect, __matrix_poll};
+    pub use crate::keyboard::{__encoder_poll, __layout_collect, __matrix_poll};
+    #[cfg(feature = "storage")]
+    pub use crate::settings::storage::__keyboard_settings_storage_task;
+    #[cfg(feature = "storage")]
+    pub use crate::storage::__config_backup_task;
+
+    #[cfg(all(feature = "storage", feature = "analog-matrix"))]
+    pub use crate::matrix::analog::storage::__analog_matrix_storage_task;
+
+    #[cfg(all(feature = "storage", feature = "unicode"))]
+    pub use crate::unicode::storage::__unicode_storage_task;
+
+    #[cfg(feature = "profiles")]
+    pub use crate::profiles::__profile_storage_task;
 
     #[cfg(feature = "simple-backlight")]
     pub use crate::backlight::simple_backlight::__simple_backlight_task;
@@ -180,8 +215,14 @@ pub mod tasks {
     #[cfg(feature = "display")]
     pub use crate::display::__display_task;
 
+    #[cfg(feature = "pointing-device")]
+    pub use crate::pointing::__pointing_task;
+
     #[cfg(feature = "usb")]
-    pub use crate::usb::{__start_usb, __usb_hid_consumer_write_task, __usb_hid_kb_write_task};
+    pub use crate::usb::{
+        __start_usb, __usb_hid_consumer_write_task, __usb_hid_kb_write_task,
+        __usb_hid_mouse_write_task,
+    };
 
     #[cfg(all(feature = "via", feature = "usb"))]
     pub use crate::usb::__usb_hid_via_read_task;
@@ -217,3 +258,25 @@ pub mod tasks {
     #[cfg(all(feature = "nrf-ble", feature = "split-peripheral"))]
     pub use crate::drivers::nrf_ble::peripheral::__nrf_ble_peripheral_task;
 }
+
+/// `defmt` logger used when running tests on the host machine. Log messages are discarded.
+#[cfg(test)]
+mod test_logger {
+    #[defmt::global_logger]
+    struct Logger;
+
+    unsafe impl defmt::Logger for Logger {
+        fn acquire() {}
+
+        unsafe fn flush() {}
+
+        unsafe fn release() {}
+
+        unsafe fn write(_bytes: &[u8]) {}
+    }
+
+    #[defmt::panic_handler]
+    fn panic() -> ! {
+        core::panic!("defmt panic")
+    }
+}
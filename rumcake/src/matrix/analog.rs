@@ -0,0 +1,601 @@
+//! Analog matrices, for keyboards with Hall effect (magnetic) switches.
+//!
+//! Instead of reading whether a switch is closed, an [`AnalogMatrix`] samples a sensor for each
+//! key with an ADC, through one or more analog multiplexers. The samples are converted into the
+//! travel of each key, which is compared against the actuation and release points in
+//! [`AnalogConfig`] to decide whether the key is pressed. With rapid trigger enabled, a key is
+//! released as soon as it starts moving up, and pressed again as soon as it starts moving down, as
+//! long as it is past the actuation point. A key that is released by rapid trigger doesn't need to
+//! return to the release point before it can be pressed again.
+//!
+//! The analog config can be changed at runtime using [`ANALOG_CONFIG_STATE`], or with
+//! [`crate::keyboard::Keycode::Analog`] keycodes.
+//!
+//! ## Calibration
+//!
+//! Key travel is measured relative to the sensor value of each key at rest, and when it is fully
+//! pressed ("bottomed out"). When the matrix is first scanned, the rest values are measured, and
+//! the bottom-out values are estimated using the range passed to [`AnalogMatrix::new`]. For more
+//! accurate results, you can start calibration with [`AnalogCommand::ToggleCalibration`], press
+//! every key all the way down, and finish calibration with the same command. Keys must not be
+//! pressed when calibration starts. If storage is enabled, the calibration is saved, and restored
+//! the next time the keyboard starts.
+
+use core::convert::Infallible;
+
+use defmt::{assert, info, warn};
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+use embedded_hal::digital::v2::OutputPin;
+use heapless::Vec;
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+use crate::hw::mcu::RawMutex;
+use crate::State;
+
+use super::MatrixScanner;
+
+/// Size of the buffer used to transfer calibration data to and from storage. Each key uses 4
+/// bytes, so this limits analog matrices to 256 keys.
+pub const CALIBRATION_BUFFER_SIZE: usize = 1024;
+
+/// Calibration keys with a range smaller than this fraction of the default range (`1 / n`) are
+/// considered to not have been pressed during calibration, and use the default range instead.
+const MIN_CALIBRATED_RANGE_DIVISOR: i32 = 4;
+
+/// Settings that control when the keys of an analog matrix are pressed and released.
+///
+/// Key travel is represented by a value from 0 (at rest) to 255 (fully pressed).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, MaxSize)]
+pub struct AnalogConfig {
+    /// Travel at which a key is pressed.
+    pub actuation_point: u8,
+    /// Travel at which a pressed key is released. This should be lower than
+    /// [`AnalogConfig::actuation_point`], to avoid chattering around the actuation point.
+    pub release_point: u8,
+    /// Whether rapid trigger is enabled.
+    pub rapid_trigger: bool,
+    /// With rapid trigger enabled, how far a key has to move in the opposite direction before it
+    /// is pressed or released again.
+    pub rapid_trigger_sensitivity: u8,
+}
+
+impl AnalogConfig {
+    /// Create a new analog config, with the default settings: actuation at 50% travel, release at
+    /// 40% travel, and rapid trigger disabled with a sensitivity of 5% travel.
+    pub const fn new() -> Self {
+        Self {
+            actuation_point: 128,
+            release_point: 102,
+            rapid_trigger: false,
+            rapid_trigger_sensitivity: 13,
+        }
+    }
+}
+
+impl Default for AnalogConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Commands that can be used to control an analog matrix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnalogCommand {
+    /// Enable or disable rapid trigger.
+    ToggleRapidTrigger,
+    /// Start calibration, or finish calibration if it is already running.
+    ToggleCalibration,
+}
+
+/// State that contains the current analog config.
+pub static ANALOG_CONFIG_STATE: State<AnalogConfig> = State::new(
+    AnalogConfig::new(),
+    &[
+        &ANALOG_CONFIG_LISTENER,
+        #[cfg(feature = "storage")]
+        &storage::ANALOG_CONFIG_STATE_LISTENER,
+    ],
+);
+
+pub(crate) static ANALOG_CONFIG_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+pub(crate) static CALIBRATION_SIGNAL: Signal<RawMutex, ()> = Signal::new();
+
+/// Calibration data loaded from storage, in the same format as [`CALIBRATION_SAVE_SIGNAL`].
+pub(crate) static CALIBRATION_LOAD_SIGNAL: Signal<RawMutex, Vec<u8, CALIBRATION_BUFFER_SIZE>> =
+    Signal::new();
+
+/// Calibration data to save to storage, as the rest and bottom-out values of each key (in
+/// little-endian), in row-major order.
+pub(crate) static CALIBRATION_SAVE_SIGNAL: Signal<RawMutex, Vec<u8, CALIBRATION_BUFFER_SIZE>> =
+    Signal::new();
+
+/// Process an [`AnalogCommand`].
+pub(crate) async fn process_command(command: AnalogCommand) {
+    match command {
+        AnalogCommand::ToggleRapidTrigger => {
+            ANALOG_CONFIG_STATE
+                .update(|config| config.rapid_trigger = !config.rapid_trigger)
+                .await;
+        }
+        AnalogCommand::ToggleCalibration => {
+            CALIBRATION_SIGNAL.signal(());
+        }
+    }
+}
+
+/// A trait for ADCs that can sample the output of the multiplexers in an [`AnalogMatrix`].
+///
+/// On RP2040, an implementation can be created with `setup_adc_sampler!`. On other MCUs, you must
+/// implement this trait yourself, since each HAL has a different ADC API. Implementations should
+/// configure the ADC inputs ahead of time, so that samples can be taken quickly.
+pub trait AnalogSampler {
+    /// Take a sample from the given ADC input. `input` is the index of the multiplexer (or
+    /// sensor) that is connected to the input.
+    async fn sample(&mut self, input: usize) -> u16;
+}
+
+/// Analog multiplexers (e.g. 74HC4051, 74HC4067) that share a set of `S` select pins.
+pub struct AnalogMultiplexer<P: OutputPin<Error = Infallible>, const S: usize> {
+    select: [P; S],
+}
+
+impl<P: OutputPin<Error = Infallible>, const S: usize> AnalogMultiplexer<P, S> {
+    /// Create a new multiplexer with the given select pins. The first pin is the least
+    /// significant bit of the channel (usually `S0` or `A`).
+    pub fn new(select: [P; S]) -> Self {
+        Self { select }
+    }
+
+    /// Select the given channel.
+    pub fn select(&mut self, channel: usize) {
+        for (bit, pin) in self.select.iter_mut().enumerate() {
+            if channel & (1 << bit) != 0 {
+                pin.set_high().unwrap();
+            } else {
+                pin.set_low().unwrap();
+            }
+        }
+    }
+}
+
+/// The rest and bottom-out values of a key's sensor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct KeyCalibration {
+    rest: u16,
+    bottom_out: u16,
+}
+
+impl KeyCalibration {
+    /// Calibration for a key at rest, with an estimated bottom-out value.
+    fn estimate(rest: u16, range: i16) -> Self {
+        Self {
+            rest,
+            bottom_out: (rest as i32 + range as i32).clamp(0, u16::MAX as i32) as u16,
+        }
+    }
+
+    /// Convert a sample to the travel of the key, from 0 (at rest) to 255 (fully pressed). This
+    /// works for sensors that increase or decrease as the key is pressed.
+    fn travel(&self, sample: u16) -> u8 {
+        let range = self.bottom_out as i32 - self.rest as i32;
+        if range == 0 {
+            return 0;
+        }
+
+        ((sample as i32 - self.rest as i32) * 255 / range).clamp(0, 255) as u8
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct KeyState {
+    pressed: bool,
+    /// With rapid trigger, the lowest travel since the key was released, or the highest travel
+    /// since the key was pressed.
+    extreme: u8,
+}
+
+impl KeyState {
+    fn update(&mut self, travel: u8, config: &AnalogConfig) {
+        if config.rapid_trigger {
+            if self.pressed {
+                self.extreme = self.extreme.max(travel);
+                if travel <= config.release_point
+                    || travel.saturating_add(config.rapid_trigger_sensitivity) <= self.extreme
+                {
+                    self.pressed = false;
+                    self.extreme = travel;
+                }
+            } else {
+                self.extreme = self.extreme.min(travel);
+                if travel >= config.actuation_point
+                    && travel
+                        >= self
+                            .extreme
+                            .saturating_add(config.rapid_trigger_sensitivity)
+                {
+                    self.pressed = true;
+                    self.extreme = travel;
+                }
+            }
+        } else if self.pressed {
+            if travel <= config.release_point {
+                self.pressed = false;
+            }
+        } else if travel >= config.actuation_point {
+            self.pressed = true;
+        }
+    }
+}
+
+/// A matrix of analog sensors (e.g. Hall effect sensors), sampled by an ADC through analog
+/// multiplexers.
+///
+/// Each multiplexer is connected to its own ADC input, and all multiplexers share the same `S`
+/// select pins. The position of each key is given as `(input, channel)`, where `input` is the
+/// index passed to [`AnalogSampler::sample`], and `channel` is the multiplexer channel that the
+/// sensor is connected to. Positions without a key use `None`.
+pub struct AnalogMatrix<
+    A: AnalogSampler,
+    P: OutputPin<Error = Infallible>,
+    const S: usize,
+    const C: usize,
+    const R: usize,
+> {
+    sampler: A,
+    multiplexer: AnalogMultiplexer<P, S>,
+    keys: [[Option<(u8, u8)>; C]; R],
+    default_range: i16,
+    config: AnalogConfig,
+    calibration: [[KeyCalibration; C]; R],
+    state: [[KeyState; C]; R],
+    calibrated: bool,
+    calibrating: bool,
+}
+
+impl<
+        A: AnalogSampler,
+        P: OutputPin<Error = Infallible>,
+        const S: usize,
+        const C: usize,
+        const R: usize,
+    > AnalogMatrix<A, P, S, C, R>
+{
+    /// Create a new analog matrix.
+    ///
+    /// `default_range` is the expected difference between the sensor value of a fully pressed
+    /// key, and a key at rest. This is negative if the sensor value decreases as the key is
+    /// pressed. It is used for keys that haven't been calibrated.
+    pub fn new(
+        sampler: A,
+        multiplexer: AnalogMultiplexer<P, S>,
+        keys: [[Option<(u8, u8)>; C]; R],
+        default_range: i16,
+    ) -> Self {
+        assert!(
+            C * R * 4 <= CALIBRATION_BUFFER_SIZE,
+            "Analog matrix has too many keys to store its calibration"
+        );
+
+        Self {
+            sampler,
+            multiplexer,
+            keys,
+            default_range,
+            config: AnalogConfig::new(),
+            calibration: [[KeyCalibration {
+                rest: 0,
+                bottom_out: 0,
+            }; C]; R],
+            state: [[KeyState::default(); C]; R],
+            calibrated: false,
+            calibrating: false,
+        }
+    }
+
+    async fn sample(&mut self) -> [[u16; C]; R] {
+        let mut samples = [[0; C]; R];
+
+        for channel in 0..(1 << S) {
+            self.multiplexer.select(channel);
+            // Give the multiplexer time to settle
+            embassy_time::block_for(Duration::from_ticks(2));
+
+            for (keys, samples) in self.keys.iter().zip(samples.iter_mut()) {
+                for (key, sample) in keys.iter().zip(samples.iter_mut()) {
+                    if let Some((input, key_channel)) = key {
+                        if *key_channel as usize == channel {
+                            *sample = self.sampler.sample(*input as usize).await;
+                        }
+                    }
+                }
+            }
+        }
+
+        samples
+    }
+
+    fn load_calibration(&mut self, data: &[u8]) {
+        if data.len() != C * R * 4 {
+            warn!("[ANALOG] Stored calibration does not match the matrix, ignoring it.");
+            return;
+        }
+
+        for (calibration, bytes) in self
+            .calibration
+            .iter_mut()
+            .flatten()
+            .zip(data.chunks_exact(4))
+        {
+            calibration.rest = u16::from_le_bytes([bytes[0], bytes[1]]);
+            calibration.bottom_out = u16::from_le_bytes([bytes[2], bytes[3]]);
+        }
+
+        self.calibrated = true;
+        info!("[ANALOG] Loaded calibration from storage.");
+    }
+
+    fn start_calibration(&mut self, samples: &[[u16; C]; R]) {
+        info!("[ANALOG] Calibration started. Press every key all the way down.");
+
+        for (calibration, sample) in self
+            .calibration
+            .iter_mut()
+            .flatten()
+            .zip(samples.iter().flatten())
+        {
+            calibration.rest = *sample;
+            calibration.bottom_out = *sample;
+        }
+
+        self.state = [[KeyState::default(); C]; R];
+        self.calibrating = true;
+    }
+
+    fn finish_calibration(&mut self) {
+        let min_range = (self.default_range as i32 / MIN_CALIBRATED_RANGE_DIVISOR).abs();
+        let mut data = Vec::<u8, CALIBRATION_BUFFER_SIZE>::new();
+
+        for row in 0..R {
+            for col in 0..C {
+                let mut calibration = self.calibration[row][col];
+                if (calibration.bottom_out as i32 - calibration.rest as i32).abs() < min_range {
+                    calibration = KeyCalibration::estimate(calibration.rest, self.default_range);
+                    self.calibration[row][col] = calibration;
+                }
+
+                let _ = data.extend_from_slice(&calibration.rest.to_le_bytes());
+                let _ = data.extend_from_slice(&calibration.bottom_out.to_le_bytes());
+            }
+        }
+
+        self.calibrating = false;
+        self.calibrated = true;
+        CALIBRATION_SAVE_SIGNAL.signal(data);
+        info!("[ANALOG] Calibration finished.");
+    }
+}
+
+impl<
+        A: AnalogSampler,
+        P: OutputPin<Error = Infallible>,
+        const S: usize,
+        const C: usize,
+        const R: usize,
+    > MatrixScanner<C, R> for AnalogMatrix<A, P, S, C, R>
+{
+    async fn scan(&mut self) -> [[bool; C]; R] {
+        if ANALOG_CONFIG_LISTENER.try_take().is_some() {
+            self.config = ANALOG_CONFIG_STATE.get().await;
+        }
+
+        if let Some(data) = CALIBRATION_LOAD_SIGNAL.try_take() {
+            self.load_calibration(&data);
+        }
+
+        let samples = self.sample().await;
+
+        // Measure the rest values on the first scan, if there is no stored calibration
+        if !self.calibrated {
+            for (calibration, sample) in self
+                .calibration
+                .iter_mut()
+                .flatten()
+                .zip(samples.iter().flatten())
+            {
+                *calibration = KeyCalibration::estimate(*sample, self.default_range);
+            }
+            self.calibrated = true;
+        }
+
+        if CALIBRATION_SIGNAL.try_take().is_some() {
+            if self.calibrating {
+                self.finish_calibration();
+            } else {
+                self.start_calibration(&samples);
+            }
+        }
+
+        // Keys don't generate events while calibrating
+        if self.calibrating {
+            for (calibration, sample) in self
+                .calibration
+                .iter_mut()
+                .flatten()
+                .zip(samples.iter().flatten())
+            {
+                let deflection = (*sample as i32 - calibration.rest as i32).abs();
+                let current = (calibration.bottom_out as i32 - calibration.rest as i32).abs();
+                if deflection > current {
+                    calibration.bottom_out = *sample;
+                }
+            }
+
+            return [[false; C]; R];
+        }
+
+        let mut keys = [[false; C]; R];
+        for row in 0..R {
+            for col in 0..C {
+                if self.keys[row][col].is_some() {
+                    let travel = self.calibration[row][col].travel(samples[row][col]);
+                    self.state[row][col].update(travel, &self.config);
+                    keys[row][col] = self.state[row][col].pressed;
+                }
+            }
+        }
+
+        keys
+    }
+
+    /// Analog sensors can't notify the MCU when a key is pressed, so the matrix is scanned every
+    /// 10 milliseconds while waiting for a key press.
+    async fn wait_for_press(&mut self) {
+        while !self.scan().await.iter().flatten().any(|pressed| *pressed) {
+            Timer::after(Duration::from_millis(10)).await;
+        }
+    }
+}
+
+#[cfg(feature = "storage")]
+pub mod storage {
+    use defmt::{info, warn, Debug2Format};
+    use embassy_futures::select;
+    use embassy_futures::select::Either;
+    use embassy_sync::signal::Signal;
+    use embassy_time::Duration;
+    use embassy_time::Timer;
+    use heapless::Vec;
+
+    use crate::hw::mcu::RawMutex;
+    use crate::keyboard::KeyboardMatrix;
+    use crate::storage::{FlashStorage, StorageDevice};
+
+    use super::{
+        AnalogConfig, ANALOG_CONFIG_LISTENER, ANALOG_CONFIG_STATE, CALIBRATION_LOAD_SIGNAL,
+        CALIBRATION_SAVE_SIGNAL,
+    };
+
+    pub(super) static ANALOG_CONFIG_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+    impl crate::storage::StorageSchema for AnalogConfig {
+        const SCHEMA_VERSION: u16 = 1;
+    }
+
+    #[rumcake_macros::task]
+    pub async fn analog_matrix_storage_task<K: KeyboardMatrix + StorageDevice, F: FlashStorage>(
+        _k: K,
+        database: &crate::storage::StorageService<'static, F>,
+    ) where
+        [(); F::ERASE_SIZE]:,
+    {
+        {
+            // Migrate the stored analog config if it was saved with an older schema version
+            let _ = database
+                .check_schema::<AnalogConfig>(
+                    K::get_storage_buffer(),
+                    crate::storage::StorageKey::AnalogConfig,
+                    K::get_migrations(),
+                )
+                .await;
+
+            // Get analog config from storage
+            if let Ok(config) = database
+                .read(
+                    K::get_storage_buffer(),
+                    crate::storage::StorageKey::AnalogConfig,
+                )
+                .await
+            {
+                info!(
+                    "[ANALOG] Obtained analog config from storage: {}",
+                    Debug2Format(&config)
+                );
+                // Quietly update the config state so that we don't save the config to storage
+                // again, but still apply it to the matrix
+                ANALOG_CONFIG_STATE.quiet_set(config).await;
+                ANALOG_CONFIG_LISTENER.signal(());
+            } else {
+                warn!("[ANALOG] Could not get analog config from storage, using default config.",);
+            }
+        }
+
+        {
+            // The calibration depends on the size of the matrix, so it is reset if the size
+            // changes
+            let metadata = [K::MATRIX_ROWS as u8, K::MATRIX_COLS as u8];
+            let _ = database
+                .check_metadata(
+                    K::get_storage_buffer(),
+                    crate::storage::StorageKey::AnalogCalibration,
+                    &metadata,
+                )
+                .await;
+
+            // Get calibration from storage
+            match database
+                .read_raw(
+                    K::get_storage_buffer(),
+                    crate::storage::StorageKey::AnalogCalibration,
+                )
+                .await
+            {
+                Ok((stored_data, stored_len)) => {
+                    match Vec::from_slice(&stored_data[..stored_len]) {
+                        Ok(data) => CALIBRATION_LOAD_SIGNAL.signal(data),
+                        Err(()) => warn!("[ANALOG] Stored calibration is too large."),
+                    }
+                }
+                Err(()) => {
+                    warn!("[ANALOG] Could not get calibration from storage, using default calibration.");
+                }
+            }
+        }
+
+        // Save the calibration when it finishes, and the analog config if it hasn't been changed
+        // in 5 seconds
+        loop {
+            match select::select(
+                CALIBRATION_SAVE_SIGNAL.wait(),
+                ANALOG_CONFIG_STATE_LISTENER.wait(),
+            )
+            .await
+            {
+                Either::First(data) => {
+                    let _ = database
+                        .write_raw(
+                            K::get_storage_buffer(),
+                            crate::storage::StorageKey::AnalogCalibration,
+                            &data,
+                        )
+                        .await;
+                }
+                Either::Second(_) => {
+                    match select::select(
+                        Timer::after(Duration::from_secs(5)),
+                        ANALOG_CONFIG_STATE_LISTENER.wait(),
+                    )
+                    .await
+                    {
+                        Either::First(_) => {
+                            let _ = database
+                                .write(
+                                    K::get_storage_buffer(),
+                                    crate::storage::StorageKey::AnalogConfig,
+                                    ANALOG_CONFIG_STATE.get().await,
+                                )
+                                .await;
+                        }
+                        Either::Second(_) => {
+                            // Re-signal, so that we skip the `wait()` call at the beginning of
+                            // this loop
+                            ANALOG_CONFIG_STATE_LISTENER.signal(());
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
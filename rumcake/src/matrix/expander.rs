@@ -0,0 +1,178 @@
+//! Matrices scanned through an I/O expander.
+//!
+//! [`ExpanderMatrix`] scans a matrix whose rows and columns are connected to a GPIO expander,
+//! like an MCP23017 ([`crate::drivers::mcp23017`]) or a PCA9555 ([`crate::drivers::pca9555`]).
+//! This is commonly used in split keyboards where one half has no MCU (e.g. the Ergodox). It can
+//! be combined with a matrix connected to the MCU using a [`MergedMatrix`](super::MergedMatrix).
+//!
+//! If the expander can't be reached (for example, if the other half of the keyboard is
+//! unplugged), all of its keys are considered released, and the matrix will periodically try to
+//! reconnect to it.
+
+use defmt::{info, warn, Debug2Format};
+use embassy_time::{Duration, Instant, Timer};
+
+use super::MatrixScanner;
+
+/// How long to wait between attempts to reconnect to an expander.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A trait for 16-bit GPIO expanders. Each bit of a value corresponds to a pin on the expander.
+pub trait GpioExpander {
+    /// Type of error returned by the expander.
+    type Error: core::fmt::Debug;
+
+    /// Configure the direction of the pins. Pins with their bit set in `outputs` are used as
+    /// outputs, and the rest are used as inputs. If the expander has internal pull-ups, they
+    /// should be enabled on the inputs.
+    async fn configure(&mut self, outputs: u16) -> Result<(), Self::Error>;
+
+    /// Set the level of the output pins. A set bit drives the pin high.
+    async fn write_outputs(&mut self, value: u16) -> Result<(), Self::Error>;
+
+    /// Read the level of the input pins. A set bit means that the pin is high.
+    async fn read_inputs(&mut self) -> Result<u16, Self::Error>;
+}
+
+/// A matrix with rows and columns connected to a [`GpioExpander`].
+///
+/// Rows are active low, and columns are expected to be pulled up. The pins are identified by their
+/// bit in the expander's values, so for an MCP23017, `GPA0` to `GPA7` are pins 0 to 7, and `GPB0`
+/// to `GPB7` are pins 8 to 15.
+pub struct ExpanderMatrix<E: GpioExpander, const C: usize, const R: usize> {
+    expander: E,
+    rows: [u8; R],
+    cols: [u8; C],
+    connected: bool,
+    last_attempt: Option<Instant>,
+}
+
+impl<E: GpioExpander, const C: usize, const R: usize> ExpanderMatrix<E, C, R> {
+    /// Create a new matrix, with the given expander pins used as rows and columns. The expander is
+    /// configured the first time that the matrix is scanned.
+    pub fn new(expander: E, rows: [u8; R], cols: [u8; C]) -> Self {
+        Self {
+            expander,
+            rows,
+            cols,
+            connected: false,
+            last_attempt: None,
+        }
+    }
+
+    fn row_mask(&self) -> u16 {
+        self.rows.iter().fold(0, |mask, row| mask | 1 << row)
+    }
+
+    /// Try to configure the expander if it isn't connected. Returns whether the expander is
+    /// connected.
+    async fn ensure_connected(&mut self) -> bool {
+        if self.connected {
+            return true;
+        }
+
+        if self
+            .last_attempt
+            .is_some_and(|last| last.elapsed() < RECONNECT_INTERVAL)
+        {
+            return false;
+        }
+
+        self.last_attempt = Some(Instant::now());
+
+        let row_mask = self.row_mask();
+        let result = match self.expander.configure(row_mask).await {
+            Ok(()) => self.expander.write_outputs(row_mask).await,
+            Err(err) => Err(err),
+        };
+
+        match result {
+            Ok(()) => {
+                info!("[EXPANDER] Connected to expander");
+                self.connected = true;
+            }
+            Err(err) => {
+                warn!(
+                    "[EXPANDER] Could not connect to expander: {}",
+                    Debug2Format(&err)
+                );
+            }
+        }
+
+        self.connected
+    }
+
+    fn disconnect(&mut self, err: E::Error) {
+        warn!(
+            "[EXPANDER] Lost connection to expander: {}",
+            Debug2Format(&err)
+        );
+        self.connected = false;
+        self.last_attempt = Some(Instant::now());
+    }
+
+    async fn read_columns(&mut self) -> Result<[bool; C], E::Error> {
+        let inputs = self.expander.read_inputs().await?;
+        let mut cols = [false; C];
+        for (col, pin) in cols.iter_mut().zip(self.cols.iter()) {
+            *col = inputs & (1 << pin) == 0;
+        }
+        Ok(cols)
+    }
+
+    async fn try_scan(&mut self) -> Result<[[bool; C]; R], E::Error> {
+        let row_mask = self.row_mask();
+        let mut keys = [[false; C]; R];
+
+        for (row, keys) in keys.iter_mut().enumerate() {
+            self.expander
+                .write_outputs(row_mask & !(1 << self.rows[row]))
+                .await?;
+            *keys = self.read_columns().await?;
+        }
+
+        self.expander.write_outputs(row_mask).await?;
+        Ok(keys)
+    }
+
+    async fn try_wait_for_press(&mut self) -> Result<(), E::Error> {
+        self.expander.write_outputs(0).await?;
+        while !self.read_columns().await?.iter().any(|active| *active) {
+            Timer::after(Duration::from_millis(10)).await;
+        }
+        self.expander.write_outputs(self.row_mask()).await
+    }
+}
+
+impl<E: GpioExpander, const C: usize, const R: usize> MatrixScanner<C, R>
+    for ExpanderMatrix<E, C, R>
+{
+    async fn scan(&mut self) -> [[bool; C]; R] {
+        if !self.ensure_connected().await {
+            return [[false; C]; R];
+        }
+
+        match self.try_scan().await {
+            Ok(keys) => keys,
+            Err(err) => {
+                self.disconnect(err);
+                [[false; C]; R]
+            }
+        }
+    }
+
+    /// Expanders are polled every 10 milliseconds while waiting for a key press.
+    async fn wait_for_press(&mut self) {
+        loop {
+            if !self.ensure_connected().await {
+                Timer::after(RECONNECT_INTERVAL).await;
+                continue;
+            }
+
+            match self.try_wait_for_press().await {
+                Ok(()) => break,
+                Err(err) => self.disconnect(err),
+            }
+        }
+    }
+}
@@ -0,0 +1,370 @@
+//! Switch matrix implementations.
+//!
+//! A switch matrix is anything that implements [`MatrixScanner`]. By default, matrices built
+//! with [`crate::keyboard::build_matrix`] use `keyberon`'s [`Matrix`], with rows and columns
+//! connected directly to the MCU. Matrices that drive their rows or read their columns in other
+//! ways (for example, through [`shift_register`]s) can be built with [`StrobedMatrix`]. Matrices
+//! can be combined with [`MergedMatrix`], for example to add the keys scanned by an
+//! [`expander::ExpanderMatrix`] to the keys connected to the MCU.
+//!
+//! Keyboards with a pin for each switch can use `keyberon`'s [`DirectPinMatrix`] (see
+//! [`crate::keyboard::build_direct_pin_matrix`]), and keyboards with a duplex matrix can use
+//! [`DuplexMatrix`] (see [`crate::keyboard::build_duplex_matrix`]).
+
+use core::convert::Infallible;
+use core::future::pending;
+
+use embassy_futures::select::{select, select_array};
+use embassy_time::{Duration, Timer};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use embedded_hal_async::digital::Wait;
+use keyberon::matrix::{DirectPinMatrix, Matrix};
+
+#[cfg(feature = "analog-matrix")]
+pub mod analog;
+pub mod expander;
+pub mod shift_register;
+
+/// A trait for switch matrices that can be scanned by [`crate::keyboard::matrix_poll`].
+pub trait MatrixScanner<const C: usize, const R: usize> {
+    /// Scan the matrix, and return which keys are currently pressed.
+    async fn scan(&mut self) -> [[bool; C]; R];
+
+    /// Wait until any key is pressed. This is used while the keyboard is idle, so implementations
+    /// should avoid scanning the matrix if possible.
+    async fn wait_for_press(&mut self);
+
+    /// Whether [`MatrixScanner::wait_for_press`] only waits for a change in level on pins connected
+    /// directly to the MCU. On nRF5x, this is required to wake up the keyboard from deep sleep,
+    /// since the MCU can't run any code in System OFF.
+    const CAN_WAKE_FROM_DEEP_SLEEP: bool = false;
+}
+
+impl<I, O, const C: usize, const R: usize> MatrixScanner<C, R> for Matrix<I, O, C, R>
+where
+    I: InputPin<Error = Infallible> + Wait<Error = Infallible>,
+    O: OutputPin<Error = Infallible>,
+{
+    const CAN_WAKE_FROM_DEEP_SLEEP: bool = true;
+
+    async fn scan(&mut self) -> [[bool; C]; R] {
+        // Rows may still be active if waiting for a key press was cancelled.
+        self.release_rows().unwrap();
+        self.get_with_delay(|| {
+            embassy_time::block_for(Duration::from_ticks(2));
+        })
+        .unwrap()
+    }
+
+    async fn wait_for_press(&mut self) {
+        // With all rows active, pressing any key will pull its column low.
+        self.activate_all_rows().unwrap();
+        select_array(self.cols_mut().each_mut().map(|col| col.wait_for_low())).await;
+        self.release_rows().unwrap();
+    }
+}
+
+impl<P, const C: usize, const R: usize> MatrixScanner<C, R> for DirectPinMatrix<P, C, R>
+where
+    P: InputPin<Error = Infallible> + Wait<Error = Infallible>,
+{
+    const CAN_WAKE_FROM_DEEP_SLEEP: bool = true;
+
+    async fn scan(&mut self) -> [[bool; C]; R] {
+        self.get().unwrap()
+    }
+
+    async fn wait_for_press(&mut self) {
+        select_array(self.pins_mut().each_mut().map(|row| {
+            select_array(row.each_mut().map(|pin| async move {
+                match pin {
+                    Some(pin) => pin.wait_for_low().await.unwrap(),
+                    None => pending().await,
+                }
+            }))
+        }))
+        .await;
+    }
+}
+
+/// A trait for the rows of a [`StrobedMatrix`]. Rows are activated one at a time while the
+/// matrix is being scanned.
+pub trait RowDriver<const R: usize> {
+    /// Activate the given row, and deactivate all other rows.
+    async fn activate_row(&mut self, row: usize);
+
+    /// Activate all rows at the same time.
+    async fn activate_all_rows(&mut self);
+
+    /// Deactivate all rows.
+    async fn release_rows(&mut self);
+}
+
+/// A trait for the columns of a [`StrobedMatrix`].
+pub trait ColumnReader<const C: usize> {
+    /// Read the state of the columns. A column is `true` if a key in the active row is pressed.
+    async fn read_columns(&mut self) -> [bool; C];
+
+    /// Wait until any of the columns become active.
+    ///
+    /// By default, this reads the columns every 10 milliseconds. Implementations should override
+    /// this if the columns can notify the MCU of a change.
+    async fn wait_for_active(&mut self) {
+        while !self.read_columns().await.iter().any(|active| *active) {
+            Timer::after(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Whether [`ColumnReader::wait_for_active`] only waits for a change in level on pins
+    /// connected directly to the MCU. See [`MatrixScanner::CAN_WAKE_FROM_DEEP_SLEEP`].
+    const CAN_WAKE_FROM_DEEP_SLEEP: bool = false;
+}
+
+/// Row pins connected directly to the MCU. Rows are active low.
+impl<P: OutputPin<Error = Infallible>, const R: usize> RowDriver<R> for [P; R] {
+    async fn activate_row(&mut self, row: usize) {
+        for (i, pin) in self.iter_mut().enumerate() {
+            if i == row {
+                pin.set_low().unwrap();
+            } else {
+                pin.set_high().unwrap();
+            }
+        }
+    }
+
+    async fn activate_all_rows(&mut self) {
+        for pin in self.iter_mut() {
+            pin.set_low().unwrap();
+        }
+    }
+
+    async fn release_rows(&mut self) {
+        for pin in self.iter_mut() {
+            pin.set_high().unwrap();
+        }
+    }
+}
+
+/// Column pins connected directly to the MCU. Columns should have pull-ups, so they are active
+/// low.
+impl<P: InputPin<Error = Infallible> + Wait<Error = Infallible>, const C: usize> ColumnReader<C>
+    for [P; C]
+{
+    const CAN_WAKE_FROM_DEEP_SLEEP: bool = true;
+
+    async fn read_columns(&mut self) -> [bool; C] {
+        let mut cols = [false; C];
+        for (col, pin) in cols.iter_mut().zip(self.iter()) {
+            *col = pin.is_low().unwrap();
+        }
+        cols
+    }
+
+    async fn wait_for_active(&mut self) {
+        select_array(self.each_mut().map(|pin| pin.wait_for_low())).await;
+    }
+}
+
+/// A switch matrix that is scanned by activating each row, and reading the columns.
+///
+/// Unlike `keyberon`'s [`Matrix`], the rows and columns can be connected to anything that
+/// implements [`RowDriver`] and [`ColumnReader`] respectively, so they can be mixed. For example,
+/// you can drive the rows with a [`shift_register::HC595Rows`], and read the columns from pins
+/// connected directly to the MCU.
+pub struct StrobedMatrix<D, I> {
+    rows: D,
+    cols: I,
+}
+
+impl<D, I> StrobedMatrix<D, I> {
+    /// Create a new matrix from the given rows and columns.
+    pub fn new(rows: D, cols: I) -> Self {
+        Self { rows, cols }
+    }
+}
+
+impl<D: RowDriver<R>, I: ColumnReader<C>, const C: usize, const R: usize> MatrixScanner<C, R>
+    for StrobedMatrix<D, I>
+{
+    const CAN_WAKE_FROM_DEEP_SLEEP: bool = I::CAN_WAKE_FROM_DEEP_SLEEP;
+
+    async fn scan(&mut self) -> [[bool; C]; R] {
+        let mut keys = [[false; C]; R];
+
+        for (row, keys) in keys.iter_mut().enumerate() {
+            self.rows.activate_row(row).await;
+            embassy_time::block_for(Duration::from_ticks(2));
+            *keys = self.cols.read_columns().await;
+        }
+
+        self.rows.release_rows().await;
+        keys
+    }
+
+    async fn wait_for_press(&mut self) {
+        self.rows.activate_all_rows().await;
+        self.cols.wait_for_active().await;
+        self.rows.release_rows().await;
+    }
+}
+
+/// A matrix made of two matrices with the same number of rows, placed side by side.
+///
+/// The `CA` columns of the first matrix come first, followed by the `CB` columns of the second
+/// matrix. The resulting matrix must have `CA + CB` columns.
+pub struct MergedMatrix<A, B, const CA: usize, const CB: usize> {
+    first: A,
+    second: B,
+}
+
+impl<A, B, const CA: usize, const CB: usize> MergedMatrix<A, B, CA, CB> {
+    /// Create a new matrix from the given matrices.
+    pub fn new(first: A, second: B) -> Self {
+        Self { first, second }
+    }
+}
+
+/// Compile-time check that a [`MergedMatrix`] has as many columns as the matrices it is made of.
+struct MergedColumns<const CA: usize, const CB: usize, const C: usize>;
+
+impl<const CA: usize, const CB: usize, const C: usize> MergedColumns<CA, CB, C> {
+    const CHECK: () = assert!(
+        C == CA + CB,
+        "A merged matrix must have as many columns as the matrices it is made of"
+    );
+}
+
+impl<A, B, const CA: usize, const CB: usize, const C: usize, const R: usize> MatrixScanner<C, R>
+    for MergedMatrix<A, B, CA, CB>
+where
+    A: MatrixScanner<CA, R>,
+    B: MatrixScanner<CB, R>,
+{
+    const CAN_WAKE_FROM_DEEP_SLEEP: bool =
+        A::CAN_WAKE_FROM_DEEP_SLEEP && B::CAN_WAKE_FROM_DEEP_SLEEP;
+
+    async fn scan(&mut self) -> [[bool; C]; R] {
+        #[allow(clippy::let_unit_value)]
+        let () = MergedColumns::<CA, CB, C>::CHECK;
+
+        let first = self.first.scan().await;
+        let second = self.second.scan().await;
+
+        let mut keys = [[false; C]; R];
+        for (keys, (first, second)) in keys.iter_mut().zip(first.iter().zip(second.iter())) {
+            keys[..CA].copy_from_slice(first);
+            keys[CA..].copy_from_slice(second);
+        }
+        keys
+    }
+
+    async fn wait_for_press(&mut self) {
+        select(self.first.wait_for_press(), self.second.wait_for_press()).await;
+    }
+}
+
+/// A trait for pins that can switch between being an input and an output. This is used by
+/// [`DuplexMatrix`], where rows and columns take turns driving each other.
+pub trait FlexPin {
+    /// Configure the pin as an input, with a pull-up.
+    fn release(&mut self);
+
+    /// Configure the pin as an output, and drive it low.
+    fn drive_low(&mut self);
+
+    /// Check if the pin is low.
+    fn is_low(&self) -> bool;
+}
+
+/// A duplex matrix, where each pair of row and column pins is connected to two switches, with
+/// diodes in opposite directions.
+///
+/// With `RP` row pins, the matrix has `2 * RP` rows. Rows `0` to `RP - 1` contain the switches
+/// that are read by driving a row pin low, and reading the column pins. Rows `RP` to `2 * RP - 1`
+/// contain the switches that are read by driving a column pin low, and reading the row pins.
+///
+/// The pins can't notify the MCU when a key is pressed, so the matrix is scanned every 10
+/// milliseconds while the keyboard is idle.
+pub struct DuplexMatrix<P: FlexPin, const C: usize, const RP: usize> {
+    rows: [P; RP],
+    cols: [P; C],
+}
+
+impl<P: FlexPin, const C: usize, const RP: usize> DuplexMatrix<P, C, RP> {
+    /// Create a new matrix from the given row and column pins.
+    pub fn new(mut rows: [P; RP], mut cols: [P; C]) -> Self {
+        for pin in rows.iter_mut().chain(cols.iter_mut()) {
+            pin.release();
+        }
+        Self { rows, cols }
+    }
+
+    fn any_pressed(&mut self) -> bool {
+        for pin in self.rows.iter_mut() {
+            pin.drive_low();
+        }
+        embassy_time::block_for(Duration::from_ticks(2));
+        let forward = self.cols.iter().any(|pin| pin.is_low());
+        for pin in self.rows.iter_mut() {
+            pin.release();
+        }
+
+        for pin in self.cols.iter_mut() {
+            pin.drive_low();
+        }
+        embassy_time::block_for(Duration::from_ticks(2));
+        let backward = self.rows.iter().any(|pin| pin.is_low());
+        for pin in self.cols.iter_mut() {
+            pin.release();
+        }
+
+        forward || backward
+    }
+}
+
+/// Compile-time check that a [`DuplexMatrix`] has twice as many rows as row pins.
+struct DuplexRows<const RP: usize, const R: usize>;
+
+impl<const RP: usize, const R: usize> DuplexRows<RP, R> {
+    const CHECK: () = assert!(
+        R == 2 * RP,
+        "A duplex matrix must have twice as many rows as row pins"
+    );
+}
+
+impl<P: FlexPin, const C: usize, const RP: usize, const R: usize> MatrixScanner<C, R>
+    for DuplexMatrix<P, C, RP>
+{
+    async fn scan(&mut self) -> [[bool; C]; R] {
+        #[allow(clippy::let_unit_value)]
+        let () = DuplexRows::<RP, R>::CHECK;
+
+        let mut keys = [[false; C]; R];
+
+        for (row, pin) in self.rows.iter_mut().enumerate() {
+            pin.drive_low();
+            embassy_time::block_for(Duration::from_ticks(2));
+            for (key, col) in keys[row].iter_mut().zip(self.cols.iter()) {
+                *key = col.is_low();
+            }
+            pin.release();
+        }
+
+        for (col, pin) in self.cols.iter_mut().enumerate() {
+            pin.drive_low();
+            embassy_time::block_for(Duration::from_ticks(2));
+            for (keys, row) in keys[RP..].iter_mut().zip(self.rows.iter()) {
+                keys[col] = row.is_low();
+            }
+            pin.release();
+        }
+
+        keys
+    }
+
+    async fn wait_for_press(&mut self) {
+        while !self.any_pressed() {
+            Timer::after(Duration::from_millis(10)).await;
+        }
+    }
+}
@@ -0,0 +1,286 @@
+//! Matrix rows and columns connected through shift registers.
+//!
+//! [`HC595Rows`] drives the rows of a matrix with a chain of 74HC595 serial-in, parallel-out shift
+//! registers, and [`HC165Columns`] reads the columns of a matrix with a chain of 74HC165
+//! parallel-in, serial-out shift registers. Both can be used with a
+//! [`StrobedMatrix`](super::StrobedMatrix).
+//!
+//! The shift registers can be connected to SPI peripherals ([`SpiShiftOut`], [`SpiShiftIn`]), or
+//! to regular GPIO pins ([`BitBangShiftOut`], [`BitBangShiftIn`]). SPI peripherals should be
+//! configured in mode 0, and send the most significant bit first.
+//!
+//! In a chain of shift registers, the first register is the one connected to the MCU. Output `Q0`
+//! (or input `D0`) of the first register is row (or column) 0, `Q7` is row 7, and `Q0` of the
+//! second register is row 8, and so on.
+
+use core::convert::Infallible;
+
+use defmt::{warn, Debug2Format};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use embedded_hal_async::spi::SpiBus;
+
+use super::{ColumnReader, RowDriver};
+
+/// A trait for shifting bytes out to a chain of shift registers.
+pub trait ShiftOut {
+    /// Shift out a byte, most significant bit first.
+    async fn write(&mut self, byte: u8);
+
+    /// Latch the shifted bytes to the outputs of the shift registers.
+    async fn latch(&mut self);
+}
+
+/// A trait for shifting bytes in from a chain of shift registers.
+pub trait ShiftIn {
+    /// Load the state of the inputs into the shift registers.
+    async fn load(&mut self);
+
+    /// Shift in a byte, most significant bit first.
+    async fn read(&mut self) -> u8;
+}
+
+/// Shift out bytes with an SPI bus, and a latch pin (`RCLK` on 74HC595 registers).
+pub struct SpiShiftOut<S: SpiBus, L: OutputPin<Error = Infallible>> {
+    spi: S,
+    latch: L,
+}
+
+impl<S: SpiBus, L: OutputPin<Error = Infallible>> SpiShiftOut<S, L> {
+    /// Create a new instance with the given SPI bus and latch pin.
+    pub fn new(spi: S, mut latch: L) -> Self {
+        latch.set_low().unwrap();
+        Self { spi, latch }
+    }
+}
+
+impl<S: SpiBus, L: OutputPin<Error = Infallible>> ShiftOut for SpiShiftOut<S, L> {
+    async fn write(&mut self, byte: u8) {
+        if let Err(err) = self.spi.write(&[byte]).await {
+            warn!(
+                "[SHIFT_REGISTER] Could not write to shift register: {}",
+                Debug2Format(&err)
+            );
+        }
+    }
+
+    async fn latch(&mut self) {
+        if let Err(err) = self.spi.flush().await {
+            warn!(
+                "[SHIFT_REGISTER] Could not write to shift register: {}",
+                Debug2Format(&err)
+            );
+        }
+        self.latch.set_high().unwrap();
+        self.latch.set_low().unwrap();
+    }
+}
+
+/// Shift out bytes with regular GPIO pins for the data (`SER`), clock (`SRCLK`) and latch (`RCLK`)
+/// lines.
+pub struct BitBangShiftOut<
+    D: OutputPin<Error = Infallible>,
+    C: OutputPin<Error = Infallible>,
+    L: OutputPin<Error = Infallible>,
+> {
+    data: D,
+    clock: C,
+    latch: L,
+}
+
+impl<
+        D: OutputPin<Error = Infallible>,
+        C: OutputPin<Error = Infallible>,
+        L: OutputPin<Error = Infallible>,
+    > BitBangShiftOut<D, C, L>
+{
+    /// Create a new instance with the given data, clock and latch pins.
+    pub fn new(data: D, mut clock: C, mut latch: L) -> Self {
+        clock.set_low().unwrap();
+        latch.set_low().unwrap();
+        Self { data, clock, latch }
+    }
+}
+
+impl<
+        D: OutputPin<Error = Infallible>,
+        C: OutputPin<Error = Infallible>,
+        L: OutputPin<Error = Infallible>,
+    > ShiftOut for BitBangShiftOut<D, C, L>
+{
+    async fn write(&mut self, byte: u8) {
+        for bit in (0..8).rev() {
+            if byte & (1 << bit) != 0 {
+                self.data.set_high().unwrap();
+            } else {
+                self.data.set_low().unwrap();
+            }
+            self.clock.set_high().unwrap();
+            self.clock.set_low().unwrap();
+        }
+    }
+
+    async fn latch(&mut self) {
+        self.latch.set_high().unwrap();
+        self.latch.set_low().unwrap();
+    }
+}
+
+/// Shift in bytes with an SPI bus, and a load pin (`SH/LD` on 74HC165 registers).
+pub struct SpiShiftIn<S: SpiBus, L: OutputPin<Error = Infallible>> {
+    spi: S,
+    load: L,
+}
+
+impl<S: SpiBus, L: OutputPin<Error = Infallible>> SpiShiftIn<S, L> {
+    /// Create a new instance with the given SPI bus and load pin.
+    pub fn new(spi: S, mut load: L) -> Self {
+        load.set_high().unwrap();
+        Self { spi, load }
+    }
+}
+
+impl<S: SpiBus, L: OutputPin<Error = Infallible>> ShiftIn for SpiShiftIn<S, L> {
+    async fn load(&mut self) {
+        self.load.set_low().unwrap();
+        self.load.set_high().unwrap();
+    }
+
+    async fn read(&mut self) -> u8 {
+        let mut buf = [0xFF];
+        if let Err(err) = self.spi.read(&mut buf).await {
+            warn!(
+                "[SHIFT_REGISTER] Could not read from shift register: {}",
+                Debug2Format(&err)
+            );
+        }
+        buf[0]
+    }
+}
+
+/// Shift in bytes with regular GPIO pins for the data (`QH`), clock (`CLK`) and load (`SH/LD`)
+/// lines.
+pub struct BitBangShiftIn<
+    D: InputPin<Error = Infallible>,
+    C: OutputPin<Error = Infallible>,
+    L: OutputPin<Error = Infallible>,
+> {
+    data: D,
+    clock: C,
+    load: L,
+}
+
+impl<
+        D: InputPin<Error = Infallible>,
+        C: OutputPin<Error = Infallible>,
+        L: OutputPin<Error = Infallible>,
+    > BitBangShiftIn<D, C, L>
+{
+    /// Create a new instance with the given data, clock and load pins.
+    pub fn new(data: D, mut clock: C, mut load: L) -> Self {
+        clock.set_low().unwrap();
+        load.set_high().unwrap();
+        Self { data, clock, load }
+    }
+}
+
+impl<
+        D: InputPin<Error = Infallible>,
+        C: OutputPin<Error = Infallible>,
+        L: OutputPin<Error = Infallible>,
+    > ShiftIn for BitBangShiftIn<D, C, L>
+{
+    async fn load(&mut self) {
+        self.load.set_low().unwrap();
+        self.load.set_high().unwrap();
+    }
+
+    async fn read(&mut self) -> u8 {
+        let mut byte = 0;
+        for bit in (0..8).rev() {
+            if self.data.is_high().unwrap() {
+                byte |= 1 << bit;
+            }
+            self.clock.set_high().unwrap();
+            self.clock.set_low().unwrap();
+        }
+        byte
+    }
+}
+
+/// Matrix rows driven by a chain of 74HC595 shift registers. Rows are active low.
+pub struct HC595Rows<S: ShiftOut> {
+    shift_out: S,
+}
+
+impl<S: ShiftOut> HC595Rows<S> {
+    /// Create the matrix rows, using the given [`ShiftOut`] implementation to write to the shift
+    /// registers.
+    pub fn new(shift_out: S) -> Self {
+        Self { shift_out }
+    }
+
+    async fn write_rows<const R: usize>(&mut self, active: impl Fn(usize) -> bool) {
+        // The last byte shifted out ends up in the first register of the chain
+        for register in (0..R.div_ceil(8)).rev() {
+            let mut byte = 0xFF;
+            for bit in 0..8 {
+                let row = register * 8 + bit;
+                if row < R && active(row) {
+                    byte &= !(1 << bit);
+                }
+            }
+            self.shift_out.write(byte).await;
+        }
+
+        self.shift_out.latch().await;
+    }
+}
+
+impl<S: ShiftOut, const R: usize> RowDriver<R> for HC595Rows<S> {
+    async fn activate_row(&mut self, row: usize) {
+        self.write_rows::<R>(|r| r == row).await;
+    }
+
+    async fn activate_all_rows(&mut self) {
+        self.write_rows::<R>(|_| true).await;
+    }
+
+    async fn release_rows(&mut self) {
+        self.write_rows::<R>(|_| false).await;
+    }
+}
+
+/// Matrix columns read from a chain of 74HC165 shift registers. Columns should have pull-ups, so
+/// they are active low.
+///
+/// The shift registers can't notify the MCU when a key is pressed, so the columns are read
+/// periodically while the keyboard is idle.
+pub struct HC165Columns<S: ShiftIn> {
+    shift_in: S,
+}
+
+impl<S: ShiftIn> HC165Columns<S> {
+    /// Create the matrix columns, using the given [`ShiftIn`] implementation to read from the
+    /// shift registers.
+    pub fn new(shift_in: S) -> Self {
+        Self { shift_in }
+    }
+}
+
+impl<S: ShiftIn, const C: usize> ColumnReader<C> for HC165Columns<S> {
+    async fn read_columns(&mut self) -> [bool; C] {
+        let mut cols = [false; C];
+
+        self.shift_in.load().await;
+        for register in 0..C.div_ceil(8) {
+            let byte = self.shift_in.read().await;
+            for bit in 0..8 {
+                if let Some(col) = cols.get_mut(register * 8 + bit) {
+                    *col = byte & (1 << bit) == 0;
+                }
+            }
+        }
+
+        cols
+    }
+}
@@ -0,0 +1,335 @@
+//! Support for mouse keys.
+//!
+//! Mouse keys allow you to move the cursor, scroll and click using keys on your layout. The
+//! behaviour of mouse keys is based on QMK's implementation, and supports both an accelerated mode
+//! and a constant speed mode. Mouse keys can be placed on your layout using the [`Keycode::Mouse`]
+//! variant. Movement can be configured using [`crate::keyboard::KeyboardLayout::MOUSE_KEYS_CONFIG`].
+//!
+//! [`Keycode::Mouse`]: crate::keyboard::Keycode::Mouse
+
+use num_derive::FromPrimitive;
+use usbd_human_interface_device::device::mouse::WheelMouseReport;
+
+/// Keys that control the mouse.
+///
+/// The order of these variants matches the order of QMK's `KC_MS_*` keycodes.
+#[repr(u8)]
+#[derive(FromPrimitive, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseKeycode {
+    /// Move the cursor up.
+    Up,
+    /// Move the cursor down.
+    Down,
+    /// Move the cursor left.
+    Left,
+    /// Move the cursor right.
+    Right,
+    /// Press mouse button 1 (usually the left button).
+    Button1,
+    /// Press mouse button 2 (usually the right button).
+    Button2,
+    /// Press mouse button 3 (usually the middle button).
+    Button3,
+    /// Press mouse button 4.
+    Button4,
+    /// Press mouse button 5.
+    Button5,
+    /// Press mouse button 6.
+    Button6,
+    /// Press mouse button 7.
+    Button7,
+    /// Press mouse button 8.
+    Button8,
+    /// Scroll up.
+    WheelUp,
+    /// Scroll down.
+    WheelDown,
+    /// Scroll left.
+    WheelLeft,
+    /// Scroll right.
+    WheelRight,
+    /// While held, move and scroll at the slowest speed.
+    Accel0,
+    /// While held, move and scroll at a medium speed.
+    Accel1,
+    /// While held, move and scroll at the fastest speed.
+    Accel2,
+}
+
+impl MouseKeycode {
+    fn mask(self) -> u32 {
+        1 << self as u8
+    }
+}
+
+/// Determines how the speed of the cursor and scroll wheel changes while mouse keys are held.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseKeysMode {
+    /// Speed increases the longer the key is held, until it reaches the maximum speed. The
+    /// acceleration keys can be held to move at a fixed speed instead.
+    Accelerated,
+    /// Speed stays the same while the key is held. The acceleration keys change the speed. If no
+    /// acceleration key is held, the medium speed is used.
+    Constant,
+}
+
+/// Options that control the movement of the cursor and scroll wheel when using mouse keys.
+///
+/// Default values match QMK's defaults.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseKeysConfig {
+    /// How the speed of movement changes while mouse keys are held.
+    pub mode: MouseKeysMode,
+
+    /// Amount of time (in milliseconds) between the first movement of the cursor, and when it
+    /// starts repeating.
+    pub move_delay: u16,
+
+    /// Amount of time (in milliseconds) between each movement of the cursor.
+    pub move_interval: u16,
+
+    /// Distance that the cursor moves on each step, at the slowest speed.
+    pub move_delta: u8,
+
+    /// Multiplier applied to [`MouseKeysConfig::move_delta`] when the cursor is at its fastest
+    /// speed.
+    pub move_max_speed: u8,
+
+    /// Number of steps it takes for the cursor to reach its fastest speed, in accelerated mode.
+    pub move_time_to_max: u8,
+
+    /// Amount of time (in milliseconds) between the first scroll, and when it starts repeating.
+    pub wheel_delay: u16,
+
+    /// Amount of time (in milliseconds) between each scroll.
+    pub wheel_interval: u16,
+
+    /// Distance that is scrolled on each step, at the slowest speed.
+    pub wheel_delta: u8,
+
+    /// Multiplier applied to [`MouseKeysConfig::wheel_delta`] when scrolling at the fastest
+    /// speed.
+    pub wheel_max_speed: u8,
+
+    /// Number of steps it takes to reach the fastest scrolling speed, in accelerated mode.
+    pub wheel_time_to_max: u8,
+}
+
+impl MouseKeysConfig {
+    /// Create a new mouse keys config, using the default values.
+    pub const fn new() -> Self {
+        Self {
+            mode: MouseKeysMode::Accelerated,
+            move_delay: 100,
+            move_interval: 20,
+            move_delta: 8,
+            move_max_speed: 10,
+            move_time_to_max: 30,
+            wheel_delay: 100,
+            wheel_interval: 80,
+            wheel_delta: 1,
+            wheel_max_speed: 8,
+            wheel_time_to_max: 40,
+        }
+    }
+}
+
+impl Default for MouseKeysConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Timing for the cursor or the scroll wheel.
+struct Repeater {
+    /// Number of steps since movement started.
+    repeat: u8,
+
+    /// Time (in milliseconds) until the next step.
+    timer: u16,
+}
+
+impl Repeater {
+    const fn new() -> Self {
+        Self {
+            repeat: 0,
+            timer: 0,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.repeat = 0;
+        self.timer = 0;
+    }
+
+    /// Update the timer, returning the number of steps taken so far if a step should be taken.
+    fn tick(&mut self, delay: u16, interval: u16) -> Option<u8> {
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return None;
+        }
+
+        let repeat = self.repeat;
+        self.timer = if repeat == 0 { delay } else { interval };
+        self.repeat = self.repeat.saturating_add(1);
+        Some(repeat)
+    }
+}
+
+/// Converts mouse key presses into mouse reports.
+pub(crate) struct MouseKeyProcessor {
+    /// Bitmask of the mouse keys that are held down. Bits correspond to [`MouseKeycode`] variants.
+    held: u32,
+    move_repeater: Repeater,
+    wheel_repeater: Repeater,
+
+    /// Buttons that were sent in the last report.
+    last_buttons: u8,
+}
+
+const MOVE_KEYS: u32 = (1 << MouseKeycode::Up as u8)
+    | (1 << MouseKeycode::Down as u8)
+    | (1 << MouseKeycode::Left as u8)
+    | (1 << MouseKeycode::Right as u8);
+
+const WHEEL_KEYS: u32 = (1 << MouseKeycode::WheelUp as u8)
+    | (1 << MouseKeycode::WheelDown as u8)
+    | (1 << MouseKeycode::WheelLeft as u8)
+    | (1 << MouseKeycode::WheelRight as u8);
+
+impl MouseKeyProcessor {
+    pub(crate) const fn new() -> Self {
+        Self {
+            held: 0,
+            move_repeater: Repeater::new(),
+            wheel_repeater: Repeater::new(),
+            last_buttons: 0,
+        }
+    }
+
+    fn is_held(&self, keycode: MouseKeycode) -> bool {
+        self.held & keycode.mask() != 0
+    }
+
+    fn axis(&self, positive: MouseKeycode, negative: MouseKeycode) -> i16 {
+        self.is_held(positive) as i16 - self.is_held(negative) as i16
+    }
+
+    pub(crate) fn press(&mut self, keycode: MouseKeycode) {
+        // Start moving right away if nothing was moving before
+        if keycode.mask() & MOVE_KEYS != 0 && self.held & MOVE_KEYS == 0 {
+            self.move_repeater.reset();
+        }
+        if keycode.mask() & WHEEL_KEYS != 0 && self.held & WHEEL_KEYS == 0 {
+            self.wheel_repeater.reset();
+        }
+
+        self.held |= keycode.mask();
+    }
+
+    pub(crate) fn release(&mut self, keycode: MouseKeycode) {
+        self.held &= !keycode.mask();
+    }
+
+    pub(crate) fn buttons(&self) -> u8 {
+        (self.held >> MouseKeycode::Button1 as u8) as u8
+    }
+
+    /// Obtain the distance to move on a step, given the number of steps taken so far.
+    fn unit(&self, mode: MouseKeysMode, repeat: u8, delta: u8, max_speed: u8, ttm: u8) -> i16 {
+        let delta = delta as u16;
+        let max = delta * max_speed as u16;
+
+        let unit = if self.is_held(MouseKeycode::Accel0) {
+            match mode {
+                MouseKeysMode::Accelerated => max / 4,
+                MouseKeysMode::Constant => delta,
+            }
+        } else if self.is_held(MouseKeycode::Accel1) {
+            max / 2
+        } else if self.is_held(MouseKeycode::Accel2) {
+            max
+        } else {
+            match mode {
+                MouseKeysMode::Accelerated => {
+                    if repeat == 0 {
+                        delta
+                    } else if repeat >= ttm {
+                        max
+                    } else {
+                        max * repeat as u16 / ttm as u16
+                    }
+                }
+                MouseKeysMode::Constant => max / 2,
+            }
+        };
+
+        unit.clamp(1, i8::MAX as u16) as i16
+    }
+
+    /// Update the mouse key timers, and obtain a report to send to the host if the mouse has
+    /// changed. This should be called every millisecond.
+    pub(crate) fn tick(&mut self, config: &MouseKeysConfig) -> Option<WheelMouseReport> {
+        let buttons = self.buttons();
+        let mut report = WheelMouseReport {
+            buttons,
+            x: 0,
+            y: 0,
+            vertical_wheel: 0,
+            horizontal_wheel: 0,
+        };
+        let mut changed = buttons != self.last_buttons;
+        self.last_buttons = buttons;
+
+        if self.held & MOVE_KEYS != 0 {
+            if let Some(repeat) = self
+                .move_repeater
+                .tick(config.move_delay, config.move_interval)
+            {
+                let unit = self.unit(
+                    config.mode,
+                    repeat,
+                    config.move_delta,
+                    config.move_max_speed,
+                    config.move_time_to_max,
+                );
+                let mut x = self.axis(MouseKeycode::Right, MouseKeycode::Left) * unit;
+                let mut y = self.axis(MouseKeycode::Down, MouseKeycode::Up) * unit;
+
+                // Scale diagonal movement by 1/sqrt(2), so that it isn't faster
+                if x != 0 && y != 0 {
+                    x = x.signum() * (x.abs() * 181 / 256).max(1);
+                    y = y.signum() * (y.abs() * 181 / 256).max(1);
+                }
+
+                report.x = x as i8;
+                report.y = y as i8;
+                changed |= x != 0 || y != 0;
+            }
+        }
+
+        if self.held & WHEEL_KEYS != 0 {
+            if let Some(repeat) = self
+                .wheel_repeater
+                .tick(config.wheel_delay, config.wheel_interval)
+            {
+                let unit = self.unit(
+                    config.mode,
+                    repeat,
+                    config.wheel_delta,
+                    config.wheel_max_speed,
+                    config.wheel_time_to_max,
+                );
+                let vertical = self.axis(MouseKeycode::WheelUp, MouseKeycode::WheelDown) * unit;
+                let horizontal =
+                    self.axis(MouseKeycode::WheelRight, MouseKeycode::WheelLeft) * unit;
+
+                report.vertical_wheel = vertical as i8;
+                report.horizontal_wheel = horizontal as i8;
+                changed |= vertical != 0 || horizontal != 0;
+            }
+        }
+
+        changed.then_some(report)
+    }
+}
@@ -0,0 +1,23 @@
+//! A set of traits that pointing device drivers must implement.
+
+use core::fmt::Debug;
+
+use super::{PointingDevice, PointingMotion};
+
+/// Trait that drivers must implement to work with the pointing task.
+pub trait PointingDriver<K: PointingDevice> {
+    /// The type of error that the driver will return if it fails to communicate with the sensor.
+    type DriverError: Debug;
+
+    /// Read the motion that the sensor has detected since the last read.
+    ///
+    /// Called every [`PointingDevice::POLL_INTERVAL_MS`] milliseconds. If the sensor hasn't moved,
+    /// a [`PointingMotion`] of zero should be returned.
+    async fn read_motion(&mut self) -> Result<PointingMotion, Self::DriverError>;
+
+    /// Change the resolution (counts per inch) of the sensor.
+    ///
+    /// Called when the pointing task starts, and when the CPI is changed. If your sensor does not
+    /// support changing its resolution, you may simply return `Ok(())`.
+    async fn set_cpi(&mut self, cpi: u16) -> Result<(), Self::DriverError>;
+}
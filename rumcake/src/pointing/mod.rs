@@ -0,0 +1,291 @@
+//! Pointing device feature.
+//!
+//! This allows you to use sensors like trackballs and trackpads to control the mouse cursor. To
+//! use a pointing device, keyboards must implement [`PointingDevice`], along with the trait
+//! corresponding to the chosen driver (which should implement [`drivers::PointingDriver`]).
+//!
+//! Motion read from the sensor is turned into mouse reports by the device that communicates with
+//! the host. On a split keyboard, the motion from a peripheral's sensor is sent to the central
+//! device (see [`crate::split::MessageToCentral::PointingMotion`]).
+
+use defmt::{error, Debug2Format};
+use embassy_futures::select::select;
+use embassy_sync::channel::Channel;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Ticker, Timer};
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+use usbd_human_interface_device::device::mouse::WheelMouseReport;
+
+pub mod drivers;
+
+use self::drivers::PointingDriver;
+use crate::hw::mcu::RawMutex;
+use crate::keyboard::IDLE_STATE;
+use crate::State;
+
+/// A trait that keyboards must implement to use a pointing device.
+pub trait PointingDevice {
+    /// Resolution (counts per inch) that the sensor will use when it starts.
+    const CPI: u16 = 1600;
+
+    /// Amount of time (in milliseconds) between each read of the sensor.
+    const POLL_INTERVAL_MS: u16 = 1;
+
+    /// Amount of time (in milliseconds) between each read of the sensor while the keyboard is
+    /// idle. Moving the sensor while the keyboard is idle will wake it up.
+    const IDLE_POLL_INTERVAL_MS: u16 = 100;
+
+    /// Whether the X axis of the sensor should be inverted.
+    const INVERT_X: bool = false;
+
+    /// Whether the Y axis of the sensor should be inverted.
+    const INVERT_Y: bool = false;
+
+    /// Whether the X and Y axes of the sensor should be swapped. This is applied before the axes
+    /// are inverted.
+    const SWAP_XY: bool = false;
+}
+
+/// Options that control how the motion from a pointing device is sent to the host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PointingConfig {
+    /// Layer that turns the motion from the pointing device into scrolling, while it is active.
+    pub scroll_layer: Option<usize>,
+
+    /// Amount of motion needed to scroll by one step, when scrolling with the pointing device.
+    pub scroll_divisor: u8,
+
+    /// Whether the direction of scrolling should be inverted.
+    pub invert_scroll: bool,
+}
+
+impl PointingConfig {
+    /// Create a new pointing config, using the default values.
+    pub const fn new() -> Self {
+        Self {
+            scroll_layer: None,
+            scroll_divisor: 8,
+            invert_scroll: false,
+        }
+    }
+}
+
+impl Default for PointingConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Motion read from a pointing device.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, MaxSize)]
+pub struct PointingMotion {
+    /// Movement along the X axis. Positive values move the cursor right.
+    pub x: i16,
+    /// Movement along the Y axis. Positive values move the cursor down.
+    pub y: i16,
+}
+
+impl PointingMotion {
+    fn is_zero(&self) -> bool {
+        self.x == 0 && self.y == 0
+    }
+}
+
+/// Commands that can be used to control a pointing device.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, MaxSize)]
+pub enum PointingCommand {
+    /// While held, motion from the pointing device will scroll instead of moving the cursor.
+    DragScroll,
+    /// Toggle whether motion from the pointing device will scroll instead of moving the cursor.
+    ToggleDragScroll,
+    /// Change the resolution (counts per inch) of the pointing device's sensor.
+    SetCpi(u16),
+}
+
+/// Channel with motion read from the pointing device.
+///
+/// Channel messages should be consumed by the layout task, or the peripheral task on a split
+/// keyboard, so user-level code should **not** attempt to receive messages from the channel.
+pub(crate) static POINTING_MOTION_CHANNEL: Channel<RawMutex, PointingMotion, 4> = Channel::new();
+
+/// State that contains the resolution (counts per inch) that the pointing device's sensor should
+/// use. If this is `None`, [`PointingDevice::CPI`] is used.
+pub static POINTING_CPI_STATE: State<Option<u16>> =
+    State::new(None, &[&POINTING_CPI_STATE_LISTENER]);
+
+static POINTING_CPI_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+pub(crate) static IDLE_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+#[rumcake_macros::task]
+pub async fn pointing_task<K: PointingDevice>(_k: K, mut driver: impl PointingDriver<K>) {
+    let mut ticker = Ticker::every(Duration::from_millis(K::POLL_INTERVAL_MS as u64));
+
+    // Make sure that the initial CPI gets applied
+    POINTING_CPI_STATE_LISTENER.signal(());
+
+    loop {
+        if POINTING_CPI_STATE_LISTENER.try_take().is_some() {
+            let cpi = POINTING_CPI_STATE.get().await.unwrap_or(K::CPI);
+            if let Err(err) = driver.set_cpi(cpi).await {
+                error!(
+                    "[POINTING] Could not set the CPI of the sensor: {}",
+                    Debug2Format(&err)
+                );
+            }
+        }
+
+        match driver.read_motion().await {
+            Ok(motion) => {
+                if !motion.is_zero() {
+                    crate::keyboard::ACTIVITY_SIGNAL.signal(());
+
+                    let (x, y) = if K::SWAP_XY {
+                        (motion.y, motion.x)
+                    } else {
+                        (motion.x, motion.y)
+                    };
+                    let x = if K::INVERT_X { -x } else { x };
+                    let y = if K::INVERT_Y { -y } else { y };
+
+                    POINTING_MOTION_CHANNEL.send(PointingMotion { x, y }).await;
+                }
+            }
+            Err(err) => {
+                error!(
+                    "[POINTING] Could not read motion from the sensor: {}",
+                    Debug2Format(&err)
+                );
+            }
+        }
+
+        // The sensor is read less often while the keyboard is idle
+        if IDLE_STATE.get().await {
+            select(
+                Timer::after(Duration::from_millis(K::IDLE_POLL_INTERVAL_MS as u64)),
+                IDLE_STATE_LISTENER.wait(),
+            )
+            .await;
+
+            ticker.reset();
+        } else {
+            ticker.next().await;
+        }
+    }
+}
+
+/// Converts motion from the pointing device into mouse reports.
+pub(crate) struct PointingProcessor {
+    /// Motion that hasn't been sent to the host yet.
+    pending: PointingMotion,
+
+    /// Motion that hasn't been turned into a scroll step yet.
+    scroll_remainder: PointingMotion,
+
+    drag_scroll_held: bool,
+    drag_scroll_toggled: bool,
+}
+
+fn clamp_to_i8(value: i16) -> i8 {
+    value.clamp(-(i8::MAX as i16), i8::MAX as i16) as i8
+}
+
+impl PointingProcessor {
+    pub(crate) const fn new() -> Self {
+        Self {
+            pending: PointingMotion { x: 0, y: 0 },
+            scroll_remainder: PointingMotion { x: 0, y: 0 },
+            drag_scroll_held: false,
+            drag_scroll_toggled: false,
+        }
+    }
+
+    /// Add motion received from a pointing device.
+    pub(crate) fn add_motion(&mut self, motion: PointingMotion) {
+        self.pending.x = self.pending.x.saturating_add(motion.x);
+        self.pending.y = self.pending.y.saturating_add(motion.y);
+    }
+
+    /// Process a drag scroll command. CPI changes must be handled by the caller.
+    pub(crate) fn process_command(&mut self, command: PointingCommand, pressed: bool) {
+        match command {
+            PointingCommand::DragScroll => {
+                self.drag_scroll_held = pressed;
+            }
+            PointingCommand::ToggleDragScroll => {
+                if pressed {
+                    self.drag_scroll_toggled = !self.drag_scroll_toggled;
+                }
+            }
+            PointingCommand::SetCpi(_) => {}
+        }
+    }
+
+    /// Add the pending motion to a mouse report. If `report` is `None`, a new report is created
+    /// with the given buttons. Returns `None` if there is nothing to send.
+    pub(crate) fn report(
+        &mut self,
+        config: &PointingConfig,
+        layer: usize,
+        buttons: u8,
+        report: Option<WheelMouseReport>,
+    ) -> Option<WheelMouseReport> {
+        let scrolling =
+            self.drag_scroll_held || self.drag_scroll_toggled || config.scroll_layer == Some(layer);
+
+        if !scrolling {
+            self.scroll_remainder = PointingMotion::default();
+        }
+
+        if self.pending.is_zero() {
+            return report;
+        }
+
+        let base = report;
+        let mut report = base.unwrap_or(WheelMouseReport {
+            buttons,
+            x: 0,
+            y: 0,
+            vertical_wheel: 0,
+            horizontal_wheel: 0,
+        });
+
+        if scrolling {
+            self.scroll_remainder.x = self.scroll_remainder.x.saturating_add(self.pending.x);
+            self.scroll_remainder.y = self.scroll_remainder.y.saturating_add(self.pending.y);
+            self.pending = PointingMotion::default();
+
+            let divisor = config.scroll_divisor.max(1) as i16;
+            let horizontal = clamp_to_i8(self.scroll_remainder.x / divisor);
+            let vertical = clamp_to_i8(self.scroll_remainder.y / divisor);
+            self.scroll_remainder.x -= horizontal as i16 * divisor;
+            self.scroll_remainder.y -= vertical as i16 * divisor;
+
+            if horizontal == 0 && vertical == 0 {
+                return base;
+            }
+
+            // Moving up scrolls up, which is a positive value for the wheel
+            let (horizontal, vertical) = if config.invert_scroll {
+                (-horizontal, vertical)
+            } else {
+                (horizontal, -vertical)
+            };
+
+            report.horizontal_wheel = report.horizontal_wheel.saturating_add(horizontal);
+            report.vertical_wheel = report.vertical_wheel.saturating_add(vertical);
+        } else {
+            // Motion that doesn't fit in a single report is sent in the next report
+            let x = clamp_to_i8(self.pending.x);
+            let y = clamp_to_i8(self.pending.y);
+            self.pending.x -= x as i16;
+            self.pending.y -= y as i16;
+
+            report.x = report.x.saturating_add(x);
+            report.y = report.y.saturating_add(y);
+        }
+
+        Some(report)
+    }
+}
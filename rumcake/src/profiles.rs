@@ -0,0 +1,152 @@
+//! Support for switching between storage profiles.
+//!
+//! Each profile has its own dynamic keymap (including encoders and Via macros) and lighting
+//! configs, so that a keyboard can be shared by multiple people. Other data, like Vial tap dances,
+//! combos and key overrides, is shared by all profiles (see
+//! [`crate::storage::StorageKey::is_profile_specific`]).
+//!
+//! The active profile can be changed at runtime using [`ACTIVE_PROFILE_STATE`], or with
+//! [`Keycode::Profile`], [`Keycode::NextProfile`] and [`Keycode::PrevProfile`]. When it changes,
+//! the storage tasks save any pending changes to the previous profile, and load the data of the new
+//! profile. Profiles that don't have any stored data start with the default layout and lighting
+//! configs. The active profile is stored, so it is kept after the keyboard is powered off.
+//!
+//! [`Keycode::Profile`]: crate::keyboard::Keycode::Profile
+//! [`Keycode::NextProfile`]: crate::keyboard::Keycode::NextProfile
+//! [`Keycode::PrevProfile`]: crate::keyboard::Keycode::PrevProfile
+
+use defmt::{info, warn};
+use embassy_futures::select;
+use embassy_futures::select::Either;
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Timer};
+
+use crate::hw::mcu::RawMutex;
+use crate::keyboard::KeyboardLayout;
+use crate::storage::{FlashStorage, StorageDevice, StorageKey, StorageService};
+use crate::State;
+
+/// State that contains the index of the active storage profile.
+pub static ACTIVE_PROFILE_STATE: State<u8> = State::new(
+    0,
+    &[
+        &ACTIVE_PROFILE_STATE_LISTENER,
+        #[cfg(feature = "via")]
+        &crate::via::storage::VIA_PROFILE_LISTENER,
+        #[cfg(feature = "underglow")]
+        &crate::underglow::storage::UNDERGLOW_PROFILE_LISTENER,
+        #[cfg(feature = "simple-backlight")]
+        &crate::backlight::simple_backlight::storage::BACKLIGHT_PROFILE_LISTENER,
+        #[cfg(feature = "simple-backlight-matrix")]
+        &crate::backlight::simple_backlight_matrix::storage::BACKLIGHT_PROFILE_LISTENER,
+        #[cfg(feature = "rgb-backlight-matrix")]
+        &crate::backlight::rgb_backlight_matrix::storage::BACKLIGHT_PROFILE_LISTENER,
+    ],
+);
+
+static ACTIVE_PROFILE_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+/// Switch to the given profile. Profiles outside of [`KeyboardLayout::PROFILE_COUNT`] are ignored.
+pub(crate) async fn set_profile<K: KeyboardLayout>(profile: u8) {
+    if profile < K::PROFILE_COUNT {
+        ACTIVE_PROFILE_STATE.set(profile).await;
+    } else {
+        warn!(
+            "[PROFILES] Profile {} does not exist. The keyboard has {} profiles.",
+            profile,
+            K::PROFILE_COUNT
+        );
+    }
+}
+
+/// Switch to the next profile, wrapping around to the first profile.
+pub(crate) async fn next_profile<K: KeyboardLayout>() {
+    ACTIVE_PROFILE_STATE
+        .update(|profile| **profile = (**profile + 1) % K::PROFILE_COUNT)
+        .await;
+}
+
+/// Switch to the previous profile, wrapping around to the last profile.
+pub(crate) async fn prev_profile<K: KeyboardLayout>() {
+    ACTIVE_PROFILE_STATE
+        .update(|profile| **profile = (**profile + K::PROFILE_COUNT - 1) % K::PROFILE_COUNT)
+        .await;
+}
+
+impl crate::storage::StorageSchema for u8 {
+    const SCHEMA_VERSION: u16 = 1;
+}
+
+#[rumcake_macros::task]
+pub async fn profile_storage_task<K: StorageDevice + KeyboardLayout, F: FlashStorage>(
+    _k: K,
+    database: &StorageService<'static, F>,
+) where
+    [(); F::ERASE_SIZE]:,
+{
+    {
+        // Migrate the stored profile index if it was saved with an older schema version
+        let _ = database
+            .check_schema::<u8>(
+                K::get_storage_buffer(),
+                StorageKey::ActiveProfile,
+                K::get_migrations(),
+            )
+            .await;
+
+        // Get the active profile from storage
+        match database
+            .read::<u8>(K::get_storage_buffer(), StorageKey::ActiveProfile)
+            .await
+        {
+            Ok(profile) if profile < K::PROFILE_COUNT => {
+                info!(
+                    "[PROFILES] Obtained active profile from storage: {}",
+                    profile
+                );
+                // Notify the other storage tasks, so that they load the data for this profile,
+                // but don't save the profile to storage again
+                ACTIVE_PROFILE_STATE.set(profile).await;
+                ACTIVE_PROFILE_STATE_LISTENER.reset();
+            }
+            Ok(profile) => {
+                warn!(
+                    "[PROFILES] Stored profile {} does not exist, using the first profile.",
+                    profile
+                );
+            }
+            Err(()) => {
+                warn!("[PROFILES] Could not get active profile from storage, using the first profile.");
+            }
+        }
+    }
+
+    let save = || async {
+        let _ = database
+            .write(
+                K::get_storage_buffer(),
+                StorageKey::ActiveProfile,
+                ACTIVE_PROFILE_STATE.get().await,
+            )
+            .await;
+    };
+
+    // Save the active profile if it hasn't been changed in 5 seconds
+    loop {
+        ACTIVE_PROFILE_STATE_LISTENER.wait().await;
+        match select::select(
+            Timer::after(Duration::from_secs(5)),
+            ACTIVE_PROFILE_STATE_LISTENER.wait(),
+        )
+        .await
+        {
+            Either::First(_) => {
+                save().await;
+            }
+            Either::Second(_) => {
+                // Re-signal, so that we skip the `wait()` call at the beginning of this loop
+                ACTIVE_PROFILE_STATE_LISTENER.signal(());
+            }
+        }
+    }
+}
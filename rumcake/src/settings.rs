@@ -0,0 +1,222 @@
+//! Keyboard settings that can be changed at runtime.
+//!
+//! These settings control the timing and behaviour of hold-tap keys, one shot keys, combos, auto
+//! shift and debouncing. They mirror the QMK settings that can be changed using Vial, and are
+//! applied to the layout and matrix while the keyboard is running. A setting that is `None` uses
+//! the value defined by your keyboard or by the action in your layout.
+
+use embassy_sync::signal::Signal;
+use keyberon::action::HoldTapConfig;
+use keyberon::layout::LayoutSettings;
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+
+use crate::hw::mcu::RawMutex;
+use crate::State;
+
+/// Auto shift timeout used if [`KeyboardSettings::auto_shift_timeout`] is `None`.
+pub const DEFAULT_AUTO_SHIFT_TIMEOUT: u16 = 175;
+
+/// Determines how a hold-tap key chooses between its hold and tap action.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, MaxSize)]
+pub enum HoldTapMode {
+    /// Only the tapping term determines whether the hold or tap action is triggered.
+    Default,
+    /// The hold action is triggered if another key is pressed and released while the hold-tap
+    /// key is held.
+    PermissiveHold,
+    /// The hold action is triggered if another key is pressed while the hold-tap key is held.
+    HoldOnOtherKeyPress,
+}
+
+impl From<HoldTapMode> for HoldTapConfig {
+    fn from(value: HoldTapMode) -> Self {
+        match value {
+            HoldTapMode::Default => HoldTapConfig::Default,
+            HoldTapMode::PermissiveHold => HoldTapConfig::PermissiveHold,
+            HoldTapMode::HoldOnOtherKeyPress => HoldTapConfig::HoldOnOtherKeyPress,
+        }
+    }
+}
+
+/// Settings that can be changed while the keyboard is running.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, MaxSize)]
+pub struct KeyboardSettings {
+    /// Amount of time (in milliseconds) that a hold-tap key must be held to trigger its hold
+    /// action. This applies to all hold-tap keys on your layout.
+    pub tapping_term: Option<u16>,
+
+    /// How hold-tap keys choose between their hold and tap action. This applies to all hold-tap
+    /// keys on your layout.
+    pub hold_tap_mode: Option<HoldTapMode>,
+
+    /// Number of consecutive taps on a one shot key that will lock it, until it is tapped again.
+    /// If this is 0, one shot keys are never locked.
+    pub oneshot_tap_toggle: Option<u8>,
+
+    /// Amount of time (in milliseconds) before an active one shot key is released. If this is 0,
+    /// one shot keys stay active until another key is pressed.
+    pub oneshot_timeout: Option<u16>,
+
+    /// Amount of time (in milliseconds) that all keys of a combo must be pressed within. Combos
+    /// that specify their own timeout are not affected. If this is `None`,
+    /// [`crate::keyboard::KeyboardLayout::COMBO_TIMEOUT_MS`] is used.
+    pub combo_term: Option<u16>,
+
+    /// Amount of time (in milliseconds) that switches in the matrix are debounced for. If this is
+    /// `None`, [`crate::keyboard::KeyboardMatrix::DEBOUNCE_MS`] is used.
+    pub debounce: Option<u16>,
+
+    /// Whether auto shift is enabled.
+    pub auto_shift: Option<bool>,
+
+    /// Amount of time (in milliseconds) that a key must be held to be shifted by auto shift.
+    pub auto_shift_timeout: Option<u16>,
+}
+
+impl KeyboardSettings {
+    /// Create a new set of settings, where nothing is changed from the keyboard's defaults.
+    pub const fn new() -> Self {
+        Self {
+            tapping_term: None,
+            hold_tap_mode: None,
+            oneshot_tap_toggle: None,
+            oneshot_timeout: None,
+            combo_term: None,
+            debounce: None,
+            auto_shift: None,
+            auto_shift_timeout: None,
+        }
+    }
+
+    /// Obtain the settings that should be applied to a keyberon layout.
+    pub fn layout_settings(&self) -> LayoutSettings {
+        LayoutSettings {
+            hold_tap_timeout: self.tapping_term,
+            hold_tap_config: self.hold_tap_mode.map(Into::into),
+            // keyberon releases one shot keys immediately if the timeout is 0
+            oneshot_timeout: self.oneshot_timeout.map(|timeout| {
+                if timeout == 0 {
+                    u16::MAX
+                } else {
+                    timeout
+                }
+            }),
+            oneshot_tap_toggle: self.oneshot_tap_toggle.unwrap_or_default(),
+        }
+    }
+}
+
+impl Default for KeyboardSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// State that contains the current keyboard settings.
+pub static KEYBOARD_SETTINGS_STATE: State<KeyboardSettings> = State::new(
+    KeyboardSettings::new(),
+    &[
+        &LAYOUT_SETTINGS_LISTENER,
+        &MATRIX_SETTINGS_LISTENER,
+        #[cfg(feature = "storage")]
+        &storage::KEYBOARD_SETTINGS_STATE_LISTENER,
+    ],
+);
+
+/// Signalled when the settings change, so that the layout can apply them.
+pub(crate) static LAYOUT_SETTINGS_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+/// Signalled when the settings change, so that the matrix can apply them.
+pub(crate) static MATRIX_SETTINGS_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+#[cfg(feature = "storage")]
+pub mod storage {
+    use defmt::{info, warn, Debug2Format};
+    use embassy_futures::select;
+    use embassy_futures::select::Either;
+    use embassy_sync::signal::Signal;
+    use embassy_time::Duration;
+    use embassy_time::Timer;
+
+    use crate::hw::mcu::RawMutex;
+    use crate::storage::{FlashStorage, StorageDevice};
+
+    use super::KeyboardSettings;
+    use super::{KEYBOARD_SETTINGS_STATE, LAYOUT_SETTINGS_LISTENER, MATRIX_SETTINGS_LISTENER};
+
+    pub(super) static KEYBOARD_SETTINGS_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+    impl crate::storage::StorageSchema for KeyboardSettings {
+        const SCHEMA_VERSION: u16 = 1;
+    }
+
+    #[rumcake_macros::task]
+    pub async fn keyboard_settings_storage_task<K: StorageDevice, F: FlashStorage>(
+        _k: K,
+        database: &crate::storage::StorageService<'static, F>,
+    ) where
+        [(); F::ERASE_SIZE]:,
+    {
+        {
+            // Migrate the stored keyboard settings if it was saved with an older schema version
+            let _ = database
+                .check_schema::<KeyboardSettings>(
+                    K::get_storage_buffer(),
+                    crate::storage::StorageKey::KeyboardSettings,
+                    K::get_migrations(),
+                )
+                .await;
+
+            // Get keyboard settings from storage
+            if let Ok(settings) = database
+                .read(
+                    K::get_storage_buffer(),
+                    crate::storage::StorageKey::KeyboardSettings,
+                )
+                .await
+            {
+                info!(
+                    "[SETTINGS] Obtained keyboard settings from storage: {}",
+                    Debug2Format(&settings)
+                );
+                // Quietly update the settings so that we don't save them to storage again, but
+                // still apply them to the layout and matrix
+                KEYBOARD_SETTINGS_STATE.quiet_set(settings).await;
+                LAYOUT_SETTINGS_LISTENER.signal(());
+                MATRIX_SETTINGS_LISTENER.signal(());
+            } else {
+                warn!("[SETTINGS] Could not get keyboard settings from storage, using default settings.",);
+            }
+        }
+
+        let save = || async {
+            let _ = database
+                .write(
+                    K::get_storage_buffer(),
+                    crate::storage::StorageKey::KeyboardSettings,
+                    KEYBOARD_SETTINGS_STATE.get().await,
+                )
+                .await;
+        };
+
+        // Save the keyboard settings if they haven't been changed in 5 seconds
+        loop {
+            KEYBOARD_SETTINGS_STATE_LISTENER.wait().await;
+            match select::select(
+                Timer::after(Duration::from_secs(5)),
+                KEYBOARD_SETTINGS_STATE_LISTENER.wait(),
+            )
+            .await
+            {
+                Either::First(_) => {
+                    save().await;
+                }
+                Either::Second(_) => {
+                    // Re-signal, so that we skip the `wait()` call at the beginning of this loop
+                    KEYBOARD_SETTINGS_STATE_LISTENER.signal(());
+                }
+            }
+        }
+    }
+}
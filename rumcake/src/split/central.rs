@@ -35,11 +35,23 @@ pub async fn central_task(mut driver: impl CentralDeviceDriver) {
         .await
         {
             Either::First(message) => match message {
-                Ok(event) => match event {
-                    MessageToCentral::KeyPress(_, _) | MessageToCentral::KeyRelease(_, _) => {
-                        POLLED_EVENTS_CHANNEL.send(event.try_into().unwrap()).await;
+                Ok(event) => {
+                    crate::keyboard::ACTIVITY_SIGNAL.signal(());
+
+                    match event {
+                        MessageToCentral::KeyPress(_, _) | MessageToCentral::KeyRelease(_, _) => {
+                            POLLED_EVENTS_CHANNEL.send(event.try_into().unwrap()).await;
+                        }
+                        #[cfg(feature = "pointing-device")]
+                        MessageToCentral::PointingMotion(x, y) => {
+                            crate::pointing::POINTING_MOTION_CHANNEL
+                                .send(crate::pointing::PointingMotion { x, y })
+                                .await;
+                        }
+                        #[cfg(not(feature = "pointing-device"))]
+                        MessageToCentral::PointingMotion(_, _) => {}
                     }
-                },
+                }
                 Err(err) => {
                     error!(
                         "[SPLIT_CENTRAL] Error when attempting to receive from peripheral: {}",
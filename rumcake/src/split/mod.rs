@@ -19,6 +19,10 @@ pub enum MessageToCentral {
     KeyPress(u8, u8),
     /// Key release in the form of (row, col).
     KeyRelease(u8, u8),
+
+    /// Motion from the peripheral's pointing device, in the form of (x, y). This is always
+    /// available, so that the message format doesn't depend on the enabled features.
+    PointingMotion(i16, i16),
 }
 
 /// Size of buffer used when sending messages to a central device
@@ -40,6 +44,7 @@ impl TryFrom<MessageToCentral> for Event {
         match message {
             MessageToCentral::KeyPress(row, col) => Ok(Event::Press(row, col)),
             MessageToCentral::KeyRelease(row, col) => Ok(Event::Release(row, col)),
+            MessageToCentral::PointingMotion(_, _) => Err(()),
         }
     }
 }
@@ -67,6 +72,10 @@ pub enum MessageToPeripheral {
     #[cfg(feature = "underglow")]
     /// An [`UnderglowCommand`](crate::underglow::animations::UnderglowCommand) to be processed by the peripheral's backlight animator.
     Underglow(crate::underglow::animations::UnderglowCommand),
+
+    #[cfg(feature = "pointing-device")]
+    /// Resolution (counts per inch) that the peripheral's pointing device should use.
+    PointingCpi(u16),
 }
 
 /// Size of buffer used when sending messages to a peripheral device
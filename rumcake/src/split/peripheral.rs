@@ -7,7 +7,7 @@
 //! device (see [`MessageToPeripheral`]).
 
 use defmt::{error, Debug2Format};
-use embassy_futures::select::{select, Either};
+use embassy_futures::select::{select3, Either3};
 use embassy_sync::pubsub::PubSubBehavior;
 
 use crate::keyboard::{MATRIX_EVENTS, POLLED_EVENTS_CHANNEL};
@@ -19,13 +19,19 @@ use super::drivers::PeripheralDeviceDriver;
 #[rumcake_macros::task]
 pub async fn peripheral_task(mut driver: impl PeripheralDeviceDriver) {
     loop {
-        match select(
+        #[cfg(feature = "pointing-device")]
+        let pointing_motion = crate::pointing::POINTING_MOTION_CHANNEL.receive();
+        #[cfg(not(feature = "pointing-device"))]
+        let pointing_motion = core::future::pending::<()>();
+
+        match select3(
             driver.receive_message_from_central(),
             POLLED_EVENTS_CHANNEL.receive(),
+            pointing_motion,
         )
         .await
         {
-            Either::First(message) => match message {
+            Either3::First(message) => match message {
                 Ok(message) => match message {
                     #[cfg(feature = "simple-backlight")]
                     MessageToPeripheral::SimpleBacklight(command) => {
@@ -51,6 +57,10 @@ pub async fn peripheral_task(mut driver: impl PeripheralDeviceDriver) {
                             .send(command)
                             .await
                     }
+                    #[cfg(feature = "pointing-device")]
+                    MessageToPeripheral::PointingCpi(cpi) => {
+                        crate::pointing::POINTING_CPI_STATE.set(Some(cpi)).await
+                    }
                     #[allow(unreachable_patterns)]
                     _ => {}
                 },
@@ -61,7 +71,7 @@ pub async fn peripheral_task(mut driver: impl PeripheralDeviceDriver) {
                     )
                 }
             },
-            Either::Second(event) => {
+            Either3::Second(event) => {
                 MATRIX_EVENTS.publish_immediate(event);
 
                 if let Err(err) = driver.send_message_to_central(event.into()).await {
@@ -71,6 +81,21 @@ pub async fn peripheral_task(mut driver: impl PeripheralDeviceDriver) {
                     )
                 };
             }
+            #[allow(unused_variables)]
+            Either3::Third(motion) => {
+                #[cfg(feature = "pointing-device")]
+                if let Err(err) = driver
+                    .send_message_to_central(crate::split::MessageToCentral::PointingMotion(
+                        motion.x, motion.y,
+                    ))
+                    .await
+                {
+                    error!(
+                        "[SPLIT_PERIPHERAL] Error sending pointing motion to central: {}",
+                        Debug2Format(&err)
+                    )
+                };
+            }
         }
     }
 }
@@ -8,13 +8,17 @@
 //! your `memory.x` file. Refer to [`crate::hw::__config_start`], and the corresponding
 //! `feature-storage.md` doc for more information.
 
+use core::any::TypeId;
 use core::cell::{Cell, RefCell};
 use core::fmt::Debug;
 use core::hash::{Hash, Hasher, SipHasher};
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use defmt::{assert, debug};
 use defmt::{error, info, warn, Debug2Format};
+use embassy_sync::channel::Channel;
 use embassy_sync::mutex::{Mutex, MutexGuard};
+use embassy_sync::signal::Signal;
 use embedded_storage::nor_flash::ReadNorFlash;
 use embedded_storage_async::nor_flash::{
     ErrorType, NorFlash as AsyncNorFlash, ReadNorFlash as AsyncReadNorFlash,
@@ -22,7 +26,7 @@ use embedded_storage_async::nor_flash::{
 use num_derive::FromPrimitive;
 use once_cell::sync::OnceCell;
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tickv::success_codes::SuccessCode;
 use tickv::{AsyncTicKV, ErrorCode, FlashController, MAIN_KEY};
 
@@ -35,7 +39,7 @@ fn get_hashed_key(key: &[u8]) -> u64 {
 }
 
 /// Keys for data to be stored in the database.
-#[derive(Debug, FromPrimitive, Copy, Clone)]
+#[derive(Debug, FromPrimitive, Copy, Clone, PartialEq, Eq)]
 #[repr(u8)]
 pub enum StorageKey {
     /// Key to store [`crate::backlight::simple_backlight::animations::BacklightConfig`].
@@ -62,6 +66,78 @@ pub enum StorageKey {
     DynamicKeymapCombo = 0x41,
     /// Key to store the current state of the key overrides in the Vial dynamic keyboard layout.
     DynamicKeymapKeyOverride = 0x42,
+    /// Key to store [`crate::settings::KeyboardSettings`].
+    KeyboardSettings = 0x50,
+    /// Key to store [`crate::matrix::analog::AnalogConfig`].
+    AnalogConfig = 0x60,
+    /// Key to store the calibration of an [`crate::matrix::analog::AnalogMatrix`].
+    AnalogCalibration = 0x61,
+    /// Key to store [`crate::unicode::UnicodeMode`].
+    UnicodeMode = 0x70,
+    /// Key to store the index of the active storage profile. See [`crate::profiles`].
+    ActiveProfile = 0x80,
+}
+
+impl StorageKey {
+    /// Check if the data stored at this key belongs to a storage profile. Each profile has its own
+    /// dynamic keymap (including encoders and macros) and lighting configs. Other data, like Via
+    /// layout options, Vial tap dances, combos and key overrides, and keyboard settings, is shared
+    /// by all profiles.
+    pub const fn is_profile_specific(&self) -> bool {
+        matches!(
+            self,
+            StorageKey::SimpleBacklightConfig
+                | StorageKey::SimpleBacklightMatrixConfig
+                | StorageKey::RGBBacklightMatrixConfig
+                | StorageKey::UnderglowConfig
+                | StorageKey::DynamicKeymap
+                | StorageKey::DynamicKeymapEncoder
+                | StorageKey::DynamicKeymapMacro
+        )
+    }
+
+    /// Get the key used to store this data in the given profile. If the data is shared by all
+    /// profiles (see [`StorageKey::is_profile_specific`]), `profile` is ignored.
+    pub const fn in_profile(self, profile: u8) -> ProfileKey {
+        ProfileKey {
+            key: self,
+            profile: if self.is_profile_specific() {
+                profile
+            } else {
+                0
+            },
+        }
+    }
+}
+
+/// Maximum number of storage profiles that can be stored.
+pub const MAX_PROFILES: u8 = 8;
+
+/// A [`StorageKey`] in a storage profile. Methods of [`StorageService`] accept either a
+/// [`StorageKey`], which refers to the data in the first profile, or a [`ProfileKey`], which can be
+/// obtained using [`StorageKey::in_profile`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ProfileKey {
+    key: StorageKey,
+    profile: u8,
+}
+
+impl ProfileKey {
+    /// Get the bytes used to look up this key in the database. Keys in the first profile use the
+    /// same bytes as they did before profiles were supported, so that existing data is kept.
+    fn id(&self, key_type: StorageKeyType) -> heapless::Vec<u8, 3> {
+        let mut id = heapless::Vec::from_slice(&[self.key as u8, key_type as u8]).unwrap();
+        if self.profile > 0 {
+            id.push(self.profile).unwrap();
+        }
+        id
+    }
+}
+
+impl From<StorageKey> for ProfileKey {
+    fn from(key: StorageKey) -> Self {
+        ProfileKey { key, profile: 0 }
+    }
 }
 
 #[repr(u8)]
@@ -70,6 +146,334 @@ enum StorageKeyType {
     Metadata,
 }
 
+/// Types that can be stored using a [`StorageService`]. The schema version of the type is stored
+/// alongside its data, so that data written by an older firmware can be converted using a
+/// [`Migration`], instead of being discarded.
+pub trait StorageSchema: Serialize + DeserializeOwned {
+    /// Version of the serialized format of this type. This must be increased whenever the type
+    /// changes in a way that affects how it is serialized by [`postcard`] (e.g. adding, removing or
+    /// reordering fields).
+    const SCHEMA_VERSION: u16;
+}
+
+/// Converts the data stored at a [`StorageKey`] from one schema version to the next. Migrations
+/// can be provided using [`StorageDevice::get_migrations`].
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    /// Key of the data that this migration applies to.
+    pub key: StorageKey,
+
+    /// Schema version of the data that this migration converts. The converted data has a schema
+    /// version of `from + 1`.
+    pub from: u16,
+
+    /// Function that converts the stored bytes (first argument) to the bytes of the next schema
+    /// version, by writing them to the second argument. Returns the length of the converted data.
+    /// [`migrate_from`] can be used to implement this.
+    pub migrate: fn(&[u8], &mut [u8]) -> Result<usize, ()>,
+}
+
+impl Migration {
+    /// Create a new migration for the data stored at `key`, from schema version `from`.
+    pub const fn new(
+        key: StorageKey,
+        from: u16,
+        migrate: fn(&[u8], &mut [u8]) -> Result<usize, ()>,
+    ) -> Self {
+        Self { key, from, migrate }
+    }
+}
+
+/// Convert serialized data of type `Old` to type `New`, using `New`'s [`From<Old>`]
+/// implementation. This can be used as the `migrate` function of a [`Migration`], e.g.
+/// `Migration::new(StorageKey::UnderglowConfig, 1, migrate_from::<OldConfig, UnderglowConfig>)`.
+pub fn migrate_from<Old: DeserializeOwned, New: Serialize + From<Old>>(
+    old: &[u8],
+    new: &mut [u8],
+) -> Result<usize, ()> {
+    let old: Old = postcard::from_bytes(old).map_err(|error| {
+        error!(
+            "[STORAGE] Deserialization error while migrating: {}",
+            Debug2Format(&error)
+        );
+    })?;
+
+    postcard::to_slice(&New::from(old), new)
+        .map(|serialized| serialized.len())
+        .map_err(|error| {
+            error!(
+                "[STORAGE] Serialization error while migrating: {}",
+                Debug2Format(&error)
+            );
+        })
+}
+
+/// Apply migrations to the data in `buf[..len]`, until it reaches the `to` schema version. The
+/// rest of `buf` is used to store the output of each migration. Returns the length of the migrated
+/// data, or `None` if a migration is missing or fails.
+fn apply_migrations(
+    buf: &mut [u8],
+    mut len: usize,
+    key: StorageKey,
+    from: u16,
+    to: u16,
+    migrations: &[Migration],
+) -> Option<usize> {
+    for version in from..to {
+        let migration = migrations
+            .iter()
+            .find(|m| m.key == key && m.from == version)?;
+
+        let (old, new) = buf.split_at_mut(len);
+        let new_len = (migration.migrate)(old, new).ok()?;
+        buf.copy_within(len..(len + new_len), 0);
+        len = new_len;
+    }
+
+    Some(len)
+}
+
+/// Number of bytes that TicKV stores with each value (a header before the value, and a checksum
+/// after it).
+const TICKV_OBJECT_OVERHEAD: usize = 11 + 4;
+
+/// Bytes at the start of every backup created by [`StorageService::export_config`].
+const BACKUP_MAGIC: [u8; 4] = *b"RMCK";
+
+/// Version of the backup format. This must be increased whenever [`BackupHeader`] or
+/// [`BackupEntry`] changes. Backups with an older version are converted when they are imported.
+const BACKUP_VERSION: u16 = 2;
+
+#[derive(Serialize, Deserialize)]
+struct BackupHeader {
+    magic: [u8; 4],
+    version: u16,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BackupEntry<'a> {
+    key: u8,
+    profile: u8,
+    metadata: &'a [u8],
+    data: &'a [u8],
+}
+
+/// Entry of a backup created before storage profiles were supported (version 1). All of its data
+/// belongs to the first profile.
+#[derive(Deserialize)]
+struct BackupEntryV1<'a> {
+    key: u8,
+    metadata: &'a [u8],
+    data: &'a [u8],
+}
+
+/// Read the next entry of a backup with the given format version. Returns the entry, and the rest
+/// of the backup.
+fn take_backup_entry(version: u16, bytes: &[u8]) -> Result<(BackupEntry<'_>, &[u8]), ()> {
+    match version {
+        1 => postcard::take_from_bytes::<BackupEntryV1>(bytes).map(|(entry, rest)| {
+            let entry = BackupEntry {
+                key: entry.key,
+                profile: 0,
+                metadata: entry.metadata,
+                data: entry.data,
+            };
+            (entry, rest)
+        }),
+        _ => postcard::take_from_bytes::<BackupEntry>(bytes),
+    }
+    .map_err(|_| ())
+}
+
+/// Iterate over every key that data can be stored at, in every storage profile.
+fn all_profile_keys() -> impl Iterator<Item = ProfileKey> {
+    (0..=u8::MAX)
+        .filter_map(<StorageKey as num::FromPrimitive>::from_u8)
+        .flat_map(|key: StorageKey| {
+            let profiles = if key.is_profile_specific() {
+                MAX_PROFILES
+            } else {
+                1
+            };
+            (0..profiles).map(move |profile| key.in_profile(profile))
+        })
+}
+
+/// Compute the CRC-32 (IEEE) checksum of the given data.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFFFFFF;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// Check that a backup is complete and compatible, and that every value in it fits in a buffer
+/// with a size of `buffer_len`. Returns the format version and the serialized entries of the backup.
+fn validate_backup(data: &[u8], buffer_len: usize) -> Result<(u16, &[u8]), ()> {
+    if data.len() < 4 {
+        error!("[STORAGE] Backup is too short.");
+        return Err(());
+    }
+
+    let (body, crc) = data.split_at(data.len() - 4);
+    if crc32(body).to_le_bytes() != crc {
+        error!("[STORAGE] Backup checksum does not match.");
+        return Err(());
+    }
+
+    let (header, entries) = postcard::take_from_bytes::<BackupHeader>(body)
+        .map_err(|_| error!("[STORAGE] Could not read backup header."))?;
+    if header.magic != BACKUP_MAGIC || !(1..=BACKUP_VERSION).contains(&header.version) {
+        error!(
+            "[STORAGE] Unsupported backup format (version {}).",
+            header.version
+        );
+        return Err(());
+    }
+
+    let mut rest = entries;
+    while !rest.is_empty() {
+        let (entry, remaining) = take_backup_entry(header.version, rest)
+            .map_err(|_| error!("[STORAGE] Could not read backup entry."))?;
+
+        match <StorageKey as num::FromPrimitive>::from_u8(entry.key) {
+            None => {
+                error!("[STORAGE] Backup contains an unknown key: {}", entry.key);
+                return Err(());
+            }
+            Some(key)
+                if entry.profile >= MAX_PROFILES
+                    || (entry.profile > 0 && !key.is_profile_specific()) =>
+            {
+                error!(
+                    "[STORAGE] Backup contains key {} for an invalid profile: {}",
+                    entry.key, entry.profile
+                );
+                return Err(());
+            }
+            Some(_) => {}
+        }
+
+        if entry.data.len() > buffer_len || entry.metadata.len() > buffer_len {
+            error!(
+                "[STORAGE] Backup entry for key {} is too large for the storage buffer.",
+                entry.key
+            );
+            return Err(());
+        }
+
+        rest = remaining;
+    }
+
+    Ok((header.version, entries))
+}
+
+/// Operations that can be requested from the [`config_backup_task`].
+pub(crate) enum BackupOperation {
+    /// Export a backup to the backup buffer.
+    Export { include_bluetooth: bool },
+    /// Import a backup with the given length from the backup buffer.
+    Import { len: usize, include_bluetooth: bool },
+}
+
+/// Buffer that contains a backup that is being transferred to or from the host. This is set by the
+/// [`config_backup_task`], using [`StorageDevice::get_backup_buffer`].
+pub(crate) static BACKUP_BUFFER: Mutex<RawMutex, Option<&'static mut [u8]>> = Mutex::new(None);
+
+static BACKUP_OPERATION_CHANNEL: Channel<RawMutex, BackupOperation, 1> = Channel::new();
+static BACKUP_OPERATION_COMPLETE: Signal<RawMutex, Result<usize, ()>> = Signal::new();
+
+/// Dispatch a backup operation to the [`config_backup_task`], and wait for the result. Returns the
+/// length of the exported or imported backup.
+pub(crate) async fn request_backup_operation(operation: BackupOperation) -> Result<usize, ()> {
+    if BACKUP_BUFFER.lock().await.is_none() {
+        warn!("[STORAGE] Backups are not available, because the backup task is not running.");
+        return Err(());
+    }
+
+    BACKUP_OPERATION_CHANNEL.send(operation).await;
+    BACKUP_OPERATION_COMPLETE.wait().await
+}
+
+#[rumcake_macros::task]
+pub async fn config_backup_task<K: StorageDevice, F: FlashStorage>(
+    _k: K,
+    database: &StorageService<'static, F>,
+) where
+    [(); F::ERASE_SIZE]:,
+{
+    BACKUP_BUFFER.lock().await.replace(K::get_backup_buffer());
+
+    loop {
+        let operation = BACKUP_OPERATION_CHANNEL.receive().await;
+
+        let mut backup = BACKUP_BUFFER.lock().await;
+        let backup = backup.as_deref_mut().unwrap();
+        let exclude: &[StorageKey] = match operation {
+            BackupOperation::Export {
+                include_bluetooth: true,
+            }
+            | BackupOperation::Import {
+                include_bluetooth: true,
+                ..
+            } => &[],
+            _ => &[StorageKey::BluetoothProfiles],
+        };
+
+        let result = match operation {
+            BackupOperation::Export { .. } => {
+                database
+                    .export_config(K::get_storage_buffer(), backup, exclude)
+                    .await
+            }
+            BackupOperation::Import { len, .. } => match backup.get(..len) {
+                Some(data) => database
+                    .import_config(K::get_storage_buffer(), data, exclude)
+                    .await
+                    .map(|()| len),
+                None => Err(()),
+            },
+        };
+
+        BACKUP_OPERATION_COMPLETE.signal(result);
+    }
+}
+
+/// Get the index of the active storage profile. This is always `0` if the `profiles` feature is
+/// disabled.
+pub(crate) async fn active_profile() -> u8 {
+    #[cfg(feature = "profiles")]
+    return crate::profiles::ACTIVE_PROFILE_STATE.get().await;
+
+    #[cfg(not(feature = "profiles"))]
+    0
+}
+
+/// Wait until the active storage profile changes, using a listener of
+/// [`crate::profiles::ACTIVE_PROFILE_STATE`], and return the new profile. If the `profiles`
+/// feature is disabled, this never completes.
+pub(crate) async fn wait_for_profile_change(listener: &Signal<RawMutex, ()>) -> u8 {
+    #[cfg(feature = "profiles")]
+    {
+        listener.wait().await;
+        crate::profiles::ACTIVE_PROFILE_STATE.get().await
+    }
+
+    #[cfg(not(feature = "profiles"))]
+    {
+        let _ = listener;
+        core::future::pending().await
+    }
+}
+
 /// A wrapper around a TicKV instance which allows you to receive requests to read, write or delete
 /// data from a storage peripheral.
 pub struct StorageService<'a, F: FlashStorage>
@@ -77,6 +481,10 @@ where
     [(); F::ERASE_SIZE]:,
 {
     database: OnceCell<Mutex<RawMutex, AsyncTicKV<'a, FlashDevice<'a, F>, { F::ERASE_SIZE }>>>,
+
+    /// Set after a backup is imported, so that tasks can't overwrite the imported data with their
+    /// in-memory state before the keyboard restarts.
+    read_only: AtomicBool,
 }
 
 impl<'a, F: FlashStorage> StorageService<'a, F>
@@ -88,7 +496,20 @@ where
     pub const fn new() -> Self {
         StorageService {
             database: OnceCell::new(),
+            read_only: AtomicBool::new(false),
+        }
+    }
+
+    /// Check if writes are blocked, because a backup was imported.
+    fn is_read_only(&self, key: &ProfileKey) -> bool {
+        let read_only = self.read_only.load(Ordering::Relaxed);
+        if read_only {
+            warn!(
+                "[STORAGE] Ignoring change to {}, because a backup was imported. Restart the keyboard to use the imported data.",
+                Debug2Format(key)
+            );
         }
+        read_only
     }
 
     async fn get_database(
@@ -127,41 +548,32 @@ where
     pub(crate) async fn check_metadata(
         &self,
         buffer: &'static mut [u8],
-        key: StorageKey,
+        key: impl Into<ProfileKey>,
         current_metadata: &[u8],
     ) -> Result<(), ()> {
+        let key = key.into();
         let mut database = self.get_database().await;
 
         // Verify if the underlying data type has changed since last boot
-        let (will_reset, buf) = match get_key(
-            &mut database,
-            &[key as u8, StorageKeyType::Metadata as u8],
-            buffer,
-        )
-        .await
-        {
-            (Ok(_), Some(buf), len) => {
-                let changed = current_metadata.len() != len || *current_metadata != *buf;
-                if changed {
+        let (will_reset, buf) =
+            match get_key(&mut database, &key.id(StorageKeyType::Metadata), buffer).await {
+                (Ok(_), Some(buf), len) => {
+                    let changed = current_metadata.len() != len || *current_metadata != *buf;
+                    if changed {
+                        warn!("[STORAGE] Metadata for {} has changed.", Debug2Format(&key),);
+                    }
+                    (changed, buf)
+                }
+                (Err(error), Some(buf), _len) => {
                     warn!(
-                        "[STORAGE] Metadata for {} has changed.",
-                        Debug2Format(
-                            &<StorageKey as num::FromPrimitive>::from_u8(key as u8).unwrap()
-                        ),
+                        "[STORAGE] Could not read metadata for {}: {}",
+                        Debug2Format(&key),
+                        Debug2Format(&error)
                     );
+                    (true, buf)
                 }
-                (changed, buf)
-            }
-            (Err(error), Some(buf), _len) => {
-                warn!(
-                    "[STORAGE] Could not read metadata for {}: {}",
-                    Debug2Format(&<StorageKey as num::FromPrimitive>::from_u8(key as u8).unwrap()),
-                    Debug2Format(&error)
-                );
-                (true, buf)
-            }
-            _ => unreachable!(),
-        };
+                _ => unreachable!(),
+            };
 
         buf[..current_metadata.len()].copy_from_slice(current_metadata);
 
@@ -169,19 +581,19 @@ where
         if will_reset {
             warn!(
                 "[STORAGE] Deleting old data and updating stored metadata for {}.",
-                Debug2Format(&<StorageKey as num::FromPrimitive>::from_u8(key as u8).unwrap()),
+                Debug2Format(&key),
             );
 
             // Invalidate old data
-            let _ =
-                invalidate_key(&mut database, &[key as u8, StorageKeyType::Metadata as u8]).await;
+            let _ = invalidate_key(&mut database, &key.id(StorageKeyType::Data)).await;
+            let _ = invalidate_key(&mut database, &key.id(StorageKeyType::Metadata)).await;
             garbage_collect(&mut database).await.0.unwrap();
 
             // Add new metadata
             let length = current_metadata.len();
             append_key(
                 &mut database,
-                &[key as u8, StorageKeyType::Metadata as u8],
+                &key.id(StorageKeyType::Metadata),
                 buf,
                 length,
             )
@@ -193,32 +605,138 @@ where
         Ok(())
     }
 
+    /// This function checks the schema version stored for the given key. If the stored data was
+    /// written with an older schema version than [`StorageSchema::SCHEMA_VERSION`], it will be
+    /// converted using `migrations`. If the data can't be migrated, or was written with a newer
+    /// schema version, the existing entry for that key will be invalidated.
+    ///
+    /// Data written before schema versions were stored (which used the type's `TypeId` as
+    /// metadata) is assumed to have a schema version of 1, as long as the stored `TypeId` matches
+    /// `T`. Any other metadata is unrecognized, so the existing entry will be invalidated.
+    pub(crate) async fn check_schema<T: StorageSchema + 'static>(
+        &self,
+        buffer: &'static mut [u8],
+        key: impl Into<ProfileKey>,
+        migrations: &[Migration],
+    ) -> Result<(), ()> {
+        let key = key.into();
+        let mut database = self.get_database().await;
+
+        let legacy_metadata: [u8; core::mem::size_of::<TypeId>()] =
+            unsafe { core::mem::transmute(TypeId::of::<T>()) };
+
+        let (stored_version, has_metadata, mut buf) =
+            match get_key(&mut database, &key.id(StorageKeyType::Metadata), buffer).await {
+                (Ok(_), Some(buf), 2) => (Some(u16::from_le_bytes([buf[0], buf[1]])), true, buf),
+                (Ok(_), Some(buf), len) if buf[..len] == legacy_metadata[..] => {
+                    (Some(1), true, buf)
+                }
+                (Ok(_), Some(buf), _len) => {
+                    warn!(
+                        "[STORAGE] Unrecognized metadata for {}. Deleting old data.",
+                        Debug2Format(&key),
+                    );
+                    (None, true, buf)
+                }
+                (Err(_), Some(buf), _len) => (None, false, buf),
+                _ => unreachable!(),
+            };
+
+        if stored_version == Some(T::SCHEMA_VERSION) {
+            return Ok(());
+        }
+
+        let mut migrated = None;
+
+        if let Some(version) = stored_version {
+            let mut has_data = false;
+
+            if version < T::SCHEMA_VERSION {
+                let (result, data_buf, len) =
+                    get_key(&mut database, &key.id(StorageKeyType::Data), buf).await;
+                buf = data_buf.unwrap();
+
+                has_data = result.is_ok();
+                if has_data {
+                    migrated =
+                        apply_migrations(buf, len, key.key, version, T::SCHEMA_VERSION, migrations);
+                }
+            }
+
+            match migrated {
+                Some(_) => info!(
+                    "[STORAGE] Migrating {} data from schema version {} to {}.",
+                    Debug2Format(&key),
+                    version,
+                    T::SCHEMA_VERSION
+                ),
+                None if version > T::SCHEMA_VERSION => warn!(
+                    "[STORAGE] {} data has a newer schema version ({}) than expected ({}). Deleting old data.",
+                    Debug2Format(&key),
+                    version,
+                    T::SCHEMA_VERSION
+                ),
+                None if has_data => warn!(
+                    "[STORAGE] Could not migrate {} data from schema version {} to {}. Deleting old data.",
+                    Debug2Format(&key),
+                    version,
+                    T::SCHEMA_VERSION
+                ),
+                None => {}
+            }
+        }
+
+        if has_metadata {
+            // Invalidate old data, which is replaced by the migrated data
+            let _ = invalidate_key(&mut database, &key.id(StorageKeyType::Data)).await;
+            let _ = invalidate_key(&mut database, &key.id(StorageKeyType::Metadata)).await;
+            garbage_collect(&mut database).await.0.unwrap();
+        }
+
+        if let Some(len) = migrated {
+            buf = match append_key(&mut database, &key.id(StorageKeyType::Data), buf, len).await {
+                (Ok(_), Some(buf), _len) => buf,
+                (result, _, _) => {
+                    error!(
+                        "[STORAGE] Write error while migrating {}: {}",
+                        Debug2Format(&key),
+                        Debug2Format(&result)
+                    );
+                    return Err(());
+                }
+            };
+        }
+
+        // Add new metadata
+        buf[..2].copy_from_slice(&T::SCHEMA_VERSION.to_le_bytes());
+        append_key(&mut database, &key.id(StorageKeyType::Metadata), buf, 2)
+            .await
+            .0
+            .unwrap();
+
+        Ok(())
+    }
+
     /// Read and deserialize data from the storage peripheral, using the given
     /// key to look it up. Uses [`postcard`] for deserialization.
     pub async fn read<T: DeserializeOwned>(
         &self,
         buffer: &'static mut [u8],
-        key: StorageKey,
+        key: impl Into<ProfileKey>,
     ) -> Result<T, ()> {
+        let key = key.into();
         let mut database = self.get_database().await;
 
-        info!(
-            "[STORAGE] Reading {} data.",
-            Debug2Format(&<StorageKey as num::FromPrimitive>::from_u8(key as u8).unwrap()),
-        );
+        info!("[STORAGE] Reading {} data.", Debug2Format(&key),);
 
-        let (result, buf, len) = get_key(
-            &mut database,
-            &[key as u8, StorageKeyType::Data as u8],
-            buffer,
-        )
-        .await;
+        let (result, buf, len) =
+            get_key(&mut database, &key.id(StorageKeyType::Data), buffer).await;
 
         result
             .map_err(|error| {
                 error!(
                     "[STORAGE] Read error for {}: {}",
-                    Debug2Format(&<StorageKey as num::FromPrimitive>::from_u8(key as u8).unwrap()),
+                    Debug2Format(&key),
                     Debug2Format(&error)
                 );
             })
@@ -226,9 +744,7 @@ where
                 Some(buf) => postcard::from_bytes(&buf[..len]).map_err(|error| {
                     error!(
                         "[STORAGE] Deserialization error while reading {}: {}",
-                        Debug2Format(
-                            &<StorageKey as num::FromPrimitive>::from_u8(key as u8).unwrap()
-                        ),
+                        Debug2Format(&key),
                         Debug2Format(&error)
                     );
                 }),
@@ -241,27 +757,21 @@ where
     pub async fn read_raw(
         &self,
         buffer: &'static mut [u8],
-        key: StorageKey,
+        key: impl Into<ProfileKey>,
     ) -> Result<(&[u8], usize), ()> {
+        let key = key.into();
         let mut database = self.get_database().await;
 
-        info!(
-            "[STORAGE] Reading {} data.",
-            Debug2Format(&<StorageKey as num::FromPrimitive>::from_u8(key as u8).unwrap()),
-        );
+        info!("[STORAGE] Reading {} data.", Debug2Format(&key),);
 
-        let (result, buf, len) = get_key(
-            &mut database,
-            &[key as u8, StorageKeyType::Data as u8],
-            buffer,
-        )
-        .await;
+        let (result, buf, len) =
+            get_key(&mut database, &key.id(StorageKeyType::Data), buffer).await;
 
         result
             .map_err(|error| {
                 error!(
                     "[STORAGE] Read error for {}: {}",
-                    Debug2Format(&<StorageKey as num::FromPrimitive>::from_u8(key as u8).unwrap()),
+                    Debug2Format(&key),
                     Debug2Format(&error)
                 );
             })
@@ -273,24 +783,24 @@ where
     pub async fn write<T: Serialize>(
         &self,
         buffer: &'static mut [u8],
-        key: StorageKey,
+        key: impl Into<ProfileKey>,
         data: T,
     ) -> Result<(), ()> {
+        let key = key.into();
+        if self.is_read_only(&key) {
+            return Err(());
+        }
         let mut database = self.get_database().await;
 
-        info!(
-            "[STORAGE] Writing new {} data.",
-            Debug2Format(&<StorageKey as num::FromPrimitive>::from_u8(key as u8).unwrap()),
-        );
+        info!("[STORAGE] Writing new {} data.", Debug2Format(&key),);
 
         let result = match postcard::to_slice(&data, buffer) {
             Ok(serialized) => {
-                let _ =
-                    invalidate_key(&mut database, &[key as u8, StorageKeyType::Data as u8]).await;
+                let _ = invalidate_key(&mut database, &key.id(StorageKeyType::Data)).await;
                 garbage_collect(&mut database).await.0.unwrap();
                 append_key(
                     &mut database,
-                    &[key as u8, StorageKeyType::Data as u8],
+                    &key.id(StorageKeyType::Data),
                     serialized,
                     serialized.len(),
                 )
@@ -299,9 +809,7 @@ where
                 .map_err(|error| {
                     error!(
                         "[STORAGE] Write error for {}: {}",
-                        Debug2Format(
-                            &<StorageKey as num::FromPrimitive>::from_u8(key as u8).unwrap()
-                        ),
+                        Debug2Format(&key),
                         Debug2Format(&error)
                     );
                 })
@@ -309,7 +817,7 @@ where
             Err(error) => {
                 error!(
                     "[STORAGE] Serialization error while writing {}: {}",
-                    Debug2Format(&<StorageKey as num::FromPrimitive>::from_u8(key as u8).unwrap()),
+                    Debug2Format(&key),
                     Debug2Format(&error)
                 );
                 Err(())
@@ -324,23 +832,24 @@ where
     pub async fn write_raw(
         &self,
         buffer: &'static mut [u8],
-        key: StorageKey,
+        key: impl Into<ProfileKey>,
         data: &[u8],
     ) -> Result<(), ()> {
+        let key = key.into();
+        if self.is_read_only(&key) {
+            return Err(());
+        }
         let mut database = self.get_database().await;
 
-        info!(
-            "[STORAGE] Writing new {} data.",
-            Debug2Format(&<StorageKey as num::FromPrimitive>::from_u8(key as u8).unwrap()),
-        );
+        info!("[STORAGE] Writing new {} data.", Debug2Format(&key),);
 
         buffer[..data.len()].copy_from_slice(data);
 
-        let _ = invalidate_key(&mut database, &[key as u8, StorageKeyType::Data as u8]).await;
+        let _ = invalidate_key(&mut database, &key.id(StorageKeyType::Data)).await;
         garbage_collect(&mut database).await.0.unwrap();
         let result = append_key(
             &mut database,
-            &[key as u8, StorageKeyType::Data as u8],
+            &key.id(StorageKeyType::Data),
             buffer,
             data.len(),
         )
@@ -349,7 +858,7 @@ where
         .map_err(|error| {
             error!(
                 "[STORAGE] Write error for {}: {}",
-                Debug2Format(&<StorageKey as num::FromPrimitive>::from_u8(key as u8).unwrap()),
+                Debug2Format(&key),
                 Debug2Format(&error)
             );
         });
@@ -357,16 +866,197 @@ where
         result.map(|_code| {})
     }
 
+    /// Serialize all stored data into a single backup, which can be restored using
+    /// [`StorageService::import_config`]. Keys listed in `exclude` are not included. The backup is
+    /// written to `out`, and its length is returned.
+    ///
+    /// The backup contains a header with a format version, followed by the data and metadata of
+    /// each stored key in every storage profile (serialized using [`postcard`]), and a CRC-32
+    /// checksum.
+    pub async fn export_config(
+        &self,
+        mut buffer: &'static mut [u8],
+        out: &mut [u8],
+        exclude: &[StorageKey],
+    ) -> Result<usize, ()> {
+        let mut database = self.get_database().await;
+
+        info!("[STORAGE] Exporting backup.");
+
+        let header = BackupHeader {
+            magic: BACKUP_MAGIC,
+            version: BACKUP_VERSION,
+        };
+        let mut len = postcard::to_slice(&header, &mut *out)
+            .map_err(|_| error!("[STORAGE] Backup does not fit in the output buffer."))?
+            .len();
+
+        for key in all_profile_keys() {
+            if exclude.contains(&key.key) {
+                continue;
+            }
+
+            // Metadata is small, so it is copied out of the buffer before reading the data
+            let mut metadata = [0; 32];
+            let (result, buf, metadata_len) =
+                get_key(&mut database, &key.id(StorageKeyType::Metadata), buffer).await;
+            buffer = buf.unwrap();
+            let metadata_len = match result {
+                Ok(_) if metadata_len <= metadata.len() => {
+                    metadata[..metadata_len].copy_from_slice(&buffer[..metadata_len]);
+                    metadata_len
+                }
+                _ => 0,
+            };
+
+            let (result, buf, data_len) =
+                get_key(&mut database, &key.id(StorageKeyType::Data), buffer).await;
+            buffer = buf.unwrap();
+            if result.is_err() {
+                // Nothing is stored for this key
+                continue;
+            }
+
+            let entry = BackupEntry {
+                key: key.key as u8,
+                profile: key.profile,
+                metadata: &metadata[..metadata_len],
+                data: &buffer[..data_len],
+            };
+            len += postcard::to_slice(&entry, &mut out[len..])
+                .map_err(|_| error!("[STORAGE] Backup does not fit in the output buffer."))?
+                .len();
+        }
+
+        let crc = crc32(&out[..len]);
+        out.get_mut(len..(len + 4))
+            .ok_or_else(|| error!("[STORAGE] Backup does not fit in the output buffer."))?
+            .copy_from_slice(&crc.to_le_bytes());
+
+        Ok(len + 4)
+    }
+
+    /// Restore a backup created by [`StorageService::export_config`]. The backup is validated, and
+    /// checked to fit in the storage region before any data is changed. Stored data for keys that
+    /// are not in the backup is deleted, except for keys listed in `exclude`, which are left
+    /// unchanged.
+    ///
+    /// The restored data is used after the keyboard restarts. Until then, any other writes are
+    /// ignored, so that the restored data isn't overwritten.
+    pub async fn import_config(
+        &self,
+        mut buffer: &'static mut [u8],
+        data: &[u8],
+        exclude: &[StorageKey],
+    ) -> Result<(), ()> {
+        let (version, entries) = validate_backup(data, buffer.len())?;
+
+        let mut database = self.get_database().await;
+
+        info!("[STORAGE] Importing backup.");
+
+        // Check that the backup fits, along with the data of excluded keys, before deleting
+        // anything. TicKV's main key is always stored.
+        let capacity = database.tickv.controller.end - database.tickv.controller.start;
+        let mut required = TICKV_OBJECT_OVERHEAD;
+        for key in all_profile_keys().filter(|key| exclude.contains(&key.key)) {
+            for key_type in [StorageKeyType::Metadata, StorageKeyType::Data] {
+                let (result, buf, len) = get_key(&mut database, &key.id(key_type), buffer).await;
+                buffer = buf.unwrap();
+                if result.is_ok() {
+                    required += len + TICKV_OBJECT_OVERHEAD;
+                }
+            }
+        }
+        let mut rest = entries;
+        while let Ok((entry, remaining)) = take_backup_entry(version, rest) {
+            rest = remaining;
+
+            if exclude.contains(&num::FromPrimitive::from_u8(entry.key).unwrap()) {
+                continue;
+            }
+
+            for value in [entry.metadata, entry.data] {
+                if value.len() + TICKV_OBJECT_OVERHEAD > F::ERASE_SIZE {
+                    error!(
+                        "[STORAGE] Backup entry for key {} does not fit in a flash page.",
+                        entry.key
+                    );
+                    return Err(());
+                }
+            }
+
+            if !entry.metadata.is_empty() {
+                required += entry.metadata.len() + TICKV_OBJECT_OVERHEAD;
+            }
+            required += entry.data.len() + TICKV_OBJECT_OVERHEAD;
+        }
+        if required > capacity {
+            error!(
+                "[STORAGE] Backup is too large for the storage region ({} bytes needed, {} bytes available).",
+                required, capacity
+            );
+            return Err(());
+        }
+
+        for key in all_profile_keys() {
+            if !exclude.contains(&key.key) {
+                let _ = invalidate_key(&mut database, &key.id(StorageKeyType::Data)).await;
+                let _ = invalidate_key(&mut database, &key.id(StorageKeyType::Metadata)).await;
+            }
+        }
+        garbage_collect(&mut database).await.0.unwrap();
+
+        let mut rest = entries;
+        while let Ok((entry, remaining)) = take_backup_entry(version, rest) {
+            rest = remaining;
+
+            let key: StorageKey = num::FromPrimitive::from_u8(entry.key).unwrap();
+            if exclude.contains(&key) {
+                continue;
+            }
+            let key = key.in_profile(entry.profile);
+
+            for (key_type, value) in [
+                (StorageKeyType::Metadata, entry.metadata),
+                (StorageKeyType::Data, entry.data),
+            ] {
+                if value.is_empty() && matches!(key_type, StorageKeyType::Metadata) {
+                    continue;
+                }
+
+                buffer[..value.len()].copy_from_slice(value);
+                buffer =
+                    match append_key(&mut database, &key.id(key_type), buffer, value.len()).await {
+                        (Ok(_), Some(buf), _len) => buf,
+                        (result, _, _) => {
+                            error!(
+                                "[STORAGE] Write error while importing {}: {}",
+                                Debug2Format(&key),
+                                Debug2Format(&result)
+                            );
+                            return Err(());
+                        }
+                    };
+            }
+        }
+
+        self.read_only.store(true, Ordering::Relaxed);
+
+        Ok(())
+    }
+
     /// Deletes the data at a given key.
-    pub async fn delete(&self, key: StorageKey) -> Result<(), ()> {
+    pub async fn delete(&self, key: impl Into<ProfileKey>) -> Result<(), ()> {
+        let key = key.into();
+        if self.is_read_only(&key) {
+            return Err(());
+        }
         let mut database = self.get_database().await;
 
-        info!(
-            "[STORAGE] Deleting {} data.",
-            Debug2Format(&<StorageKey as num::FromPrimitive>::from_u8(key as u8).unwrap()),
-        );
+        info!("[STORAGE] Deleting {} data.", Debug2Format(&key),);
 
-        let result = invalidate_key(&mut database, &[key as u8, StorageKeyType::Data as u8])
+        let result = invalidate_key(&mut database, &key.id(StorageKeyType::Data))
             .await
             .0
             .map_err(|error| {
@@ -539,6 +1229,26 @@ pub trait StorageDevice {
         static mut STORAGE_BUFFER: [u8; 1024] = [0; 1024];
         unsafe { &mut STORAGE_BUFFER }
     }
+
+    /// Obtain the migrations used to convert data that was stored by an older version of your
+    /// firmware. If the schema version of a stored value has changed, and there is no migration
+    /// for it, the stored value is deleted and its default is used instead.
+    ///
+    /// The buffer obtained from [`StorageDevice::get_storage_buffer`] is used to store both the
+    /// old and the migrated data, so it must be large enough to contain both.
+    fn get_migrations() -> &'static [Migration] {
+        &[]
+    }
+
+    /// Obtain a static mutable reference to a buffer used to transfer configuration backups to
+    /// and from the host (see [`StorageService::export_config`]). The buffer must be large enough
+    /// to contain all of your keyboard's stored data.
+    ///
+    /// By default, this buffer is empty, so backups can't be created. Override this to enable
+    /// backups.
+    fn get_backup_buffer() -> &'static mut [u8] {
+        &mut []
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -605,6 +1315,115 @@ impl<F: ReadNorFlash + AsyncNorFlash> FlashStorage for F {
     }
 }
 
+/// Errors that can occur when using a [`RamFlash`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RamFlashError {
+    /// The operation accessed addresses outside of the memory.
+    OutOfBounds,
+    /// The erase operation did not start or end on a multiple of the erase size.
+    NotAligned,
+}
+
+/// A [`FlashStorage`] implementation that keeps its data in RAM. This can be used to test storage
+/// related code without hardware.
+///
+/// Like NOR flash, erasing sets every byte to `0xFF`, and writing can only clear bits. The memory
+/// is borrowed as a slice of [`Cell`]s, so that it can be inspected, or passed to a new
+/// [`StorageService`] to simulate a reset.
+///
+/// A power loss can be simulated using [`RamFlash::lose_power_after`]. Once the given number of
+/// bytes have been written, any remaining writes and erases are dropped, as if the keyboard lost
+/// power in the middle of the write.
+pub struct RamFlash<'a, const ERASE_SIZE: usize> {
+    memory: &'a [Cell<u8>],
+    write_budget: Option<usize>,
+}
+
+impl<'a, const ERASE_SIZE: usize> RamFlash<'a, ERASE_SIZE> {
+    /// Create a new RAM flash device, using the given memory. The size of the memory should be a
+    /// multiple of `ERASE_SIZE`.
+    pub fn new(memory: &'a [Cell<u8>]) -> Self {
+        Self {
+            memory,
+            write_budget: None,
+        }
+    }
+
+    /// Simulate a power loss after `bytes` more bytes have been written. Writes and erases after
+    /// this point have no effect.
+    pub fn lose_power_after(mut self, bytes: usize) -> Self {
+        self.write_budget = Some(bytes);
+        self
+    }
+
+    /// Check if a simulated power loss has occurred.
+    pub fn has_lost_power(&self) -> bool {
+        self.write_budget == Some(0)
+    }
+
+    fn get_range(&self, offset: u32, len: usize) -> Result<&'a [Cell<u8>], RamFlashError> {
+        let start = offset as usize;
+        self.memory
+            .get(start..(start + len))
+            .ok_or(RamFlashError::OutOfBounds)
+    }
+}
+
+impl<'a, const ERASE_SIZE: usize> FlashStorage for RamFlash<'a, ERASE_SIZE> {
+    type Error = RamFlashError;
+
+    const ERASE_SIZE: usize = ERASE_SIZE;
+
+    async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        if from as usize % ERASE_SIZE != 0 || to as usize % ERASE_SIZE != 0 {
+            return Err(RamFlashError::NotAligned);
+        }
+
+        let region = self.get_range(from, to.saturating_sub(from) as usize)?;
+
+        if !self.has_lost_power() {
+            for byte in region {
+                byte.set(0xFF);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let region = self.get_range(offset, bytes.len())?;
+
+        let len = match self.write_budget {
+            Some(budget) => {
+                let len = budget.min(bytes.len());
+                self.write_budget = Some(budget - len);
+                len
+            }
+            None => bytes.len(),
+        };
+
+        for (byte, data) in region.iter().zip(&bytes[..len]) {
+            byte.set(byte.get() & data);
+        }
+
+        Ok(())
+    }
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.blocking_read(offset, bytes)
+    }
+
+    fn blocking_read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let region = self.get_range(offset, bytes.len())?;
+
+        for (data, byte) in bytes.iter_mut().zip(region) {
+            *data = byte.get();
+        }
+
+        Ok(())
+    }
+}
+
 /// Data structure that wraps around an implementor of [`FlashStorage`]. If you want to read, write
 /// or delete existing data (like [`crate::underglow::animations::UnderglowConfig`]), see
 /// [`StorageService`]. Reading, writing or deleting *custom* data using the same storage
@@ -799,3 +1618,399 @@ impl<'a, F: FlashStorage> FlashController<{ F::ERASE_SIZE }> for FlashDevice<'a,
         Err(tickv::ErrorCode::EraseNotReady(region_number))
     }
 }
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+    use super::*;
+    use embassy_futures::block_on;
+    use std::boxed::Box;
+    use std::vec;
+
+    const ERASE_SIZE: usize = 512;
+    const FLASH_SIZE: usize = ERASE_SIZE * 4;
+
+    type TestStorage = StorageService<'static, RamFlash<'static, ERASE_SIZE>>;
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    struct TestConfig {
+        value: u32,
+        padding: [u8; 16],
+    }
+
+    impl TestConfig {
+        fn new(value: u32) -> Self {
+            Self {
+                value,
+                padding: [value as u8; 16],
+            }
+        }
+    }
+
+    impl StorageSchema for TestConfig {
+        const SCHEMA_VERSION: u16 = 2;
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct OldTestConfig {
+        value: u16,
+    }
+
+    impl From<OldTestConfig> for TestConfig {
+        fn from(old: OldTestConfig) -> Self {
+            Self::new(old.value as u32)
+        }
+    }
+
+    fn memory() -> &'static [Cell<u8>] {
+        Cell::from_mut(Box::leak(vec![0xFF; FLASH_SIZE].into_boxed_slice())).as_slice_of_cells()
+    }
+
+    fn buffer() -> &'static mut [u8] {
+        Box::leak(vec![0; 256].into_boxed_slice())
+    }
+
+    fn setup(flash: RamFlash<'static, ERASE_SIZE>) -> TestStorage {
+        let storage = StorageService::new();
+        block_on(storage.setup(
+            flash,
+            0,
+            FLASH_SIZE,
+            Box::leak(Box::new([0; ERASE_SIZE])),
+            Box::leak(Box::new([0; ERASE_SIZE])),
+        ));
+        storage
+    }
+
+    fn read_config(storage: &TestStorage) -> Result<TestConfig, ()> {
+        block_on(storage.read(buffer(), StorageKey::KeyboardSettings))
+    }
+
+    fn write_config(storage: &TestStorage, config: TestConfig) -> Result<(), ()> {
+        block_on(storage.write(buffer(), StorageKey::KeyboardSettings, config))
+    }
+
+    #[test]
+    fn read_missing_key() {
+        let storage = setup(RamFlash::new(memory()));
+        assert_eq!(read_config(&storage), Err(()));
+    }
+
+    #[test]
+    fn write_and_read() {
+        let storage = setup(RamFlash::new(memory()));
+        write_config(&storage, TestConfig::new(1)).unwrap();
+        assert_eq!(read_config(&storage), Ok(TestConfig::new(1)));
+
+        write_config(&storage, TestConfig::new(2)).unwrap();
+        assert_eq!(read_config(&storage), Ok(TestConfig::new(2)));
+    }
+
+    #[test]
+    fn write_and_read_raw() {
+        let storage = setup(RamFlash::new(memory()));
+        block_on(storage.write_raw(buffer(), StorageKey::DynamicKeymap, &[1, 2, 3])).unwrap();
+
+        let (data, len) = block_on(storage.read_raw(buffer(), StorageKey::DynamicKeymap)).unwrap();
+        assert_eq!(&data[..len], &[1, 2, 3]);
+    }
+
+    #[test]
+    fn keys_are_independent() {
+        let storage = setup(RamFlash::new(memory()));
+        write_config(&storage, TestConfig::new(1)).unwrap();
+        block_on(storage.write(buffer(), StorageKey::UnderglowConfig, TestConfig::new(2))).unwrap();
+
+        assert_eq!(read_config(&storage), Ok(TestConfig::new(1)));
+        assert_eq!(
+            block_on(storage.read(buffer(), StorageKey::UnderglowConfig)),
+            Ok(TestConfig::new(2))
+        );
+    }
+
+    #[test]
+    fn delete() {
+        let storage = setup(RamFlash::new(memory()));
+        write_config(&storage, TestConfig::new(1)).unwrap();
+        block_on(storage.delete(StorageKey::KeyboardSettings)).unwrap();
+        assert_eq!(read_config(&storage), Err(()));
+
+        write_config(&storage, TestConfig::new(2)).unwrap();
+        assert_eq!(read_config(&storage), Ok(TestConfig::new(2)));
+    }
+
+    #[test]
+    fn profiles_are_independent() {
+        let storage = setup(RamFlash::new(memory()));
+        let key = StorageKey::UnderglowConfig;
+        block_on(storage.write(buffer(), key, TestConfig::new(1))).unwrap();
+        block_on(storage.write(buffer(), key.in_profile(1), TestConfig::new(2))).unwrap();
+
+        assert_eq!(
+            block_on(storage.read(buffer(), key.in_profile(0))),
+            Ok(TestConfig::new(1))
+        );
+        assert_eq!(
+            block_on(storage.read(buffer(), key.in_profile(1))),
+            Ok(TestConfig::new(2))
+        );
+        assert_eq!(
+            block_on(storage.read::<TestConfig>(buffer(), key.in_profile(2))),
+            Err(())
+        );
+
+        // Shared keys ignore the profile
+        write_config(&storage, TestConfig::new(3)).unwrap();
+        assert_eq!(
+            block_on(storage.read(buffer(), StorageKey::KeyboardSettings.in_profile(1))),
+            Ok(TestConfig::new(3))
+        );
+    }
+
+    #[test]
+    fn backup_and_restore() {
+        let storage = setup(RamFlash::new(memory()));
+        let key = StorageKey::UnderglowConfig;
+        write_config(&storage, TestConfig::new(1)).unwrap();
+        block_on(storage.write(buffer(), key.in_profile(1), TestConfig::new(2))).unwrap();
+
+        let mut backup = [0; 256];
+        let len = block_on(storage.export_config(buffer(), &mut backup, &[])).unwrap();
+
+        let storage = setup(RamFlash::new(memory()));
+        block_on(storage.write(buffer(), key, TestConfig::new(3))).unwrap();
+        block_on(storage.import_config(buffer(), &backup[..len], &[])).unwrap();
+
+        // Data that isn't in the backup is deleted
+        assert_eq!(read_config(&storage), Ok(TestConfig::new(1)));
+        assert_eq!(block_on(storage.read::<TestConfig>(buffer(), key)), Err(()));
+        assert_eq!(
+            block_on(storage.read(buffer(), key.in_profile(1))),
+            Ok(TestConfig::new(2))
+        );
+
+        // Corrupted backups are rejected
+        backup[len / 2] ^= 0xFF;
+        assert_eq!(
+            block_on(storage.import_config(buffer(), &backup[..len], &[])),
+            Err(())
+        );
+    }
+
+    #[test]
+    fn writes_are_ignored_after_import() {
+        let storage = setup(RamFlash::new(memory()));
+        write_config(&storage, TestConfig::new(1)).unwrap();
+
+        let mut backup = [0; 256];
+        let len = block_on(storage.export_config(buffer(), &mut backup, &[])).unwrap();
+        block_on(storage.import_config(buffer(), &backup[..len], &[])).unwrap();
+
+        assert_eq!(write_config(&storage, TestConfig::new(2)), Err(()));
+        assert_eq!(read_config(&storage), Ok(TestConfig::new(1)));
+    }
+
+    #[test]
+    fn oversized_backup_is_rejected() {
+        let storage = setup(RamFlash::new(memory()));
+        write_config(&storage, TestConfig::new(1)).unwrap();
+
+        // Every entry fits in the buffer, but all of them don't fit in the flash
+        let mut backup = [0; 4096];
+        let header = BackupHeader {
+            magic: BACKUP_MAGIC,
+            version: BACKUP_VERSION,
+        };
+        let mut len = postcard::to_slice(&header, &mut backup).unwrap().len();
+        for profile in 0..MAX_PROFILES {
+            let entry = BackupEntry {
+                key: StorageKey::DynamicKeymap as u8,
+                profile,
+                metadata: &[],
+                data: &[0; 250],
+            };
+            len += postcard::to_slice(&entry, &mut backup[len..])
+                .unwrap()
+                .len();
+        }
+        let crc = crc32(&backup[..len]);
+        backup[len..(len + 4)].copy_from_slice(&crc.to_le_bytes());
+        len += 4;
+
+        assert_eq!(
+            block_on(storage.import_config(buffer(), &backup[..len], &[])),
+            Err(())
+        );
+
+        // Nothing was deleted
+        assert_eq!(read_config(&storage), Ok(TestConfig::new(1)));
+    }
+
+    #[test]
+    fn garbage_collection() {
+        // Each write invalidates the previous value. Without garbage collection, the flash would
+        // fill up after a few writes.
+        let storage = setup(RamFlash::new(memory()));
+        for value in 0..200 {
+            write_config(&storage, TestConfig::new(value)).unwrap();
+            assert_eq!(read_config(&storage), Ok(TestConfig::new(value)));
+        }
+    }
+
+    #[test]
+    fn data_persists_after_reset() {
+        let memory = memory();
+
+        let storage = setup(RamFlash::new(memory));
+        write_config(&storage, TestConfig::new(1)).unwrap();
+        drop(storage);
+
+        let storage = setup(RamFlash::new(memory));
+        assert_eq!(read_config(&storage), Ok(TestConfig::new(1)));
+    }
+
+    #[test]
+    fn metadata_change_resets_data() {
+        let memory = memory();
+
+        let storage = setup(RamFlash::new(memory));
+        block_on(storage.check_metadata(buffer(), StorageKey::KeyboardSettings, &[1])).unwrap();
+        write_config(&storage, TestConfig::new(1)).unwrap();
+
+        // Unchanged metadata keeps the stored data
+        block_on(storage.check_metadata(buffer(), StorageKey::KeyboardSettings, &[1])).unwrap();
+        assert_eq!(read_config(&storage), Ok(TestConfig::new(1)));
+        drop(storage);
+
+        // Changed metadata deletes the stored data, even after a reset
+        let storage = setup(RamFlash::new(memory));
+        block_on(storage.check_metadata(buffer(), StorageKey::KeyboardSettings, &[1, 2])).unwrap();
+        assert_eq!(read_config(&storage), Err(()));
+
+        // The new metadata is stored
+        write_config(&storage, TestConfig::new(2)).unwrap();
+        block_on(storage.check_metadata(buffer(), StorageKey::KeyboardSettings, &[1, 2])).unwrap();
+        assert_eq!(read_config(&storage), Ok(TestConfig::new(2)));
+    }
+
+    #[test]
+    fn schema_migration() {
+        const MIGRATIONS: &[Migration] = &[Migration::new(
+            StorageKey::KeyboardSettings,
+            1,
+            migrate_from::<OldTestConfig, TestConfig>,
+        )];
+
+        let storage = setup(RamFlash::new(memory()));
+
+        // Metadata containing the type's `TypeId` is assumed to be version 1
+        let legacy_metadata: [u8; core::mem::size_of::<TypeId>()] =
+            unsafe { core::mem::transmute(TypeId::of::<TestConfig>()) };
+        block_on(storage.check_metadata(buffer(), StorageKey::KeyboardSettings, &legacy_metadata))
+            .unwrap();
+        block_on(storage.write(
+            buffer(),
+            StorageKey::KeyboardSettings,
+            OldTestConfig { value: 5 },
+        ))
+        .unwrap();
+
+        block_on(storage.check_schema::<TestConfig>(
+            buffer(),
+            StorageKey::KeyboardSettings,
+            MIGRATIONS,
+        ))
+        .unwrap();
+        assert_eq!(read_config(&storage), Ok(TestConfig::new(5)));
+
+        // The new schema version is stored, so the data isn't migrated again
+        block_on(storage.check_schema::<TestConfig>(
+            buffer(),
+            StorageKey::KeyboardSettings,
+            MIGRATIONS,
+        ))
+        .unwrap();
+        assert_eq!(read_config(&storage), Ok(TestConfig::new(5)));
+
+        // Data that can't be migrated is deleted
+        block_on(storage.check_metadata(buffer(), StorageKey::KeyboardSettings, &[1, 0])).unwrap();
+        block_on(storage.write(
+            buffer(),
+            StorageKey::KeyboardSettings,
+            OldTestConfig { value: 6 },
+        ))
+        .unwrap();
+        block_on(storage.check_schema::<TestConfig>(buffer(), StorageKey::KeyboardSettings, &[]))
+            .unwrap();
+        assert_eq!(read_config(&storage), Err(()));
+
+        // Unrecognized metadata (e.g. the `TypeId` of a different type) is deleted
+        block_on(storage.check_metadata(buffer(), StorageKey::KeyboardSettings, &[0xAA; 8]))
+            .unwrap();
+        write_config(&storage, TestConfig::new(7)).unwrap();
+        block_on(storage.check_schema::<TestConfig>(
+            buffer(),
+            StorageKey::KeyboardSettings,
+            MIGRATIONS,
+        ))
+        .unwrap();
+        assert_eq!(read_config(&storage), Err(()));
+    }
+
+    #[test]
+    fn power_loss_during_write() {
+        // Each write rewrites a full page when invalidating the old value, and again when
+        // appending the new value.
+        for offset in (0..(ERASE_SIZE * 2)).step_by(32) {
+            let memory = memory();
+
+            let storage = setup(RamFlash::new(memory));
+            write_config(&storage, TestConfig::new(1)).unwrap();
+            drop(storage);
+
+            let storage = setup(RamFlash::new(memory).lose_power_after(offset));
+            let _ = write_config(&storage, TestConfig::new(2));
+            drop(storage);
+
+            // The interrupted write may or may not have been applied, but the old value must
+            // never be replaced with something else
+            let storage = setup(RamFlash::new(memory));
+            if let Ok(config) = read_config(&storage) {
+                assert!(
+                    config == TestConfig::new(1) || config == TestConfig::new(2),
+                    "unexpected value after power loss at offset {}: {:?}",
+                    offset,
+                    config
+                );
+            }
+
+            // The storage must still be usable
+            write_config(&storage, TestConfig::new(3)).unwrap();
+            assert_eq!(read_config(&storage), Ok(TestConfig::new(3)));
+        }
+    }
+
+    #[test]
+    fn ram_flash_behaves_like_nor_flash() {
+        let mut flash = RamFlash::<ERASE_SIZE>::new(memory()).lose_power_after(4);
+        let mut data = [0; 4];
+
+        block_on(flash.write(0, &[0x0F, 0xF0])).unwrap();
+        block_on(flash.write(0, &[0xFF, 0x00])).unwrap();
+        block_on(flash.read(0, &mut data)).unwrap();
+        assert_eq!(data, [0x0F, 0x00, 0xFF, 0xFF]);
+
+        // The write budget is used up, so these have no effect
+        assert!(flash.has_lost_power());
+        block_on(flash.write(2, &[0x00, 0x00])).unwrap();
+        block_on(flash.erase(0, ERASE_SIZE as u32)).unwrap();
+        flash.blocking_read(0, &mut data).unwrap();
+        assert_eq!(data, [0x0F, 0x00, 0xFF, 0xFF]);
+
+        assert_eq!(block_on(flash.erase(0, 1)), Err(RamFlashError::NotAligned));
+        assert_eq!(
+            block_on(flash.read(FLASH_SIZE as u32, &mut data)),
+            Err(RamFlashError::OutOfBounds)
+        );
+    }
+}
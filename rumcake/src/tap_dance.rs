@@ -0,0 +1,282 @@
+//! Support for runtime-editable tap dance keys.
+//!
+//! A tap dance key performs a different action depending on whether it is tapped, held, double
+//! tapped, or tapped and then held. This mirrors the tap dance entries that can be edited with
+//! Vial. Tap dance keys can be placed on your layout using [`Keycode::TapDance`] (or the `TD(n)`
+//! keycode in Via/Vial).
+//!
+//! To use tap dances, your keyboard must implement
+//! [`crate::keyboard::KeyboardLayout::NUM_TAP_DANCES`] and
+//! [`crate::keyboard::KeyboardLayout::get_tap_dances`]. It is recommended to use
+//! [`setup_tap_dances`] to implement these.
+
+use embassy_sync::mutex::{Mutex, MutexGuard};
+use heapless::Vec;
+use keyberon::action::Action;
+use keyberon::layout::{Event, Layout as KeyberonLayout};
+
+use crate::hw::mcu::RawMutex;
+use crate::keyboard::Keycode;
+
+pub use rumcake_macros::setup_tap_dances;
+
+/// Tapping term used by a tap dance if its entry has a tapping term of 0.
+pub const DEFAULT_TAPPING_TERM: u16 = 200;
+
+/// Maximum number of tap dance keys that can be held down at the same time, after their action
+/// has been decided.
+const MAX_HELD_TAP_DANCES: usize = 8;
+
+/// Layout row used for the virtual keys that are pressed when a tap dance is resolved. The column
+/// of the virtual key corresponds to the index of the tap dance.
+const TAP_DANCE_ROW: u8 = u8::MAX - 1;
+
+/// Actions and timing of a tap dance key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TapDanceEntry {
+    /// Action performed when the key is tapped once.
+    pub on_tap: Action<Keycode>,
+
+    /// Action performed when the key is held.
+    pub on_hold: Action<Keycode>,
+
+    /// Action performed when the key is tapped twice. If this is [`Action::NoOp`], the tap action
+    /// is performed twice instead.
+    pub on_double_tap: Action<Keycode>,
+
+    /// Action performed when the key is tapped once, and then held.
+    pub on_tap_hold: Action<Keycode>,
+
+    /// Amount of time (in milliseconds) used to distinguish between taps and holds. If this is 0,
+    /// [`DEFAULT_TAPPING_TERM`] is used.
+    pub tapping_term: u16,
+}
+
+impl TapDanceEntry {
+    /// Create a tap dance entry that does nothing.
+    pub const fn empty() -> Self {
+        Self {
+            on_tap: Action::NoOp,
+            on_hold: Action::NoOp,
+            on_double_tap: Action::NoOp,
+            on_tap_hold: Action::NoOp,
+            tapping_term: 0,
+        }
+    }
+
+    fn tapping_term(&self) -> u16 {
+        if self.tapping_term == 0 {
+            DEFAULT_TAPPING_TERM
+        } else {
+            self.tapping_term
+        }
+    }
+
+    /// Whether the key needs to wait for another tap before its action can be decided.
+    fn has_second_tap(&self) -> bool {
+        self.on_double_tap != Action::NoOp || self.on_tap_hold != Action::NoOp
+    }
+}
+
+/// Mutex-guarded tap dance entries. This allows tap dances to be changed at runtime (e.g. using
+/// Vial).
+pub struct TapDances<const N: usize> {
+    tap_dances: Mutex<RawMutex, [TapDanceEntry; N]>,
+}
+
+impl<const N: usize> TapDances<N> {
+    /// Create a new set of tap dances.
+    pub const fn new(tap_dances: [TapDanceEntry; N]) -> Self {
+        Self {
+            tap_dances: Mutex::new(tap_dances),
+        }
+    }
+
+    /// Obtain a lock on the tap dances, so that they can be read or modified.
+    pub async fn lock(&self) -> MutexGuard<RawMutex, [TapDanceEntry; N]> {
+        self.tap_dances.lock().await
+    }
+}
+
+/// A tap dance key that is being pressed, and hasn't decided its action yet.
+struct ActiveTapDance {
+    index: u8,
+    coord: (u8, u8),
+    taps: u8,
+    pressed: bool,
+    /// Time (in milliseconds) since the key was last pressed or released.
+    elapsed: u16,
+}
+
+/// Resolves tap dance keys from matrix events, before they are sent to the layout.
+pub(crate) struct TapDanceProcessor {
+    active: Option<ActiveTapDance>,
+
+    /// Tap dance keys that are still held after their action was decided, along with the index
+    /// of their tap dance.
+    held: Vec<((u8, u8), u8), MAX_HELD_TAP_DANCES>,
+}
+
+impl TapDanceProcessor {
+    pub(crate) fn new() -> Self {
+        Self {
+            active: None,
+            held: Vec::new(),
+        }
+    }
+
+    /// Press the action of a tap dance, and keep it pressed until the key is released.
+    fn press<const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+        coord: (u8, u8),
+        index: u8,
+        action: Action<Keycode>,
+    ) {
+        if self.held.push((coord, index)).is_ok() {
+            layout.event_with_action((TAP_DANCE_ROW, index), action);
+        }
+    }
+
+    /// Press and release the action of a tap dance.
+    fn tap<const C: usize, const R: usize, const L: usize>(
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+        index: u8,
+        action: Action<Keycode>,
+    ) {
+        layout.event_with_action((TAP_DANCE_ROW, index), action);
+        layout.event(Event::Release(TAP_DANCE_ROW, index));
+    }
+
+    /// Decide the action of the active tap dance. If another key was pressed before the tapping
+    /// term has passed (`interrupted`), the key is treated as tapped, even if it is still held.
+    fn finish<const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+        tap_dances: &[TapDanceEntry],
+        interrupted: bool,
+    ) {
+        let Some(active) = self.active.take() else {
+            return;
+        };
+        let Some(entry) = tap_dances.get(active.index as usize) else {
+            return;
+        };
+        let held = active.pressed && !interrupted;
+
+        let action = if active.taps == 1 {
+            if held && entry.on_hold != Action::NoOp {
+                entry.on_hold
+            } else {
+                entry.on_tap
+            }
+        } else if held && entry.on_tap_hold != Action::NoOp {
+            entry.on_tap_hold
+        } else if entry.on_double_tap != Action::NoOp {
+            entry.on_double_tap
+        } else {
+            // Tapping twice without a double tap action performs the tap action twice
+            Self::tap(layout, active.index, entry.on_tap);
+            entry.on_tap
+        };
+
+        if active.pressed {
+            self.press(layout, active.coord, active.index, action);
+        } else {
+            Self::tap(layout, active.index, action);
+        }
+    }
+
+    /// Process a matrix event. If the event is not for a tap dance key, it is returned so that it
+    /// can be sent to the layout.
+    pub(crate) fn event<const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+        tap_dances: &[TapDanceEntry],
+        event: Event,
+    ) -> Option<Event> {
+        match event {
+            Event::Press(row, col) => {
+                if let Some(active) = &mut self.active {
+                    if active.coord == (row, col) {
+                        active.taps += 1;
+                        active.pressed = true;
+                        active.elapsed = 0;
+                        return None;
+                    }
+                }
+
+                // Pressing any other key decides the action of the active tap dance
+                self.finish(layout, tap_dances, true);
+
+                let Action::Custom(Keycode::TapDance(index)) = layout.action_at((row, col)) else {
+                    return Some(event);
+                };
+                let Some(entry) = tap_dances.get(index as usize) else {
+                    return None;
+                };
+
+                self.active = Some(ActiveTapDance {
+                    index,
+                    coord: (row, col),
+                    taps: 1,
+                    pressed: true,
+                    elapsed: 0,
+                });
+
+                // Without any hold or second tap actions, the tap action can be performed right
+                // away
+                if entry.on_hold == Action::NoOp && !entry.has_second_tap() {
+                    self.finish(layout, tap_dances, false);
+                }
+
+                None
+            }
+            Event::Release(row, col) => {
+                if let Some(active) = &mut self.active {
+                    if active.coord == (row, col) {
+                        active.pressed = false;
+                        active.elapsed = 0;
+
+                        let has_second_tap = tap_dances
+                            .get(active.index as usize)
+                            .is_some_and(TapDanceEntry::has_second_tap);
+                        if active.taps >= 2 || !has_second_tap {
+                            self.finish(layout, tap_dances, false);
+                        }
+
+                        return None;
+                    }
+                }
+
+                if let Some(idx) = self.held.iter().position(|(coord, _)| *coord == (row, col)) {
+                    let (_coord, index) = self.held.swap_remove(idx);
+                    layout.event(Event::Release(TAP_DANCE_ROW, index));
+                    return None;
+                }
+
+                Some(event)
+            }
+        }
+    }
+
+    /// Update the tap dance timers. This should be called every millisecond.
+    pub(crate) fn tick<const C: usize, const R: usize, const L: usize>(
+        &mut self,
+        layout: &mut KeyberonLayout<C, R, L, Keycode>,
+        tap_dances: &[TapDanceEntry],
+    ) {
+        let Some(active) = &mut self.active else {
+            return;
+        };
+
+        active.elapsed = active.elapsed.saturating_add(1);
+
+        let tapping_term = tap_dances
+            .get(active.index as usize)
+            .map_or(DEFAULT_TAPPING_TERM, TapDanceEntry::tapping_term);
+        if active.elapsed >= tapping_term {
+            self.finish(layout, tap_dances, false);
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use super::drivers::UnderglowDriver;
 use super::UnderglowDevice;
+use crate::hw::HOST_LED_STATE;
 use crate::math::{scale, sin};
 use crate::{Cycle, LEDEffect};
 use postcard::experimental::max_size::MaxSize;
@@ -43,7 +44,7 @@ impl Default for UnderglowConfig {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, MaxSize)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, MaxSize)]
 pub enum UnderglowCommand {
     Toggle,
     TurnOn,
@@ -63,6 +64,7 @@ pub enum UnderglowCommand {
     SetSpeed(u8),
     IncreaseSpeed(u8),
     DecreaseSpeed(u8),
+    SetConfig(UnderglowConfig), // normally used internally for switching storage profiles
     #[cfg(feature = "storage")]
     SaveConfig,
     ResetTime, // normally used internally for syncing LEDs for split keyboards
@@ -248,6 +250,9 @@ where
             UnderglowCommand::DecreaseSpeed(amount) => {
                 self.config.speed = self.config.speed.saturating_sub(amount);
             }
+            UnderglowCommand::SetConfig(config) => {
+                self.config = config;
+            }
             #[cfg(feature = "storage")]
             UnderglowCommand::SaveConfig => {
                 super::storage::UNDERGLOW_SAVE_SIGNAL.signal(());
@@ -523,7 +528,23 @@ where
             }
         }
 
-        if let Err(err) = self.driver.write(self.buf.iter().cloned()).await {
+        let result = if D::LED_INDICATORS.is_empty() {
+            self.driver.write(self.buf.iter().cloned()).await
+        } else {
+            // Indicators are drawn on a copy, so that they don't affect the effect's next frame
+            let host_leds = HOST_LED_STATE.get().await;
+            let mut buf = self.buf;
+            for (indicator, led) in D::LED_INDICATORS {
+                if host_leds.contains(*indicator) {
+                    if let Some(led) = buf.get_mut(*led) {
+                        *led = RGB8::new(self.config.val, self.config.val, self.config.val);
+                    }
+                }
+            }
+            self.driver.write(buf.iter().cloned()).await
+        };
+
+        if let Err(err) = result {
             error!(
                 "[UNDERGLOW] Couldn't update underglow colors: {}",
                 Debug2Format(&err)
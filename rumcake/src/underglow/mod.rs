@@ -3,12 +3,15 @@
 //! To use underglow features, keyboards must implement [`UnderglowDevice`], and the trait
 //! corresponding to a driver that implements [`drivers::UnderglowDriver`].
 
-use embassy_futures::select::{select, Either};
+use embassy_futures::select::{select, select3, Either, Either3};
 use embassy_sync::channel::Channel;
 use embassy_time::{Duration, Ticker};
 
+use embassy_sync::signal::Signal;
+
 use crate::hw::mcu::RawMutex;
-use crate::keyboard::MATRIX_EVENTS;
+use crate::hw::LedIndicators;
+use crate::keyboard::{IDLE_STATE, MATRIX_EVENTS};
 use crate::{LEDEffect, State};
 
 use self::animations::{
@@ -35,6 +38,11 @@ pub trait UnderglowDevice {
     /// animations.
     const NUM_LEDS: usize;
 
+    /// Underglow LEDs used to show the state of the host's LEDs (e.g. Caps Lock), in the form of
+    /// `(indicator, led)`. While an indicator is on, its LED is lit white at the current
+    /// brightness, regardless of the current effect.
+    const LED_INDICATORS: &'static [(LedIndicators, usize)] = &[];
+
     // Effect settings
     underglow_effect_items!();
 }
@@ -55,6 +63,9 @@ pub static UNDERGLOW_CONFIG_STATE: State<UnderglowConfig> = State::new(
     ],
 );
 
+pub(crate) static HOST_LED_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+pub(crate) static IDLE_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
 #[rumcake_macros::task]
 pub async fn underglow_task<D: UnderglowDevice>(_k: D, driver: impl UnderglowDriver<D>)
 where
@@ -72,9 +83,36 @@ where
     animator.tick().await; // Force a frame to be rendered in the event that the initial effect is static.
 
     loop {
+        // Turn off the LEDs while the keyboard is idle, and wait for it to wake up.
+        if IDLE_STATE.get().await {
+            if animator.config.enabled {
+                animator.turn_off().await;
+            }
+
+            while IDLE_STATE.get().await {
+                IDLE_STATE_LISTENER.wait().await;
+            }
+
+            if animator.config.enabled {
+                animator.turn_on().await;
+            }
+
+            ticker.reset();
+        }
+
         let command = if !(animator.config.enabled && animator.config.effect.is_animated()) {
             // We want to wait for a command if the animator is not rendering any animated effects. This allows the task to sleep when the LEDs are static.
-            Some(UNDERGLOW_COMMAND_CHANNEL.receive().await)
+            // A change in the host's LEDs renders a new frame, to update the indicators.
+            match select3(
+                UNDERGLOW_COMMAND_CHANNEL.receive(),
+                HOST_LED_STATE_LISTENER.wait(),
+                IDLE_STATE_LISTENER.wait(),
+            )
+            .await
+            {
+                Either3::First(command) => Some(command),
+                Either3::Second(()) | Either3::Third(()) => None,
+            }
         } else {
             match select(ticker.next(), UNDERGLOW_COMMAND_CHANNEL.receive()).await {
                 Either::First(()) => {
@@ -163,25 +201,30 @@ where
 
 #[cfg(feature = "storage")]
 pub mod storage {
-    use core::any::TypeId;
-
     use defmt::{info, warn, Debug2Format};
     use embassy_futures::select;
-    use embassy_futures::select::Either;
+    use embassy_futures::select::Either3;
     use embassy_sync::signal::Signal;
     use embassy_time::Duration;
     use embassy_time::Timer;
 
     use crate::hw::mcu::RawMutex;
-    use crate::storage::{FlashStorage, StorageDevice};
+    use crate::storage::{FlashStorage, StorageDevice, StorageKey};
 
+    use super::animations::UnderglowCommand;
     use super::UnderglowConfig;
-    use super::UNDERGLOW_CONFIG_STATE;
+    use super::{UNDERGLOW_COMMAND_CHANNEL, UNDERGLOW_CONFIG_STATE};
 
     pub(super) static UNDERGLOW_CONFIG_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
 
     pub(super) static UNDERGLOW_SAVE_SIGNAL: Signal<RawMutex, ()> = Signal::new();
 
+    pub(crate) static UNDERGLOW_PROFILE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+    impl crate::storage::StorageSchema for UnderglowConfig {
+        const SCHEMA_VERSION: u16 = 1;
+    }
+
     #[rumcake_macros::task]
     pub async fn underglow_storage_task<K: StorageDevice, F: FlashStorage>(
         _k: K,
@@ -189,78 +232,96 @@ pub mod storage {
     ) where
         [(); F::ERASE_SIZE]:,
     {
-        {
-            // Check stored underglow config metadata (type id) to see if it has changed
-            let metadata: [u8; core::mem::size_of::<TypeId>()] =
-                unsafe { core::mem::transmute(TypeId::of::<UnderglowConfig>()) };
+        let load = |profile: u8| async move {
+            let key = StorageKey::UnderglowConfig.in_profile(profile);
+
+            // Migrate the stored underglow config if it was saved with an older schema version
             let _ = database
-                .check_metadata(
-                    K::get_storage_buffer(),
-                    crate::storage::StorageKey::UnderglowConfig,
-                    &metadata,
-                )
+                .check_schema::<UnderglowConfig>(K::get_storage_buffer(), key, K::get_migrations())
                 .await;
 
             // Get underglow config from storage
-            if let Ok(config) = database
-                .read(
-                    K::get_storage_buffer(),
-                    crate::storage::StorageKey::UnderglowConfig,
-                )
-                .await
-            {
-                info!(
+            let config = database.read(K::get_storage_buffer(), key).await;
+            match config {
+                Ok(config) => info!(
                     "[UNDERGLOW] Obtained underglow config from storage: {}",
                     Debug2Format(&config)
-                );
-                // Quietly update the config state so that we don't save the config to storage again
-                UNDERGLOW_CONFIG_STATE.quiet_set(config).await;
-            } else {
-                warn!("[UNDERGLOW] Could not get underglow config from storage, using default config.",);
+                ),
+                Err(()) => warn!(
+                    "[UNDERGLOW] Could not get underglow config from storage, using default config.",
+                ),
             }
-        }
+            config.ok()
+        };
 
-        let save = || async {
+        let save = |profile: u8| async move {
             let _ = database
                 .write(
                     K::get_storage_buffer(),
-                    crate::storage::StorageKey::UnderglowConfig,
+                    StorageKey::UnderglowConfig.in_profile(profile),
                     UNDERGLOW_CONFIG_STATE.get().await,
                 )
                 .await;
         };
 
+        let mut profile = crate::storage::active_profile().await;
+        if let Some(config) = load(profile).await {
+            // Quietly update the config state so that we don't save the config to storage again
+            UNDERGLOW_CONFIG_STATE.quiet_set(config).await;
+        }
+
         // Save the underglow config if it hasn't been changed in 5 seconds, or if a save was signalled
         loop {
-            match select::select(
+            let new_profile = match select::select3(
                 UNDERGLOW_SAVE_SIGNAL.wait(),
                 UNDERGLOW_CONFIG_STATE_LISTENER.wait(),
+                crate::storage::wait_for_profile_change(&UNDERGLOW_PROFILE_LISTENER),
             )
             .await
             {
-                Either::First(_) => {
-                    save().await;
+                Either3::First(_) => {
+                    save(profile).await;
+                    None
                 }
-                Either::Second(_) => {
-                    match select::select(
+                Either3::Second(_) => {
+                    match select::select3(
                         select::select(
                             Timer::after(Duration::from_secs(5)),
                             UNDERGLOW_SAVE_SIGNAL.wait(),
                         ),
                         UNDERGLOW_CONFIG_STATE_LISTENER.wait(),
+                        crate::storage::wait_for_profile_change(&UNDERGLOW_PROFILE_LISTENER),
                     )
                     .await
                     {
-                        Either::First(_) => {
-                            save().await;
+                        Either3::First(_) => {
+                            save(profile).await;
+                            None
                         }
-                        Either::Second(_) => {
+                        Either3::Second(_) => {
                             // Re-signal, so that we skip the `wait()` call at the beginning of this loop
                             UNDERGLOW_CONFIG_STATE_LISTENER.signal(());
+                            None
+                        }
+                        Either3::Third(new_profile) => {
+                            // Save pending changes to the previous profile before switching
+                            save(profile).await;
+                            Some(new_profile)
                         }
                     }
                 }
+                Either3::Third(new_profile) => Some(new_profile),
             };
+
+            // Apply the config of the new profile. The underglow task updates the config state
+            // once it has been applied.
+            if let Some(new_profile) = new_profile.filter(|new_profile| *new_profile != profile) {
+                profile = new_profile;
+                let config = load(profile).await.unwrap_or_default();
+                UNDERGLOW_COMMAND_CHANNEL
+                    .send(UnderglowCommand::SetConfig(config))
+                    .await;
+            }
         }
     }
 }
@@ -0,0 +1,329 @@
+//! Support for typing Unicode characters.
+//!
+//! HID keyboards can't send arbitrary characters to the host. Instead, the host's Unicode input
+//! method is used, by typing a sequence of keys that contains the character's code point in
+//! hexadecimal. Since each operating system has a different input method, the sequence depends on
+//! the current [`UnicodeMode`], which can be changed at runtime using [`UNICODE_MODE_STATE`], or
+//! with [`Keycode::UnicodeMode`] keycodes. The behaviour is based on QMK's implementation.
+//!
+//! Characters can be placed on your layout using [`Keycode::Unicode`], or using
+//! [`Keycode::UnicodeMap`] to refer to a character in
+//! [`crate::keyboard::KeyboardLayout::UNICODE_MAP`].
+//!
+//! [`Keycode::Unicode`]: crate::keyboard::Keycode::Unicode
+//! [`Keycode::UnicodeMap`]: crate::keyboard::Keycode::UnicodeMap
+//! [`Keycode::UnicodeMode`]: crate::keyboard::Keycode::UnicodeMode
+
+use defmt::warn;
+use embassy_sync::signal::Signal;
+use heapless::{Deque, Vec};
+use postcard::experimental::max_size::MaxSize;
+use serde::{Deserialize, Serialize};
+use usbd_human_interface_device::page::Keyboard as KeyboardKeycode;
+
+use crate::hw::mcu::RawMutex;
+use crate::State;
+
+/// Maximum number of reports that can be queued. This is enough for at least 3 characters in any
+/// mode.
+const MAX_QUEUED_REPORTS: usize = 64;
+
+/// Maximum number of keys held in a single report of an input sequence.
+const MAX_SEQUENCE_KEYS: usize = 3;
+
+/// Input methods that can be used to type Unicode characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, MaxSize)]
+pub enum UnicodeMode {
+    /// macOS, using the "Unicode Hex Input" input source. Characters outside of the Basic
+    /// Multilingual Plane are typed as UTF-16 surrogate pairs.
+    MacOS,
+    /// Linux, using IBus (Ctrl+Shift+U).
+    Linux,
+    /// Windows, using the built-in Alt code input. This requires the `EnableHexNumpad` registry
+    /// key to be set, and only supports characters in the Basic Multilingual Plane.
+    Windows,
+    /// Windows, using [WinCompose](https://github.com/samhocevar/wincompose).
+    WinCompose,
+}
+
+impl UnicodeMode {
+    /// Modes in the order that they are cycled through.
+    const ALL: [UnicodeMode; 4] = [
+        UnicodeMode::MacOS,
+        UnicodeMode::Linux,
+        UnicodeMode::Windows,
+        UnicodeMode::WinCompose,
+    ];
+
+    fn next(self) -> Self {
+        let idx = Self::ALL.iter().position(|m| *m == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+
+    fn previous(self) -> Self {
+        let idx = Self::ALL.iter().position(|m| *m == self).unwrap_or(0);
+        Self::ALL[(idx + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+}
+
+/// Commands that can be used to change the Unicode input mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnicodeCommand {
+    /// Switch to the next input mode.
+    NextMode,
+    /// Switch to the previous input mode.
+    PreviousMode,
+    /// Switch to the given input mode.
+    SetMode(UnicodeMode),
+}
+
+/// State that contains the current Unicode input mode.
+pub static UNICODE_MODE_STATE: State<UnicodeMode> = State::new(
+    UnicodeMode::Linux,
+    &[
+        &UNICODE_MODE_LISTENER,
+        #[cfg(feature = "storage")]
+        &storage::UNICODE_MODE_STATE_LISTENER,
+    ],
+);
+
+/// Signalled when the input mode changes, so that the layout can apply it.
+pub(crate) static UNICODE_MODE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+/// Process a [`UnicodeCommand`], by changing the input mode.
+pub(crate) async fn process_command(command: UnicodeCommand) {
+    UNICODE_MODE_STATE
+        .update(|mode| {
+            **mode = match command {
+                UnicodeCommand::NextMode => mode.next(),
+                UnicodeCommand::PreviousMode => mode.previous(),
+                UnicodeCommand::SetMode(new) => new,
+            }
+        })
+        .await;
+}
+
+/// Convert a hexadecimal digit to the key that types it. Windows only accepts digits from the
+/// keypad while Alt is held, so keypad keys are used for 0-9 in [`UnicodeMode::Windows`].
+fn hex_digit_keycode(digit: u32, mode: UnicodeMode) -> KeyboardKeycode {
+    match digit {
+        0 if mode == UnicodeMode::Windows => KeyboardKeycode::Keypad0Insert,
+        1..=9 if mode == UnicodeMode::Windows => {
+            KeyboardKeycode::try_from(KeyboardKeycode::Keypad1End as u8 + digit as u8 - 1)
+                .unwrap_or(KeyboardKeycode::NoEventIndicated)
+        }
+        0 => KeyboardKeycode::Keyboard0,
+        1..=9 => KeyboardKeycode::try_from(KeyboardKeycode::Keyboard1 as u8 + digit as u8 - 1)
+            .unwrap_or(KeyboardKeycode::NoEventIndicated),
+        _ => KeyboardKeycode::try_from(KeyboardKeycode::A as u8 + digit as u8 - 0xA)
+            .unwrap_or(KeyboardKeycode::NoEventIndicated),
+    }
+}
+
+/// Converts characters into sequences of keyboard reports, and queues them until they can be
+/// sent.
+pub(crate) struct UnicodeProcessor {
+    mode: UnicodeMode,
+    queue: Deque<Vec<KeyboardKeycode, MAX_SEQUENCE_KEYS>, MAX_QUEUED_REPORTS>,
+}
+
+impl UnicodeProcessor {
+    pub(crate) fn new() -> Self {
+        Self {
+            mode: UnicodeMode::Linux,
+            queue: Deque::new(),
+        }
+    }
+
+    /// Change the input mode used for characters that are typed after this.
+    pub(crate) fn set_mode(&mut self, mode: UnicodeMode) {
+        self.mode = mode;
+    }
+
+    /// Get the next report that should be sent.
+    pub(crate) fn peek(&self) -> Option<&Vec<KeyboardKeycode, MAX_SEQUENCE_KEYS>> {
+        self.queue.front()
+    }
+
+    /// Remove the next report, after it has been sent.
+    pub(crate) fn pop(&mut self) {
+        self.queue.pop_front();
+    }
+
+    /// Queue the input sequence for a character. If the queue doesn't have enough space for the
+    /// whole sequence, the character is dropped.
+    pub(crate) fn type_char(&mut self, c: char) {
+        let mut sequence = Sequence::new();
+        let code = c as u32;
+
+        match self.mode {
+            UnicodeMode::MacOS => {
+                let held = [KeyboardKeycode::LeftAlt];
+                push_report(&mut sequence, &held);
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units).iter() {
+                    push_hex(&mut sequence, &held, *unit as u32, self.mode);
+                }
+                push_report(&mut sequence, &[]);
+            }
+            UnicodeMode::Linux => {
+                push_report(
+                    &mut sequence,
+                    &[
+                        KeyboardKeycode::LeftControl,
+                        KeyboardKeycode::LeftShift,
+                        KeyboardKeycode::U,
+                    ],
+                );
+                push_report(&mut sequence, &[]);
+                push_hex(&mut sequence, &[], code, self.mode);
+                push_report(&mut sequence, &[KeyboardKeycode::Space]);
+                push_report(&mut sequence, &[]);
+            }
+            UnicodeMode::Windows => {
+                if code > 0xFFFF {
+                    warn!(
+                        "[UNICODE] Characters outside of the BMP can't be typed in Windows mode."
+                    );
+                    return;
+                }
+                let held = [KeyboardKeycode::LeftAlt];
+                push_report(&mut sequence, &held);
+                push_report(
+                    &mut sequence,
+                    &[KeyboardKeycode::LeftAlt, KeyboardKeycode::KeypadAdd],
+                );
+                push_report(&mut sequence, &held);
+                push_hex(&mut sequence, &held, code, self.mode);
+                push_report(&mut sequence, &[]);
+            }
+            UnicodeMode::WinCompose => {
+                push_report(&mut sequence, &[KeyboardKeycode::RightAlt]);
+                push_report(&mut sequence, &[]);
+                push_report(&mut sequence, &[KeyboardKeycode::U]);
+                push_report(&mut sequence, &[]);
+                push_hex(&mut sequence, &[], code, self.mode);
+                push_report(&mut sequence, &[KeyboardKeycode::ReturnEnter]);
+                push_report(&mut sequence, &[]);
+            }
+        }
+
+        if self.queue.capacity() - self.queue.len() < sequence.len() {
+            warn!("[UNICODE] Too many characters queued, dropping character.");
+            return;
+        }
+
+        for report in sequence {
+            let _ = self.queue.push_back(report);
+        }
+    }
+}
+
+/// Reports for the input sequence of a single character.
+type Sequence = Vec<Vec<KeyboardKeycode, MAX_SEQUENCE_KEYS>, 24>;
+
+fn push_report(sequence: &mut Sequence, keys: &[KeyboardKeycode]) {
+    let _ = sequence.push(Vec::from_slice(keys).unwrap_or_default());
+}
+
+/// Type a value in hexadecimal while holding the given keys. At least 4 digits are typed, since
+/// some input methods (e.g. macOS) require it.
+fn push_hex(sequence: &mut Sequence, held: &[KeyboardKeycode], value: u32, mode: UnicodeMode) {
+    let digits = (u32::BITS - value.leading_zeros()).div_ceil(4).max(4);
+    for i in (0..digits).rev() {
+        let mut keys =
+            Vec::<KeyboardKeycode, MAX_SEQUENCE_KEYS>::from_slice(held).unwrap_or_default();
+        let _ = keys.push(hex_digit_keycode((value >> (i * 4)) & 0xF, mode));
+        push_report(sequence, &keys);
+        push_report(sequence, held);
+    }
+}
+
+#[cfg(feature = "storage")]
+pub mod storage {
+    use defmt::{info, warn, Debug2Format};
+    use embassy_futures::select;
+    use embassy_futures::select::Either;
+    use embassy_sync::signal::Signal;
+    use embassy_time::Duration;
+    use embassy_time::Timer;
+
+    use crate::hw::mcu::RawMutex;
+    use crate::storage::{FlashStorage, StorageDevice};
+
+    use super::{UnicodeMode, UNICODE_MODE_LISTENER, UNICODE_MODE_STATE};
+
+    pub(super) static UNICODE_MODE_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+    impl crate::storage::StorageSchema for UnicodeMode {
+        const SCHEMA_VERSION: u16 = 1;
+    }
+
+    #[rumcake_macros::task]
+    pub async fn unicode_storage_task<K: StorageDevice, F: FlashStorage>(
+        _k: K,
+        database: &crate::storage::StorageService<'static, F>,
+    ) where
+        [(); F::ERASE_SIZE]:,
+    {
+        {
+            // Migrate the stored Unicode mode if it was saved with an older schema version
+            let _ = database
+                .check_schema::<UnicodeMode>(
+                    K::get_storage_buffer(),
+                    crate::storage::StorageKey::UnicodeMode,
+                    K::get_migrations(),
+                )
+                .await;
+
+            // Get Unicode mode from storage
+            if let Ok(mode) = database
+                .read(
+                    K::get_storage_buffer(),
+                    crate::storage::StorageKey::UnicodeMode,
+                )
+                .await
+            {
+                info!(
+                    "[UNICODE] Obtained Unicode mode from storage: {}",
+                    Debug2Format(&mode)
+                );
+                // Quietly update the mode so that we don't save it to storage again, but still
+                // apply it to the layout
+                UNICODE_MODE_STATE.quiet_set(mode).await;
+                UNICODE_MODE_LISTENER.signal(());
+            } else {
+                warn!("[UNICODE] Could not get Unicode mode from storage, using default mode.",);
+            }
+        }
+
+        let save = || async {
+            let _ = database
+                .write(
+                    K::get_storage_buffer(),
+                    crate::storage::StorageKey::UnicodeMode,
+                    UNICODE_MODE_STATE.get().await,
+                )
+                .await;
+        };
+
+        // Save the Unicode mode if it hasn't been changed in 5 seconds
+        loop {
+            UNICODE_MODE_STATE_LISTENER.wait().await;
+            match select::select(
+                Timer::after(Duration::from_secs(5)),
+                UNICODE_MODE_STATE_LISTENER.wait(),
+            )
+            .await
+            {
+                Either::First(_) => {
+                    save().await;
+                }
+                Either::Second(_) => {
+                    // Re-signal, so that we skip the `wait()` call at the beginning of this loop
+                    UNICODE_MODE_STATE_LISTENER.signal(());
+                }
+            }
+        }
+    }
+}
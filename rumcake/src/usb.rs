@@ -2,7 +2,7 @@
 //!
 //! To use USB host communication, keyboards must implement [`USBKeyboard`].
 
-use defmt::{error, info, Debug2Format};
+use defmt::{debug, error, info, Debug2Format};
 use embassy_futures::select::{self, select};
 use embassy_sync::signal::Signal;
 use embassy_usb::class::hid::{
@@ -19,11 +19,13 @@ use usbd_human_interface_device::device::consumer::{
 use usbd_human_interface_device::device::keyboard::{
     NKROBootKeyboardReport, NKRO_BOOT_KEYBOARD_REPORT_DESCRIPTOR,
 };
+use usbd_human_interface_device::device::mouse::{WheelMouseReport, WHEEL_MOUSE_REPORT_DESCRIPTOR};
 
 use crate::hw::mcu::RawMutex;
 use crate::hw::{HIDOutput, CURRENT_OUTPUT_STATE};
 use crate::keyboard::{
     Keyboard, KeyboardLayout, CONSUMER_REPORT_HID_SEND_CHANNEL, KEYBOARD_REPORT_HID_SEND_CHANNEL,
+    MOUSE_REPORT_HID_SEND_CHANNEL,
 };
 use crate::{State, StaticArray};
 
@@ -53,7 +55,7 @@ pub fn setup_usb_hid_nkro_writer(
     static KB_STATE: StaticCell<UsbState> = StaticCell::new();
     let kb_state = KB_STATE.init(UsbState::new());
     let kb_hid_config = Config {
-        request_handler: None,
+        request_handler: Some(&KEYBOARD_LED_HANDLER),
         report_descriptor: NKRO_BOOT_KEYBOARD_REPORT_DESCRIPTOR,
         poll_ms: 1,
         max_packet_size: 64,
@@ -65,6 +67,32 @@ pub fn setup_usb_hid_nkro_writer(
     )
 }
 
+struct KeyboardLedHandler;
+
+static KEYBOARD_LED_HANDLER: KeyboardLedHandler = KeyboardLedHandler;
+
+impl RequestHandler for KeyboardLedHandler {
+    fn get_report(&self, _id: ReportId, _buf: &mut [u8]) -> Option<usize> {
+        None
+    }
+
+    fn set_report(&self, _id: ReportId, buf: &[u8]) -> OutResponse {
+        // The keyboard only has one output report, which contains the host's LED state
+        if let Some(leds) = buf.first() {
+            debug!("[USB] Received LED output report: {=u8:#X}", leds);
+            crate::hw::set_host_leds(HIDOutput::Usb, *leds);
+        }
+
+        OutResponse::Accepted
+    }
+
+    fn get_idle_ms(&self, _id: Option<ReportId>) -> Option<u32> {
+        None
+    }
+
+    fn set_idle_ms(&self, _id: Option<ReportId>, _duration_ms: u32) {}
+}
+
 /// Configure the HID report writer, for consumer commands.
 ///
 /// The HID writer produced should be passed to [`usb_hid_consumer_write_task`].
@@ -91,6 +119,32 @@ pub fn setup_usb_hid_consumer_writer(
     )
 }
 
+/// Configure the HID report writer, for mouse reports.
+///
+/// The HID writer produced should be passed to [`usb_hid_mouse_write_task`].
+pub fn setup_usb_hid_mouse_writer(
+    b: &mut Builder<'static, impl Driver<'static>>,
+) -> HidWriter<
+    'static,
+    impl Driver<'static>,
+    { <<WheelMouseReport as PackedStruct>::ByteArray as StaticArray>::LEN },
+> {
+    // Mouse HID setup
+    static MOUSE_STATE: StaticCell<UsbState> = StaticCell::new();
+    let mouse_state = MOUSE_STATE.init(UsbState::new());
+    let mouse_hid_config = Config {
+        request_handler: None,
+        report_descriptor: WHEEL_MOUSE_REPORT_DESCRIPTOR,
+        poll_ms: 1,
+        max_packet_size: 64,
+    };
+    HidWriter::<_, { <<WheelMouseReport as PackedStruct>::ByteArray as StaticArray>::LEN }>::new(
+        b,
+        mouse_state,
+        mouse_hid_config,
+    )
+}
+
 #[rumcake_macros::task]
 pub async fn start_usb(mut usb: UsbDevice<'static, impl Driver<'static>>) {
     loop {
@@ -164,6 +218,25 @@ pub async fn usb_hid_consumer_write_task(
     );
 }
 
+pub(crate) static MOUSE_CURRENT_OUTPUT_STATE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+#[rumcake_macros::task]
+pub async fn usb_hid_mouse_write_task(
+    mut hid: HidWriter<
+        'static,
+        impl Driver<'static>,
+        { <<WheelMouseReport as PackedStruct>::ByteArray as StaticArray>::LEN },
+    >,
+) {
+    usb_task_inner!(
+        hid,
+        MOUSE_CURRENT_OUTPUT_STATE_LISTENER,
+        MOUSE_REPORT_HID_SEND_CHANNEL,
+        "[USB] Writing mouse HID report to USB: {:?}",
+        "[USB] Couldn't write mouse HID report: {:?}"
+    );
+}
+
 #[cfg(feature = "via")]
 struct ViaCommandHandler;
 
@@ -220,54 +220,94 @@ pub async fn dynamic_keymap_set_keycode<K: ViaKeyboard + 'static>(
                 keycode,
             )
             .await;
+
+            save_encoder_keycodes::<K>(layer, (row, col), keycode).await;
         }
     } else {
         warn!("[VIA] Requested a dynamic keymap keycode that is out of bounds.")
     }
 }
 
-pub async fn dynamic_keymap_get_encoder<K: ViaKeyboard>(
+pub async fn dynamic_keymap_get_encoder<K: ViaKeyboard + 'static>(
     layer: u8,
     encoder_id: u8,
     clockwise: bool,
     data: &mut [u8],
-) {
+    convert_action_to_keycode: impl Fn(Action<Keycode>) -> u16,
+) where
+    [(); K::LAYERS]:,
+    [(); K::LAYOUT_ROWS]:,
+    [(); K::LAYOUT_COLS]:,
+{
     let keycode = &mut data[0..=1];
 
-    let keycode_offset = ((layer * K::NUM_ENCODERS as u8 * 2 * 2)
-        + (encoder_id * 2 * 2)
-        + if clockwise { 0 } else { 2 }) as usize;
-
     if !(layer as usize >= K::DYNAMIC_KEYMAP_LAYER_COUNT || encoder_id as usize >= K::NUM_ENCODERS)
     {
-        //TODO: encoder support
+        if let Some(&(cw, ccw)) = K::ENCODER_LAYOUT_POSITIONS.get(encoder_id as usize) {
+            if let Some(action) = K::get_layout()
+                .lock()
+                .await
+                .get_action(if clockwise { cw } else { ccw }, layer as usize)
+            {
+                keycode.copy_from_slice(&convert_action_to_keycode(action).to_be_bytes())
+            };
+        }
     } else {
         warn!("[VIA] Requested a dynamic keymap encoder that is out of bounds.")
     }
 }
 
-pub async fn dynamic_keymap_set_encoder<K: ViaKeyboard>(
+pub async fn dynamic_keymap_set_encoder<K: ViaKeyboard + 'static>(
     layer: u8,
     encoder_id: u8,
     clockwise: bool,
     data: &[u8],
-) {
+    convert_keycode_to_action: impl Fn(u16) -> Option<Action<Keycode>>,
+) where
+    [(); K::LAYERS]:,
+    [(); K::LAYOUT_ROWS]:,
+    [(); K::LAYOUT_COLS]:,
+{
     let keycode = &data[0..=1];
 
-    let keycode_offset = ((layer * K::NUM_ENCODERS as u8 * 2 * 2)
-        + (encoder_id * 2 * 2)
-        + if clockwise { 0 } else { 2 }) as usize;
-
     if !(layer as usize >= K::DYNAMIC_KEYMAP_LAYER_COUNT || encoder_id as usize >= K::NUM_ENCODERS)
     {
-        //TODO: encoder support
+        if let Some(&(cw, ccw)) = K::ENCODER_LAYOUT_POSITIONS.get(encoder_id as usize) {
+            let mut layout = K::get_layout().lock().await;
+            if let Some(action) =
+                convert_keycode_to_action(u16::from_be_bytes(keycode.try_into().unwrap()))
+            {
+                if layout
+                    .change_action(if clockwise { cw } else { ccw }, layer as usize, action)
+                    .is_err()
+                {
+                    warn!("[VIA] Encoder layout position is out of bounds.")
+                };
+            }
+        }
+
+        // The encoder's position is also part of the dynamic keymap, so both are saved, otherwise
+        // the keymap would overwrite the encoder's action when it is loaded
         #[cfg(feature = "storage")]
-        super::storage::update_data(
-            super::storage::ViaStorageKeys::DynamicKeymapEncoder,
-            keycode_offset,
-            keycode,
-        )
-        .await;
+        if let Some(&(cw, ccw)) = K::ENCODER_LAYOUT_POSITIONS.get(encoder_id as usize) {
+            let (row, col) = if clockwise { cw } else { ccw };
+
+            if (row as usize) < K::LAYOUT_ROWS && (col as usize) < K::LAYOUT_COLS {
+                let keycode_offset = ((layer as usize * K::LAYOUT_ROWS * K::LAYOUT_COLS)
+                    + (row as usize * K::LAYOUT_COLS)
+                    + col as usize)
+                    * 2;
+
+                super::storage::update_data(
+                    super::storage::ViaStorageKeys::DynamicKeymap,
+                    keycode_offset,
+                    keycode,
+                )
+                .await;
+
+                save_encoder_keycodes::<K>(layer, (row, col), keycode).await;
+            }
+        }
     } else {
         warn!("[VIA] Attempted to set a dynamic keymap encoder out of bounds.")
     }
@@ -359,6 +399,40 @@ pub async fn dynamic_keymap_set_buffer<K: ViaKeyboard + 'static>(
             &data[..len],
         )
         .await;
+
+        for byte in ((offset as usize)..(offset as usize + len)).step_by(2) {
+            let layer = byte / (K::LAYOUT_ROWS * K::LAYOUT_COLS * 2);
+            let row = (byte / (K::LAYOUT_COLS * 2)) % K::LAYOUT_ROWS;
+            let col = (byte / 2) % K::LAYOUT_COLS;
+
+            save_encoder_keycodes::<K>(
+                layer as u8,
+                (row as u8, col as u8),
+                &data[(byte - offset as usize)..(byte - offset as usize + 2)],
+            )
+            .await;
+        }
+    }
+}
+
+/// Save a keycode to the stored encoder actions of any encoders that are mapped to the given
+/// layout position, so that the stored encoder actions always match the stored dynamic keymap.
+#[cfg(feature = "storage")]
+async fn save_encoder_keycodes<K: ViaKeyboard>(layer: u8, position: (u8, u8), keycode: &[u8]) {
+    for (encoder_id, &(cw, ccw)) in K::ENCODER_LAYOUT_POSITIONS.iter().enumerate() {
+        for (encoder_position, direction_offset) in [(cw, 0), (ccw, 2)] {
+            if encoder_position == position {
+                let keycode_offset =
+                    (layer as usize * K::NUM_ENCODERS + encoder_id) * 2 * 2 + direction_offset;
+
+                super::storage::update_data(
+                    super::storage::ViaStorageKeys::DynamicKeymapEncoder,
+                    keycode_offset,
+                    keycode,
+                )
+                .await;
+            }
+        }
     }
 }
 
@@ -827,3 +901,62 @@ pub async fn underglow_save() {
         .send(crate::underglow::animations::UnderglowCommand::SaveConfig)
         .await;
 }
+
+#[cfg(feature = "storage")]
+pub fn config_backup_get_status(backup_len: Option<usize>, data: &mut [u8]) {
+    data[0] = backup_len.is_some() as u8;
+}
+
+#[cfg(feature = "storage")]
+pub fn config_backup_get_size(backup_len: Option<usize>, data: &mut [u8]) {
+    data[0..=1].copy_from_slice(&(backup_len.unwrap_or_default() as u16).to_be_bytes());
+}
+
+#[cfg(feature = "storage")]
+pub async fn config_backup_get_data(backup_len: Option<usize>, data: &mut [u8]) {
+    let offset = u16::from_be_bytes(data[0..=1].try_into().unwrap()) as usize;
+    let backup = crate::storage::BACKUP_BUFFER.lock().await;
+
+    if let (Some(backup), Some(len)) = (backup.as_deref(), backup_len) {
+        let end = (offset + data.len() - 2).min(len);
+        if offset < end {
+            data[2..(2 + end - offset)].copy_from_slice(&backup[offset..end]);
+        }
+    }
+}
+
+#[cfg(feature = "storage")]
+pub async fn config_backup_set_data(data: &[u8]) {
+    let offset = u16::from_be_bytes(data[0..=1].try_into().unwrap()) as usize;
+    let size = (data[2] as usize).min(data.len() - 3);
+    let mut backup = crate::storage::BACKUP_BUFFER.lock().await;
+
+    match backup
+        .as_deref_mut()
+        .and_then(|backup| backup.get_mut(offset..(offset + size)))
+    {
+        Some(dest) => dest.copy_from_slice(&data[3..(3 + size)]),
+        None => warn!("[VIA] Backup data does not fit in the backup buffer."),
+    }
+}
+
+#[cfg(feature = "storage")]
+pub async fn config_backup_export(backup_len: &mut Option<usize>, data: &[u8]) {
+    *backup_len =
+        crate::storage::request_backup_operation(crate::storage::BackupOperation::Export {
+            include_bluetooth: data[0] != 0,
+        })
+        .await
+        .ok();
+}
+
+#[cfg(feature = "storage")]
+pub async fn config_backup_import(backup_len: &mut Option<usize>, data: &[u8]) {
+    *backup_len =
+        crate::storage::request_backup_operation(crate::storage::BackupOperation::Import {
+            len: u16::from_be_bytes(data[0..=1].try_into().unwrap()) as usize,
+            include_bluetooth: data[2] != 0,
+        })
+        .await
+        .ok();
+}
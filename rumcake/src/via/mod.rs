@@ -203,12 +203,107 @@ where
         }
     };
 
-    join::join(report_fut, protocol::background_task::<K>(&via_state)).await;
+    join::join3(
+        report_fut,
+        protocol::background_task::<K>(&via_state),
+        dynamic_macro_save_task::<K>(),
+    )
+    .await;
+}
+
+/// Save macros recorded using [`crate::dynamic_macros`] to the macro buffer, if
+/// [`crate::dynamic_macros::DynamicMacroConfig::save_to_via`] is enabled. The recorded macro
+/// replaces the Via macro with the same index.
+pub(crate) async fn dynamic_macro_save_task<K: ViaKeyboard + 'static>()
+where
+    [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
+    [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
+{
+    #[cfg(feature = "dynamic-macros")]
+    loop {
+        let (idx, events) = crate::dynamic_macros::DYNAMIC_MACRO_SAVE_CHANNEL
+            .receive()
+            .await;
+
+        if idx >= K::DYNAMIC_KEYMAP_MACRO_COUNT {
+            defmt::warn!(
+                "[VIA] Dynamic macro {} can't be saved, because there is no Via macro with that index.",
+                idx
+            );
+            continue;
+        }
+
+        let Some(macro_data) = K::get_macro_buffer() else {
+            continue;
+        };
+
+        // Rebuild the buffer, with the recorded macro replacing the existing sequence
+        let mut buf = [0; K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize];
+        let mut len = 0;
+        let mut fits = true;
+        for (i, sequence) in macro_data
+            .buffer
+            .split(|byte| *byte == 0)
+            .take(K::DYNAMIC_KEYMAP_MACRO_COUNT as usize)
+            .enumerate()
+        {
+            let written = if i == idx as usize {
+                crate::dynamic_macros::encode_via_macro(&events, &mut buf[len..])
+            } else {
+                buf.get_mut(len..(len + sequence.len())).map(|dest| {
+                    dest.copy_from_slice(sequence);
+                    sequence.len()
+                })
+            };
+
+            // Every sequence must be followed by a null byte
+            match written {
+                Some(written) if len + written < buf.len() => len += written + 1,
+                _ => {
+                    fits = false;
+                    break;
+                }
+            }
+        }
+
+        if !fits {
+            defmt::warn!(
+                "[VIA] Dynamic macro {} can't be saved, because the macro buffer is full.",
+                idx
+            );
+            continue;
+        }
+
+        // Only the bytes that changed are written
+        let changed = |(new, old): (&u8, &u8)| new != old;
+        let (Some(start), Some(end)) = (
+            buf.iter().zip(macro_data.buffer.iter()).position(changed),
+            buf.iter().zip(macro_data.buffer.iter()).rposition(changed),
+        ) else {
+            continue;
+        };
+
+        // Written in chunks of 28 bytes, which is the most that a Via packet can contain
+        for offset in (start..=end).step_by(28) {
+            let chunk_end = (offset + 28).min(end + 1);
+            handlers::dynamic_keymap_macro_set_buffer::<K>(
+                offset as u16,
+                (chunk_end - offset) as u8,
+                &buf[offset..chunk_end],
+            )
+            .await;
+        }
+    }
+
+    #[cfg(not(feature = "dynamic-macros"))]
+    core::future::pending::<()>().await;
 }
 
 #[cfg(feature = "storage")]
 pub mod storage {
     use defmt::warn;
+    use embassy_futures::select;
+    use embassy_futures::select::Either;
     use embassy_sync::channel::Channel;
     use embassy_sync::signal::Signal;
 
@@ -269,6 +364,167 @@ pub mod storage {
 
     pub(super) static VIA_LAYOUT_OPTIONS: Signal<RawMutex, u32> = Signal::new();
 
+    pub(crate) static VIA_PROFILE_LISTENER: Signal<RawMutex, ()> = Signal::new();
+
+    /// Load the dynamic keymap, encoders and macros of the given storage profile. If the keymap or
+    /// encoders are not stored yet, the current layout is saved to the profile instead.
+    async fn load_profile<K: StorageDevice + ViaKeyboard + 'static, F: FlashStorage>(
+        database: &crate::storage::StorageService<'_, F>,
+        profile: u8,
+    ) where
+        [(); K::DYNAMIC_KEYMAP_LAYER_COUNT * K::LAYOUT_COLS * K::LAYOUT_ROWS * 2]:,
+        [(); K::DYNAMIC_KEYMAP_LAYER_COUNT * K::NUM_ENCODERS * 2 * 2]:,
+        [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
+        [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
+        [(); F::ERASE_SIZE]:,
+        [(); K::LAYERS]:,
+        [(); K::LAYOUT_ROWS]:,
+        [(); K::LAYOUT_COLS]:,
+    {
+        // Initialize layout
+        let layout_metadata = [
+            K::DYNAMIC_KEYMAP_LAYER_COUNT as u8,
+            K::LAYOUT_COLS as u8,
+            K::LAYOUT_ROWS as u8,
+        ];
+        let _ = database
+            .check_metadata(
+                K::get_storage_buffer(),
+                StorageKey::DynamicKeymap.in_profile(profile),
+                &layout_metadata,
+            )
+            .await;
+        if let Ok((stored_data, stored_len)) = database
+            .read_raw(
+                K::get_storage_buffer(),
+                StorageKey::DynamicKeymap.in_profile(profile),
+            )
+            .await
+        {
+            // Load layout from flash
+            let mut layout = K::get_layout().lock().await;
+            for byte in (0..stored_len).step_by(2) {
+                if let Some(action) = super::protocol::keycodes::convert_keycode_to_action::<K>(
+                    u16::from_be_bytes(stored_data[byte..byte + 2].try_into().unwrap()),
+                ) {
+                    let layer = byte / (K::LAYOUT_ROWS * K::LAYOUT_COLS * 2);
+                    let row = (byte / (K::LAYOUT_COLS * 2)) % K::LAYOUT_ROWS;
+                    let col = (byte / 2) % K::LAYOUT_COLS;
+
+                    layout
+                        .change_action((row as u8, col as u8), layer, action)
+                        .unwrap();
+                }
+            }
+        } else {
+            // Save default layout to flash
+            let mut layout = K::get_layout().lock().await;
+            let mut buf = [0; K::DYNAMIC_KEYMAP_LAYER_COUNT * K::LAYOUT_COLS * K::LAYOUT_ROWS * 2];
+            for byte in (0..buf.len()).step_by(2) {
+                let layer = byte / (K::LAYOUT_ROWS * K::LAYOUT_COLS * 2);
+                let row = (byte / (K::LAYOUT_COLS * 2)) % K::LAYOUT_ROWS;
+                let col = (byte / 2) % K::LAYOUT_COLS;
+
+                buf[(byte)..(byte + 2)].copy_from_slice(
+                    &super::protocol::keycodes::convert_action_to_keycode::<K>(
+                        layout.get_action((row as u8, col as u8), layer).unwrap(),
+                    )
+                    .to_be_bytes(),
+                );
+            }
+            let _ = database
+                .write_raw(
+                    K::get_storage_buffer(),
+                    StorageKey::DynamicKeymap.in_profile(profile),
+                    &buf,
+                )
+                .await;
+        };
+
+        // Initialize encoder layout
+        let encoder_metadata = [K::DYNAMIC_KEYMAP_LAYER_COUNT as u8, K::NUM_ENCODERS as u8];
+        let _ = database
+            .check_metadata(
+                K::get_storage_buffer(),
+                StorageKey::DynamicKeymapEncoder.in_profile(profile),
+                &encoder_metadata,
+            )
+            .await;
+        if let Ok((stored_data, stored_len)) = database
+            .read_raw(
+                K::get_storage_buffer(),
+                StorageKey::DynamicKeymapEncoder.in_profile(profile),
+            )
+            .await
+        {
+            // Load encoder actions from flash
+            let mut layout = K::get_layout().lock().await;
+            for byte in (0..stored_len).step_by(2) {
+                if let Some(action) = super::protocol::keycodes::convert_keycode_to_action::<K>(
+                    u16::from_be_bytes(stored_data[byte..byte + 2].try_into().unwrap()),
+                ) {
+                    let layer = byte / (K::NUM_ENCODERS * 2 * 2);
+                    let encoder_id = (byte / (2 * 2)) % K::NUM_ENCODERS;
+                    let clockwise = (byte / 2) % 2 == 0;
+
+                    if let Some(&(cw, ccw)) = K::ENCODER_LAYOUT_POSITIONS.get(encoder_id) {
+                        let _ =
+                            layout.change_action(if clockwise { cw } else { ccw }, layer, action);
+                    }
+                }
+            }
+        } else if K::NUM_ENCODERS > 0 {
+            // Save default encoder actions to flash
+            let mut layout = K::get_layout().lock().await;
+            let mut buf = [0; K::DYNAMIC_KEYMAP_LAYER_COUNT * K::NUM_ENCODERS * 2 * 2];
+            for byte in (0..buf.len()).step_by(2) {
+                let layer = byte / (K::NUM_ENCODERS * 2 * 2);
+                let encoder_id = (byte / (2 * 2)) % K::NUM_ENCODERS;
+                let clockwise = (byte / 2) % 2 == 0;
+
+                if let Some(action) =
+                    K::ENCODER_LAYOUT_POSITIONS
+                        .get(encoder_id)
+                        .and_then(|&(cw, ccw)| {
+                            layout.get_action(if clockwise { cw } else { ccw }, layer)
+                        })
+                {
+                    buf[(byte)..(byte + 2)].copy_from_slice(
+                        &super::protocol::keycodes::convert_action_to_keycode::<K>(action)
+                            .to_be_bytes(),
+                    );
+                }
+            }
+            let _ = database
+                .write_raw(
+                    K::get_storage_buffer(),
+                    StorageKey::DynamicKeymapEncoder.in_profile(profile),
+                    &buf,
+                )
+                .await;
+        };
+
+        // Initialize macros
+        let _ = database
+            .check_metadata(
+                K::get_storage_buffer(),
+                StorageKey::DynamicKeymapMacro.in_profile(profile),
+                &layout_metadata,
+            )
+            .await;
+        if let Ok((stored_data, stored_len)) = database
+            .read_raw(
+                K::get_storage_buffer(),
+                StorageKey::DynamicKeymapMacro.in_profile(profile),
+            )
+            .await
+        {
+            if let Some(macro_data) = K::get_macro_buffer() {
+                macro_data.update_buffer(0, &stored_data[..stored_len])
+            }
+        };
+    }
+
     #[rumcake_macros::task]
     pub async fn via_storage_task<K: StorageDevice + ViaKeyboard + 'static, F: FlashStorage>(
         _k: K,
@@ -306,97 +562,33 @@ pub mod storage {
                 bytes[(4 - stored_len)..].copy_from_slice(&stored_data[..stored_len]);
                 VIA_LAYOUT_OPTIONS.signal(u32::from_be_bytes(bytes))
             };
+        }
 
-            // Initialize layout
-            let layout_metadata = [
-                K::DYNAMIC_KEYMAP_LAYER_COUNT as u8,
-                K::LAYOUT_COLS as u8,
-                K::LAYOUT_ROWS as u8,
-            ];
-            let _ = database
-                .check_metadata(
-                    K::get_storage_buffer(),
-                    crate::storage::StorageKey::DynamicKeymap,
-                    &layout_metadata,
-                )
-                .await;
-            if let Ok((stored_data, stored_len)) = database
-                .read_raw(
-                    K::get_storage_buffer(),
-                    crate::storage::StorageKey::DynamicKeymap,
-                )
-                .await
-            {
-                // Load layout from flash
-                let mut layout = K::get_layout().lock().await;
-                for byte in (0..stored_len).step_by(2) {
-                    if let Some(action) = super::protocol::keycodes::convert_keycode_to_action::<K>(
-                        u16::from_be_bytes(stored_data[byte..byte + 2].try_into().unwrap()),
-                    ) {
-                        let layer = byte / (K::LAYOUT_ROWS * K::LAYOUT_COLS * 2);
-                        let row = (byte / (K::LAYOUT_COLS * 2)) % K::LAYOUT_ROWS;
-                        let col = (byte / 2) % K::LAYOUT_COLS;
-
-                        layout
-                            .change_action((row as u8, col as u8), layer, action)
-                            .unwrap();
-                    }
-                }
-            } else {
-                // Save default layout to flash
-                let mut layout = K::get_layout().lock().await;
-                let mut buf =
-                    [0; K::DYNAMIC_KEYMAP_LAYER_COUNT * K::LAYOUT_COLS * K::LAYOUT_ROWS * 2];
-                for byte in (0..buf.len()).step_by(2) {
-                    let layer = byte / (K::LAYOUT_ROWS * K::LAYOUT_COLS * 2);
-                    let row = (byte / (K::LAYOUT_COLS * 2)) % K::LAYOUT_ROWS;
-                    let col = (byte / 2) % K::LAYOUT_COLS;
-
-                    buf[(byte)..(byte + 2)].copy_from_slice(
-                        &super::protocol::keycodes::convert_action_to_keycode::<K>(
-                            layout.get_action((row as u8, col as u8), layer).unwrap(),
-                        )
-                        .to_be_bytes(),
-                    );
-                }
-                let _ = database
-                    .write_raw(K::get_storage_buffer(), StorageKey::DynamicKeymap, &buf)
-                    .await;
-            };
-
-            // Initialize encoder layout
-            let encoder_metadata = [K::DYNAMIC_KEYMAP_LAYER_COUNT as u8, K::NUM_ENCODERS as u8];
-            let _ = database
-                .check_metadata(
-                    K::get_storage_buffer(),
-                    crate::storage::StorageKey::DynamicKeymapEncoder,
-                    &encoder_metadata,
-                )
-                .await;
+        let mut profile = crate::storage::active_profile().await;
+        load_profile::<K, F>(database, profile).await;
 
-            // Initialize macros
-            let _ = database
-                .check_metadata(
-                    K::get_storage_buffer(),
-                    crate::storage::StorageKey::DynamicKeymapMacro,
-                    &layout_metadata,
-                )
-                .await;
-            if let Ok((stored_data, stored_len)) = database
-                .read_raw(
-                    K::get_storage_buffer(),
-                    crate::storage::StorageKey::DynamicKeymapMacro,
-                )
-                .await
+        loop {
+            let operation = match select::select(
+                OPERATION_CHANNEL.receive(),
+                crate::storage::wait_for_profile_change(&VIA_PROFILE_LISTENER),
+            )
+            .await
             {
-                if let Some(macro_data) = K::get_macro_buffer() {
-                    macro_data.update_buffer(0, &stored_data[..stored_len])
+                Either::First(operation) => operation,
+                Either::Second(new_profile) => {
+                    if new_profile != profile {
+                        // Start from the original layout and an empty macro buffer, so that
+                        // nothing from the previous profile is kept
+                        profile = new_profile;
+                        super::handlers::dynamic_keymap_reset::<K>().await;
+                        super::handlers::dynamic_keymap_macro_reset::<K>();
+                        load_profile::<K, F>(database, profile).await;
+                    }
+                    continue;
                 }
             };
-        }
 
-        loop {
-            match OPERATION_CHANNEL.receive().await {
+            match operation {
                 Operation::Write(data, key, offset, len) => {
                     match key {
                         ViaStorageKeys::LayoutOptions => {
@@ -410,7 +602,7 @@ pub mod storage {
                             };
                         }
                         ViaStorageKeys::DynamicKeymap => {
-                            let key = key.into();
+                            let key = StorageKey::from(key).in_profile(profile);
                             let mut buf = [0; K::DYNAMIC_KEYMAP_LAYER_COUNT
                                 * K::LAYOUT_COLS
                                 * K::LAYOUT_ROWS
@@ -436,7 +628,7 @@ pub mod storage {
                             };
                         }
                         ViaStorageKeys::DynamicKeymapMacro => {
-                            let key = key.into();
+                            let key = StorageKey::from(key).in_profile(profile);
                             let mut buf = [0; K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize];
 
                             // Read data
@@ -465,7 +657,7 @@ pub mod storage {
                             };
                         }
                         ViaStorageKeys::DynamicKeymapEncoder => {
-                            let key = key.into();
+                            let key = StorageKey::from(key).in_profile(profile);
                             let mut buf =
                                 [0; K::DYNAMIC_KEYMAP_LAYER_COUNT * K::NUM_ENCODERS * 2 * 2];
 
@@ -492,9 +684,15 @@ pub mod storage {
                 }
                 Operation::Delete => {
                     let _ = database.delete(StorageKey::LayoutOptions).await;
-                    let _ = database.delete(StorageKey::DynamicKeymap).await;
-                    let _ = database.delete(StorageKey::DynamicKeymapMacro).await;
-                    let _ = database.delete(StorageKey::DynamicKeymapEncoder).await;
+                    let _ = database
+                        .delete(StorageKey::DynamicKeymap.in_profile(profile))
+                        .await;
+                    let _ = database
+                        .delete(StorageKey::DynamicKeymapMacro.in_profile(profile))
+                        .await;
+                    let _ = database
+                        .delete(StorageKey::DynamicKeymapEncoder.in_profile(profile))
+                        .await;
                 }
             }
 
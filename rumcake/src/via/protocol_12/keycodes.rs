@@ -35,7 +35,7 @@ enum QMKKeycodeRanges {
     QK_LAYER_TAP_TOGGLE_MAX = 0x52DF,
     QK_SWAP_HANDS = 0x5600, // TODO: unhandled
     QK_SWAP_HANDS_MAX = 0x56FF,
-    QK_TAP_DANCE = 0x5700, // TODO: unhandled, switch to kanata keyberon fork
+    QK_TAP_DANCE = 0x5700,
     QK_TAP_DANCE_MAX = 0x57FF,
     QK_MAGIC = 0x7000, // TODO: unhandled
     QK_MAGIC_MAX = 0x70FF,
@@ -63,7 +63,7 @@ enum QMKKeycodeRanges {
     QK_USER_MAX = 0x7FFF,
     // QK_UNICODEMAP = 0x8000, // same as QK_UNICODE
     QK_UNICODEMAP_MAX = 0xBFFF,
-    QK_UNICODE = 0x8000, // TODO: unhandled
+    QK_UNICODE = 0x8000,
     QK_UNICODE_MAX = 0xFFFF,
     QK_UNICODEMAP_PAIR = 0xC000,
     // QK_UNICODEMAP_PAIR_MAX = 0xFFFF, // same as QK_UNICODE_MAX
@@ -269,25 +269,25 @@ enum QMKKeycodes {
     KC_ASSISTANT = 0x00C0,       // TODO: unhandled
     KC_MISSION_CONTROL = 0x00C1, // TODO: unhandled
     KC_LAUNCHPAD = 0x00C2,       // TODO: unhandled
-    KC_MS_UP = 0x00CD,           // TODO: unhandled
-    KC_MS_DOWN = 0x00CE,         // TODO: unhandled
-    KC_MS_LEFT = 0x00CF,         // TODO: unhandled
-    KC_MS_RIGHT = 0x00D0,        // TODO: unhandled
-    KC_MS_BTN1 = 0x00D1,         // TODO: unhandled
-    KC_MS_BTN2 = 0x00D2,         // TODO: unhandled
-    KC_MS_BTN3 = 0x00D3,         // TODO: unhandled
-    KC_MS_BTN4 = 0x00D4,         // TODO: unhandled
-    KC_MS_BTN5 = 0x00D5,         // TODO: unhandled
-    KC_MS_BTN6 = 0x00D6,         // TODO: unhandled
-    KC_MS_BTN7 = 0x00D7,         // TODO: unhandled
-    KC_MS_BTN8 = 0x00D8,         // TODO: unhandled
-    KC_MS_WH_UP = 0x00D9,        // TODO: unhandled
-    KC_MS_WH_DOWN = 0x00DA,      // TODO: unhandled
-    KC_MS_WH_LEFT = 0x00DB,      // TODO: unhandled
-    KC_MS_WH_RIGHT = 0x00DC,     // TODO: unhandled
-    KC_MS_ACCEL0 = 0x00DD,       // TODO: unhandled
-    KC_MS_ACCEL1 = 0x00DE,       // TODO: unhandled
-    KC_MS_ACCEL2 = 0x00DF,       // TODO: unhandled
+    KC_MS_UP = 0x00CD,
+    KC_MS_DOWN = 0x00CE,
+    KC_MS_LEFT = 0x00CF,
+    KC_MS_RIGHT = 0x00D0,
+    KC_MS_BTN1 = 0x00D1,
+    KC_MS_BTN2 = 0x00D2,
+    KC_MS_BTN3 = 0x00D3,
+    KC_MS_BTN4 = 0x00D4,
+    KC_MS_BTN5 = 0x00D5,
+    KC_MS_BTN6 = 0x00D6,
+    KC_MS_BTN7 = 0x00D7,
+    KC_MS_BTN8 = 0x00D8,
+    KC_MS_WH_UP = 0x00D9,
+    KC_MS_WH_DOWN = 0x00DA,
+    KC_MS_WH_LEFT = 0x00DB,
+    KC_MS_WH_RIGHT = 0x00DC,
+    KC_MS_ACCEL0 = 0x00DD,
+    KC_MS_ACCEL1 = 0x00DE,
+    KC_MS_ACCEL2 = 0x00DF,
     // 0xA5-0xDF end (these values are reserved, but used by QMK for consumer-related keycodes)
     KC_LEFT_CTRL = 0x00E0,
     KC_LEFT_SHIFT = 0x00E1,
@@ -938,7 +938,8 @@ where
                 UNKNOWN_KEYCODE
             }
         }
-        Action::HoldTap(_) => todo!(),
+        // TODO: hold-tap actions can't be represented as a QMK keycode yet
+        Action::HoldTap(_) => UNKNOWN_KEYCODE,
         Action::OneShot(&OneShotAction {
             action: keyberon::action::Action::Layer(layer),
             ..
@@ -970,6 +971,57 @@ where
                     UNKNOWN_KEYCODE
                 }
             }
+            Keycode::Leader => QMKKeycodes::QK_LEADER as u16,
+            Keycode::TapDance(idx) => QMKKeycodeRanges::QK_TAP_DANCE as u16 + idx as u16,
+            #[cfg(feature = "unicode")]
+            Keycode::Unicode(c) => {
+                if (c as u32) <= 0x7FFF {
+                    QMKKeycodeRanges::QK_UNICODE as u16 + c as u16
+                } else {
+                    UNKNOWN_KEYCODE
+                }
+            }
+            #[cfg(feature = "unicode")]
+            Keycode::UnicodeMode(command) => match command {
+                crate::unicode::UnicodeCommand::NextMode => {
+                    QMKKeycodes::QK_UNICODE_MODE_NEXT as u16
+                }
+                crate::unicode::UnicodeCommand::PreviousMode => {
+                    QMKKeycodes::QK_UNICODE_MODE_PREVIOUS as u16
+                }
+                crate::unicode::UnicodeCommand::SetMode(mode) => match mode {
+                    crate::unicode::UnicodeMode::MacOS => QMKKeycodes::QK_UNICODE_MODE_MACOS as u16,
+                    crate::unicode::UnicodeMode::Linux => QMKKeycodes::QK_UNICODE_MODE_LINUX as u16,
+                    crate::unicode::UnicodeMode::Windows => {
+                        QMKKeycodes::QK_UNICODE_MODE_WINDOWS as u16
+                    }
+                    crate::unicode::UnicodeMode::WinCompose => {
+                        QMKKeycodes::QK_UNICODE_MODE_WINCOMPOSE as u16
+                    }
+                },
+            },
+            Keycode::CapsWord => QMKKeycodes::QK_CAPS_WORD_TOGGLE as u16,
+            #[cfg(feature = "dynamic-macros")]
+            Keycode::DynamicMacroRecord(idx) => match idx {
+                0 => QMKKeycodes::QK_DYNAMIC_MACRO_RECORD_START_1 as u16,
+                1 => QMKKeycodes::QK_DYNAMIC_MACRO_RECORD_START_2 as u16,
+                _ => UNKNOWN_KEYCODE,
+            },
+            #[cfg(feature = "dynamic-macros")]
+            Keycode::DynamicMacroStop => QMKKeycodes::QK_DYNAMIC_MACRO_RECORD_STOP as u16,
+            #[cfg(feature = "dynamic-macros")]
+            Keycode::DynamicMacroPlay(idx) => match idx {
+                0 => QMKKeycodes::QK_DYNAMIC_MACRO_PLAY_1 as u16,
+                1 => QMKKeycodes::QK_DYNAMIC_MACRO_PLAY_2 as u16,
+                _ => UNKNOWN_KEYCODE,
+            },
+            Keycode::AutoShift(command) => match command {
+                crate::auto_shift::AutoShiftCommand::On => QMKKeycodes::QK_AUTO_SHIFT_ON as u16,
+                crate::auto_shift::AutoShiftCommand::Off => QMKKeycodes::QK_AUTO_SHIFT_OFF as u16,
+                crate::auto_shift::AutoShiftCommand::Toggle => {
+                    QMKKeycodes::QK_AUTO_SHIFT_TOGGLE as u16
+                }
+            },
             #[cfg(feature = "media-keycodes")]
             Keycode::Media(keycode) => match keycode {
                 usbd_human_interface_device::page::Consumer::Power => {
@@ -1041,6 +1093,8 @@ where
                 }
                 _ => UNKNOWN_KEYCODE,
             },
+            #[cfg(feature = "mouse-keys")]
+            Keycode::Mouse(keycode) => QMKKeycodes::KC_MS_UP as u16 + keycode as u16,
             #[cfg(feature = "underglow")]
             Keycode::Underglow(command) => match command {
                 crate::underglow::animations::UnderglowCommand::Toggle => {
@@ -1389,6 +1443,18 @@ where
                     }
                 }
 
+                #[cfg(feature = "mouse-keys")]
+                {
+                    if QMKKeycodes::KC_MS_UP as u16 <= keycode
+                        && keycode <= QMKKeycodes::KC_MS_ACCEL2 as u16
+                    {
+                        return num::FromPrimitive::from_u16(
+                            keycode - QMKKeycodes::KC_MS_UP as u16,
+                        )
+                        .map(|keycode| Action::Custom(Keycode::Mouse(keycode)));
+                    }
+                }
+
                 None
             },
             |k| Some(Action::KeyCode(k)),
@@ -1438,6 +1504,14 @@ where
         });
     }
 
+    if QMKKeycodeRanges::QK_TAP_DANCE as u16 <= keycode
+        && keycode <= QMKKeycodeRanges::QK_TAP_DANCE_MAX as u16
+    {
+        let tap_dance_number = (keycode - QMKKeycodeRanges::QK_TAP_DANCE as u16) as usize;
+        return (tap_dance_number < K::NUM_TAP_DANCES)
+            .then_some(Action::Custom(Keycode::TapDance(tap_dance_number as u8)));
+    }
+
     if QMKKeycodeRanges::QK_LIGHTING as u16 <= keycode
         && keycode <= QMKKeycodeRanges::QK_LIGHTING_MAX as u16
     {
@@ -1702,8 +1776,101 @@ where
     }
 
     if QMKKeycodeRanges::QK_QUANTUM as u16 <= keycode
-        && keycode <= QMKKeycodeRanges::QK_QUANTUM as u16
+        && keycode <= QMKKeycodeRanges::QK_QUANTUM_MAX as u16
     {
+        if keycode == QMKKeycodes::QK_CAPS_WORD_TOGGLE as u16 {
+            return Some(Action::Custom(Keycode::CapsWord));
+        }
+
+        if keycode == QMKKeycodes::QK_LEADER as u16 {
+            return Some(Action::Custom(Keycode::Leader));
+        }
+
+        #[cfg(feature = "dynamic-macros")]
+        if keycode == QMKKeycodes::QK_DYNAMIC_MACRO_RECORD_START_1 as u16 {
+            return Some(Action::Custom(Keycode::DynamicMacroRecord(0)));
+        }
+
+        #[cfg(feature = "dynamic-macros")]
+        if keycode == QMKKeycodes::QK_DYNAMIC_MACRO_RECORD_START_2 as u16 {
+            return Some(Action::Custom(Keycode::DynamicMacroRecord(1)));
+        }
+
+        #[cfg(feature = "dynamic-macros")]
+        if keycode == QMKKeycodes::QK_DYNAMIC_MACRO_RECORD_STOP as u16 {
+            return Some(Action::Custom(Keycode::DynamicMacroStop));
+        }
+
+        #[cfg(feature = "dynamic-macros")]
+        if keycode == QMKKeycodes::QK_DYNAMIC_MACRO_PLAY_1 as u16 {
+            return Some(Action::Custom(Keycode::DynamicMacroPlay(0)));
+        }
+
+        #[cfg(feature = "dynamic-macros")]
+        if keycode == QMKKeycodes::QK_DYNAMIC_MACRO_PLAY_2 as u16 {
+            return Some(Action::Custom(Keycode::DynamicMacroPlay(1)));
+        }
+
+        #[cfg(feature = "unicode")]
+        if keycode == QMKKeycodes::QK_UNICODE_MODE_NEXT as u16 {
+            return Some(Action::Custom(Keycode::UnicodeMode(
+                crate::unicode::UnicodeCommand::NextMode,
+            )));
+        }
+
+        #[cfg(feature = "unicode")]
+        if keycode == QMKKeycodes::QK_UNICODE_MODE_PREVIOUS as u16 {
+            return Some(Action::Custom(Keycode::UnicodeMode(
+                crate::unicode::UnicodeCommand::PreviousMode,
+            )));
+        }
+
+        #[cfg(feature = "unicode")]
+        if keycode == QMKKeycodes::QK_UNICODE_MODE_MACOS as u16 {
+            return Some(Action::Custom(Keycode::UnicodeMode(
+                crate::unicode::UnicodeCommand::SetMode(crate::unicode::UnicodeMode::MacOS),
+            )));
+        }
+
+        #[cfg(feature = "unicode")]
+        if keycode == QMKKeycodes::QK_UNICODE_MODE_LINUX as u16 {
+            return Some(Action::Custom(Keycode::UnicodeMode(
+                crate::unicode::UnicodeCommand::SetMode(crate::unicode::UnicodeMode::Linux),
+            )));
+        }
+
+        #[cfg(feature = "unicode")]
+        if keycode == QMKKeycodes::QK_UNICODE_MODE_WINDOWS as u16 {
+            return Some(Action::Custom(Keycode::UnicodeMode(
+                crate::unicode::UnicodeCommand::SetMode(crate::unicode::UnicodeMode::Windows),
+            )));
+        }
+
+        #[cfg(feature = "unicode")]
+        if keycode == QMKKeycodes::QK_UNICODE_MODE_WINCOMPOSE as u16 {
+            return Some(Action::Custom(Keycode::UnicodeMode(
+                crate::unicode::UnicodeCommand::SetMode(crate::unicode::UnicodeMode::WinCompose),
+            )));
+        }
+
+        if keycode == QMKKeycodes::QK_AUTO_SHIFT_ON as u16 {
+            return Some(Action::Custom(Keycode::AutoShift(
+                crate::auto_shift::AutoShiftCommand::On,
+            )));
+        }
+
+        if keycode == QMKKeycodes::QK_AUTO_SHIFT_OFF as u16 {
+            return Some(Action::Custom(Keycode::AutoShift(
+                crate::auto_shift::AutoShiftCommand::Off,
+            )));
+        }
+
+        if keycode == QMKKeycodes::QK_AUTO_SHIFT_TOGGLE as u16 {
+            return Some(Action::Custom(Keycode::AutoShift(
+                crate::auto_shift::AutoShiftCommand::Toggle,
+            )));
+        }
+
         #[cfg(all(feature = "usb", feature = "bluetooth"))]
         if keycode == QMKKeycodes::QK_OUTPUT_USB as u16 {
             return Some(Action::Custom(Keycode::Bluetooth(
@@ -1719,6 +1886,15 @@ where
         }
     }
 
+    // Unicode characters are limited to 15 bits, like QMK's `UC()` keycodes
+    #[cfg(feature = "unicode")]
+    if QMKKeycodeRanges::QK_UNICODE as u16 <= keycode
+        && keycode <= QMKKeycodeRanges::QK_UNICODE_MAX as u16
+    {
+        return char::from_u32((keycode - QMKKeycodeRanges::QK_UNICODE as u16) as u32)
+            .map(|c| Action::Custom(Keycode::Unicode(c)));
+    }
+
     if QMKKeycodeRanges::QK_KB as u16 <= keycode && keycode <= QMKKeycodeRanges::QK_KB_MAX as u16 {
         return Some(Action::Custom(Keycode::Custom(
             (keycode - QMKKeycodeRanges::QK_KB as u16) as u8,
@@ -59,6 +59,7 @@ enum ViaChannelId {
     RGBMatrix,
     // Audio, // unused
     LEDMatrix = 5,
+    ConfigBackup = 0x80, // rumcake-specific, used to back up and restore stored data
 }
 
 #[derive(FromPrimitive, Debug)]
@@ -87,6 +88,17 @@ enum ViaRGBMatrixValue {
     Enabled,
 }
 
+// To create a backup, the host sets `Export`, checks `Status`, then reads `Size` bytes of `Data`.
+// To restore a backup, the host sets `Data`, then sets `Import` and checks `Status`.
+#[derive(FromPrimitive, Debug)]
+enum ViaConfigBackupValue {
+    Status = 1,
+    Size,
+    Data,
+    Export,
+    Import,
+}
+
 #[derive(FromPrimitive, Debug)]
 enum ViaLEDMatrixValue {
     Brightness = 1,
@@ -102,6 +114,8 @@ where
     pub(crate) layout_state:
         [u8; (K::LAYOUT_COLS + u8::BITS as usize - 1) / u8::BITS as usize * K::LAYOUT_ROWS],
     pub(crate) layout_options: u32,
+    #[cfg(feature = "storage")]
+    pub(crate) backup_len: Option<usize>,
 }
 
 impl<K: ViaKeyboard> Default for ViaState<K>
@@ -113,6 +127,8 @@ where
             layout_state: [0; (K::LAYOUT_COLS + u8::BITS as usize - 1) / u8::BITS as usize
                 * K::LAYOUT_ROWS],
             layout_options: K::VIA_EEPROM_LAYOUT_OPTIONS_DEFAULT,
+            #[cfg(feature = "storage")]
+            backup_len: None,
         }
     }
 }
@@ -240,14 +256,27 @@ pub(crate) async fn process_via_command<K: ViaKeyboard + 'static>(
                 let layer = data[1];
                 let encoder_id = data[2];
                 let clockwise = data[3] != 0;
-                dynamic_keymap_get_encoder::<K>(layer, encoder_id, clockwise, &mut data[4..=5])
-                    .await
+                dynamic_keymap_get_encoder::<K>(
+                    layer,
+                    encoder_id,
+                    clockwise,
+                    &mut data[4..=5],
+                    keycodes::convert_action_to_keycode::<K>,
+                )
+                .await
             } // only if encoder map is enabled
             ViaCommandId::DynamicKeymapSetEncoder => {
                 let layer = data[1];
                 let encoder_id = data[2];
                 let clockwise = data[3] != 0;
-                dynamic_keymap_set_encoder::<K>(layer, encoder_id, clockwise, &data[4..=5]).await
+                dynamic_keymap_set_encoder::<K>(
+                    layer,
+                    encoder_id,
+                    clockwise,
+                    &data[4..=5],
+                    keycodes::convert_keycode_to_action::<K>,
+                )
+                .await
             } // only if encoder map is enabled
             ViaCommandId::DynamicKeymapGetBuffer => {
                 let offset = u16::from_be_bytes(data[1..=2].try_into().unwrap());
@@ -537,6 +566,63 @@ pub(crate) async fn process_via_command<K: ViaKeyboard + 'static>(
                             _ => unreachable!("Should not happen"),
                         };
                     }
+                    #[cfg(feature = "storage")]
+                    Some(ViaChannelId::ConfigBackup) => {
+                        match command {
+                            ViaCommandId::CustomGetValue => {
+                                match num::FromPrimitive::from_u8(data[2]) {
+                                    Some(ViaConfigBackupValue::Status) => {
+                                        config_backup_get_status(
+                                            via_state.backup_len,
+                                            &mut data[3..=3],
+                                        );
+                                    }
+                                    Some(ViaConfigBackupValue::Size) => {
+                                        config_backup_get_size(
+                                            via_state.backup_len,
+                                            &mut data[3..=4],
+                                        );
+                                    }
+                                    Some(ViaConfigBackupValue::Data) => {
+                                        config_backup_get_data(
+                                            via_state.backup_len,
+                                            &mut data[3..],
+                                        )
+                                        .await;
+                                    }
+                                    _ => {
+                                        warn!(
+                                            "[VIA] Unknown config backup get command received from host {:?}",
+                                            data[2]
+                                        )
+                                    }
+                                };
+                            }
+                            ViaCommandId::CustomSetValue => {
+                                match num::FromPrimitive::from_u8(data[2]) {
+                                    Some(ViaConfigBackupValue::Data) => {
+                                        config_backup_set_data(&data[3..]).await;
+                                    }
+                                    Some(ViaConfigBackupValue::Export) => {
+                                        config_backup_export(&mut via_state.backup_len, &data[3..])
+                                            .await;
+                                    }
+                                    Some(ViaConfigBackupValue::Import) => {
+                                        config_backup_import(&mut via_state.backup_len, &data[3..])
+                                            .await;
+                                    }
+                                    _ => {
+                                        warn!(
+                                            "[VIA] Unknown config backup set command received from host {:?}",
+                                            data[2]
+                                        )
+                                    }
+                                };
+                            }
+                            ViaCommandId::CustomSave => {}
+                            _ => unreachable!("Should not happen"),
+                        };
+                    }
                     other => {
                         match other {
                             Some(channel) => {
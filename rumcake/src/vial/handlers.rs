@@ -1,10 +1,19 @@
 use defmt::warn;
+use keyberon::action::Action;
 use smart_leds::hsv::hsv2rgb;
+use usbd_human_interface_device::page::Keyboard as KeyboardKeycode;
 
 use super::protocol::via::ViaState;
-use super::protocol::{VialState, VIAL_RAW_EPSIZE};
+use super::protocol::{QmkSettingId, VialState, VIAL_RAW_EPSIZE};
 use super::{VialKeyboard, VIAL_DIRECT_SET_CHANNEL};
 use crate::backlight::BacklightMatrixDevice;
+use crate::combos::MAX_COMBO_KEYS;
+use crate::key_overrides::{KeyOverride, KeyOverrideOptions, ModifierMask};
+use crate::keyboard::Keycode;
+use crate::settings::{
+    HoldTapMode, KeyboardSettings, DEFAULT_AUTO_SHIFT_TIMEOUT, KEYBOARD_SETTINGS_STATE,
+};
+use crate::tap_dance::{TapDanceEntry, DEFAULT_TAPPING_TERM};
 
 // Unlike the other normal Via comands, Vial overwrites the command data received from the host
 
@@ -273,21 +282,127 @@ where
     }
 }
 
+// Debounce is not listed, since Vial does not have a QMK setting for it
+const QMK_SETTINGS: [QmkSettingId; 7] = [
+    QmkSettingId::ComboTerm,
+    QmkSettingId::AutoShift,
+    QmkSettingId::AutoShiftTimeout,
+    QmkSettingId::OneShotTapToggle,
+    QmkSettingId::OneShotTimeout,
+    QmkSettingId::TappingTerm,
+    QmkSettingId::Tapping,
+];
+
+// Bits used in the value of the `Tapping` QMK setting
+const QMK_TAPPING_PERMISSIVE_HOLD: u8 = 0b00000001;
+const QMK_TAPPING_HOLD_ON_OTHER_KEY_PRESS: u8 = 0b00010000;
+
+// Bits used in the value of the `AutoShift` QMK setting
+const QMK_AUTO_SHIFT_ENABLE: u8 = 0b00000001;
+
 pub fn qmk_settings_query(data: &mut [u8]) {
-    // TODO: maybe support some QMK settings
-    data.fill(0xFF); // This indicates that we don't support any QMK settings
+    let qsid_greater_than = u16::from_le_bytes(data[2..=3].try_into().unwrap());
+    data.fill(0xFF); // 0xFFFF marks the end of the list
+
+    for (qsid, entry) in QMK_SETTINGS
+        .iter()
+        .filter(|qsid| **qsid as u16 > qsid_greater_than)
+        .zip(data.chunks_exact_mut(2))
+    {
+        entry.copy_from_slice(&(*qsid as u16).to_le_bytes());
+    }
 }
 
-pub fn qmk_settings_get(data: &mut [u8]) {
-    // TODO: maybe support some QMK settings
+pub async fn qmk_settings_get<K: VialKeyboard>(data: &mut [u8]) {
+    let qsid = u16::from_le_bytes(data[2..=3].try_into().unwrap());
+    let settings = KEYBOARD_SETTINGS_STATE.get().await;
+    data[0] = 0x00;
+
+    match num::FromPrimitive::from_u16(qsid) {
+        Some(QmkSettingId::ComboTerm) => {
+            let combo_term = settings.combo_term.unwrap_or(K::COMBO_TIMEOUT_MS);
+            data[1..=2].copy_from_slice(&combo_term.to_le_bytes());
+        }
+        Some(QmkSettingId::AutoShift) => {
+            data[1] = if settings.auto_shift.unwrap_or_default() {
+                QMK_AUTO_SHIFT_ENABLE
+            } else {
+                0
+            };
+        }
+        Some(QmkSettingId::AutoShiftTimeout) => {
+            let timeout = settings
+                .auto_shift_timeout
+                .unwrap_or(DEFAULT_AUTO_SHIFT_TIMEOUT);
+            data[1..=2].copy_from_slice(&timeout.to_le_bytes());
+        }
+        Some(QmkSettingId::OneShotTapToggle) => {
+            data[1] = settings.oneshot_tap_toggle.unwrap_or_default();
+        }
+        Some(QmkSettingId::OneShotTimeout) => {
+            let timeout = settings.oneshot_timeout.unwrap_or_default();
+            data[1..=2].copy_from_slice(&timeout.to_le_bytes());
+        }
+        Some(QmkSettingId::TappingTerm) => {
+            let tapping_term = settings.tapping_term.unwrap_or(DEFAULT_TAPPING_TERM);
+            data[1..=2].copy_from_slice(&tapping_term.to_le_bytes());
+        }
+        Some(QmkSettingId::Tapping) => {
+            data[1] = match settings.hold_tap_mode {
+                Some(HoldTapMode::PermissiveHold) => QMK_TAPPING_PERMISSIVE_HOLD,
+                Some(HoldTapMode::HoldOnOtherKeyPress) => QMK_TAPPING_HOLD_ON_OTHER_KEY_PRESS,
+                Some(HoldTapMode::Default) | None => 0,
+            };
+        }
+        None => {
+            warn!("[VIAL] Unknown QMK setting: {}", qsid);
+            data[0] = 0xFF;
+        }
+    }
 }
 
-pub fn qmk_settings_set(data: &mut [u8]) {
-    // TODO: maybe support some QMK settings
+pub async fn qmk_settings_set(data: &mut [u8]) {
+    let qsid = u16::from_le_bytes(data[2..=3].try_into().unwrap());
+    let value = u16::from_le_bytes(data[4..=5].try_into().unwrap());
+    let flags = data[4];
+
+    let supported = KEYBOARD_SETTINGS_STATE
+        .update(|settings| {
+            match num::FromPrimitive::from_u16(qsid) {
+                Some(QmkSettingId::ComboTerm) => settings.combo_term = Some(value),
+                Some(QmkSettingId::AutoShift) => {
+                    settings.auto_shift = Some(flags & QMK_AUTO_SHIFT_ENABLE != 0)
+                }
+                Some(QmkSettingId::AutoShiftTimeout) => settings.auto_shift_timeout = Some(value),
+                Some(QmkSettingId::OneShotTapToggle) => settings.oneshot_tap_toggle = Some(flags),
+                Some(QmkSettingId::OneShotTimeout) => settings.oneshot_timeout = Some(value),
+                Some(QmkSettingId::TappingTerm) => settings.tapping_term = Some(value),
+                Some(QmkSettingId::Tapping) => {
+                    settings.hold_tap_mode =
+                        Some(if flags & QMK_TAPPING_HOLD_ON_OTHER_KEY_PRESS != 0 {
+                            HoldTapMode::HoldOnOtherKeyPress
+                        } else if flags & QMK_TAPPING_PERMISSIVE_HOLD != 0 {
+                            HoldTapMode::PermissiveHold
+                        } else {
+                            HoldTapMode::Default
+                        })
+                }
+                None => return false,
+            };
+            true
+        })
+        .await;
+
+    if supported {
+        data[0] = 0x00;
+    } else {
+        warn!("[VIAL] Unknown QMK setting: {}", qsid);
+        data[0] = 0xFF;
+    }
 }
 
-pub fn qmk_settings_reset(data: &mut [u8]) {
-    // TODO: maybe support some QMK settings
+pub async fn qmk_settings_reset() {
+    KEYBOARD_SETTINGS_STATE.set(KeyboardSettings::new()).await;
 }
 
 pub fn dynamic_keymap_get_number_of_entries<K: VialKeyboard>(data: &mut [u8]) {
@@ -297,28 +412,264 @@ pub fn dynamic_keymap_get_number_of_entries<K: VialKeyboard>(data: &mut [u8]) {
     data[2] = K::VIAL_KEY_OVERRIDE_ENTRIES;
 }
 
-pub fn dynamic_keymap_get_tap_dance(data: &mut [u8]) {
-    // TODO
+// Tap dance entries are made up of 4 keycodes (on tap, on hold, on double tap, on tap hold),
+// followed by a tapping term
+const VIAL_TAP_DANCE_ENTRY_SIZE: usize = 5 * 2;
+
+pub async fn dynamic_keymap_get_tap_dance<K: VialKeyboard + 'static>(
+    data: &mut [u8],
+    convert_action_to_keycode: impl Fn(Action<Keycode>) -> u16,
+) where
+    [(); K::NUM_TAP_DANCES]:,
+{
+    let idx = data[3] as usize;
+    let entry = match K::get_tap_dances() {
+        Some(tap_dances) => tap_dances.lock().await.get(idx).copied(),
+        None => None,
+    };
+
+    data[1..=VIAL_TAP_DANCE_ENTRY_SIZE].fill(0);
+
+    if let Some(entry) = entry {
+        data[0] = 0;
+        data[1..=2].copy_from_slice(&convert_action_to_keycode(entry.on_tap).to_le_bytes());
+        data[3..=4].copy_from_slice(&convert_action_to_keycode(entry.on_hold).to_le_bytes());
+        data[5..=6].copy_from_slice(&convert_action_to_keycode(entry.on_double_tap).to_le_bytes());
+        data[7..=8].copy_from_slice(&convert_action_to_keycode(entry.on_tap_hold).to_le_bytes());
+        data[9..=10].copy_from_slice(&entry.tapping_term.to_le_bytes());
+    } else {
+        data[0] = 0xFF;
+        warn!("[VIAL] Requested a tap dance that is out of bounds.")
+    }
 }
 
-pub fn dynamic_keymap_set_tap_dance(data: &mut [u8]) {
-    // TODO
+pub async fn dynamic_keymap_set_tap_dance<K: VialKeyboard + 'static>(
+    data: &mut [u8],
+    convert_keycode_to_action: impl Fn(u16) -> Option<Action<Keycode>>,
+) where
+    [(); K::NUM_TAP_DANCES]:,
+{
+    let idx = data[3] as usize;
+    let entry = &data[4..(4 + VIAL_TAP_DANCE_ENTRY_SIZE)];
+
+    let action = |offset: usize| {
+        convert_keycode_to_action(u16::from_le_bytes(
+            entry[offset..(offset + 2)].try_into().unwrap(),
+        ))
+        .unwrap_or(Action::NoOp)
+    };
+
+    let mut updated = false;
+    if let Some(tap_dances) = K::get_tap_dances() {
+        if let Some(tap_dance) = tap_dances.lock().await.get_mut(idx) {
+            *tap_dance = TapDanceEntry {
+                on_tap: action(0),
+                on_hold: action(2),
+                on_double_tap: action(4),
+                on_tap_hold: action(6),
+                tapping_term: u16::from_le_bytes(entry[8..10].try_into().unwrap()),
+            };
+            updated = true;
+        }
+    }
+
+    if !updated {
+        data[0] = 0xFF;
+        warn!("[VIAL] Attempted to set a tap dance that is out of bounds.");
+        return;
+    }
+
+    #[cfg(feature = "storage")]
+    {
+        let mut buf = [0; VIAL_TAP_DANCE_ENTRY_SIZE];
+        buf.copy_from_slice(entry);
+        super::storage::update_data(
+            super::storage::VialStorageKeys::DynamicKeymapTapDance,
+            idx * VIAL_TAP_DANCE_ENTRY_SIZE,
+            &buf,
+        )
+        .await;
+    }
+
+    data[0] = 0;
 }
 
-pub fn dynamic_keymap_get_combo(data: &mut [u8]) {
-    // TODO
+// Combo entries are made up of 4 input keycodes, followed by an output keycode
+const VIAL_COMBO_ENTRY_SIZE: usize = (MAX_COMBO_KEYS + 1) * 2;
+
+pub async fn dynamic_keymap_get_combo<K: VialKeyboard + 'static>(
+    data: &mut [u8],
+    convert_action_to_keycode: impl Fn(Action<Keycode>) -> u16,
+) where
+    [(); K::NUM_COMBOS]:,
+{
+    let idx = data[3] as usize;
+    let combo = match K::get_combos() {
+        Some(combos) => combos.lock().await.get(idx).copied(),
+        None => None,
+    };
+
+    data[1..=VIAL_COMBO_ENTRY_SIZE].fill(0);
+
+    if let Some(combo) = combo {
+        data[0] = 0;
+        let entry = &mut data[1..=VIAL_COMBO_ENTRY_SIZE];
+        for (i, action) in combo.keys.iter().chain([&combo.output]).enumerate() {
+            entry[(i * 2)..(i * 2 + 2)]
+                .copy_from_slice(&convert_action_to_keycode(*action).to_le_bytes());
+        }
+    } else {
+        data[0] = 0xFF;
+        warn!("[VIAL] Requested a combo that is out of bounds.")
+    }
+}
+
+pub async fn dynamic_keymap_set_combo<K: VialKeyboard + 'static>(
+    data: &mut [u8],
+    convert_keycode_to_action: impl Fn(u16) -> Option<Action<Keycode>>,
+) where
+    [(); K::NUM_COMBOS]:,
+{
+    let idx = data[3] as usize;
+    let entry = &data[4..(4 + VIAL_COMBO_ENTRY_SIZE)];
+
+    let mut updated = false;
+    if let Some(combos) = K::get_combos() {
+        if let Some(combo) = combos.lock().await.get_mut(idx) {
+            let mut actions = entry.chunks_exact(2).map(|keycode| {
+                convert_keycode_to_action(u16::from_le_bytes(keycode.try_into().unwrap()))
+                    .unwrap_or(Action::NoOp)
+            });
+            for key in combo.keys.iter_mut() {
+                *key = actions.next().unwrap();
+            }
+            combo.output = actions.next().unwrap();
+            updated = true;
+        }
+    }
+
+    if !updated {
+        data[0] = 0xFF;
+        warn!("[VIAL] Attempted to set a combo that is out of bounds.");
+        return;
+    }
+
+    #[cfg(feature = "storage")]
+    {
+        let mut buf = [0; VIAL_COMBO_ENTRY_SIZE];
+        buf.copy_from_slice(entry);
+        super::storage::update_data(
+            super::storage::VialStorageKeys::DynamicKeymapCombo,
+            idx * VIAL_COMBO_ENTRY_SIZE,
+            &buf,
+        )
+        .await;
+    }
+
+    data[0] = 0;
 }
 
-pub fn dynamic_keymap_set_combo(data: &mut [u8]) {
-    // TODO
+// Key override entries are made up of a trigger keycode, replacement keycode, layer mask, trigger
+// mods, negative mod mask, suppressed mods, and options
+pub(super) const VIAL_KEY_OVERRIDE_ENTRY_SIZE: usize = 10;
+
+fn basic_keycode_to_u16(keycode: KeyboardKeycode) -> u16 {
+    if keycode == KeyboardKeycode::NoEventIndicated {
+        0
+    } else {
+        keycode as u8 as u16
+    }
 }
 
-pub fn dynamic_keymap_get_key_override(data: &mut [u8]) {
-    // TODO
+fn u16_to_basic_keycode(keycode: u16) -> KeyboardKeycode {
+    u8::try_from(keycode)
+        .ok()
+        .and_then(|keycode| KeyboardKeycode::try_from(keycode).ok())
+        .unwrap_or(KeyboardKeycode::NoEventIndicated)
 }
 
-pub fn dynamic_keymap_set_key_override(data: &mut [u8]) {
-    // TODO
+pub(super) fn key_override_to_vial_entry(
+    key_override: &KeyOverride,
+) -> [u8; VIAL_KEY_OVERRIDE_ENTRY_SIZE] {
+    let mut entry = [0; VIAL_KEY_OVERRIDE_ENTRY_SIZE];
+    entry[0..=1].copy_from_slice(&basic_keycode_to_u16(key_override.trigger).to_le_bytes());
+    entry[2..=3].copy_from_slice(&basic_keycode_to_u16(key_override.replacement).to_le_bytes());
+    entry[4..=5].copy_from_slice(&key_override.layers.to_le_bytes());
+    entry[6] = key_override.trigger_mods.bits();
+    entry[7] = key_override.negative_mod_mask.bits();
+    entry[8] = key_override.suppressed_mods.bits();
+    entry[9] = key_override.options.bits();
+    entry
+}
+
+pub(super) fn vial_entry_to_key_override(entry: &[u8]) -> KeyOverride {
+    KeyOverride {
+        trigger: u16_to_basic_keycode(u16::from_le_bytes(entry[0..=1].try_into().unwrap())),
+        replacement: u16_to_basic_keycode(u16::from_le_bytes(entry[2..=3].try_into().unwrap())),
+        layers: u16::from_le_bytes(entry[4..=5].try_into().unwrap()),
+        trigger_mods: ModifierMask::from_bits_retain(entry[6]),
+        negative_mod_mask: ModifierMask::from_bits_retain(entry[7]),
+        suppressed_mods: ModifierMask::from_bits_retain(entry[8]),
+        options: KeyOverrideOptions::from_bits_retain(entry[9]),
+    }
+}
+
+pub async fn dynamic_keymap_get_key_override<K: VialKeyboard + 'static>(data: &mut [u8])
+where
+    [(); K::NUM_KEY_OVERRIDES]:,
+{
+    let idx = data[3] as usize;
+    let key_override = match K::get_key_overrides() {
+        Some(key_overrides) => key_overrides.lock().await.get(idx).copied(),
+        None => None,
+    };
+
+    data[1..=VIAL_KEY_OVERRIDE_ENTRY_SIZE].fill(0);
+
+    if let Some(key_override) = key_override {
+        data[0] = 0;
+        data[1..=VIAL_KEY_OVERRIDE_ENTRY_SIZE]
+            .copy_from_slice(&key_override_to_vial_entry(&key_override));
+    } else {
+        data[0] = 0xFF;
+        warn!("[VIAL] Requested a key override that is out of bounds.")
+    }
+}
+
+pub async fn dynamic_keymap_set_key_override<K: VialKeyboard + 'static>(data: &mut [u8])
+where
+    [(); K::NUM_KEY_OVERRIDES]:,
+{
+    let idx = data[3] as usize;
+    let entry = &data[4..(4 + VIAL_KEY_OVERRIDE_ENTRY_SIZE)];
+
+    let mut updated = false;
+    if let Some(key_overrides) = K::get_key_overrides() {
+        if let Some(key_override) = key_overrides.lock().await.get_mut(idx) {
+            *key_override = vial_entry_to_key_override(entry);
+            updated = true;
+        }
+    }
+
+    if !updated {
+        data[0] = 0xFF;
+        warn!("[VIAL] Attempted to set a key override that is out of bounds.");
+        return;
+    }
+
+    #[cfg(feature = "storage")]
+    {
+        let mut buf = [0; VIAL_KEY_OVERRIDE_ENTRY_SIZE];
+        buf.copy_from_slice(entry);
+        super::storage::update_data(
+            super::storage::VialStorageKeys::DynamicKeymapKeyOverride,
+            idx * VIAL_KEY_OVERRIDE_ENTRY_SIZE,
+            &buf,
+        )
+        .await;
+    }
+
+    data[0] = 0;
 }
 
 pub async fn eeprom_reset() {
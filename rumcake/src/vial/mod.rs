@@ -42,9 +42,9 @@ pub trait VialKeyboard: ViaKeyboard {
     /// [`rgb-backlight-matrix`] feature flag enabled. To enable this, you should use
     /// [`enable_vial_rgb`] instead of implementing this yourself.
     const VIALRGB_ENABLE: bool = false;
-    const VIAL_TAP_DANCE_ENTRIES: u8 = 0; // TODO: Change when tap dance is implemented
-    const VIAL_COMBO_ENTRIES: u8 = 0; // TODO: Change when combo is implemented
-    const VIAL_KEY_OVERRIDE_ENTRIES: u8 = 0; // TODO: Change when key override is implemented
+    const VIAL_TAP_DANCE_ENTRIES: u8 = Self::NUM_TAP_DANCES as u8;
+    const VIAL_COMBO_ENTRIES: u8 = Self::NUM_COMBOS as u8;
+    const VIAL_KEY_OVERRIDE_ENTRIES: u8 = Self::NUM_KEY_OVERRIDES as u8;
 
     // TODO: replace with specialization if it doesn't cause an ICE
     type BacklightMatrixDevice: BacklightMatrixDevice = EmptyBacklightMatrix;
@@ -73,10 +73,15 @@ where
     [(); K::LAYOUT_COLS]:,
     [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
     [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
+    [(); K::NUM_COMBOS]:,
+    [(); K::NUM_KEY_OVERRIDES]:,
+    [(); K::NUM_TAP_DANCES]:,
 {
     assert!(K::DYNAMIC_KEYMAP_LAYER_COUNT <= K::LAYERS);
     assert!(K::DYNAMIC_KEYMAP_LAYER_COUNT <= 16);
     assert!(K::VIAL_UNLOCK_COMBO.len() < 15);
+    assert!(K::VIAL_COMBO_ENTRIES as usize <= K::NUM_COMBOS);
+    assert!(K::VIAL_KEY_OVERRIDE_ENTRIES as usize <= K::NUM_KEY_OVERRIDES);
     if K::get_macro_buffer().is_some() {
         assert!(
             K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE > 0,
@@ -121,19 +126,34 @@ where
         }
     };
 
-    join::join(report_fut, protocol::via::background_task::<K>(&via_state)).await;
+    join::join3(
+        report_fut,
+        protocol::via::background_task::<K>(&via_state),
+        crate::via::dynamic_macro_save_task::<K>(),
+    )
+    .await;
 }
 
 #[cfg(feature = "storage")]
 pub mod storage {
+    use defmt::warn;
     use embassy_sync::channel::Channel;
     use embassy_sync::signal::Signal;
 
+    use crate::combos::MAX_COMBO_KEYS;
     use crate::hw::mcu::RawMutex;
     use crate::storage::{FlashStorage, StorageDevice, StorageKey};
+    use crate::tap_dance::TapDanceEntry;
+    use crate::via::protocol::keycodes::{convert_action_to_keycode, convert_keycode_to_action};
 
+    use super::handlers::{
+        key_override_to_vial_entry, vial_entry_to_key_override, VIAL_KEY_OVERRIDE_ENTRY_SIZE,
+    };
     use super::VialKeyboard;
 
+    // Tap dance entries contain 4 keycodes, and a tapping term
+    const TAP_DANCE_ENTRY_SIZE: usize = 5 * 2;
+
     pub(super) enum VialStorageKeys {
         DynamicKeymapTapDance,
         DynamicKeymapCombo,
@@ -184,12 +204,20 @@ pub mod storage {
         _k: K,
         database: &crate::storage::StorageService<'static, F>,
     ) where
+        [(); K::NUM_COMBOS]:,
+        [(); K::NUM_COMBOS * (MAX_COMBO_KEYS + 1) * 2]:,
+        [(); K::NUM_TAP_DANCES]:,
+        [(); K::NUM_TAP_DANCES * TAP_DANCE_ENTRY_SIZE]:,
+        [(); K::NUM_KEY_OVERRIDES]:,
+        [(); K::NUM_KEY_OVERRIDES * VIAL_KEY_OVERRIDE_ENTRY_SIZE]:,
+        [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
+        [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
         [(); F::ERASE_SIZE]:,
     {
         // Initialize Vial data
         {
-            // let tap_dance_metadata: [u8; core::mem::size_of::<TypeId>()] = unsafe {core::mem::transmute(TypeId::of::<>())};
-            let tap_dance_metadata = [1];
+            // Initialize tap dances
+            let tap_dance_metadata = [K::NUM_TAP_DANCES as u8];
             let _ = database
                 .check_metadata(
                     K::get_storage_buffer(),
@@ -197,9 +225,68 @@ pub mod storage {
                     &tap_dance_metadata,
                 )
                 .await;
+            if let Ok((stored_data, stored_len)) = database
+                .read_raw(K::get_storage_buffer(), StorageKey::DynamicKeymapTapDance)
+                .await
+            {
+                // Load tap dances from flash
+                if let Some(tap_dances) = K::get_tap_dances() {
+                    for (tap_dance, entry) in tap_dances
+                        .lock()
+                        .await
+                        .iter_mut()
+                        .zip(stored_data[..stored_len].chunks_exact(TAP_DANCE_ENTRY_SIZE))
+                    {
+                        let action = |offset: usize| {
+                            convert_keycode_to_action::<K>(u16::from_le_bytes(
+                                entry[offset..(offset + 2)].try_into().unwrap(),
+                            ))
+                            .unwrap_or(keyberon::action::Action::NoOp)
+                        };
+
+                        *tap_dance = TapDanceEntry {
+                            on_tap: action(0),
+                            on_hold: action(2),
+                            on_double_tap: action(4),
+                            on_tap_hold: action(6),
+                            tapping_term: u16::from_le_bytes(entry[8..10].try_into().unwrap()),
+                        };
+                    }
+                }
+            } else if let Some(tap_dances) = K::get_tap_dances() {
+                // Save default tap dances to flash
+                let mut buf = [0; K::NUM_TAP_DANCES * TAP_DANCE_ENTRY_SIZE];
+                for (tap_dance, entry) in tap_dances
+                    .lock()
+                    .await
+                    .iter()
+                    .zip(buf.chunks_exact_mut(TAP_DANCE_ENTRY_SIZE))
+                {
+                    for (action, keycode) in [
+                        tap_dance.on_tap,
+                        tap_dance.on_hold,
+                        tap_dance.on_double_tap,
+                        tap_dance.on_tap_hold,
+                    ]
+                    .into_iter()
+                    .zip(entry.chunks_exact_mut(2))
+                    {
+                        keycode
+                            .copy_from_slice(&convert_action_to_keycode::<K>(action).to_le_bytes());
+                    }
+                    entry[8..10].copy_from_slice(&tap_dance.tapping_term.to_le_bytes());
+                }
+                let _ = database
+                    .write_raw(
+                        K::get_storage_buffer(),
+                        StorageKey::DynamicKeymapTapDance,
+                        &buf,
+                    )
+                    .await;
+            }
 
-            // let combo_metadata: [u8; core::mem::size_of::<TypeId>()] = unsafe {core::mem::transmute(TypeId::of::<>())};
-            let combo_metadata = [1];
+            // Initialize combos
+            let combo_metadata = [MAX_COMBO_KEYS as u8, K::NUM_COMBOS as u8];
             let _ = database
                 .check_metadata(
                     K::get_storage_buffer(),
@@ -207,9 +294,58 @@ pub mod storage {
                     &combo_metadata,
                 )
                 .await;
+            if let Some(combos) = K::get_combos() {
+                let mut combos = combos.lock().await;
+                if let Ok((stored_data, stored_len)) = database
+                    .read_raw(K::get_storage_buffer(), StorageKey::DynamicKeymapCombo)
+                    .await
+                {
+                    // Load combos from flash
+                    for (combo, entry) in combos
+                        .iter_mut()
+                        .zip(stored_data[..stored_len].chunks_exact((MAX_COMBO_KEYS + 1) * 2))
+                    {
+                        let mut actions = entry.chunks_exact(2).map(|keycode| {
+                            convert_keycode_to_action::<K>(u16::from_le_bytes(
+                                keycode.try_into().unwrap(),
+                            ))
+                            .unwrap_or(keyberon::action::Action::NoOp)
+                        });
+                        for key in combo.keys.iter_mut() {
+                            *key = actions.next().unwrap();
+                        }
+                        combo.output = actions.next().unwrap();
+                    }
+                } else {
+                    // Save default combos to flash
+                    let mut buf = [0; K::NUM_COMBOS * (MAX_COMBO_KEYS + 1) * 2];
+                    for (combo, entry) in combos
+                        .iter()
+                        .zip(buf.chunks_exact_mut((MAX_COMBO_KEYS + 1) * 2))
+                    {
+                        for (action, keycode) in combo
+                            .keys
+                            .iter()
+                            .chain([&combo.output])
+                            .zip(entry.chunks_exact_mut(2))
+                        {
+                            keycode.copy_from_slice(
+                                &convert_action_to_keycode::<K>(*action).to_le_bytes(),
+                            );
+                        }
+                    }
+                    let _ = database
+                        .write_raw(
+                            K::get_storage_buffer(),
+                            StorageKey::DynamicKeymapCombo,
+                            &buf,
+                        )
+                        .await;
+                }
+            }
 
-            // let key_override_metadata: [u8; core::mem::size_of::<TypeId>()] = unsafe {core::mem::transmute(TypeId::of::<>())};
-            let key_override_metadata = [1];
+            // Initialize key overrides
+            let key_override_metadata = [K::NUM_KEY_OVERRIDES as u8];
             let _ = database
                 .check_metadata(
                     K::get_storage_buffer(),
@@ -217,14 +353,114 @@ pub mod storage {
                     &key_override_metadata,
                 )
                 .await;
+            if let Some(key_overrides) = K::get_key_overrides() {
+                let mut key_overrides = key_overrides.lock().await;
+                if let Ok((stored_data, stored_len)) = database
+                    .read_raw(
+                        K::get_storage_buffer(),
+                        StorageKey::DynamicKeymapKeyOverride,
+                    )
+                    .await
+                {
+                    // Load key overrides from flash
+                    for (key_override, entry) in key_overrides
+                        .iter_mut()
+                        .zip(stored_data[..stored_len].chunks_exact(VIAL_KEY_OVERRIDE_ENTRY_SIZE))
+                    {
+                        *key_override = vial_entry_to_key_override(entry);
+                    }
+                } else {
+                    // Save default key overrides to flash
+                    let mut buf = [0; K::NUM_KEY_OVERRIDES * VIAL_KEY_OVERRIDE_ENTRY_SIZE];
+                    for (key_override, entry) in key_overrides
+                        .iter()
+                        .zip(buf.chunks_exact_mut(VIAL_KEY_OVERRIDE_ENTRY_SIZE))
+                    {
+                        entry.copy_from_slice(&key_override_to_vial_entry(key_override));
+                    }
+                    let _ = database
+                        .write_raw(
+                            K::get_storage_buffer(),
+                            StorageKey::DynamicKeymapKeyOverride,
+                            &buf,
+                        )
+                        .await;
+                }
+            }
         }
 
         loop {
             match OPERATION_CHANNEL.receive().await {
                 Operation::Write(data, key, offset, len) => match key {
-                    VialStorageKeys::DynamicKeymapTapDance => {}
-                    VialStorageKeys::DynamicKeymapCombo => {}
-                    VialStorageKeys::DynamicKeymapKeyOverride => {}
+                    VialStorageKeys::DynamicKeymapTapDance => {
+                        let key = key.into();
+                        let mut buf = [0; K::NUM_TAP_DANCES * TAP_DANCE_ENTRY_SIZE];
+
+                        // Read data
+                        match database.read_raw(K::get_storage_buffer(), key).await {
+                            Ok((stored_data, stored_len)) => {
+                                buf[..stored_len].copy_from_slice(stored_data);
+                            }
+                            Err(()) => {
+                                warn!("[VIAL] Could not read dynamic keymap tap dances.");
+                            }
+                        };
+
+                        // Update data
+                        buf[offset..(offset + len)].copy_from_slice(&data[..len]);
+
+                        if let Err(()) =
+                            database.write_raw(K::get_storage_buffer(), key, &buf).await
+                        {
+                            warn!("[VIAL] Could not write dynamic keymap tap dances.")
+                        };
+                    }
+                    VialStorageKeys::DynamicKeymapCombo => {
+                        let key = key.into();
+                        let mut buf = [0; K::NUM_COMBOS * (MAX_COMBO_KEYS + 1) * 2];
+
+                        // Read data
+                        match database.read_raw(K::get_storage_buffer(), key).await {
+                            Ok((stored_data, stored_len)) => {
+                                buf[..stored_len].copy_from_slice(stored_data);
+                            }
+                            Err(()) => {
+                                warn!("[VIAL] Could not read dynamic keymap combos.");
+                            }
+                        };
+
+                        // Update data
+                        buf[offset..(offset + len)].copy_from_slice(&data[..len]);
+
+                        if let Err(()) =
+                            database.write_raw(K::get_storage_buffer(), key, &buf).await
+                        {
+                            warn!("[VIAL] Could not write dynamic keymap combos.")
+                        };
+                    }
+                    VialStorageKeys::DynamicKeymapKeyOverride => {
+                        let key = key.into();
+                        let mut buf = [0; K::NUM_KEY_OVERRIDES * VIAL_KEY_OVERRIDE_ENTRY_SIZE];
+
+                        // Read data
+                        match database.read_raw(K::get_storage_buffer(), key).await {
+                            Ok((stored_data, stored_len)) => {
+                                buf[..stored_len].copy_from_slice(stored_data);
+                            }
+                            Err(()) => {
+                                warn!("[VIAL] Could not read dynamic keymap key overrides.");
+                            }
+                        };
+
+                        // Update data
+                        buf[offset..(offset + len)].copy_from_slice(&data[..len]);
+
+                        if let Err(()) =
+                            database.write_raw(K::get_storage_buffer(), key, &buf).await
+                        {
+                            warn!("[VIAL] Could not write dynamic keymap key overrides.")
+                        };
+                    }
                 },
                 Operation::Delete => {
                     let _ = database.delete(StorageKey::DynamicKeymapTapDance).await;
@@ -232,6 +468,8 @@ pub mod storage {
                     let _ = database.delete(StorageKey::DynamicKeymapKeyOverride).await;
                 }
             }
+
+            OPERATION_COMPLETE.signal(())
         }
     }
 }
@@ -1,6 +1,7 @@
 use super::VialKeyboard;
 use crate::backlight::BacklightMatrixDevice;
 use crate::via::handlers::{dynamic_keymap_get_encoder, dynamic_keymap_set_encoder};
+use crate::via::protocol::keycodes; // We just use the keycode conversions from the new via protocol
 use crate::vial::handlers::*;
 use defmt::{info, warn, Debug2Format};
 use num_derive::FromPrimitive;
@@ -47,6 +48,18 @@ enum VialDynamicValue {
     KeyOverrideSet,
 }
 
+// Vial only supports a subset of QMK settings, so we only list the ones that are used
+#[derive(FromPrimitive, Debug, Clone, Copy)]
+pub(super) enum QmkSettingId {
+    ComboTerm = 2,
+    AutoShift = 3,
+    AutoShiftTimeout = 4,
+    OneShotTapToggle = 5,
+    OneShotTimeout = 6,
+    TappingTerm = 7,
+    Tapping = 8,
+}
+
 #[derive(Default)]
 pub(crate) struct VialState {
     pub(crate) unlocked: bool,
@@ -68,6 +81,9 @@ pub(crate) async fn process_vial_command<K: VialKeyboard + 'static>(
     [(); K::LAYOUT_COLS]:,
     [(); K::DYNAMIC_KEYMAP_MACRO_BUFFER_SIZE as usize]:,
     [(); K::DYNAMIC_KEYMAP_MACRO_COUNT as usize]:,
+    [(); K::NUM_COMBOS]:,
+    [(); K::NUM_KEY_OVERRIDES]:,
+    [(); K::NUM_TAP_DANCES]:,
 {
     if K::handle_via_command(data) {
         return;
@@ -114,6 +130,7 @@ pub(crate) async fn process_vial_command<K: VialKeyboard + 'static>(
                                     encoder_id,
                                     false,
                                     &mut data[0..=1],
+                                    keycodes::convert_action_to_keycode::<K>,
                                 )
                                 .await;
                                 dynamic_keymap_get_encoder::<K>(
@@ -121,6 +138,7 @@ pub(crate) async fn process_vial_command<K: VialKeyboard + 'static>(
                                     encoder_id,
                                     true,
                                     &mut data[2..=3],
+                                    keycodes::convert_action_to_keycode::<K>,
                                 )
                                 .await;
                             }
@@ -132,7 +150,8 @@ pub(crate) async fn process_vial_command<K: VialKeyboard + 'static>(
                                     layer,
                                     encoder_id,
                                     clockwise,
-                                    &mut data[5..=6],
+                                    &data[5..=6],
+                                    keycodes::convert_keycode_to_action::<K>,
                                 )
                                 .await;
                             }
@@ -145,9 +164,9 @@ pub(crate) async fn process_vial_command<K: VialKeyboard + 'static>(
                             }
                             VialCommandId::Lock => lock(vial_state),
                             VialCommandId::QmkSettingsQuery => qmk_settings_query(data),
-                            VialCommandId::QmkSettingsGet => qmk_settings_get(data),
-                            VialCommandId::QmkSettingsSet => qmk_settings_set(data),
-                            VialCommandId::QmkSettingsReset => qmk_settings_reset(data),
+                            VialCommandId::QmkSettingsGet => qmk_settings_get::<K>(data).await,
+                            VialCommandId::QmkSettingsSet => qmk_settings_set(data).await,
+                            VialCommandId::QmkSettingsReset => qmk_settings_reset().await,
                             VialCommandId::DynamicEntryOp => {
                                 if let Some(cmd) = num::FromPrimitive::from_u8(data[2]) {
                                     match cmd {
@@ -155,22 +174,38 @@ pub(crate) async fn process_vial_command<K: VialKeyboard + 'static>(
                                             dynamic_keymap_get_number_of_entries::<K>(data)
                                         }
                                         VialDynamicValue::TapDanceGet => {
-                                            dynamic_keymap_get_tap_dance(data)
+                                            dynamic_keymap_get_tap_dance::<K>(
+                                                data,
+                                                keycodes::convert_action_to_keycode::<K>,
+                                            )
+                                            .await
                                         }
                                         VialDynamicValue::TapDanceSet => {
-                                            dynamic_keymap_set_tap_dance(data)
+                                            dynamic_keymap_set_tap_dance::<K>(
+                                                data,
+                                                keycodes::convert_keycode_to_action::<K>,
+                                            )
+                                            .await
                                         }
                                         VialDynamicValue::ComboGet => {
-                                            dynamic_keymap_get_combo(data)
+                                            dynamic_keymap_get_combo::<K>(
+                                                data,
+                                                keycodes::convert_action_to_keycode::<K>,
+                                            )
+                                            .await
                                         }
                                         VialDynamicValue::ComboSet => {
-                                            dynamic_keymap_set_combo(data)
+                                            dynamic_keymap_set_combo::<K>(
+                                                data,
+                                                keycodes::convert_keycode_to_action::<K>,
+                                            )
+                                            .await
                                         }
                                         VialDynamicValue::KeyOverrideGet => {
-                                            dynamic_keymap_get_key_override(data)
+                                            dynamic_keymap_get_key_override::<K>(data).await
                                         }
                                         VialDynamicValue::KeyOverrideSet => {
-                                            dynamic_keymap_set_key_override(data)
+                                            dynamic_keymap_set_key_override::<K>(data).await
                                         }
                                     }
                                 }